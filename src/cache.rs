@@ -0,0 +1,365 @@
+//! On-disk cache of previous scans, keyed by the scanned root
+//!
+//! Files live under `$XDG_CACHE_HOME/smart-tree/` (falling back to
+//! `~/.cache/smart-tree/`). A saved scan keeps the whole tree, so the next
+//! scan of the root can reuse its unchanged directories and tell which
+//! entries are new or modified since.
+
+use crate::error::{Error, Result, WithContext};
+use crate::gitignore::GitIgnoreContext;
+use crate::scanner::ScanOptions;
+use crate::types::{ChangeKind, ChangeSet, DirectoryEntry, EntryKind, EntryMetadata};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Entries of `current` that are new or modified since the `previous`
+/// scan of the same root, keyed by their path in `current`
+///
+/// Files count as modified when their size or modification time differs,
+/// directories only when their own modification time does (entries were
+/// added, removed or renamed in them), so a change isn't repeated on every
+/// directory above it.
+pub fn changes_since(previous: &DirectoryEntry, current: &DirectoryEntry) -> ChangeSet {
+    let mut changes = ChangeSet::new();
+    compare_children(previous, current, &mut changes);
+    changes
+}
+
+fn compare_children(previous: &DirectoryEntry, current: &DirectoryEntry, changes: &mut ChangeSet) {
+    let before: HashMap<&str, &DirectoryEntry> = previous
+        .children
+        .iter()
+        .map(|child| (child.name.as_str(), child))
+        .collect();
+    for entry in &current.children {
+        let Some(old) = before.get(entry.name.as_str()) else {
+            mark_new(entry, changes);
+            continue;
+        };
+        let modified = old.metadata.modified != entry.metadata.modified
+            || (!entry.is_dir && old.metadata.size != entry.metadata.size);
+        if modified {
+            changes.insert(entry.path.clone(), ChangeKind::Modified);
+        }
+        compare_children(old, entry, changes);
+    }
+}
+
+fn mark_new(entry: &DirectoryEntry, changes: &mut ChangeSet) {
+    changes.insert(entry.path.clone(), ChangeKind::New);
+    for child in &entry.children {
+        mark_new(child, changes);
+    }
+}
+
+/// Load the tree `save_scan` saved for `root`, if it was scanned with the
+/// same options and ignore files, for `ScanOptions::previous`
+///
+/// `time_bounds`, when given, stands in the key for `options.newer_than`
+/// and `older_than`: the bounds as the user wrote them, so a relative bound
+/// like `2d` finds the scan again.
+pub fn load_scan(
+    root: &Path,
+    gitignore_ctx: &GitIgnoreContext,
+    options: &ScanOptions,
+    time_bounds: Option<&str>,
+) -> Result<Option<DirectoryEntry>> {
+    let path = cache_file("scans", root)?;
+    let Some(value) = read_cache_file(&path)? else {
+        return Ok(None);
+    };
+    let key = options_key(gitignore_ctx, options, time_bounds);
+    if value["options"].as_str() != Some(key.as_str()) {
+        return Ok(None);
+    }
+    entry_from_json(&value["tree"], None)
+        .map(Some)
+        .ok_or_else(|| Error::Format(format!("invalid scan in {}", path.display())))
+}
+
+/// Save the scanned tree of `root`, replacing the one saved before, with
+/// the same arguments `load_scan` will be given
+pub fn save_scan(
+    root: &Path,
+    gitignore_ctx: &GitIgnoreContext,
+    options: &ScanOptions,
+    time_bounds: Option<&str>,
+    tree: &DirectoryEntry,
+) -> Result<()> {
+    let mut value = json!({ "options": options_key(gitignore_ctx, options, time_bounds) });
+    value["tree"] = entry_to_json(tree, None);
+    write_cache_file(&cache_file("scans", root)?, &value)
+}
+
+/// Hash of the options, rule settings and ignore files shaping the tree, so
+/// a scan made with others isn't reused
+fn options_key(
+    gitignore_ctx: &GitIgnoreContext,
+    options: &ScanOptions,
+    time_bounds: Option<&str>,
+) -> String {
+    let mut options = options.clone();
+    options.progress = None;
+    options.cancel = None;
+    options.previous = None;
+    if time_bounds.is_some() {
+        options.newer_than = None;
+        options.older_than = None;
+    }
+    let key = format!(
+        "{:?} {:?} {}",
+        options,
+        time_bounds,
+        gitignore_ctx.settings()
+    );
+    format!("{:016x}", fnv1a(key.as_bytes()))
+}
+
+/// An entry and its children as JSON; paths are only stored where they
+/// aren't the parent's path joined with the name
+fn entry_to_json(entry: &DirectoryEntry, parent: Option<&Path>) -> Value {
+    let metadata = &entry.metadata;
+    let mut value = json!({
+        "name": entry.name,
+        "dir": entry.is_dir,
+        "size": metadata.size,
+        "created": nanos(metadata.created).to_string(),
+        "modified": nanos(metadata.modified).to_string(),
+        "files": metadata.files_count,
+        "mode": metadata.mode,
+        "uid": metadata.uid,
+        "gid": metadata.gid,
+        "approximate": metadata.approximate,
+        "ignored": entry.is_gitignored,
+        "repo": entry.is_git_repo,
+        "filtered_by": entry.filtered_by,
+        "annotation": entry.filter_annotation,
+        "error": entry.error,
+        "kind": kind_name(&entry.kind),
+        "unlisted": entry.has_unlisted,
+        "omitted": entry.has_omitted,
+        "cancelled": entry.cancelled,
+    });
+    if let EntryKind::Symlink { target, broken } = &entry.kind {
+        value["target"] = json!(target);
+        value["broken"] = json!(broken);
+    }
+    if parent.is_none_or(|parent| parent.join(&entry.name) != entry.path) {
+        value["path"] = json!(entry.path.to_string_lossy());
+    }
+    // Added afterwards, as json! would copy the subtree once more at each level
+    let children = entry.children.iter();
+    value["children"] = Value::Array(
+        children
+            .map(|child| entry_to_json(child, Some(&entry.path)))
+            .collect(),
+    );
+    value
+}
+
+fn entry_from_json(value: &Value, parent: Option<&Path>) -> Option<DirectoryEntry> {
+    let string = |key: &str| value[key].as_str().map(String::from);
+    let time = |key: &str| {
+        let nanos: u128 = value[key].as_str()?.parse().ok()?;
+        let duration = Duration::new(
+            u64::try_from(nanos / 1_000_000_000).ok()?,
+            (nanos % 1_000_000_000) as u32,
+        );
+        Some(UNIX_EPOCH + duration)
+    };
+    let id = |key: &str| value[key].as_u64().and_then(|id| u32::try_from(id).ok());
+    let name = string("name")?;
+    let path = match (value["path"].as_str(), parent) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(parent)) => parent.join(&name),
+        (None, None) => return None,
+    };
+    Some(DirectoryEntry {
+        children: value["children"]
+            .as_array()?
+            .iter()
+            .map(|child| entry_from_json(child, Some(&path)))
+            .collect::<Option<_>>()?,
+        path,
+        name,
+        is_dir: value["dir"].as_bool()?,
+        metadata: EntryMetadata {
+            size: value["size"].as_u64()?,
+            created: time("created")?,
+            modified: time("modified")?,
+            files_count: usize::try_from(value["files"].as_u64()?).ok()?,
+            mode: id("mode"),
+            uid: id("uid"),
+            gid: id("gid"),
+            approximate: value["approximate"].as_bool()?,
+        },
+        is_gitignored: value["ignored"].as_bool()?,
+        is_git_repo: value["repo"].as_bool()?,
+        filtered_by: string("filtered_by"),
+        filter_annotation: string("annotation"),
+        error: string("error"),
+        is_virtual: false,
+        kind: kind_from_name(value["kind"].as_str()?, value)?,
+        has_unlisted: value["unlisted"].as_bool()?,
+        has_omitted: value["omitted"].as_bool()?,
+        cancelled: value["cancelled"].as_bool()?,
+    })
+}
+
+fn kind_name(kind: &EntryKind) -> &'static str {
+    match kind {
+        EntryKind::Regular => "regular",
+        EntryKind::Symlink { .. } => "symlink",
+        EntryKind::Socket => "socket",
+        EntryKind::Fifo => "fifo",
+        EntryKind::BlockDevice => "block",
+        EntryKind::CharDevice => "char",
+    }
+}
+
+fn kind_from_name(name: &str, value: &Value) -> Option<EntryKind> {
+    match name {
+        "regular" => Some(EntryKind::Regular),
+        "symlink" => Some(EntryKind::Symlink {
+            target: value["target"].as_str().map(String::from),
+            broken: value["broken"].as_bool()?,
+        }),
+        "socket" => Some(EntryKind::Socket),
+        "fifo" => Some(EntryKind::Fifo),
+        "block" => Some(EntryKind::BlockDevice),
+        "char" => Some(EntryKind::CharDevice),
+        _ => None,
+    }
+}
+
+/// Nanoseconds since the epoch, 0 for earlier times
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos())
+}
+
+/// Directory holding all smart-tree cache files
+pub fn cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir).join("smart-tree"));
+    }
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .ok_or_else(|| {
+            Error::Config(String::from(
+                "cannot locate a cache directory: HOME is not set",
+            ))
+        })?;
+    Ok(PathBuf::from(home).join(".cache").join("smart-tree"))
+}
+
+/// Cache file of `root` in the `kind` directory, named by a hash of the
+/// root's canonical path
+fn cache_file(kind: &str, root: &Path) -> Result<PathBuf> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("resolving {}", root.display()))?;
+    let name = format!("{:016x}.json", fnv1a(root.to_string_lossy().as_bytes()));
+    Ok(cache_dir()?.join(kind).join(name))
+}
+
+/// The JSON in a cache file, or `None` if there is no such file
+fn read_cache_file(path: &Path) -> Result<Option<Value>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| Error::Format(format!("parsing {}: {}", path.display(), e)))
+}
+
+fn write_cache_file(path: &Path, value: &Value) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    std::fs::write(path, value.to_string()).with_context(|| format!("writing {}", path.display()))
+}
+
+/// FNV-1a, a hash that stays stable across Rust releases unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scan, GitIgnoreContext};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_scan_json_round_trip() {
+        let root = tempdir().unwrap();
+        fs::create_dir_all(root.path().join("src/bin")).unwrap();
+        fs::write(root.path().join("src/bin/tool.rs"), "fn main() {}").unwrap();
+        fs::write(root.path().join("README.md"), "# demo").unwrap();
+
+        let mut ctx = GitIgnoreContext::new(root.path()).unwrap();
+        let tree = scan(root.path(), &mut ctx, &ScanOptions::default()).unwrap();
+        let json = entry_to_json(&tree, None);
+        assert!(
+            json["children"][0].get("path").is_none(),
+            "Derived from the parent"
+        );
+
+        // Timestamps survive to the nanosecond
+        let loaded = entry_from_json(&json, None).unwrap();
+        assert_eq!(entry_to_json(&loaded, None), json);
+        let src = loaded.children.iter().find(|c| c.name == "src").unwrap();
+        let tool = &src.children[0].children[0];
+        assert_eq!(tool.path, root.path().join("src/bin/tool.rs"));
+    }
+
+    #[test]
+    fn test_options_key() {
+        use crate::gitignore::IgnoreFile;
+        use crate::rules::{AllowRule, FilterRegistry};
+        use std::sync::Arc;
+        use std::time::SystemTime;
+
+        let root = tempdir().unwrap();
+        let ctx = GitIgnoreContext::new(root.path()).unwrap();
+        let key = |options: &ScanOptions| options_key(&ctx, options, None);
+        let depth = |max_depth| key(&ScanOptions::builder().max_depth(max_depth).build());
+        assert_eq!(depth(3), depth(3));
+        assert_ne!(depth(3), depth(4));
+
+        // Rule settings beyond their ids
+        let keeping = |pattern: &str| {
+            let mut registry = FilterRegistry::new();
+            registry.add_rule(AllowRule::new(vec![pattern.to_string()], true));
+            key(&ScanOptions::builder()
+                .rules(Some(Arc::new(registry)))
+                .build())
+        };
+        assert_ne!(keeping("*.log"), keeping("*.txt"));
+
+        // The ignore files applied
+        let defaults = ScanOptions::default();
+        let docker = GitIgnoreContext::with_ignore_files(root.path(), &[IgnoreFile::Docker]);
+        assert_ne!(
+            key(&defaults),
+            options_key(&docker.unwrap(), &defaults, None)
+        );
+
+        // Time bounds as written rather than as resolved
+        let now = |written| {
+            let options = ScanOptions::builder()
+                .newer_than(Some(SystemTime::now()))
+                .build();
+            options_key(&ctx, &options, Some(written))
+        };
+        assert_eq!(now("2d"), now("2d"));
+        assert_ne!(now("2d"), now("3d"));
+    }
+}
@@ -0,0 +1,241 @@
+//! User configuration file
+//!
+//! `$XDG_CONFIG_HOME/smart-tree/config.toml` (falling back to
+//! `~/.config/smart-tree/config.toml`) sets defaults for command-line flags.
+//! Keys are long flag names, with dashes or underscores:
+//!
+//! ```toml
+//! dirs-first = true
+//! max-lines = 80
+//! sort-by = "size"
+//! disable-rule = ["vcs", "build_output"]
+//! ```
+//!
+//! Named profiles bundle settings under `[profiles.<name>]` and are applied
+//! with `--profile <name>`, on top of the other settings:
+//!
+//! ```toml
+//! [profiles.review]
+//! sort-by = "modified"
+//! max-lines = 40
+//! ```
+//!
+//! A `.smart-tree.toml` at the scanned root can set a subset of the same keys
+//! for one project; with several roots only the first one's is read. Settings become arguments placed ahead of the real
+//! command line, project settings after the user's and profile settings
+//! last, so each layer overrides the ones before it.
+
+use crate::error::{Error, Result, WithContext};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Profiles available without any configuration; a profile of the same name
+/// in a configuration file replaces them
+const BUILTIN_PROFILES: &str = r#"
+# The shape of a project: a few levels of directories first
+[profiles.overview]
+max-depth = 2
+dirs-first = true
+max-lines = 60
+
+# Everything, without line or per-directory limits
+[profiles.full]
+max-lines = 1000000
+dir-limit = 1000000
+show-hidden = true
+
+# Plain, compact context for language models
+[profiles.llm]
+color = "never"
+icons = "none"
+max-width = 0
+max-lines = 300
+"#;
+
+/// Settings read from a configuration file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    settings: Table,
+    profiles: BTreeMap<String, Config>,
+}
+
+impl Config {
+    /// Load the configuration file at `path`, if it exists
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let config = Self::parse(&text)
+            .map_err(|e| Error::Config(format!("parsing {}: {}", path.display(), e)))?;
+        Ok(Some(config))
+    }
+
+    /// Parse configuration from TOML text
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut settings: Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| Error::Config(e.to_string()))?;
+        let profiles = match settings.remove("profiles") {
+            None => BTreeMap::new(),
+            Some(Value::Table(profiles)) => profiles
+                .into_iter()
+                .map(|(name, settings)| match settings {
+                    Value::Table(settings) => Ok((
+                        name,
+                        Self {
+                            settings,
+                            profiles: BTreeMap::new(),
+                        },
+                    )),
+                    _ => Err(Error::Config(format!(
+                        "profile '{}' must be a table of settings",
+                        name
+                    ))),
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => {
+                return Err(Error::Config(String::from(
+                    "'profiles' must be a table of profiles",
+                )))
+            }
+        };
+        Ok(Self { settings, profiles })
+    }
+
+    /// The built-in profiles (`overview`, `full` and `llm`)
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_PROFILES).expect("built-in profiles are valid TOML")
+    }
+
+    /// The profile of that name, if the file defines it
+    pub fn profile(&self, name: &str) -> Option<&Config> {
+        self.profiles.get(name)
+    }
+
+    /// Names of the profiles the file defines
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Names of the settings, as written in the file
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.settings.keys().map(String::as_str)
+    }
+
+    /// Command-line arguments equivalent to the settings
+    ///
+    /// `takes_value` reports whether the long flag of that name exists and
+    /// expects a value, or None for unknown flags. Booleans become bare flags
+    /// (omitted when false) and arrays repeat the flag for each element.
+    pub fn args(&self, takes_value: impl Fn(&str) -> Option<bool>) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        for (key, value) in &self.settings {
+            let flag = key.replace('_', "-");
+            let Some(takes_value) = takes_value(&flag) else {
+                return Err(Error::Config(format!("unknown setting '{}'", key)));
+            };
+            let values = match value {
+                Value::Array(items) => items.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                match value {
+                    Value::Boolean(true) if !takes_value => args.push(format!("--{}", flag)),
+                    Value::Boolean(false) if !takes_value => {}
+                    _ if !takes_value => {
+                        return Err(Error::Config(format!(
+                            "'{}' is a switch: use true or false",
+                            key
+                        )))
+                    }
+                    Value::String(text) => args.push(format!("--{}={}", flag, text)),
+                    Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => {
+                        args.push(format!("--{}={}", flag, value))
+                    }
+                    _ => return Err(Error::Config(format!("unsupported value for '{}'", key))),
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Location of the user configuration file
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join("smart-tree").join("config.toml"));
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(
+        PathBuf::from(home)
+            .join(".config")
+            .join("smart-tree")
+            .join("config.toml"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn takes_value(flag: &str) -> Option<bool> {
+        match flag {
+            "dirs-first" | "emoji" => Some(false),
+            "max-lines" | "sort-by" | "disable-rule" => Some(true),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_config_args() {
+        let config = Config::parse(
+            "dirs_first = true\nemoji = false\nmax-lines = 80\nsort-by = \"size\"\n\
+             disable-rule = [\"vcs\", \"build_output\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.args(takes_value).unwrap(),
+            vec![
+                "--dirs-first",
+                "--disable-rule=vcs",
+                "--disable-rule=build_output",
+                "--max-lines=80",
+                "--sort-by=size",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let unknown = Config::parse("colour = true").unwrap();
+        assert!(unknown.args(takes_value).is_err());
+        let switch = Config::parse("dirs-first = \"yes\"").unwrap();
+        assert!(switch.args(takes_value).is_err());
+        assert!(Config::parse("max-lines = ").is_err());
+        assert!(Config::parse("profiles = 1").is_err());
+        assert!(Config::parse("[profiles]\nreview = 1").is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let config =
+            Config::parse("dirs-first = true\n[profiles.review]\nsort-by = \"modified\"\n")
+                .unwrap();
+        assert_eq!(config.args(takes_value).unwrap(), vec!["--dirs-first"]);
+        let review = config.profile("review").unwrap();
+        assert_eq!(
+            review.args(takes_value).unwrap(),
+            vec!["--sort-by=modified"]
+        );
+        assert!(config.profile("llm").is_none());
+
+        let builtin = Config::builtin();
+        assert_eq!(
+            builtin.profile_names().collect::<Vec<_>>(),
+            vec!["full", "llm", "overview"]
+        );
+    }
+}
@@ -1,11 +1,20 @@
-use crate::types::{ColorTheme, DirectoryEntry, DisplayConfig, FileType};
+use crate::scanner::has_hidden_attribute;
+use crate::types::{
+    ChangeKind, ColorMode, ColorTheme, DirectoryEntry, DisplayConfig, EntryKind, FileType,
+    GitStatus, IconStyle,
+};
 use colored::{Color, ColoredString, Colorize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
 
 // Tree connectors with padding
 pub const TREE_BRANCH: &str = "├── "; // T-shape connector
 pub const TREE_CORNER: &str = "└── "; // L-shape corner connector
 pub const TREE_VERTICAL: &str = "│   "; // Vertical line with spacing
 pub const TREE_SPACE: &str = "    "; // Empty space for indentation
+pub const MARKDOWN_BULLET: &str = "- "; // List item marker in Markdown output
+pub const MARKDOWN_INDENT: &str = "  "; // Nesting for Markdown list items
 
 // Special strings and emoji for file types
 pub const EMOJI_DIRECTORY: &str = "📁 ";
@@ -18,6 +27,11 @@ pub const EMOJI_CODE: &str = "📝 ";
 pub const EMOJI_LINK: &str = "🔗 ";
 pub const EMOJI_HIDDEN: &str = "👁️ ";
 pub const EMOJI_LOCK: &str = "🔒 ";
+pub const EMOJI_GIT_REPO: &str = "🌿 ";
+pub const EMOJI_SOCKET: &str = "🔌 ";
+pub const EMOJI_FIFO: &str = "🔀 ";
+pub const EMOJI_BLOCK_DEVICE: &str = "💽 ";
+pub const EMOJI_CHAR_DEVICE: &str = "📟 ";
 
 /// Determines whether to use colors based on config and terminal capabilities
 pub fn should_use_colors(config: &DisplayConfig) -> bool {
@@ -28,23 +42,89 @@ pub fn should_use_colors(config: &DisplayConfig) -> bool {
     colored::control::SHOULD_COLORIZE.should_colorize()
 }
 
+/// Apply a `--color` mode process-wide, returning whether output may be colored
+///
+/// `Auto` colors only when stdout is a terminal and honors the `NO_COLOR`,
+/// `CLICOLOR` and `CLICOLOR_FORCE` conventions; `Always` forces colors even
+/// when piping, e.g. into a pager.
+pub fn set_color_mode(mode: ColorMode) -> bool {
+    match mode {
+        ColorMode::Always => colored::control::set_override(true),
+        ColorMode::Never => colored::control::set_override(false),
+        ColorMode::Auto => {
+            // An empty NO_COLOR doesn't count as set
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            if no_color {
+                colored::control::set_override(false);
+            } else {
+                colored::control::unset_override();
+            }
+        }
+    }
+    mode != ColorMode::Never
+}
+
+/// Prepare the terminal for ANSI escape sequences, returning false if it cannot render them
+///
+/// Legacy Windows consoles (cmd.exe/ConHost) only interpret escape sequences
+/// once virtual terminal processing is enabled; elsewhere this is a no-op.
+pub fn enable_ansi_support() -> bool {
+    #[cfg(windows)]
+    {
+        colored::control::set_virtual_terminal(true).is_ok()
+    }
+
+    #[cfg(not(windows))]
+    {
+        true
+    }
+}
+
 /// Returns whether to use emoji based on config
 pub fn should_use_emoji(config: &DisplayConfig) -> bool {
     config.use_emoji && should_use_colors(config)
 }
 
 /// Determine the file type from extension and metadata
-pub(super) fn determine_file_type(entry: &DirectoryEntry) -> FileType {
+pub(super) fn determine_file_type(entry: &DirectoryEntry, config: &DisplayConfig) -> FileType {
+    known_file_type(entry)
+        .or_else(|| sniff_entry(entry, config).map(|(file_type, _)| file_type))
+        .unwrap_or_else(|| fallback_file_type(entry))
+}
+
+/// The file type along with the MIME type found by sniffing its content,
+/// which is read once for both
+pub(super) fn file_type_and_mime(
+    entry: &DirectoryEntry,
+    config: &DisplayConfig,
+) -> (FileType, Option<&'static str>) {
+    let known = known_file_type(entry);
+    match sniff_entry(entry, config) {
+        Some((sniffed, mime)) => (known.unwrap_or(sniffed), Some(mime)),
+        None => (known.unwrap_or_else(|| fallback_file_type(entry)), None),
+    }
+}
+
+/// The file type told by the entry's kind, name and extension alone
+fn known_file_type(entry: &DirectoryEntry) -> Option<FileType> {
     if entry.is_dir {
-        return FileType::Directory;
+        return Some(FileType::Directory);
     }
 
-    if entry.path.is_symlink() {
-        return FileType::Symlink;
+    // Before anything that might open the file, which blocks on a FIFO
+    if let Some(file_type) = special_file_type(&entry.kind) {
+        return Some(file_type);
     }
 
-    if entry.name.starts_with('.') {
-        return FileType::Hidden;
+    if entry.name.starts_with('.')
+        || (cfg!(windows)
+            && !entry.is_virtual
+            && entry
+                .path
+                .symlink_metadata()
+                .is_ok_and(|metadata| has_hidden_attribute(&metadata)))
+    {
+        return Some(FileType::Hidden);
     }
 
     let path = &entry.path;
@@ -57,7 +137,7 @@ pub(super) fn determine_file_type(entry: &DirectoryEntry) -> FileType {
                 ext.as_str(),
                 "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "webp" | "svg"
             ) {
-                return FileType::Image;
+                return Some(FileType::Image);
             }
 
             // Videos
@@ -65,12 +145,12 @@ pub(super) fn determine_file_type(entry: &DirectoryEntry) -> FileType {
                 ext.as_str(),
                 "mp4" | "mov" | "avi" | "mkv" | "webm" | "flv" | "wmv"
             ) {
-                return FileType::Video;
+                return Some(FileType::Video);
             }
 
             // Audio
             if matches!(ext.as_str(), "mp3" | "wav" | "ogg" | "flac" | "aac" | "m4a") {
-                return FileType::Audio;
+                return Some(FileType::Audio);
             }
 
             // Archives
@@ -78,7 +158,7 @@ pub(super) fn determine_file_type(entry: &DirectoryEntry) -> FileType {
                 ext.as_str(),
                 "zip" | "rar" | "tar" | "gz" | "7z" | "bz2" | "xz"
             ) {
-                return FileType::Archive;
+                return Some(FileType::Archive);
             }
 
             // Code files
@@ -109,7 +189,7 @@ pub(super) fn determine_file_type(entry: &DirectoryEntry) -> FileType {
                     | "exs"
                     | "clj"
             ) {
-                return FileType::Code;
+                return Some(FileType::Code);
             }
 
             // Documents
@@ -117,29 +197,49 @@ pub(super) fn determine_file_type(entry: &DirectoryEntry) -> FileType {
                 ext.as_str(),
                 "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "txt" | "md" | "rst"
             ) {
-                return FileType::Document;
+                return Some(FileType::Document);
             }
 
             // Executables
             if matches!(ext.as_str(), "exe" | "dll" | "so" | "dylib" | "bin") {
-                return FileType::Executable;
+                return Some(FileType::Executable);
             }
         }
     }
 
-    // Check if file is executable (only works on Unix-like systems)
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        if let Ok(metadata) = path.metadata() {
-            let permissions = metadata.permissions();
-            if permissions.mode() & 0o111 != 0 {
-                return FileType::Executable;
-            }
-        }
+    None
+}
+
+/// The type of a file with an unknown or missing extension that wasn't sniffed
+fn fallback_file_type(entry: &DirectoryEntry) -> FileType {
+    // Executable by its permission bits (only set on Unix-like systems)
+    if entry.metadata.mode.is_some_and(|mode| mode & 0o111 != 0) {
+        FileType::Executable
+    } else {
+        FileType::Regular
     }
+}
 
-    FileType::Regular
+/// The sniffed type of a regular file read from disk, if sniffing is enabled
+fn sniff_entry(entry: &DirectoryEntry, config: &DisplayConfig) -> Option<(FileType, &'static str)> {
+    let regular = !entry.is_dir && entry.kind == EntryKind::Regular;
+    if !config.sniff_content || entry.is_virtual || !regular {
+        return None;
+    }
+    sniff_content(&entry.path)
+}
+
+/// The type of a symlink, socket, named pipe or device file, or None for
+/// anything else
+pub(crate) fn special_file_type(kind: &EntryKind) -> Option<FileType> {
+    match kind {
+        EntryKind::Regular => None,
+        EntryKind::Symlink { .. } => Some(FileType::Symlink),
+        EntryKind::Socket => Some(FileType::Socket),
+        EntryKind::Fifo => Some(FileType::Fifo),
+        EntryKind::BlockDevice => Some(FileType::BlockDevice),
+        EntryKind::CharDevice => Some(FileType::CharDevice),
+    }
 }
 
 /// Get emoji for file type
@@ -156,13 +256,45 @@ pub(super) fn get_file_emoji(file_type: FileType) -> &'static str {
         FileType::Executable => EMOJI_LOCK,
         FileType::Hidden => EMOJI_HIDDEN,
         FileType::Regular => EMOJI_FILE,
+        FileType::Socket => EMOJI_SOCKET,
+        FileType::Fifo => EMOJI_FIFO,
+        FileType::BlockDevice => EMOJI_BLOCK_DEVICE,
+        FileType::CharDevice => EMOJI_CHAR_DEVICE,
     }
 }
 
-/// Get the appropriate color for a file name based on its type
-pub(super) fn get_name_color(entry: &DirectoryEntry, config: &DisplayConfig) -> Color {
-    let file_type = determine_file_type(entry);
+/// Number of leading bytes inspected when sniffing file content
+const SNIFF_LEN: usize = 8192;
+
+/// Detect a file's type and MIME type from its magic bytes or shebang line
+///
+/// Only for regular files, as opening a named pipe blocks.
+pub(super) fn sniff_content(path: &Path) -> Option<(FileType, &'static str)> {
+    let mut buffer = [0u8; SNIFF_LEN];
+    let read = File::open(path)
+        .and_then(|mut f| f.read(&mut buffer))
+        .ok()?;
+    let head = &buffer[..read];
+
+    if head.starts_with(b"#!") {
+        return Some((FileType::Code, "text/x-script"));
+    }
+
+    let kind = infer::get(head)?;
+    let file_type = match kind.matcher_type() {
+        infer::MatcherType::Image => FileType::Image,
+        infer::MatcherType::Video => FileType::Video,
+        infer::MatcherType::Audio => FileType::Audio,
+        infer::MatcherType::Archive => FileType::Archive,
+        infer::MatcherType::Doc | infer::MatcherType::Book => FileType::Document,
+        infer::MatcherType::App => FileType::Executable,
+        _ => return None,
+    };
+    Some((file_type, kind.mime_type()))
+}
 
+/// Get the appropriate color for a file name based on its type
+pub(super) fn get_name_color(file_type: FileType, config: &DisplayConfig) -> Color {
     match config.color_theme {
         ColorTheme::Light => match file_type {
             FileType::Directory => Color::Blue,
@@ -176,6 +308,8 @@ pub(super) fn get_name_color(entry: &DirectoryEntry, config: &DisplayConfig) ->
             FileType::Executable => Color::Red,
             FileType::Hidden => Color::BrightBlack,
             FileType::Regular => Color::Black,
+            FileType::Socket => Color::Magenta,
+            FileType::Fifo | FileType::BlockDevice | FileType::CharDevice => Color::Yellow,
         },
         ColorTheme::Dark => match file_type {
             FileType::Directory => Color::BrightBlue,
@@ -189,6 +323,8 @@ pub(super) fn get_name_color(entry: &DirectoryEntry, config: &DisplayConfig) ->
             FileType::Executable => Color::BrightRed,
             FileType::Hidden => Color::BrightBlack,
             FileType::Regular => Color::White,
+            FileType::Socket => Color::BrightMagenta,
+            FileType::Fifo | FileType::BlockDevice | FileType::CharDevice => Color::BrightYellow,
         },
         _ => match file_type {
             // Auto mode - use system settings or dark by default
@@ -203,6 +339,8 @@ pub(super) fn get_name_color(entry: &DirectoryEntry, config: &DisplayConfig) ->
             FileType::Executable => Color::BrightRed,
             FileType::Hidden => Color::BrightBlack,
             FileType::Regular => Color::White,
+            FileType::Socket => Color::BrightMagenta,
+            FileType::Fifo | FileType::BlockDevice | FileType::CharDevice => Color::BrightYellow,
         },
     }
 }
@@ -365,6 +503,59 @@ pub(super) fn get_filter_annotation_color(config: &DisplayConfig) -> Color {
     }
 }
 
+/// Get color for the [empty] annotation
+pub(super) fn get_empty_annotation_color(config: &DisplayConfig) -> Color {
+    match config.color_theme {
+        ColorTheme::Light => Color::Yellow,
+        ColorTheme::Dark => Color::BrightYellow,
+        _ => Color::BrightYellow,
+    }
+}
+
+/// Get color for the annotation of entries that couldn't be read
+pub(super) fn get_error_color(config: &DisplayConfig) -> Color {
+    match config.color_theme {
+        ColorTheme::Light => Color::Red,
+        ColorTheme::Dark => Color::BrightRed,
+        _ => Color::BrightRed,
+    }
+}
+
+/// Get color for the [git repo] badge of nested repositories
+pub(super) fn get_git_repo_color(config: &DisplayConfig) -> Color {
+    match config.color_theme {
+        ColorTheme::Light => Color::Green,
+        ColorTheme::Dark => Color::BrightGreen,
+        _ => Color::BrightGreen,
+    }
+}
+
+/// Get color for entries that changed since the previous scan
+pub(super) fn get_change_color(change: ChangeKind, config: &DisplayConfig) -> Color {
+    match (change, &config.color_theme) {
+        (ChangeKind::New, ColorTheme::Light) => Color::Green,
+        (ChangeKind::New, _) => Color::BrightGreen,
+        (ChangeKind::Modified, ColorTheme::Light) => Color::Yellow,
+        (ChangeKind::Modified, _) => Color::BrightYellow,
+    }
+}
+
+/// Get color for git status markers: conflicts, then unstaged, staged and untracked changes
+pub(super) fn get_git_status_color(status: GitStatus, config: &DisplayConfig) -> Color {
+    let light = config.color_theme == ColorTheme::Light;
+    match status {
+        GitStatus {
+            conflicted: true, ..
+        } => Color::Red,
+        GitStatus { modified: true, .. } if light => Color::Yellow,
+        GitStatus { modified: true, .. } => Color::BrightYellow,
+        GitStatus { staged: true, .. } if light => Color::Green,
+        GitStatus { staged: true, .. } => Color::BrightGreen,
+        _ if light => Color::Magenta,
+        _ => Color::BrightMagenta,
+    }
+}
+
 /// Colorize a string if colors are enabled, otherwise return it as-is
 pub(super) fn colorize(text: &str, color: Color, config: &DisplayConfig) -> String {
     if should_use_colors(config) {
@@ -374,6 +565,34 @@ pub(super) fn colorize(text: &str, color: Color, config: &DisplayConfig) -> Stri
     }
 }
 
+/// Wrap text in an OSC 8 hyperlink to the file, if hyperlinks are enabled
+pub(super) fn hyperlink(text: &str, path: &Path, config: &DisplayConfig) -> String {
+    if !config.hyperlinks {
+        return text.to_string();
+    }
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", file_url(path), text)
+}
+
+/// `file://` URL of a path, made absolute and percent-encoded
+fn file_url(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        // Windows drive paths: file:///C:/...
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(char::from(byte))
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
 /// Colorize with custom styling (bold, underline, etc.)
 pub(super) fn colorize_styled(
     text: &str,
@@ -394,14 +613,66 @@ pub(super) fn colorize_styled(
     colored_text.to_string()
 }
 
-/// Format a file path for display with optional emoji
-pub(super) fn format_name_with_emoji(entry: &DirectoryEntry, config: &DisplayConfig) -> String {
+/// Colorize an entry name: with its LS_COLORS style in `--ls-colors` mode,
+/// otherwise with the palette color for its type (directories in bold)
+pub(super) fn colorize_name(
+    text: &str,
+    entry: &DirectoryEntry,
+    file_type: FileType,
+    config: &DisplayConfig,
+) -> String {
+    let Some(ls_colors) = &config.ls_colors else {
+        return colorize_styled(
+            text,
+            get_name_color(file_type, config),
+            entry.is_dir,
+            config,
+        );
+    };
+    match ls_colors.style(entry) {
+        Some(sgr) if should_use_colors(config) => format!("\x1b[{}m{}\x1b[0m", sgr, text),
+        _ => text.to_string(),
+    }
+}
+
+/// Format a file path for display with its emoji or Nerd Font icon
+pub(super) fn format_name_with_emoji(
+    entry: &DirectoryEntry,
+    file_type: FileType,
+    config: &DisplayConfig,
+) -> String {
     if !should_use_emoji(config) {
         return entry.name.clone();
     }
 
-    let file_type = determine_file_type(entry);
-    let emoji = get_file_emoji(file_type);
+    if let Some(icon) = icon_override(entry, config) {
+        return format!("{} {}", icon, entry.name);
+    }
+
+    match config.icon_style {
+        IconStyle::Nerd => {
+            let icon = super::icons::nerd_icon(entry, file_type);
+            format!("{} {}", icon, entry.name)
+        }
+        IconStyle::Emoji => {
+            let emoji = if entry.is_git_repo {
+                EMOJI_GIT_REPO
+            } else {
+                get_file_emoji(file_type)
+            };
+            format!("{}{}", emoji, entry.name)
+        }
+    }
+}
 
-    format!("{}{}", emoji, entry.name)
+/// User-configured icon for the entry's file name, or else its extension
+fn icon_override<'a>(entry: &DirectoryEntry, config: &'a DisplayConfig) -> Option<&'a str> {
+    let by_name = config.icon_overrides.get(&entry.name);
+    let by_extension = || {
+        let ext = entry.path.extension()?.to_str()?.to_lowercase();
+        config.icon_overrides.get(&ext)
+    };
+    by_name
+        .or_else(|| (!entry.is_dir).then(by_extension).flatten())
+        .map(String::as_str)
 }
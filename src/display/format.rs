@@ -1,28 +1,219 @@
 use super::colors;
+use super::report::SkipReport;
 use super::state::DisplayState;
-use super::utils::{format_metadata, sort_entries};
+use super::template::Template;
+use super::utils::{code_span, format_metadata, is_empty_entry, retain_matching, sort_entries};
+use super::width::{strip_escapes, truncate_to_width};
+use crate::error::Result;
 use crate::types::{DirectoryEntry, DisplayConfig};
-use anyhow::Result;
+use tracing::{debug_span, info_span};
 
 pub fn format_tree(root: &DirectoryEntry, config: &DisplayConfig) -> Result<String> {
+    let _span = info_span!("format_tree", max_lines = config.max_lines).entered();
+    if let Some(format_string) = &config.format_string {
+        let template = Template::parse(format_string)?;
+        let mut output = String::new();
+        let children = visible_children(root, config);
+        template.render_all(&children, &root.path, 1, config, &mut output);
+        return Ok(output);
+    }
+    Ok(render_tree(root, ".", config))
+}
+
+/// Format several roots as consecutive trees, each headed by its name
+///
+/// The line budget is shared: each tree gets an even share of what is left,
+/// and trees that need less leave their unused lines to the larger ones.
+pub fn format_trees(roots: &[DirectoryEntry], config: &DisplayConfig) -> Result<String> {
+    // The budget is in estimated tokens with max_tokens, in lines otherwise
+    let total = config.max_tokens.unwrap_or(config.max_lines);
+    let measure = |text: &str| match config.max_tokens {
+        Some(_) => count_tokens(text, config),
+        None => text.lines().count(),
+    };
+    let render = |root: &DirectoryEntry, budget: usize| {
+        let config = match config.max_tokens {
+            Some(_) => DisplayConfig {
+                max_tokens: Some(budget.max(1)),
+                ..config.clone()
+            },
+            None => DisplayConfig {
+                max_lines: budget.max(1),
+                ..config.clone()
+            },
+        };
+        match &config.format_string {
+            Some(_) => format_tree(root, &config),
+            None => Ok(render_tree(root, &root.name, &config)),
+        }
+    };
+
+    // Smallest trees first, so the budget they leave over goes to the larger ones
+    let mut trees = roots
+        .iter()
+        .map(|root| render(root, total))
+        .collect::<Result<Vec<_>>>()?;
+    let wanted: Vec<usize> = trees.iter().map(|tree| measure(tree)).collect();
+    let mut order: Vec<usize> = (0..roots.len()).collect();
+    order.sort_by_key(|&i| wanted[i]);
+
+    let mut budget_left = total;
+    for (n, &i) in order.iter().enumerate() {
+        let share = budget_left / (roots.len() - n);
+        // Only trees that don't fit their share are rendered again, smaller
+        if wanted[i] > share {
+            trees[i] = render(&roots[i], share)?;
+        }
+        budget_left = budget_left.saturating_sub(measure(&trees[i]));
+    }
+    Ok(trees.join("\n"))
+}
+
+/// The tree view of `root`, headed by `label`
+fn render_tree(root: &DirectoryEntry, label: &str, config: &DisplayConfig) -> String {
+    match config.max_tokens {
+        Some(max_tokens) => render_within_tokens(root, label, config, max_tokens),
+        None => render_lines(root, label, config),
+    }
+}
+
+/// A tree view whose estimated tokens fit in `max_tokens`
+///
+/// One render of the full tree gives the average cost of a line, which sets
+/// the line budget, so the tree keeps the shape the line-based layout gives
+/// it. Lines past the budget are cut from the end when the estimate falls
+/// short; a tree too large for even one line shows just the root.
+fn render_within_tokens(
+    root: &DirectoryEntry,
+    label: &str,
+    config: &DisplayConfig,
+    max_tokens: usize,
+) -> String {
+    let render = |max_lines| {
+        let config = DisplayConfig {
+            max_lines,
+            ..config.clone()
+        };
+        render_lines(root, label, &config)
+    };
+    let full = render(count_entries(root));
+    let tokens = count_tokens(&full, config);
+    if tokens <= max_tokens {
+        return full;
+    }
+    let per_line = tokens.div_ceil(full.lines().count());
+    let mut max_lines = (max_tokens / per_line).max(1);
+    let mut output = render(max_lines);
+    // Small budgets go partly to "... more" markers, leaving tokens over; use them once
+    let slack = max_tokens.saturating_sub(count_tokens(&output, config)) / per_line;
+    if slack > 0 {
+        max_lines += slack;
+        output = render(max_lines);
+    }
+    while count_tokens(&output, config) > max_tokens {
+        let Some(end) = output.trim_end_matches('\n').rfind('\n') else {
+            break;
+        };
+        output.truncate(end + 1);
+    }
+    output
+}
+
+/// Estimated tokens of the visible text
+fn count_tokens(output: &str, config: &DisplayConfig) -> usize {
+    config.tokenizer.count(&strip_escapes(output))
+}
+
+fn count_entries(entry: &DirectoryEntry) -> usize {
+    1 + entry.children.iter().map(count_entries).sum::<usize>()
+}
+
+/// The tree view of `root` within `config.max_lines`
+fn render_lines(root: &DirectoryEntry, label: &str, config: &DisplayConfig) -> String {
+    let mut state = layout(root, label, config);
+    if config.skip_report {
+        let report = state.report.render(&root.path, config);
+        state.output.push_str(&report);
+    }
+    match config.max_width {
+        Some(max_width) => fit_lines(&state.output, max_width),
+        None => state.output,
+    }
+}
+
+/// Ellipsize lines that would wrap, which breaks the tree shape
+fn fit_lines(output: &str, max_width: usize) -> String {
+    output
+        .split_inclusive('\n')
+        .map(|line| match line.strip_suffix('\n') {
+            Some(line) => truncate_to_width(line, max_width) + "\n",
+            None => truncate_to_width(line, max_width),
+        })
+        .collect()
+}
+
+/// Collect what the tree view of `root` would fold or truncate
+pub fn skip_report(root: &DirectoryEntry, config: &DisplayConfig) -> SkipReport {
+    layout(root, ".", config).report
+}
+
+fn layout<'a>(root: &DirectoryEntry, label: &str, config: &'a DisplayConfig) -> DisplayState<'a> {
     let mut state = DisplayState::new(config.max_lines, config);
 
-    // Colorize the root directory entry
-    let root_dir = colors::colorize_styled(
-        ".",
-        colors::get_name_color(root, config),
-        true, // Bold for directory
-        config,
-    );
-    state.output.push_str(&format!("{}\n", root_dir));
-    state.lines_remaining -= 1;
+    // A root listed only in part (over the memory limit or cancelled) says so
+    let annotation = match (&root.filter_annotation, &root.filtered_by) {
+        (Some(annotation), None) => colors::colorize(
+            &format!(" {}", annotation),
+            colors::get_filter_annotation_color(config),
+            config,
+        ),
+        _ => String::new(),
+    };
 
-    let mut children = root.children.clone();
-    sort_entries(&mut children, config);
+    // Colorize the root directory entry; in Markdown it heads the list
+    let prefix = if config.markdown {
+        let root_dir = code_span(label);
+        state.output.push_str(&format!(
+            "{}{}{}\n",
+            colors::MARKDOWN_BULLET,
+            root_dir,
+            annotation
+        ));
+        colors::MARKDOWN_INDENT
+    } else {
+        let file_type = colors::determine_file_type(root, config);
+        let root_dir = colors::colorize_name(label, root, file_type, config);
+        state
+            .output
+            .push_str(&format!("{}{}\n", root_dir, annotation));
+        ""
+    };
+    state.lines_remaining -= 1;
 
-    state.show_items(&children, "");
+    let children = visible_children(root, config);
+    debug_span!("layout").in_scope(|| state.show_items(&children, prefix));
+    state.report.record_unreadable(root);
+    state
+}
 
-    Ok(state.output)
+/// The root's children after filtering and sorting
+pub(super) fn visible_children(
+    root: &DirectoryEntry,
+    config: &DisplayConfig,
+) -> Vec<DirectoryEntry> {
+    let mut children = debug_span!("filter").in_scope(|| {
+        if config.only_empty || config.min_depth > 1 {
+            retain_matching(&root.children, 1, &|entry, depth| {
+                depth >= config.min_depth && (!config.only_empty || is_empty_entry(entry, config))
+            })
+        } else {
+            root.children.clone()
+        }
+    });
+    debug_span!("sort").in_scope(|| {
+        sort_entries(&mut children, &root.path, config);
+    });
+    children
 }
 
 #[allow(dead_code)]
@@ -48,7 +239,7 @@ fn format_single_entry(
     let name_color = if entry.is_gitignored {
         colors::get_gitignored_color(config)
     } else {
-        colors::get_name_color(entry, config)
+        colors::get_name_color(colors::determine_file_type(entry, config), config)
     };
 
     let name = colors::colorize_styled(
@@ -59,7 +250,7 @@ fn format_single_entry(
     );
 
     // Format metadata with colors
-    let metadata_str = format_metadata(entry);
+    let metadata_str = format_metadata(entry, config);
     let metadata = colors::colorize(&metadata_str, colors::get_metadata_color(config), config);
 
     let mut output = format!("{}{}{}", colorized_prefix, connector, name);
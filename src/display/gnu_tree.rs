@@ -0,0 +1,194 @@
+//! Output compatible with GNU tree's `-J` and `-X`, for `--format tree-json`
+//! and `--format xml`
+//!
+//! The layout follows tree's own: one object or element per entry with
+//! `type`, `name` and `size`, symlinks as `link` with their `target`, and a
+//! closing report of directory and file counts. Folded directories are listed
+//! without their contents, as in the tree view.
+
+use super::format::visible_children;
+use super::html::escape_html;
+use super::utils::{is_folded, is_symlink, link_target};
+use crate::types::{DirectoryEntry, DisplayConfig};
+
+#[derive(Default)]
+struct Counts {
+    directories: usize,
+    files: usize,
+}
+
+/// Kind of entry, as tree names it
+fn entry_type(entry: &DirectoryEntry) -> &'static str {
+    if is_symlink(entry) {
+        "link"
+    } else if entry.is_dir {
+        "directory"
+    } else {
+        "file"
+    }
+}
+
+/// Name of a root: the path as given, like tree's own argument
+fn root_name(root: &DirectoryEntry) -> String {
+    if root.path.as_os_str().is_empty() {
+        root.name.clone()
+    } else {
+        root.path.display().to_string()
+    }
+}
+
+/// Entries shown below a directory, or none when it is folded
+fn contents<'a>(
+    entry: &'a DirectoryEntry,
+    config: &DisplayConfig,
+    is_root: bool,
+) -> &'a [DirectoryEntry] {
+    if is_root || !is_folded(entry, config) {
+        &entry.children
+    } else {
+        &[]
+    }
+}
+
+/// Render the roots like `tree -J`
+pub fn format_tree_json(roots: &[DirectoryEntry], config: &DisplayConfig) -> String {
+    let mut counts = Counts::default();
+    let mut out = String::from("[");
+    for (i, root) in roots.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        let mut root = root.clone();
+        root.children = visible_children(&root, config);
+        push_json(
+            &root,
+            &root_name(&root),
+            config,
+            1,
+            true,
+            &mut counts,
+            &mut out,
+        );
+    }
+    out.push_str(&format!(
+        "\n,\n  {{\"type\":\"report\",\"directories\":{},\"files\":{}}}\n]\n",
+        counts.directories, counts.files
+    ));
+    out
+}
+
+fn push_json(
+    entry: &DirectoryEntry,
+    name: &str,
+    config: &DisplayConfig,
+    depth: usize,
+    is_root: bool,
+    counts: &mut Counts,
+    out: &mut String,
+) {
+    let kind = entry_type(entry);
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(&format!(
+        "{{\"type\":\"{}\",\"name\":{}",
+        kind,
+        json_string(name)
+    ));
+    if kind == "link" {
+        if let Some(target) = link_target(entry) {
+            out.push_str(&format!(",\"target\":{}", json_string(target)));
+        }
+    }
+    out.push_str(&format!(",\"size\":{}", entry.metadata.size));
+
+    if !is_root {
+        if entry.is_dir {
+            counts.directories += 1;
+        } else {
+            counts.files += 1;
+        }
+    }
+    if !entry.is_dir {
+        out.push('}');
+        return;
+    }
+
+    out.push_str(",\"contents\":[");
+    let children = contents(entry, config, is_root);
+    for (i, child) in children.iter().enumerate() {
+        out.push_str(if i == 0 { "\n" } else { ",\n" });
+        push_json(child, &child.name, config, depth + 1, false, counts, out);
+    }
+    if !children.is_empty() {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    out.push_str("]}");
+}
+
+fn json_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_else(|_| String::from("\"\""))
+}
+
+/// Render the roots like `tree -X`
+pub fn format_tree_xml(roots: &[DirectoryEntry], config: &DisplayConfig) -> String {
+    let mut counts = Counts::default();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tree>\n");
+    for root in roots {
+        let mut root = root.clone();
+        root.children = visible_children(&root, config);
+        push_xml(
+            &root,
+            &root_name(&root),
+            config,
+            1,
+            true,
+            &mut counts,
+            &mut out,
+        );
+    }
+    out.push_str(&format!(
+        "  <report>\n    <directories>{}</directories>\n    <files>{}</files>\n  </report>\n</tree>\n",
+        counts.directories, counts.files
+    ));
+    out
+}
+
+fn push_xml(
+    entry: &DirectoryEntry,
+    name: &str,
+    config: &DisplayConfig,
+    depth: usize,
+    is_root: bool,
+    counts: &mut Counts,
+    out: &mut String,
+) {
+    let kind = entry_type(entry);
+    let indent = "  ".repeat(depth);
+    out.push_str(&format!("{}<{} name=\"", indent, kind));
+    escape_html(name, out);
+    out.push('"');
+    if kind == "link" {
+        if let Some(target) = link_target(entry) {
+            out.push_str(" target=\"");
+            escape_html(target, out);
+            out.push('"');
+        }
+    }
+    out.push_str(&format!(" size=\"{}\">", entry.metadata.size));
+
+    if !is_root {
+        if entry.is_dir {
+            counts.directories += 1;
+        } else {
+            counts.files += 1;
+        }
+    }
+    if !entry.is_dir {
+        out.push_str(&format!("</{}>\n", kind));
+        return;
+    }
+
+    out.push('\n');
+    for child in contents(entry, config, is_root) {
+        push_xml(child, &child.name, config, depth + 1, false, counts, out);
+    }
+    out.push_str(&format!("{}</{}>\n", indent, kind));
+}
@@ -0,0 +1,244 @@
+//! Conversion of colorized terminal output into HTML
+
+/// CSS colors for the 16 standard ANSI colors (normal 0-7, bright 8-15)
+const ANSI_PALETTE: [&str; 16] = [
+    "#000000", "#cd3131", "#0dbc79", "#e5e510", "#2472c8", "#bc3fbc", "#11a8cd", "#e5e5e5",
+    "#666666", "#f14c4c", "#23d18b", "#f5f543", "#3b8eea", "#d670d6", "#29b8db", "#ffffff",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Style {
+    color: Option<String>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    fn css(&self) -> String {
+        let mut css = Vec::new();
+        if let Some(color) = &self.color {
+            css.push(format!("color:{}", color));
+        }
+        if self.bold {
+            css.push(String::from("font-weight:bold"));
+        }
+        if self.dim {
+            css.push(String::from("opacity:0.6"));
+        }
+        if self.italic {
+            css.push(String::from("font-style:italic"));
+        }
+        if self.underline {
+            css.push(String::from("text-decoration:underline"));
+        }
+        css.join(";")
+    }
+
+    /// Apply the parameters of a single SGR (`ESC[...m`) sequence
+    fn apply_sgr(&mut self, params: &str) {
+        let codes: Vec<u32> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+        let mut i = 0;
+        while i < codes.len() {
+            match codes[i] {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                code @ 30..=37 => self.color = Some(ANSI_PALETTE[(code - 30) as usize].into()),
+                code @ 90..=97 => self.color = Some(ANSI_PALETTE[(code - 90 + 8) as usize].into()),
+                39 => self.color = None,
+                38 => {
+                    // Extended colors: 38;5;n (256-color) or 38;2;r;g;b (truecolor)
+                    match codes.get(i + 1) {
+                        Some(5) => {
+                            if let Some(&n) = codes.get(i + 2) {
+                                self.color = Some(xterm_256_color(n));
+                            }
+                            i += 2;
+                        }
+                        Some(2) => {
+                            if let (Some(r), Some(g), Some(b)) =
+                                (codes.get(i + 2), codes.get(i + 3), codes.get(i + 4))
+                            {
+                                self.color = Some(format!("#{:02x}{:02x}{:02x}", r, g, b));
+                            }
+                            i += 4;
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Map an xterm 256-color index to a CSS color
+fn xterm_256_color(n: u32) -> String {
+    match n {
+        0..=15 => ANSI_PALETTE[n as usize].to_string(),
+        16..=231 => {
+            let n = n - 16;
+            let level = |v: u32| if v == 0 { 0 } else { 55 + v * 40 };
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                level(n / 36),
+                level((n / 6) % 6),
+                level(n % 6)
+            )
+        }
+        _ => {
+            let gray = 8 + (n.min(255) - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+    }
+}
+
+pub(super) fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Append text in the given style, opening a span lazily so that styles
+/// without any text produce no markup
+fn push_text(text: &str, style: &Style, out: &mut String, span_open: &mut bool) {
+    if text.is_empty() {
+        return;
+    }
+    if !*span_open && !style.is_plain() {
+        out.push_str(&format!("<span style=\"{}\">", style.css()));
+        *span_open = true;
+    }
+    escape_html(text, out);
+}
+
+/// Convert text containing ANSI SGR escape sequences into HTML with inline-styled spans
+///
+/// OSC 8 hyperlinks become `<a>` elements.
+pub fn ansi_to_html(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut style = Style::default();
+    let mut span_open = false;
+    let mut rest = input;
+
+    while let Some(start) = rest.find('\x1b') {
+        push_text(&rest[..start], &style, &mut out, &mut span_open);
+        let after = &rest[start + 1..];
+
+        if let Some(osc) = after.strip_prefix(']') {
+            // OSC sequences end with ST (ESC \) or BEL
+            let Some(end) = osc.find(['\x1b', '\x07']) else {
+                rest = "";
+                break;
+            };
+            if let Some(url) = osc[..end]
+                .strip_prefix("8;")
+                .and_then(|p| p.split_once(';'))
+            {
+                if span_open {
+                    out.push_str("</span>");
+                    span_open = false;
+                }
+                match url.1 {
+                    "" => out.push_str("</a>"),
+                    url => {
+                        out.push_str("<a href=\"");
+                        escape_html(url, &mut out);
+                        out.push_str("\">");
+                    }
+                }
+            }
+            let terminator = if osc[end..].starts_with('\x1b') { 2 } else { 1 };
+            rest = osc.get(end + terminator..).unwrap_or("");
+            continue;
+        }
+
+        let Some(csi) = after.strip_prefix('[') else {
+            // A lone escape character carries no visible content
+            rest = after;
+            continue;
+        };
+
+        // Find the final byte of the control sequence
+        let Some(end) = csi.find(|c: char| c.is_ascii_alphabetic()) else {
+            rest = "";
+            break;
+        };
+
+        if csi.as_bytes()[end] == b'm' {
+            style.apply_sgr(&csi[..end]);
+            if span_open {
+                out.push_str("</span>");
+                span_open = false;
+            }
+        }
+        // Other control sequences carry no visible content and are dropped
+
+        rest = &csi[end + 1..];
+    }
+
+    push_text(rest, &style, &mut out, &mut span_open);
+    if span_open {
+        out.push_str("</span>");
+    }
+    out
+}
+
+/// Wrap converted tree output in a standalone HTML document
+pub fn html_document(body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>smart-tree</title>\n</head>\n<body style=\"background:#1e1e1e;color:#e5e5e5\">\n<pre class=\"smart-tree\" style=\"font-family:monospace\">\n{}</pre>\n</body>\n</html>\n",
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ansi_to_html_colors_and_escaping() {
+        let input = "\x1b[1;94msrc\x1b[0m <dir> \x1b[32mok\x1b[0m";
+        let html = ansi_to_html(input);
+        assert_eq!(
+            html,
+            "<span style=\"color:#3b8eea;font-weight:bold\">src</span> &lt;dir&gt; \
+             <span style=\"color:#0dbc79\">ok</span>"
+        );
+    }
+
+    #[test]
+    fn test_ansi_to_html_plain_text() {
+        assert_eq!(ansi_to_html("a & b"), "a &amp; b");
+    }
+
+    #[test]
+    fn test_ansi_to_html_hyperlinks() {
+        let input = "\x1b]8;;file:///tmp/a%20b\x1b\\\x1b[32ma b\x1b[0m\x1b]8;;\x1b\\ x";
+        assert_eq!(
+            ansi_to_html(input),
+            "<a href=\"file:///tmp/a%20b\"><span style=\"color:#0dbc79\">a b</span></a> x"
+        );
+    }
+}
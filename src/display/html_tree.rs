@@ -0,0 +1,148 @@
+//! Standalone HTML page for `--format html`
+//!
+//! Directories are `<details>` elements, open unless the tree view would fold
+//! them, so the page starts out like the terminal view and the folded parts
+//! expand on click. Names carry a class per file type for coloring.
+
+use super::colors::determine_file_type;
+use super::format::visible_children;
+use super::html::escape_html;
+use super::utils::{error_label, format_metadata, is_folded};
+use crate::types::{DirectoryEntry, DisplayConfig, FileType};
+
+const STYLE: &str = "\
+body{background:#1e1e1e;color:#e5e5e5;font-family:monospace}\
+ul{list-style:none;margin:0;padding-left:1.5em}\
+ul.smart-tree{padding-left:0}\
+summary{cursor:pointer}\
+li.file{padding-left:1.1em}\
+.meta{color:#888}\
+.tag{color:#e5e510}\
+.more{color:#888;font-style:italic}\
+.directory{color:#3b8eea;font-weight:bold}\
+.symlink{color:#29b8db}\
+.image{color:#d670d6}\
+.video{color:#bc3fbc}\
+.audio{color:#11a8cd}\
+.archive{color:#f14c4c}\
+.code{color:#23d18b}\
+.document{color:#f5f543}\
+.executable{color:#0dbc79;font-weight:bold}\
+.hidden{color:#666666}\
+.socket{color:#d670d6;font-weight:bold}\
+.fifo,.block-device,.char-device{color:#e5e510}";
+
+/// Render the tree below `root` as a standalone HTML page
+pub fn format_html(root: &DirectoryEntry, config: &DisplayConfig) -> String {
+    let mut body = String::new();
+    let mut root = root.clone();
+    root.children = visible_children(&root, config);
+    push_entry(&root, config, true, &mut body);
+
+    let mut title = String::new();
+    escape_html(&root.name, &mut title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{} - smart-tree</title>\n<style>{}</style>\n</head>\n<body>\n<ul class=\"smart-tree\">\n{}</ul>\n</body>\n</html>\n",
+        title, STYLE, body
+    )
+}
+
+fn push_entry(entry: &DirectoryEntry, config: &DisplayConfig, is_root: bool, out: &mut String) {
+    let mut label = format!(
+        "<span class=\"{}\">",
+        type_class(&determine_file_type(entry, config))
+    );
+    escape_html(if is_root { "." } else { &entry.name }, &mut label);
+    label.push_str("</span>");
+    if config.show_metadata {
+        label.push_str(" <span class=\"meta\">");
+        escape_html(&format_metadata(entry, config), &mut label);
+        label.push_str("</span>");
+    }
+    for tag in tags(entry) {
+        label.push_str(" <span class=\"tag\">[");
+        escape_html(&tag, &mut label);
+        label.push_str("]</span>");
+    }
+
+    if !entry.is_dir || entry.children.is_empty() {
+        out.push_str(&format!("<li class=\"file\">{}</li>\n", label));
+        return;
+    }
+
+    let open = if is_folded(entry, config) {
+        ""
+    } else {
+        " open"
+    };
+    out.push_str(&format!(
+        "<li><details{}><summary>{}</summary>\n<ul>\n",
+        open, label
+    ));
+    // Like the tree view, long listings keep their first and last entries
+    let children = &entry.children;
+    let limit = config.dir_limit.max(2);
+    if children.len() <= limit {
+        for child in children {
+            push_entry(child, config, false, out);
+        }
+    } else {
+        let tail = (limit - 1) / 2;
+        let head = limit - 1 - tail;
+        for child in &children[..head] {
+            push_entry(child, config, false, out);
+        }
+        out.push_str(&format!(
+            "<li class=\"file more\">... {} items hidden ...</li>\n",
+            children.len() - head - tail
+        ));
+        for child in &children[children.len() - tail..] {
+            push_entry(child, config, false, out);
+        }
+    }
+    out.push_str("</ul>\n</details></li>\n");
+}
+
+/// Annotations shown after the name, as in the tree view
+fn tags(entry: &DirectoryEntry) -> Vec<String> {
+    if entry.is_gitignored && entry.is_dir {
+        return vec![String::from("folded: system")];
+    }
+    let mut tags = Vec::new();
+    if let Some(annotation) = &entry.filter_annotation {
+        // Annotations carry their own brackets
+        tags.push(
+            annotation
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+        );
+    }
+    if entry.is_git_repo {
+        tags.push(String::from("git repo"));
+    }
+    if let Some(error) = &entry.error {
+        tags.push(error_label(error));
+    }
+    tags
+}
+
+fn type_class(file_type: &FileType) -> &'static str {
+    match file_type {
+        FileType::Directory => "directory",
+        FileType::Symlink => "symlink",
+        FileType::Regular => "regular",
+        FileType::Image => "image",
+        FileType::Video => "video",
+        FileType::Audio => "audio",
+        FileType::Archive => "archive",
+        FileType::Code => "code",
+        FileType::Document => "document",
+        FileType::Executable => "executable",
+        FileType::Hidden => "hidden",
+        FileType::Socket => "socket",
+        FileType::Fifo => "fifo",
+        FileType::BlockDevice => "block-device",
+        FileType::CharDevice => "char-device",
+    }
+}
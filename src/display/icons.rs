@@ -0,0 +1,97 @@
+//! Nerd Font icons for `--icons nerd`
+//!
+//! Glyphs come from the Nerd Fonts private-use range, so they take a single
+//! column in patched fonts (unlike emoji, whose widths vary by terminal).
+
+use crate::types::{DirectoryEntry, FileType};
+
+const NERD_DIRECTORY: &str = "\u{f07b}";
+const NERD_GIT_REPO: &str = "\u{e702}";
+const NERD_SYMLINK: &str = "\u{f481}";
+const NERD_FILE: &str = "\u{f15b}";
+const NERD_IMAGE: &str = "\u{f1c5}";
+const NERD_VIDEO: &str = "\u{f1c8}";
+const NERD_AUDIO: &str = "\u{f1c7}";
+const NERD_ARCHIVE: &str = "\u{f1c6}";
+const NERD_CODE: &str = "\u{f121}";
+const NERD_DOCUMENT: &str = "\u{f15c}";
+const NERD_EXECUTABLE: &str = "\u{f489}";
+const NERD_HIDDEN: &str = "\u{f023}";
+const NERD_SOCKET: &str = "\u{f1e6}";
+const NERD_FIFO: &str = "\u{f07e5}";
+const NERD_BLOCK_DEVICE: &str = "\u{f0a0}";
+const NERD_CHAR_DEVICE: &str = "\u{f11c}";
+
+/// Icon for well-known file names, checked before extensions
+fn name_icon(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "Cargo.toml" | "Cargo.lock" => "\u{e7a8}",
+        "Dockerfile" | "docker-compose.yml" | "compose.yaml" => "\u{f308}",
+        "Makefile" | "CMakeLists.txt" => "\u{e779}",
+        "package.json" | "package-lock.json" => "\u{e71e}",
+        ".gitignore" | ".gitattributes" | ".gitmodules" => "\u{f1d3}",
+        "LICENSE" | "LICENSE.md" | "COPYING" => "\u{f0219}",
+        _ => return None,
+    })
+}
+
+/// Icon for a (lowercase) file extension
+fn extension_icon(ext: &str) -> Option<&'static str> {
+    Some(match ext {
+        "rs" => "\u{e7a8}",
+        "py" => "\u{e606}",
+        "js" | "mjs" | "cjs" => "\u{e74e}",
+        "ts" | "tsx" => "\u{e628}",
+        "jsx" => "\u{e7ba}",
+        "go" => "\u{e626}",
+        "c" | "h" => "\u{e61e}",
+        "cpp" | "cc" | "hpp" => "\u{e61d}",
+        "java" => "\u{e738}",
+        "rb" => "\u{e791}",
+        "php" => "\u{e73d}",
+        "html" | "htm" => "\u{e736}",
+        "css" | "scss" => "\u{e749}",
+        "md" | "markdown" => "\u{f48a}",
+        "json" => "\u{e60b}",
+        "toml" | "yaml" | "yml" | "ini" | "cfg" => "\u{e615}",
+        "sh" | "bash" | "zsh" | "fish" => "\u{f489}",
+        "lock" => "\u{f023}",
+        "txt" => "\u{f15c}",
+        "pdf" => "\u{f1c1}",
+        "sql" | "db" | "sqlite" => "\u{f1c0}",
+        _ => return None,
+    })
+}
+
+/// Nerd Font icon for an entry: file name, then extension, then file type
+pub(super) fn nerd_icon(entry: &DirectoryEntry, file_type: FileType) -> &'static str {
+    if entry.is_git_repo {
+        return NERD_GIT_REPO;
+    }
+    if !entry.is_dir && !file_type.is_special() {
+        let by_extension = || {
+            let ext = entry.path.extension()?.to_str()?.to_lowercase();
+            extension_icon(&ext)
+        };
+        if let Some(icon) = name_icon(&entry.name).or_else(by_extension) {
+            return icon;
+        }
+    }
+    match file_type {
+        FileType::Directory => NERD_DIRECTORY,
+        FileType::Symlink => NERD_SYMLINK,
+        FileType::Image => NERD_IMAGE,
+        FileType::Video => NERD_VIDEO,
+        FileType::Audio => NERD_AUDIO,
+        FileType::Archive => NERD_ARCHIVE,
+        FileType::Code => NERD_CODE,
+        FileType::Document => NERD_DOCUMENT,
+        FileType::Executable => NERD_EXECUTABLE,
+        FileType::Hidden => NERD_HIDDEN,
+        FileType::Regular => NERD_FILE,
+        FileType::Socket => NERD_SOCKET,
+        FileType::Fifo => NERD_FIFO,
+        FileType::BlockDevice => NERD_BLOCK_DEVICE,
+        FileType::CharDevice => NERD_CHAR_DEVICE,
+    }
+}
@@ -0,0 +1,161 @@
+//! JSON output of a scanned tree
+//!
+//! Every entry carries an `error` field: `null` when all data is available,
+//! otherwise a short reason (e.g. "permission denied"), so consumers can tell
+//! a real zero from data that could not be read. With a skip report, the
+//! root object also carries a `skipped` field listing what the tree view
+//! would fold or truncate, and with checksums every file carries a `sha256`
+//! field, so the export can serve as a manifest for `smart-tree verify`.
+//! Directories carry `complete: false` when their `children` leave out
+//! entries on disk (filtered, excluded, folded or past a limit).
+//!
+//! NDJSON output streams one object per line with the same fields, plus the
+//! entry's `depth`, but without directory totals or `children`.
+
+use super::report::SkipReport;
+use crate::scan_iter::ScannedEntry;
+use crate::types::DirectoryEntry;
+use crate::verify::file_checksum;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Render the tree below `root` as pretty-printed JSON
+pub fn format_json(root: &DirectoryEntry, report: Option<&SkipReport>, checksums: bool) -> String {
+    let mut value = entry_to_json(root, checksums);
+    if let Some(report) = report {
+        value["skipped"] = report.to_json();
+    }
+    serde_json::to_string_pretty(&value).unwrap_or_else(|_| String::from("{}"))
+}
+
+fn entry_to_json(entry: &DirectoryEntry, checksums: bool) -> Value {
+    let mut value = json!({
+        "name": entry.name,
+        "path": entry.path.to_string_lossy(),
+        "type": if entry.is_dir { "directory" } else { "file" },
+        "size": entry.metadata.size,
+        "modified": unix_seconds(entry.metadata.modified),
+        "created": unix_seconds(entry.metadata.created),
+        "gitignored": entry.is_gitignored,
+        "git_repo": entry.is_git_repo,
+        "filtered_by": entry.filtered_by,
+        "annotation": entry.filter_annotation,
+        "error": entry.error,
+    });
+    if entry.is_dir {
+        value["files_count"] = json!(entry.metadata.files_count);
+        // Whether `children` lists everything on disk, for `verify`
+        value["complete"] = json!(
+            !entry.has_omitted && !entry.has_unlisted && !entry.cancelled && entry.error.is_none()
+        );
+        if entry.metadata.approximate {
+            value["approximate"] = json!(true);
+        }
+        value["children"] = Value::Array(
+            entry
+                .children
+                .iter()
+                .map(|child| entry_to_json(child, checksums))
+                .collect(),
+        );
+    } else if checksums {
+        value["sha256"] = json!(file_checksum(&entry.path).ok());
+    }
+    value
+}
+
+/// Render an entry from the streaming scanner as one line of NDJSON
+pub fn format_ndjson(entry: &ScannedEntry) -> String {
+    json!({
+        "name": entry.name,
+        "path": entry.path.to_string_lossy(),
+        "type": if entry.is_dir { "directory" } else { "file" },
+        "depth": entry.depth,
+        "size": entry.size,
+        "modified": unix_seconds(entry.modified),
+        "created": unix_seconds(entry.created),
+        "gitignored": entry.is_gitignored,
+        "filtered_by": entry.filtered_by,
+        "annotation": entry.filter_annotation,
+        "error": entry.error,
+    })
+    .to_string()
+}
+
+/// Seconds since the Unix epoch, or null for times before it
+fn unix_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EntryKind, EntryMetadata};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_error_field_is_always_present() {
+        let entry = |name: &str, error: Option<&str>| DirectoryEntry {
+            path: PathBuf::from(name),
+            name: name.to_string(),
+            is_dir: false,
+            metadata: EntryMetadata {
+                size: 0,
+                created: UNIX_EPOCH,
+                modified: UNIX_EPOCH,
+                files_count: 0,
+                mode: None,
+                uid: None,
+                gid: None,
+                approximate: false,
+            },
+            children: Vec::new(),
+            is_gitignored: false,
+            is_git_repo: false,
+            filtered_by: None,
+            filter_annotation: None,
+            error: error.map(String::from),
+            is_virtual: false,
+            kind: EntryKind::Regular,
+            has_unlisted: false,
+            has_omitted: false,
+            cancelled: false,
+        };
+        let mut root = entry(".", None);
+        root.is_dir = true;
+        root.children = vec![
+            entry("empty", None),
+            entry("secret", Some("permission denied")),
+        ];
+
+        let value: Value = serde_json::from_str(&format_json(&root, None, false)).unwrap();
+        assert_eq!(value["error"], Value::Null);
+        assert_eq!(value["children"][0]["error"], Value::Null);
+        assert_eq!(value["children"][0]["size"], 0);
+        assert_eq!(value["children"][1]["error"], "permission denied");
+    }
+
+    #[test]
+    fn test_ndjson_line() {
+        let entry = ScannedEntry {
+            path: PathBuf::from("src/main.rs"),
+            name: String::from("main.rs"),
+            depth: 1,
+            is_dir: false,
+            size: 42,
+            created: UNIX_EPOCH,
+            modified: UNIX_EPOCH + std::time::Duration::from_secs(60),
+            is_gitignored: false,
+            filtered_by: None,
+            filter_annotation: None,
+            error: None,
+        };
+        let line = format_ndjson(&entry);
+        assert!(!line.contains('\n'));
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["path"], "src/main.rs");
+        assert_eq!(value["depth"], 1);
+        assert_eq!(value["modified"], 60);
+        assert_eq!(value["error"], Value::Null);
+    }
+}
@@ -0,0 +1,176 @@
+//! Name colors from the `LS_COLORS` environment variable
+//!
+//! Follows GNU `ls`: file kinds (`di`, `ln`, `ex`, ...) start from the
+//! built-in defaults and are overridden by `LS_COLORS`, and `*suffix`
+//! patterns only apply to regular, non-executable files.
+
+use crate::types::{DirectoryEntry, EntryKind, LsColors};
+
+/// Styles GNU `ls` uses for file kinds LS_COLORS doesn't mention
+const DEFAULTS: &[(&str, &str)] = &[
+    ("di", "01;34"),
+    ("ln", "01;36"),
+    ("pi", "40;33"),
+    ("so", "01;35"),
+    ("bd", "40;33;01"),
+    ("cd", "40;33;01"),
+    ("ex", "01;32"),
+];
+
+impl LsColors {
+    /// Parse the `LS_COLORS` environment variable (GNU defaults if unset)
+    pub fn from_env() -> Self {
+        Self::parse(&std::env::var("LS_COLORS").unwrap_or_default())
+    }
+
+    /// Parse an LS_COLORS value such as `di=01;34:*.rs=38;5;208`
+    pub fn parse(spec: &str) -> Self {
+        let mut colors = LsColors {
+            kinds: DEFAULTS
+                .iter()
+                .map(|(kind, sgr)| (kind.to_string(), sgr.to_string()))
+                .collect(),
+            suffixes: Vec::new(),
+        };
+        for (key, sgr) in spec.split(':').filter_map(|item| item.split_once('=')) {
+            match key.strip_prefix('*') {
+                Some(suffix) => colors.suffixes.push((suffix.to_string(), sgr.to_string())),
+                None => {
+                    colors.kinds.insert(key.to_string(), sgr.to_string());
+                }
+            }
+        }
+        colors
+    }
+
+    /// SGR parameters for an entry's name, None to leave it uncolored
+    pub fn style(&self, entry: &DirectoryEntry) -> Option<&str> {
+        let kind = match &entry.kind {
+            EntryKind::Symlink { broken, .. } => {
+                if *broken && self.kinds.contains_key("or") {
+                    "or"
+                } else {
+                    "ln"
+                }
+            }
+            _ if entry.is_dir => "di",
+            EntryKind::Fifo => "pi",
+            EntryKind::Socket => "so",
+            EntryKind::BlockDevice => "bd",
+            EntryKind::CharDevice => "cd",
+            EntryKind::Regular => return self.file_style(entry),
+        };
+        self.kind(kind)
+    }
+
+    /// Regular files: executable, then the longest matching suffix, then `fi`
+    fn file_style(&self, entry: &DirectoryEntry) -> Option<&str> {
+        if entry.metadata.mode.is_some_and(|mode| mode & 0o111 != 0) {
+            return self.kind("ex");
+        }
+        self.suffix(&entry.name).or_else(|| self.kind("fi"))
+    }
+
+    fn kind(&self, kind: &str) -> Option<&str> {
+        self.kinds
+            .get(kind)
+            .map(String::as_str)
+            .filter(|sgr| has_style(sgr))
+    }
+
+    /// Longest suffix matching the name, exact case preferred
+    fn suffix(&self, name: &str) -> Option<&str> {
+        let lower = name.to_lowercase();
+        let longest = |exact: bool| {
+            self.suffixes
+                .iter()
+                .filter(|(suffix, _)| {
+                    if exact {
+                        name.ends_with(suffix.as_str())
+                    } else {
+                        lower.ends_with(&suffix.to_lowercase())
+                    }
+                })
+                .max_by_key(|(suffix, _)| suffix.len())
+        };
+        longest(true)
+            .or_else(|| longest(false))
+            .map(|(_, sgr)| sgr.as_str())
+            .filter(|sgr| has_style(sgr))
+    }
+}
+
+/// `0`, `00` and empty values mean "no color"
+fn has_style(sgr: &str) -> bool {
+    !sgr.trim_start_matches('0').is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EntryMetadata;
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    fn entry(name: &str, is_dir: bool) -> DirectoryEntry {
+        DirectoryEntry {
+            path: PathBuf::from(name),
+            name: name.to_string(),
+            is_dir,
+            metadata: EntryMetadata {
+                size: 0,
+                created: SystemTime::UNIX_EPOCH,
+                modified: SystemTime::UNIX_EPOCH,
+                files_count: 0,
+                mode: None,
+                uid: None,
+                gid: None,
+                approximate: false,
+            },
+            children: Vec::new(),
+            is_gitignored: false,
+            is_git_repo: false,
+            filtered_by: None,
+            filter_annotation: None,
+            error: None,
+            is_virtual: false,
+            kind: EntryKind::Regular,
+            has_unlisted: false,
+            has_omitted: false,
+            cancelled: false,
+        }
+    }
+
+    #[test]
+    fn test_ls_colors_styles() {
+        let colors = LsColors::parse("di=01;35:fi=0:*.rs=38;5;208:*.gz=31:*.tar.gz=32:*.jpg=33");
+
+        let style = |name: &str| colors.style(&entry(name, false));
+        assert_eq!(colors.style(&entry("src", true)), Some("01;35"));
+        assert_eq!(style("main.rs"), Some("38;5;208"));
+        assert_eq!(style("a.tar.gz"), Some("32"));
+        assert_eq!(style("b.gz"), Some("31"));
+        assert_eq!(style("photo.JPG"), Some("33"));
+        assert_eq!(style("README"), None);
+    }
+
+    #[test]
+    fn test_ls_colors_defaults() {
+        let mut script = entry("run.rs", false);
+        script.metadata.mode = Some(0o755);
+        let mut dangling = entry("dangling", false);
+        dangling.kind = EntryKind::Symlink {
+            target: Some(String::from("missing")),
+            broken: true,
+        };
+        let mut pipe = entry("queue", false);
+        pipe.kind = EntryKind::Fifo;
+
+        let colors = LsColors::parse("*.rs=31");
+        assert_eq!(colors.style(&entry("src", true)), Some("01;34"));
+        assert_eq!(colors.style(&script), Some("01;32"));
+        assert_eq!(colors.style(&dangling), Some("01;36"));
+        assert_eq!(colors.style(&pipe), Some("40;33"));
+        assert_eq!(LsColors::parse("or=01;31").style(&dangling), Some("01;31"));
+    }
+}
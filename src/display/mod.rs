@@ -1,11 +1,28 @@
 //! Display module handles the formatting and output of directory trees
 mod colors;
 mod format;
+mod gnu_tree;
+mod html;
+mod html_tree;
+mod icons;
+mod json;
+mod ls_colors;
+mod owners;
+mod report;
 mod state;
+mod template;
+mod tokens;
 mod utils;
+mod width;
 
 #[cfg(test)]
 mod tests;
 
-pub use colors::should_use_colors;
-pub use format::format_tree;
+pub use colors::{enable_ansi_support, set_color_mode, should_use_colors};
+pub use format::{format_tree, format_trees, skip_report};
+pub use gnu_tree::{format_tree_json, format_tree_xml};
+pub use html::{ansi_to_html, html_document};
+pub use html_tree::format_html;
+pub use json::{format_json, format_ndjson};
+pub use report::{FoldedDir, SkipReport, TruncatedDir, UnreadablePath};
+pub use width::terminal_width;
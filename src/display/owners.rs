@@ -0,0 +1,85 @@
+//! User and group names for `--owner`
+//!
+//! Lookups go through the system user database and are cached, since most
+//! entries in a tree share a handful of owners. Unknown ids print as numbers.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+type NameCache = LazyLock<Mutex<HashMap<u32, String>>>;
+
+static USER_NAMES: NameCache = LazyLock::new(Default::default);
+static GROUP_NAMES: NameCache = LazyLock::new(Default::default);
+
+/// Name of the user with this id, or the id itself
+pub(super) fn user_name(uid: u32) -> String {
+    cached(&USER_NAMES, uid, lookup_user)
+}
+
+/// Name of the group with this id, or the id itself
+pub(super) fn group_name(gid: u32) -> String {
+    cached(&GROUP_NAMES, gid, lookup_group)
+}
+
+fn cached(cache: &NameCache, id: u32, lookup: fn(u32) -> Option<String>) -> String {
+    let mut names = cache.lock().unwrap_or_else(|e| e.into_inner());
+    names
+        .entry(id)
+        .or_insert_with(|| lookup(id).unwrap_or_else(|| id.to_string()))
+        .clone()
+}
+
+#[cfg(unix)]
+fn lookup_user(uid: u32) -> Option<String> {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: getpwuid_r only writes into the struct and buffer we pass, and
+    // pw_name points into the buffer when an entry is found
+    let status = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if status != 0 || result.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+fn lookup_group(gid: u32) -> Option<String> {
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: as for getpwuid_r above
+    let status = unsafe {
+        libc::getgrgid_r(
+            gid,
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if status != 0 || result.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(group.gr_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn lookup_user(_uid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+fn lookup_group(_gid: u32) -> Option<String> {
+    None
+}
@@ -0,0 +1,165 @@
+//! Report of what the tree view left out
+//!
+//! Folded directories and entries cut by head/tail truncation are collected
+//! while laying out the tree, so they can be listed after it instead of
+//! disappearing silently. Paths that couldn't be read are listed as well,
+//! wherever they are in the tree.
+
+use super::colors;
+use crate::scanner::FILE_LIMIT_RULE;
+use crate::stats::{format_count, format_size};
+use crate::types::{DirectoryEntry, DisplayConfig};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// A directory shown without its contents
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldedDir {
+    pub path: PathBuf,
+    pub reason: String,
+    /// Total size, unknown for directories whose contents weren't sized
+    pub size: Option<u64>,
+    pub files_count: usize,
+}
+
+/// Entries of one directory hidden to fit the line budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct TruncatedDir {
+    pub path: PathBuf,
+    pub hidden: usize,
+}
+
+/// An entry whose contents or metadata couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnreadablePath {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Everything the tree view skipped, in display order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SkipReport {
+    pub folded: Vec<FoldedDir>,
+    pub truncated: Vec<TruncatedDir>,
+    pub unreadable: Vec<UnreadablePath>,
+}
+
+impl SkipReport {
+    pub fn is_empty(&self) -> bool {
+        self.folded.is_empty() && self.truncated.is_empty() && self.unreadable.is_empty()
+    }
+
+    pub(super) fn record_folded(&mut self, entry: &DirectoryEntry, config: &DisplayConfig) {
+        self.folded.push(FoldedDir {
+            path: entry.path.clone(),
+            reason: fold_reason(entry, config).to_string(),
+            // Over --filelimit only the directory itself was sized
+            size: Some(entry.metadata.size)
+                .filter(|_| entry.filtered_by.as_deref() != Some(FILE_LIMIT_RULE)),
+            files_count: entry.metadata.files_count,
+        });
+    }
+
+    pub(super) fn record_truncated(&mut self, path: &Path, hidden: usize) {
+        self.truncated.push(TruncatedDir {
+            path: path.to_path_buf(),
+            hidden,
+        });
+    }
+
+    /// Record every entry below `entry` that couldn't be read, shown or not
+    pub(super) fn record_unreadable(&mut self, entry: &DirectoryEntry) {
+        if let Some(error) = &entry.error {
+            self.unreadable.push(UnreadablePath {
+                path: entry.path.clone(),
+                error: error.clone(),
+            });
+        }
+        for child in &entry.children {
+            self.record_unreadable(child);
+        }
+    }
+
+    /// Render the report as text to print after the tree, with paths shown
+    /// relative to `root`
+    pub fn render(&self, root: &Path, config: &DisplayConfig) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        let mut lines = vec![String::from("Skipped:")];
+        for dir in &self.folded {
+            let size = dir
+                .size
+                .map_or(String::new(), |size| format!(", {}", format_size(size)));
+            lines.push(format!(
+                "  {}/ folded: {} ({} files{})",
+                relative(&dir.path, root),
+                dir.reason,
+                format_count(dir.files_count),
+                size
+            ));
+        }
+        for dir in &self.truncated {
+            lines.push(format!(
+                "  {}/ {} items hidden by truncation",
+                relative(&dir.path, root),
+                format_count(dir.hidden)
+            ));
+        }
+        if !self.unreadable.is_empty() {
+            lines.push(String::from("Unreadable:"));
+        }
+        for path in &self.unreadable {
+            lines.push(format!("  {}: {}", relative(&path.path, root), path.error));
+        }
+
+        let text = lines.join("\n");
+        format!(
+            "\n{}\n",
+            colors::colorize(&text, colors::get_hidden_items_color(config), config)
+        )
+    }
+
+    pub(super) fn to_json(&self) -> Value {
+        json!({
+            "folded": self.folded.iter().map(|dir| json!({
+                "path": dir.path.to_string_lossy(),
+                "reason": dir.reason,
+                "size": dir.size,
+                "files_count": dir.files_count,
+            })).collect::<Vec<_>>(),
+            "truncated": self.truncated.iter().map(|dir| json!({
+                "path": dir.path.to_string_lossy(),
+                "hidden": dir.hidden,
+            })).collect::<Vec<_>>(),
+            "unreadable": self.unreadable.iter().map(|path| json!({
+                "path": path.path.to_string_lossy(),
+                "error": path.error,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Why a directory is folded, matching the annotation shown next to it
+fn fold_reason<'a>(entry: &'a DirectoryEntry, config: &DisplayConfig) -> &'a str {
+    if entry.is_gitignored && !config.show_system_dirs {
+        "system"
+    } else if let Some(rule) = entry
+        .filtered_by
+        .as_deref()
+        .filter(|_| !config.show_filtered)
+    {
+        rule
+    } else {
+        "git repo"
+    }
+}
+
+fn relative(path: &Path, root: &Path) -> String {
+    match path.strip_prefix(root) {
+        Ok(rel) if rel.as_os_str().is_empty() => String::from("."),
+        Ok(rel) => rel.to_string_lossy().into_owned(),
+        Err(_) => path.to_string_lossy().into_owned(),
+    }
+}
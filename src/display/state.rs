@@ -1,6 +1,12 @@
 use super::colors;
-use crate::types::{DirectoryEntry, DisplayConfig};
-use log::{debug, info, trace};
+use super::report::SkipReport;
+use super::width::{display_width, truncate_to_width};
+use crate::scanner::FILE_LIMIT_RULE;
+use crate::stats::format_count;
+use crate::types::{ChangeKind, DirectoryEntry, DisplayConfig};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, trace};
 
 #[derive(Debug)]
 struct DisplaySection {
@@ -15,6 +21,12 @@ pub(super) struct DisplayState<'a> {
     depth: usize,
     budget_stack: Vec<usize>,
     config: &'a DisplayConfig,
+    /// Workspace members and every directory above them
+    member_dirs: HashSet<PathBuf>,
+    /// Levels still expandable inside a folded directory, None outside one
+    peek_left: Option<usize>,
+    /// Folded directories and truncated listings, for the skip report
+    pub report: SkipReport,
 }
 
 struct FormatContext {
@@ -31,6 +43,14 @@ impl<'a> DisplayState<'a> {
             depth: 0,
             budget_stack: vec![max_lines],
             config,
+            member_dirs: config
+                .badges
+                .keys()
+                .flat_map(|path| path.ancestors())
+                .map(Path::to_path_buf)
+                .collect(),
+            peek_left: None,
+            report: SkipReport::default(),
         }
     }
 
@@ -142,13 +162,8 @@ impl<'a> DisplayState<'a> {
         );
 
         // Get colorized connector
-        let connector_str = if ctx.is_last {
-            colors::TREE_CORNER
-        } else {
-            colors::TREE_BRANCH
-        };
         let connector = colors::colorize(
-            connector_str,
+            self.connector(ctx.is_last),
             colors::get_connector_color(self.config),
             self.config,
         );
@@ -161,31 +176,93 @@ impl<'a> DisplayState<'a> {
         );
 
         // Get colorized name with optional emoji
-        let name_color = if entry.is_gitignored {
-            colors::get_gitignored_color(self.config)
+        let change = self.config.changes.get(&entry.path).copied();
+        let git_status = self.config.git_status.get(&entry.path).copied();
+        let name_color = if let Some(change) = change {
+            Some(colors::get_change_color(change, self.config))
+        } else if let Some(status) = git_status.filter(|_| !entry.is_dir) {
+            // Directories only carry the marker, so changed files stand out
+            Some(colors::get_git_status_color(status, self.config))
+        } else if entry.is_gitignored
+            || (!entry.is_dir && super::utils::is_folded(entry, self.config))
+        {
+            // Files matched by a rule (lockfiles, bundles) are dimmed rather than
+            // folded, unless filtered entries are shown as usual
+            Some(colors::get_gitignored_color(self.config))
         } else {
-            colors::get_name_color(entry, self.config)
+            None
         };
 
+        // Classified once for the icon and the color, sniffing content at most once
+        let file_type = colors::determine_file_type(entry, self.config);
+
         // Use emoji if enabled
         let display_name = if colors::should_use_emoji(self.config) {
-            colors::format_name_with_emoji(entry, self.config)
+            colors::format_name_with_emoji(entry, file_type, self.config)
         } else {
             entry.name.clone()
         };
+        // Cut a name too wide for the line before it is linked and colored, as
+        // cutting the finished line could leave the link open
+        let name_room = self.config.max_width.map(|max_width| {
+            let code_ticks = if self.config.markdown { 2 } else { 0 };
+            let used = display_width(&ctx.prefix)
+                + display_width(self.connector(ctx.is_last))
+                + code_ticks;
+            max_width.saturating_sub(used)
+        });
+        let name_cut = name_room.is_some_and(|room| display_width(&display_name) > room);
+        let display_name = match name_room {
+            Some(room) if name_cut => truncate_to_width(&display_name, room),
+            _ => display_name,
+        };
+        let display_name = if self.config.markdown {
+            super::utils::code_span(&display_name)
+        } else {
+            display_name
+        };
 
-        let name = colors::colorize_styled(
-            &display_name,
-            name_color,
-            entry.is_dir, // Bold directories
-            self.config,
-        );
+        let name = match name_color {
+            Some(color) => colors::colorize_styled(
+                &display_name,
+                color,
+                entry.is_dir, // Bold directories
+                self.config,
+            ),
+            None => colors::colorize_name(&display_name, entry, file_type, self.config),
+        };
+        let mut name = colors::hyperlink(&name, &entry.path, self.config);
+        if name_cut {
+            // Nothing else fits on the line
+            return format!("{}{}{}\n", colorized_prefix, connector, name);
+        }
+
+        // Symlinks, and junctions on Windows, show where they point
+        if super::utils::is_symlink(entry) {
+            if let Some(target) = super::utils::link_target(entry) {
+                name.push_str(&colors::colorize(
+                    &format!(" -> {}", target),
+                    colors::get_metadata_color(self.config),
+                    self.config,
+                ));
+            }
+        }
 
-        // Format metadata with enhanced colors
-        let colorized_metadata = if self.config.detailed_metadata {
-            super::utils::format_detailed_metadata(entry, self.config)
+        // Format metadata with enhanced colors, after a space
+        let colorized_metadata = if entry.filtered_by.as_deref() == Some(FILE_LIMIT_RULE) {
+            format!(" ({} files)", format_count(entry.metadata.files_count))
+        } else if !self.config.show_metadata {
+            String::new()
+        } else if self.config.detailed_metadata {
+            format!(
+                " {}",
+                super::utils::format_detailed_metadata(entry, self.config)
+            )
         } else {
-            super::utils::format_colorized_metadata(entry, self.config)
+            format!(
+                " {}",
+                super::utils::format_colorized_metadata(entry, self.config)
+            )
         };
 
         // Combine parts into output
@@ -200,7 +277,7 @@ impl<'a> DisplayState<'a> {
                     colors::get_gitignored_color(self.config),
                     self.config,
                 );
-                output.push_str(&format!(" {}{}\n", colorized_metadata, system_dir_text));
+                output.push_str(&format!("{}{}\n", colorized_metadata, system_dir_text));
             } else {
                 // Traditional folded indicator when not showing system directories
                 let folded_text = colors::colorize(
@@ -208,22 +285,82 @@ impl<'a> DisplayState<'a> {
                     colors::get_gitignored_color(self.config),
                     self.config,
                 );
-                output.push_str(&format!(" {}{}\n", colorized_metadata, folded_text));
+                output.push_str(&format!("{}{}\n", colorized_metadata, folded_text));
             }
         } else {
             // Add basic output with metadata
-            output.push_str(&format!(" {}", colorized_metadata));
+            output.push_str(&colorized_metadata);
 
             // Add filter annotation if present
             if let Some(annotation) = &entry.filter_annotation {
                 let annotation_text = colors::colorize(
-                    &format!(" [{}]", annotation),
+                    &format!(" {}", annotation),
                     colors::get_filter_annotation_color(self.config),
                     self.config,
                 );
                 output.push_str(&annotation_text);
             }
 
+            if let Some(change) = change {
+                let label = match change {
+                    ChangeKind::New => " [new]",
+                    ChangeKind::Modified => " [modified]",
+                };
+                let change_text = colors::colorize(
+                    label,
+                    colors::get_change_color(change, self.config),
+                    self.config,
+                );
+                output.push_str(&change_text);
+            }
+
+            if let Some(status) = git_status {
+                let status_text = colors::colorize(
+                    &format!(" [{}]", status.labels().join(", ")),
+                    colors::get_git_status_color(status, self.config),
+                    self.config,
+                );
+                output.push_str(&status_text);
+            }
+
+            if let Some(badge) = self.config.badges.get(&entry.path) {
+                let badge_text = colors::colorize(
+                    &format!(" [{}]", badge),
+                    colors::get_git_repo_color(self.config),
+                    self.config,
+                );
+                output.push_str(&badge_text);
+            }
+
+            if entry.is_git_repo {
+                let repo_text = colors::colorize(
+                    " [git repo]",
+                    colors::get_git_repo_color(self.config),
+                    self.config,
+                );
+                output.push_str(&repo_text);
+            }
+
+            if let Some(error) = &entry.error {
+                let error_text = colors::colorize(
+                    &format!(" [{}]", super::utils::error_label(error)),
+                    colors::get_error_color(self.config),
+                    self.config,
+                );
+                output.push_str(&error_text);
+            }
+
+            if (self.config.mark_empty || self.config.only_empty)
+                && super::utils::is_empty_entry(entry, self.config)
+            {
+                let empty_text = colors::colorize(
+                    " [empty]",
+                    colors::get_empty_annotation_color(self.config),
+                    self.config,
+                );
+                output.push_str(&empty_text);
+            }
+
             output.push('\n');
         }
 
@@ -231,6 +368,58 @@ impl<'a> DisplayState<'a> {
         output
     }
 
+    /// Connector before an entry's name: tree lines, or a Markdown bullet
+    fn connector(&self, is_last: bool) -> &'static str {
+        match (self.config.markdown, is_last) {
+            (true, _) => colors::MARKDOWN_BULLET,
+            (false, true) => colors::TREE_CORNER,
+            (false, false) => colors::TREE_BRANCH,
+        }
+    }
+
+    /// What a directory's children are indented with, below its connector
+    fn indent(&self, is_last: bool) -> &'static str {
+        match (self.config.markdown, is_last) {
+            (true, _) => colors::MARKDOWN_INDENT,
+            (false, true) => colors::TREE_SPACE,
+            (false, false) => colors::TREE_VERTICAL,
+        }
+    }
+
+    /// Recurse into a directory's children unless it is folded
+    ///
+    /// Folded directories still show `peek_depth` levels of children; the
+    /// remaining depth is tracked in `peek_left` while inside one.
+    fn show_children(&mut self, item: &DirectoryEntry, prefix: &str, is_last: bool) {
+        if !item.is_dir || self.lines_remaining == 0 {
+            return;
+        }
+
+        let peek_left = match self.peek_left {
+            Some(0) => return,
+            Some(left) => Some(left - 1),
+            None if super::utils::is_folded(item, self.config) => {
+                self.report.record_folded(item, self.config);
+                match self.config.peek_depth {
+                    0 => return,
+                    depth => Some(depth - 1),
+                }
+            }
+            None => None,
+        };
+
+        debug!("Processing directory: {}", item.name);
+        let new_prefix = format!("{}{}", prefix, self.indent(is_last));
+        let outer_peek = std::mem::replace(&mut self.peek_left, peek_left);
+        self.show_items(&item.children, &new_prefix);
+        self.peek_left = outer_peek;
+    }
+
+    /// Whether an entry is a workspace member or has one below it
+    fn holds_member(&self, entry: &DirectoryEntry) -> bool {
+        entry.is_dir && self.member_dirs.contains(&entry.path)
+    }
+
     pub(super) fn show_items(&mut self, items: &[DirectoryEntry], prefix: &str) {
         info!(
             "show_items: start (count={}, depth={}, remaining={})",
@@ -255,14 +444,34 @@ impl<'a> DisplayState<'a> {
         }
 
         let budget = self.calculate_level_budget(items.len());
-        let section =
+        let mut section =
             self.calculate_display_section(items.len(), budget.min(self.config.dir_limit));
 
+        // When the listing is cut, workspace members (and the directories
+        // holding them) move to the front so they are the ones left visible
+        let mut items: Vec<&DirectoryEntry> = items.iter().collect();
+        if section.total_hidden > 0 {
+            let (mut members, others): (Vec<_>, Vec<_>) =
+                items.iter().partition(|item| self.holds_member(item));
+            if !members.is_empty() {
+                members.extend(others);
+                items = members;
+                section.head_count += section.tail_count;
+                section.tail_count = 0;
+            }
+        }
+
         debug!(
             "Display plan: budget={}, head={}, tail={}, hidden={}",
             budget, section.head_count, section.tail_count, section.total_hidden
         );
 
+        if section.total_hidden > 0 {
+            if let Some(parent) = items[0].path.parent() {
+                self.report.record_truncated(parent, section.total_hidden);
+            }
+        }
+
         self.depth += 1;
         self.budget_stack.push(self.lines_remaining);
 
@@ -298,25 +507,7 @@ impl<'a> DisplayState<'a> {
             self.output.push_str(&entry_line);
             self.lines_remaining -= 1;
 
-            // Process directories if:
-            // 1. We have lines remaining AND
-            // 2. Not filtered or we explicitly want to show filtered items
-            let should_skip = (item.is_gitignored && !self.config.show_system_dirs)
-                || (item.filtered_by.is_some() && !self.config.show_filtered);
-
-            if item.is_dir && self.lines_remaining > 0 && !should_skip {
-                debug!("Processing directory: {}", item.name);
-                let new_prefix = format!(
-                    "{}{}",
-                    prefix,
-                    if is_last {
-                        colors::TREE_SPACE
-                    } else {
-                        colors::TREE_VERTICAL
-                    }
-                );
-                self.show_items(&item.children, &new_prefix);
-            }
+            self.show_children(item, prefix, is_last);
         }
 
         // Show hidden items message if needed
@@ -329,7 +520,7 @@ impl<'a> DisplayState<'a> {
 
             // Colorize the hidden items message
             let connector = colors::colorize(
-                colors::TREE_BRANCH,
+                self.connector(section.tail_count == 0),
                 colors::get_connector_color(self.config),
                 self.config,
             );
@@ -381,26 +572,7 @@ impl<'a> DisplayState<'a> {
                 self.output.push_str(&entry_line);
                 self.lines_remaining -= 1;
 
-                // Process directories if:
-                // 1. We have lines remaining AND
-                // 2. Not filtered or we explicitly want to show filtered items
-                let should_skip = (item.is_gitignored && !self.config.show_system_dirs)
-                    || (item.filtered_by.is_some() && !self.config.show_filtered);
-
-                if item.is_dir && self.lines_remaining > 0 && !should_skip {
-                    debug!("Processing directory: {}", item.name);
-                    // Use the tree spaces and vertical constants for consistency
-                    let new_prefix = format!(
-                        "{}{}",
-                        prefix,
-                        if is_last {
-                            colors::TREE_SPACE
-                        } else {
-                            colors::TREE_VERTICAL
-                        }
-                    );
-                    self.show_items(&item.children, &new_prefix);
-                }
+                self.show_children(item, prefix, is_last);
             }
         }
 
@@ -0,0 +1,225 @@
+//! User-defined entry lines for `--format-string`
+//!
+//! A template is literal text with `{field}` or `{field:spec}` placeholders,
+//! `{{`/`}}` for literal braces and `\t`/`\n` escapes. Timestamp fields take
+//! a strftime spec, e.g. `{mtime:%Y-%m-%d}`.
+
+use super::utils::{format_datetime, format_timestamp, is_folded};
+use crate::error::{Error, Result};
+use crate::stats::format_size;
+use crate::types::{DirectoryEntry, DisplayConfig};
+use chrono::format::{Item, StrftimeItems};
+use std::path::Path;
+
+const FIELDS: &[&str] = &[
+    "name",
+    "path",
+    "type",
+    "size",
+    "bytes",
+    "files",
+    "mtime",
+    "ctime",
+    "annotation",
+    "depth",
+    "indent",
+];
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field { name: String, spec: Option<String> },
+}
+
+/// A parsed `--format-string` template
+#[derive(Debug)]
+pub(super) struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub(super) fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some(other) => literal.push(other),
+                    None => literal.push('\\'),
+                },
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => {
+                                return Err(Error::Pattern(String::from(
+                                    "unclosed '{' in format string",
+                                )))
+                            }
+                        }
+                    }
+                    let (name, spec) = match placeholder.split_once(':') {
+                        Some((name, spec)) => (name, Some(spec.to_string())),
+                        None => (placeholder.as_str(), None),
+                    };
+                    validate_field(name, spec.as_deref())?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field {
+                        name: name.to_string(),
+                        spec,
+                    });
+                }
+                '}' => {
+                    return Err(Error::Pattern(String::from(
+                        "unmatched '}' in format string (use '}}' for a literal brace)",
+                    )))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Render the line for one entry at the given depth (root's children are at 1)
+    pub(super) fn render(
+        &self,
+        entry: &DirectoryEntry,
+        root: &Path,
+        depth: usize,
+        config: &DisplayConfig,
+    ) -> String {
+        let mut line = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => line.push_str(text),
+                Segment::Field { name, spec } => {
+                    line.push_str(&field(entry, root, depth, name, spec.as_deref(), config))
+                }
+            }
+        }
+        line
+    }
+
+    /// Render every visible entry below `entries` depth-first, one line each
+    ///
+    /// Folded directories are listed without their contents; the line budget
+    /// does not apply, so scripts always see the complete listing.
+    pub(super) fn render_all(
+        &self,
+        entries: &[DirectoryEntry],
+        root: &Path,
+        depth: usize,
+        config: &DisplayConfig,
+        output: &mut String,
+    ) {
+        for entry in entries {
+            output.push_str(&self.render(entry, root, depth, config));
+            output.push('\n');
+            if entry.is_dir && !is_folded(entry, config) {
+                self.render_all(&entry.children, root, depth + 1, config, output);
+            }
+        }
+    }
+}
+
+fn validate_field(name: &str, spec: Option<&str>) -> Result<()> {
+    if !FIELDS.contains(&name) {
+        return Err(Error::Pattern(format!(
+            "unknown field '{{{}}}' in format string (available: {})",
+            name,
+            FIELDS.join(", ")
+        )));
+    }
+    match spec {
+        Some(_) if name != "mtime" && name != "ctime" => Err(Error::Pattern(format!(
+            "field '{{{}}}' does not take a format spec",
+            name
+        ))),
+        Some(spec) if StrftimeItems::new(spec).any(|item| matches!(item, Item::Error)) => Err(
+            Error::Pattern(format!("invalid time format '{}' in '{{{}}}'", spec, name)),
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn field(
+    entry: &DirectoryEntry,
+    root: &Path,
+    depth: usize,
+    name: &str,
+    spec: Option<&str>,
+    config: &DisplayConfig,
+) -> String {
+    let time = |time| match spec {
+        Some(spec) => format_datetime(time, spec, config),
+        None => format_timestamp(time, config),
+    };
+    match name {
+        "name" => entry.name.clone(),
+        "path" => entry
+            .path
+            .strip_prefix(root)
+            .unwrap_or(&entry.path)
+            .to_string_lossy()
+            .into_owned(),
+        "type" => String::from(if entry.is_dir { "dir" } else { "file" }),
+        "size" => format_size(entry.metadata.size),
+        "bytes" => entry.metadata.size.to_string(),
+        "files" => entry.metadata.files_count.to_string(),
+        "mtime" => time(entry.metadata.modified),
+        "ctime" => time(entry.metadata.created),
+        "annotation" => entry.filter_annotation.clone().unwrap_or_default(),
+        "depth" => depth.to_string(),
+        "indent" => "  ".repeat(depth.saturating_sub(1)),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        let template = Template::parse("{name}\\t{mtime:%Y} {{x}}").unwrap();
+        assert_eq!(
+            template.segments,
+            vec![
+                Segment::Field {
+                    name: String::from("name"),
+                    spec: None
+                },
+                Segment::Literal(String::from("\t")),
+                Segment::Field {
+                    name: String::from("mtime"),
+                    spec: Some(String::from("%Y"))
+                },
+                Segment::Literal(String::from(" {x}")),
+            ]
+        );
+
+        assert!(Template::parse("{nope}").is_err());
+        assert!(Template::parse("{name").is_err());
+        assert!(Template::parse("{size:%Y}").is_err());
+        assert!(Template::parse("{mtime:%Q}").is_err());
+    }
+}
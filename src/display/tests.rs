@@ -1,5 +1,8 @@
 use super::state::DisplayState;
-use crate::types::{ColorTheme, DirectoryEntry, DisplayConfig, EntryMetadata, SortBy};
+use crate::types::{
+    ColorTheme, DirectoryEntry, DisplayConfig, EntryKind, EntryMetadata, Grouping, SortBy,
+    TimeStyle, Tokenizer,
+};
 use std::path::PathBuf;
 use std::time::SystemTime;
 
@@ -21,11 +24,22 @@ mod test_utils {
                 created: SystemTime::now(),
                 modified: SystemTime::now(),
                 files_count: if is_dir { children.len() } else { 0 },
+                mode: None,
+                uid: None,
+                gid: None,
+                approximate: false,
             },
             children,
             is_gitignored: false,
+            is_git_repo: false,
             filtered_by: None,
             filter_annotation: None,
+            error: None,
+            is_virtual: false,
+            kind: EntryKind::Regular,
+            has_unlisted: false,
+            has_omitted: false,
+            cancelled: false,
         }
     }
 
@@ -56,23 +70,10 @@ fn test_basic_line_limit() {
         .collect::<Vec<_>>();
 
     for max_lines in [3, 5, 7, 10] {
-        let config = DisplayConfig {
-            max_lines,
-            dir_limit: 20,
-            sort_by: SortBy::Name,
-            dirs_first: false,
-            use_colors: false,
-            color_theme: ColorTheme::None,
-            use_emoji: false,
-            size_colorize: false,
-            date_colorize: false,
-            detailed_metadata: false,
-            show_system_dirs: false,
-            show_filtered: false,
-            disable_rules: Vec::new(),
-            enable_rules: Vec::new(),
-            rule_debug: false,
-        };
+        let config = DisplayConfig::builder()
+            .max_lines(max_lines)
+            .color_theme(ColorTheme::None)
+            .build();
 
         let mut state = DisplayState::new(max_lines, &config);
         state.show_items(&files, "");
@@ -96,23 +97,10 @@ fn test_head_tail_pattern() {
         .map(|i| create_test_entry(&format!("file{}.rs", i), false, vec![]))
         .collect::<Vec<_>>();
 
-    let config = DisplayConfig {
-        max_lines: 7,
-        dir_limit: 20,
-        sort_by: SortBy::Name,
-        dirs_first: false,
-        use_colors: false,
-        color_theme: ColorTheme::None,
-        use_emoji: false,
-        size_colorize: false,
-        date_colorize: false,
-        detailed_metadata: false,
-        show_system_dirs: false,
-        show_filtered: false,
-        disable_rules: Vec::new(),
-        enable_rules: Vec::new(),
-        rule_debug: false,
-    };
+    let config = DisplayConfig::builder()
+        .max_lines(7)
+        .color_theme(ColorTheme::None)
+        .build();
 
     let mut state = DisplayState::new(config.max_lines, &config);
     state.show_items(&files, "");
@@ -155,23 +143,10 @@ fn test_nested_directory_budget() {
         create_test_entry("test", true, nested_files),
     ];
 
-    let config = DisplayConfig {
-        max_lines: 10,
-        dir_limit: 20,
-        sort_by: SortBy::Name,
-        dirs_first: false,
-        use_colors: false,
-        color_theme: ColorTheme::None,
-        use_emoji: false,
-        size_colorize: false,
-        date_colorize: false,
-        detailed_metadata: false,
-        show_system_dirs: false,
-        show_filtered: false,
-        disable_rules: Vec::new(),
-        enable_rules: Vec::new(),
-        rule_debug: false,
-    };
+    let config = DisplayConfig::builder()
+        .max_lines(10)
+        .color_theme(ColorTheme::None)
+        .build();
 
     let mut state = DisplayState::new(config.max_lines, &config);
     state.show_items(&dirs, "");
@@ -220,23 +195,11 @@ fn test_real_project_structure() {
     ];
 
     for max_lines in [10, 15, 20] {
-        let config = DisplayConfig {
-            max_lines,
-            dir_limit: 20,
-            sort_by: SortBy::Modified,
-            dirs_first: false,
-            use_colors: false,
-            color_theme: ColorTheme::None,
-            use_emoji: false,
-            size_colorize: false,
-            date_colorize: false,
-            detailed_metadata: false,
-            show_system_dirs: false,
-            show_filtered: false,
-            disable_rules: Vec::new(),
-            enable_rules: Vec::new(),
-            rule_debug: false,
-        };
+        let config = DisplayConfig::builder()
+            .max_lines(max_lines)
+            .sort_by(SortBy::Modified)
+            .color_theme(ColorTheme::None)
+            .build();
 
         let mut state = DisplayState::new(config.max_lines, &config);
         state.show_items(&src_contents, "");
@@ -360,23 +323,11 @@ fn test_expanded_project_structure() {
         println!("\nTesting with max_lines = {}", max_lines);
         println!("Expected: {:?}", expected);
 
-        let config = DisplayConfig {
-            max_lines,
-            dir_limit: 20,
-            sort_by: SortBy::Modified,
-            dirs_first: false,
-            use_colors: false,
-            color_theme: ColorTheme::None,
-            use_emoji: false,
-            size_colorize: false,
-            date_colorize: false,
-            detailed_metadata: false,
-            show_system_dirs: false,
-            show_filtered: false,
-            disable_rules: Vec::new(),
-            enable_rules: Vec::new(),
-            rule_debug: false,
-        };
+        let config = DisplayConfig::builder()
+            .max_lines(max_lines)
+            .sort_by(SortBy::Modified)
+            .color_theme(ColorTheme::None)
+            .build();
 
         let mut state = DisplayState::new(config.max_lines, &config);
         state.show_items(&root_contents, "");
@@ -479,23 +430,10 @@ fn test_extended_head_tail_pattern() {
 
     let root_contents = vec![create_test_entry("src", true, many_files)];
 
-    let config = DisplayConfig {
-        max_lines: 10,
-        dir_limit: 20,
-        sort_by: SortBy::Name,
-        dirs_first: false,
-        use_colors: false,
-        color_theme: ColorTheme::None,
-        use_emoji: false,
-        size_colorize: false,
-        date_colorize: false,
-        detailed_metadata: false,
-        show_system_dirs: false,
-        show_filtered: false,
-        disable_rules: Vec::new(),
-        enable_rules: Vec::new(),
-        rule_debug: false,
-    };
+    let config = DisplayConfig::builder()
+        .max_lines(10)
+        .color_theme(ColorTheme::None)
+        .build();
 
     let mut state = DisplayState::new(config.max_lines, &config);
     state.show_items(&root_contents, "");
@@ -538,23 +476,10 @@ fn test_last_item_connector() {
 
     let root_contents = vec![create_test_entry("src", true, files)];
 
-    let config = DisplayConfig {
-        max_lines: 20,
-        dir_limit: 20,
-        sort_by: SortBy::Name,
-        dirs_first: false,
-        use_colors: false,
-        color_theme: ColorTheme::None,
-        use_emoji: false,
-        size_colorize: false,
-        date_colorize: false,
-        detailed_metadata: false,
-        show_system_dirs: false,
-        show_filtered: false,
-        disable_rules: Vec::new(),
-        enable_rules: Vec::new(),
-        rule_debug: false,
-    };
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .build();
 
     let mut state = DisplayState::new(config.max_lines, &config);
     state.show_items(&root_contents, "");
@@ -593,23 +518,11 @@ fn test_no_collapse_single_item() {
     let root_contents = vec![create_test_entry("src", true, files)];
 
     // Configure to only show 2 lines in directory (force 1 item to be hidden)
-    let config = DisplayConfig {
-        max_lines: 5, // Root + src + 2 files + maybe hidden indicator
-        dir_limit: 2, // Only show 2 files in directory
-        sort_by: SortBy::Name,
-        dirs_first: false,
-        use_colors: false,
-        color_theme: ColorTheme::None,
-        use_emoji: false,
-        size_colorize: false,
-        date_colorize: false,
-        detailed_metadata: false,
-        show_system_dirs: false,
-        show_filtered: false,
-        disable_rules: Vec::new(),
-        enable_rules: Vec::new(),
-        rule_debug: false,
-    };
+    let config = DisplayConfig::builder()
+        .max_lines(5) // Root + src + 2 files + maybe hidden indicator
+        .dir_limit(2) // Only show 2 files in directory
+        .color_theme(ColorTheme::None)
+        .build();
 
     let mut state = DisplayState::new(config.max_lines, &config);
     state.show_items(&root_contents, "");
@@ -633,23 +546,11 @@ fn test_no_collapse_single_item() {
 
     let more_root_contents = vec![create_test_entry("src", true, more_files)];
 
-    let more_config = DisplayConfig {
-        max_lines: 5,
-        dir_limit: 2,
-        sort_by: SortBy::Name,
-        dirs_first: false,
-        use_colors: false,
-        color_theme: ColorTheme::None,
-        use_emoji: false,
-        size_colorize: false,
-        date_colorize: false,
-        detailed_metadata: false,
-        show_system_dirs: false,
-        show_filtered: false,
-        disable_rules: Vec::new(),
-        enable_rules: Vec::new(),
-        rule_debug: false,
-    };
+    let more_config = DisplayConfig::builder()
+        .max_lines(5)
+        .dir_limit(2)
+        .color_theme(ColorTheme::None)
+        .build();
 
     let mut more_state = DisplayState::new(more_config.max_lines, &more_config);
     more_state.show_items(&more_root_contents, "");
@@ -663,3 +564,851 @@ fn test_no_collapse_single_item() {
         "Should collapse when 2 or more items would be hidden"
     );
 }
+
+#[test]
+fn test_max_width_keeps_links_closed() {
+    use test_utils::*;
+
+    let mut file = create_test_entry("a-rather-long-file-name.txt", false, vec![]);
+    file.path = PathBuf::from("/srv/a-rather-long-file-name.txt");
+    let root = create_test_entry(".", true, vec![file]);
+    let config = DisplayConfig::builder()
+        .max_width(Some(16))
+        .hyperlinks(true)
+        .show_metadata(true)
+        .build();
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    let line = output.lines().nth(1).unwrap();
+    assert!(line.contains("a-rather-lo…"), "{:?}", line);
+    assert_eq!(
+        line.matches("\x1b]8;;file://").count(),
+        line.matches("\x1b]8;;\x1b\\").count(),
+        "Every link is closed: {:?}",
+        line
+    );
+    assert_eq!(super::width::display_width(line), 16);
+}
+
+#[test]
+fn test_only_empty_keeps_ancestors() {
+    use test_utils::*;
+
+    let mut empty_file = create_test_entry("empty.txt", false, vec![]);
+    empty_file.metadata.size = 0;
+    let empty_dir = create_test_entry("scaffold", true, vec![]);
+    // Left unlisted at the depth limit, with something in it
+    let mut unlisted_dir = create_test_entry("vendor", true, vec![]);
+    unlisted_dir.has_unlisted = true;
+
+    let root = create_test_entry(
+        ".",
+        true,
+        vec![
+            create_test_entry("src", true, vec![empty_file]),
+            create_test_entry("docs", true, vec![create_test_entry("a.md", false, vec![])]),
+            empty_dir,
+            unlisted_dir,
+        ],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .only_empty(true)
+        .build();
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    println!("Output:\n{}", output);
+
+    assert!(output.contains("empty.txt"), "Empty file should be shown");
+    assert!(
+        output.contains("src"),
+        "Ancestor of empty file should be kept"
+    );
+    assert!(
+        output.contains("scaffold"),
+        "Empty directory should be shown"
+    );
+    assert!(
+        !output.contains("docs"),
+        "Non-empty directory should be pruned"
+    );
+    assert!(
+        !output.contains("vendor"),
+        "Unlisted directory with contents should be pruned"
+    );
+    assert_eq!(output.matches("[empty]").count(), 2);
+}
+
+#[test]
+fn test_format_time_uses_injected_clock() {
+    use super::utils::format_time;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(format_time(now, now), "just now");
+    assert_eq!(
+        format_time(now - Duration::from_secs(5 * 60), now),
+        "5m ago"
+    );
+    assert_eq!(
+        format_time(now - Duration::from_secs(3 * 86400), now),
+        "3d ago"
+    );
+    // Timestamps in the future relative to the clock never underflow
+    assert_eq!(
+        format_time(now + Duration::from_secs(3600), now),
+        "just now"
+    );
+}
+
+#[test]
+fn test_peek_depth_shows_top_level_of_folded_dirs() {
+    use test_utils::*;
+
+    let package = create_test_entry(
+        "left-pad",
+        true,
+        vec![create_test_entry("index.js", false, vec![])],
+    );
+    let mut node_modules = create_test_entry("node_modules", true, vec![package]);
+    node_modules.filtered_by = Some("dependencies".to_string());
+    node_modules.filter_annotation = Some("[folded: dependencies]".to_string());
+    let root = create_test_entry(".", true, vec![node_modules]);
+
+    let mut config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .build();
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(
+        !output.contains("left-pad"),
+        "Folded dir should hide children"
+    );
+    assert!(output.contains(" [folded: dependencies]") && !output.contains("[["));
+
+    config.peek_depth = 1;
+    let output = crate::format_tree(&root, &config).unwrap();
+    println!("Output:\n{}", output);
+    assert!(
+        output.contains("left-pad"),
+        "Top-level package should be peeked"
+    );
+    assert!(
+        !output.contains("index.js"),
+        "Peek should stop after one level"
+    );
+}
+
+#[test]
+fn test_min_depth_keeps_structural_ancestors() {
+    use test_utils::*;
+
+    let root = create_test_entry(
+        ".",
+        true,
+        vec![
+            create_test_entry("README.md", false, vec![]),
+            create_test_entry(
+                "src",
+                true,
+                vec![
+                    create_test_entry("lib.rs", false, vec![]),
+                    create_test_entry(
+                        "display",
+                        true,
+                        vec![create_test_entry("format.rs", false, vec![])],
+                    ),
+                ],
+            ),
+        ],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .min_depth(3)
+        .build();
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    println!("Output:\n{}", output);
+
+    assert!(output.contains("format.rs"), "Deep entry should be shown");
+    assert!(output.contains("display"), "Ancestor should be kept");
+    assert!(
+        !output.contains("README.md"),
+        "Shallow file should be hidden"
+    );
+    assert!(!output.contains("lib.rs"), "Shallow file should be hidden");
+}
+
+#[test]
+fn test_sort_ties_are_broken_by_name() {
+    use test_utils::*;
+
+    let root = create_test_entry(
+        ".",
+        true,
+        vec![
+            create_test_entry("b.rs", false, vec![]),
+            create_test_entry(
+                "nested",
+                true,
+                vec![
+                    create_test_entry("z.rs", false, vec![]),
+                    create_test_entry("y.rs", false, vec![]),
+                ],
+            ),
+            create_test_entry("a.rs", false, vec![]),
+        ],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .sort_by(SortBy::Size)
+        .color_theme(ColorTheme::None)
+        .build();
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    println!("Output:\n{}", output);
+
+    let position = |name: &str| output.find(name).unwrap();
+    assert!(position("a.rs") < position("b.rs"));
+    assert!(position("b.rs") < position("nested"));
+    assert!(
+        position("y.rs") < position("z.rs"),
+        "Nested ties sorted too"
+    );
+}
+
+#[test]
+fn test_group_dirs_first_or_last() {
+    use test_utils::*;
+
+    let root = create_test_entry(
+        ".",
+        true,
+        vec![
+            create_test_entry("c-dir", true, vec![]),
+            create_test_entry("b.rs", false, vec![]),
+            create_test_entry("a-dir", true, vec![]),
+        ],
+    );
+
+    let order = |group_by: Grouping| {
+        let config = DisplayConfig::builder().group_by(group_by).build();
+        let output = crate::format_tree(&root, &config).unwrap();
+        let mut names = ["a-dir", "b.rs", "c-dir"];
+        names.sort_by_key(|name| output.find(name).unwrap());
+        names
+    };
+    assert_eq!(order(Grouping::None), ["a-dir", "b.rs", "c-dir"]);
+    assert_eq!(order(Grouping::DirsFirst), ["a-dir", "c-dir", "b.rs"]);
+    assert_eq!(order(Grouping::DirsLast), ["b.rs", "a-dir", "c-dir"]);
+}
+
+#[test]
+fn test_absolute_time_styles() {
+    use super::utils::format_timestamp;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let mut config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .time_style(TimeStyle::Iso)
+        .utc(true)
+        .build();
+
+    // 2024-05-01 13:45:12 UTC
+    let time = UNIX_EPOCH + Duration::from_secs(1_714_571_112);
+    assert_eq!(format_timestamp(time, &config), "2024-05-01 13:45");
+
+    config.time_style = TimeStyle::Full;
+    assert_eq!(
+        format_timestamp(time, &config),
+        "2024-05-01 13:45:12 +00:00"
+    );
+
+    config.time_style = TimeStyle::Relative;
+    config.now = time + Duration::from_secs(120);
+    assert_eq!(format_timestamp(time, &config), "2m ago");
+}
+
+#[test]
+fn test_sniff_content_detects_unknown_files() {
+    use super::colors::sniff_content;
+    use crate::types::FileType;
+
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("deploy");
+    std::fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+    let archive = dir.path().join("backup.dat");
+    std::fs::write(&archive, b"PK\x03\x04\x14\x00\x00\x00\x08\x00").unwrap();
+    let text = dir.path().join("notes");
+    std::fs::write(&text, "just some text").unwrap();
+
+    assert_eq!(sniff_content(&script).unwrap().0, FileType::Code);
+    let mut entry = test_utils::create_test_entry("backup.dat", false, vec![]);
+    entry.path = archive.clone();
+    let config = DisplayConfig::builder().sniff_content(true).build();
+    assert_eq!(
+        super::colors::file_type_and_mime(&entry, &config),
+        (FileType::Archive, Some("application/zip"))
+    );
+    assert_eq!(
+        sniff_content(&archive).unwrap(),
+        (FileType::Archive, "application/zip")
+    );
+    assert!(sniff_content(&text).is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_special_file_types() {
+    use super::colors::{file_type_and_mime, get_file_emoji, special_file_type, EMOJI_SOCKET};
+    use crate::scanner::entry_kind;
+    use crate::types::{EntryKind, FileType};
+    use std::os::unix::net::UnixListener;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("app.sock");
+    let _listener = UnixListener::bind(&socket).unwrap();
+    let file = dir.path().join("app.log");
+    std::fs::write(&file, "").unwrap();
+    let kind = |path: &std::path::Path| entry_kind(path, &path.symlink_metadata().unwrap());
+
+    assert_eq!(kind(&socket), EntryKind::Socket);
+    assert_eq!(special_file_type(&kind(&socket)), Some(FileType::Socket));
+    assert_eq!(get_file_emoji(FileType::Socket), EMOJI_SOCKET);
+    // Never opened, even when sniffing
+    let mut entry = test_utils::create_test_entry("app.sock", false, vec![]);
+    entry.path = socket.clone();
+    entry.kind = kind(&socket);
+    let config = DisplayConfig::builder().sniff_content(true).build();
+    assert_eq!(
+        file_type_and_mime(&entry, &config),
+        (FileType::Socket, None)
+    );
+    assert_eq!(kind("/dev/null".as_ref()), EntryKind::CharDevice);
+    assert_eq!(kind(&file), EntryKind::Regular);
+    assert_eq!(kind(dir.path()), EntryKind::Regular);
+    assert_eq!(special_file_type(&EntryKind::Regular), None);
+
+    let dangling = dir.path().join("dangling");
+    std::os::unix::fs::symlink("missing", &dangling).unwrap();
+    let broken = EntryKind::Symlink {
+        target: Some(String::from("missing")),
+        broken: true,
+    };
+    assert_eq!(kind(&dangling), broken);
+
+    // The scanner records the kind, so displaying the socket needs no lookup
+    let root = crate::scan(
+        dir.path(),
+        &mut crate::GitIgnoreContext::new(dir.path()).unwrap(),
+        &crate::ScanOptions::default(),
+    )
+    .unwrap();
+    let scanned = root.children.iter().find(|c| c.name == "app.sock").unwrap();
+    assert_eq!(scanned.kind, EntryKind::Socket);
+}
+
+#[test]
+fn test_symlink_target_shown() {
+    use test_utils::*;
+
+    let mut entry = create_test_entry("current", false, vec![]);
+    entry.kind = EntryKind::Symlink {
+        target: Some(String::from("releases/v2")),
+        broken: false,
+    };
+    let root = create_test_entry(".", true, vec![entry]);
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .show_metadata(false)
+        .build();
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(output.contains("current -> releases/v2"), "{}", output);
+
+    // Nothing is looked up when displaying, so an unreadable link shows no target
+    let mut root = root;
+    root.children[0].kind = EntryKind::Symlink {
+        target: None,
+        broken: true,
+    };
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(!output.contains("->"), "{}", output);
+}
+
+#[test]
+fn test_nested_repo_badge_and_fold() {
+    use test_utils::*;
+
+    let mut vendor = create_test_entry(
+        "vendored-lib",
+        true,
+        vec![create_test_entry("lib.rs", false, vec![])],
+    );
+    vendor.is_git_repo = true;
+    let root = create_test_entry(".", true, vec![vendor]);
+
+    let mut config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .build();
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(output.contains("vendored-lib"));
+    assert!(
+        output.contains("[git repo]"),
+        "Nested repo should be badged"
+    );
+    assert!(output.contains("lib.rs"), "Nested repo expands by default");
+
+    config.fold_nested_repos = true;
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(output.contains("[git repo]"));
+    assert!(!output.contains("lib.rs"), "Nested repo should be folded");
+}
+
+#[test]
+fn test_workspace_member_badges() {
+    use test_utils::*;
+
+    let mut member = create_test_entry(
+        "foo",
+        true,
+        vec![create_test_entry("Cargo.toml", false, vec![])],
+    );
+    member.path = PathBuf::from("crates/foo");
+    let mut crates = create_test_entry("crates", true, vec![member]);
+    crates.path = PathBuf::from("crates");
+    let mut children: Vec<DirectoryEntry> = (0..8)
+        .map(|i| create_test_entry(&format!("a{}.txt", i), false, vec![]))
+        .collect();
+    children.push(crates);
+    children.extend((0..8).map(|i| create_test_entry(&format!("z{}.txt", i), false, vec![])));
+    let root = create_test_entry(".", true, children);
+
+    let mut config = DisplayConfig::builder()
+        .max_lines(40)
+        .dir_limit(30)
+        .color_theme(ColorTheme::None)
+        .build();
+    config
+        .badges
+        .insert(PathBuf::from("crates/foo"), "crate: foo v0.3.1".to_string());
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(
+        output.contains("foo (1 files, 100B, modified just now) [crate: foo v0.3.1]"),
+        "{}",
+        output
+    );
+    // With room for everything, the listing keeps its order
+    assert!(output.find("a7.txt").unwrap() < output.find("crates").unwrap());
+
+    // A tight budget keeps the member in view, whatever its position
+    config.dir_limit = 4;
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(output.contains("[crate: foo v0.3.1]"), "{}", output);
+    assert!(output.contains("items hidden"));
+}
+
+#[test]
+fn test_skip_report_lists_folded_and_truncated() {
+    use std::path::PathBuf;
+    use test_utils::*;
+
+    let mut files: Vec<DirectoryEntry> = (0..30)
+        .map(|i| create_test_entry(&format!("file{:02}.rs", i), false, vec![]))
+        .collect();
+    for file in &mut files {
+        file.path = PathBuf::from("./src").join(&file.name);
+    }
+    files[0].error = Some(String::from("permission denied"));
+    files[15].error = Some(String::from("No such device"));
+    let mut src = create_test_entry("src", true, files);
+    src.path = PathBuf::from("./src");
+    let mut deps = create_test_entry("node_modules", true, vec![]);
+    deps.path = PathBuf::from("./node_modules");
+    deps.is_gitignored = true;
+    deps.metadata.files_count = 1204;
+    let mut root = create_test_entry(".", true, vec![deps, src]);
+    root.path = PathBuf::from(".");
+
+    let mut config = DisplayConfig::builder()
+        .max_lines(20)
+        .dir_limit(10)
+        .color_theme(ColorTheme::None)
+        .build();
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(!output.contains("Skipped:"), "Report is opt-in");
+    assert!(output.contains("file00.rs (") && output.contains(") [permission denied]\n"));
+
+    let report = crate::skip_report(&root, &config);
+    assert_eq!(report.folded.len(), 1);
+    assert_eq!(report.folded[0].reason, "system");
+    assert_eq!(report.truncated.len(), 1);
+    assert_eq!(report.truncated[0].path, PathBuf::from("./src"));
+    // Hidden entries that couldn't be read are reported too
+    assert_eq!(report.unreadable.len(), 2);
+
+    config.skip_report = true;
+    let output = crate::format_tree(&root, &config).unwrap();
+    println!("Output:\n{}", output);
+    assert!(output.contains("node_modules/ folded: system (1,204 files"));
+    assert!(output.contains(&format!(
+        "src/ {} items hidden by truncation",
+        report.truncated[0].hidden
+    )));
+    assert!(output.contains("Unreadable:\n  src/file00.rs: permission denied\n"));
+    assert!(output.contains("  src/file15.rs: No such device"));
+}
+
+#[test]
+fn test_skip_report_leaves_out_unsized_file_limit_dirs() {
+    use std::path::PathBuf;
+    use test_utils::*;
+
+    let mut big = create_test_entry("big", true, vec![]);
+    big.path = PathBuf::from("./big");
+    big.filtered_by = Some(String::from("filelimit"));
+    big.filter_annotation = Some(String::from("[too many entries]"));
+    big.metadata.files_count = 30;
+    big.metadata.size = 4096;
+    let mut root = create_test_entry(".", true, vec![big]);
+    root.path = PathBuf::from(".");
+
+    let config = DisplayConfig::builder()
+        .color_theme(ColorTheme::None)
+        .skip_report(true)
+        .build();
+    let report = crate::skip_report(&root, &config);
+    assert_eq!(report.folded.len(), 1);
+    assert_eq!(report.folded[0].size, None);
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert!(
+        output.contains("big/ folded: filelimit (30 files)\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn test_priorities_reorder_every_level() {
+    use crate::types::Placement;
+    use test_utils::*;
+
+    let mut clippy = create_test_entry("clippy.toml", false, vec![]);
+    clippy.metadata.size = 5000;
+    let root = create_test_entry(
+        ".",
+        true,
+        vec![
+            create_test_entry("Cargo.toml", false, vec![]),
+            create_test_entry("LICENSE", false, vec![]),
+            clippy,
+            create_test_entry("docs", true, vec![]),
+            create_test_entry(
+                "src",
+                true,
+                vec![
+                    create_test_entry("a.rs", false, vec![]),
+                    create_test_entry("b.toml", false, vec![]),
+                    create_test_entry("c.rs", false, vec![]),
+                ],
+            ),
+        ],
+    );
+
+    let mut config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .priorities(vec![
+            (String::from("src"), Placement::First),
+            (String::from("Cargo.toml"), Placement::First),
+            (String::from("*.toml"), Placement::Last),
+        ])
+        .build();
+
+    let top_level = |output: &str| -> Vec<String> {
+        output
+            .lines()
+            .filter(|l| l.starts_with("├── ") || l.starts_with("└── "))
+            .map(|l| l[10..].split(' ').next().unwrap().to_string())
+            .collect()
+    };
+
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert_eq!(
+        top_level(&output),
+        vec!["Cargo.toml", "src", "LICENSE", "docs", "clippy.toml"]
+    );
+
+    // Demoted entries stay last under any sort key, even the largest one
+    config.sort_by = SortBy::Size;
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert_eq!(
+        top_level(&output),
+        vec!["Cargo.toml", "src", "LICENSE", "docs", "clippy.toml"]
+    );
+
+    // Priorities apply inside subdirectories too
+    let position = |name: &str| output.find(name).unwrap();
+    assert!(position("a.rs") < position("c.rs"));
+    assert!(position("c.rs") < position("b.toml"));
+}
+
+#[test]
+fn test_format_string_lines() {
+    use test_utils::*;
+
+    let mut cache = create_test_entry(
+        "cache",
+        true,
+        vec![create_test_entry("blob", false, vec![])],
+    );
+    cache.is_gitignored = true;
+    let root = create_test_entry(
+        ".",
+        true,
+        vec![
+            create_test_entry(
+                "src",
+                true,
+                vec![create_test_entry("main.rs", false, vec![])],
+            ),
+            cache,
+        ],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(2)
+        .color_theme(ColorTheme::None)
+        .utc(true)
+        .format_string(Some(String::from(
+            "{indent}{name}\\t{type}\\t{bytes}\\t{depth}",
+        )))
+        .build();
+
+    // Every visible entry is listed regardless of max_lines, folded ones without contents
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert_eq!(
+        output,
+        "cache\tdir\t100\t1\nsrc\tdir\t100\t1\n  main.rs\tfile\t100\t2\n"
+    );
+}
+
+#[test]
+fn test_nerd_icons() {
+    use super::icons::nerd_icon;
+    use crate::types::FileType;
+    use test_utils::*;
+
+    let icon = |name: &str, is_dir, file_type| {
+        nerd_icon(&create_test_entry(name, is_dir, vec![]), file_type)
+    };
+
+    // File names win over extensions, extensions over the detected type
+    assert_eq!(icon("Cargo.toml", false, FileType::Regular), "\u{e7a8}");
+    assert_eq!(icon("main.rs", false, FileType::Code), "\u{e7a8}");
+    assert_eq!(icon("config.toml", false, FileType::Regular), "\u{e615}");
+    assert_eq!(icon("notes.xyz", false, FileType::Regular), "\u{f15b}");
+    assert_eq!(icon("build.rs", true, FileType::Directory), "\u{f07b}");
+}
+
+#[test]
+fn test_format_mode() {
+    use super::utils::format_mode;
+
+    assert_eq!(format_mode(0o755), "rwxr-xr-x");
+    assert_eq!(format_mode(0o640), "rw-r-----");
+    assert_eq!(format_mode(0o4755), "rwsr-xr-x");
+    assert_eq!(format_mode(0o2644), "rw-r-Sr--");
+    assert_eq!(format_mode(0o1777), "rwxrwxrwt");
+}
+
+#[test]
+fn test_format_trees_shares_budget() {
+    use test_utils::*;
+
+    let files = (0..30)
+        .map(|i| create_test_entry(&format!("file{:02}.txt", i), false, vec![]))
+        .collect();
+    let big = create_test_entry("big", true, files);
+    let small = create_test_entry(
+        "small",
+        true,
+        vec![create_test_entry("only.txt", false, vec![])],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .utc(true)
+        .build();
+
+    // Each tree is headed by its name, in the order given, and the small tree
+    // leaves its unused share to the big one
+    let output = crate::format_trees(&[big, small], &config).unwrap();
+    let trees: Vec<&str> = output.split("\n\n").collect();
+    assert_eq!(trees.len(), 2);
+    assert!(trees[0].starts_with("big\n"));
+    assert!(trees[1].starts_with("small\n└── only.txt"));
+    assert!(trees[0].lines().count() > 10);
+    assert!(trees[0].lines().count() + trees[1].lines().count() <= 20);
+}
+
+#[test]
+fn test_markdown_list() {
+    use test_utils::*;
+
+    let root = create_test_entry(
+        ".",
+        true,
+        vec![
+            create_test_entry(
+                "src",
+                true,
+                vec![create_test_entry("__init__.py", false, vec![])],
+            ),
+            create_test_entry("notes`1`.md", false, vec![]),
+        ],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .utc(true)
+        .show_metadata(false)
+        .markdown(true)
+        .build();
+
+    // Names are code spans, so Markdown characters in them stay literal
+    let output = crate::format_tree(&root, &config).unwrap();
+    assert_eq!(
+        output,
+        "- `.`\n  - ``notes`1`.md``\n  - `src`\n    - `__init__.py`\n"
+    );
+    assert_eq!(super::utils::code_span("`tick"), "`` `tick ``");
+}
+
+#[test]
+fn test_html_details() {
+    use test_utils::*;
+
+    let mut cache = create_test_entry("cache", true, vec![create_test_entry("x", false, vec![])]);
+    cache.is_gitignored = true;
+    let root = create_test_entry(
+        "project",
+        true,
+        vec![
+            create_test_entry(
+                "src",
+                true,
+                vec![create_test_entry("a<b>.rs", false, vec![])],
+            ),
+            cache,
+        ],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .utc(true)
+        .show_metadata(false)
+        .build();
+
+    // Expanded directories start open, folded ones closed, and names are escaped
+    let html = crate::format_html(&root, &config);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>project - smart-tree</title>"));
+    assert!(
+        html.contains("<li><details open><summary><span class=\"directory\">src</span></summary>")
+    );
+    assert!(html.contains("<li class=\"file\"><span class=\"code\">a&lt;b&gt;.rs</span></li>"));
+    assert!(html.contains(
+        "<li><details><summary><span class=\"directory\">cache</span> \
+         <span class=\"tag\">[folded: system]</span></summary>"
+    ));
+}
+
+#[test]
+fn test_gnu_tree_formats() {
+    use test_utils::*;
+
+    let mut cache = create_test_entry("cache", true, vec![create_test_entry("x", false, vec![])]);
+    cache.is_gitignored = true;
+    let root = create_test_entry(
+        "proj",
+        true,
+        vec![create_test_entry("a\"b.txt", false, vec![]), cache],
+    );
+
+    let config = DisplayConfig::builder()
+        .max_lines(20)
+        .color_theme(ColorTheme::None)
+        .utc(true)
+        .show_metadata(false)
+        .build();
+
+    // Folded directories are listed without their contents
+    let json = crate::format_tree_json(std::slice::from_ref(&root), &config);
+    assert_eq!(
+        json,
+        "[\n  {\"type\":\"directory\",\"name\":\"proj\",\"size\":100,\"contents\":[\n    \
+         {\"type\":\"file\",\"name\":\"a\\\"b.txt\",\"size\":100},\n    \
+         {\"type\":\"directory\",\"name\":\"cache\",\"size\":100,\"contents\":[]}\n  ]}\n,\n  \
+         {\"type\":\"report\",\"directories\":1,\"files\":1}\n]\n"
+    );
+    assert!(serde_json::from_str::<serde_json::Value>(&json).is_ok());
+
+    let xml = crate::format_tree_xml(&[root], &config);
+    assert_eq!(
+        xml,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tree>\n  \
+         <directory name=\"proj\" size=\"100\">\n    \
+         <file name=\"a&quot;b.txt\" size=\"100\"></file>\n    \
+         <directory name=\"cache\" size=\"100\">\n    </directory>\n  </directory>\n  \
+         <report>\n    <directories>1</directories>\n    <files>1</files>\n  </report>\n</tree>\n"
+    );
+}
+
+#[test]
+fn test_max_tokens_budget() {
+    use test_utils::*;
+
+    let files = (0..50)
+        .map(|i| create_test_entry(&format!("file{:02}.txt", i), false, vec![]))
+        .collect();
+    let root = create_test_entry(".", true, files);
+
+    let config = DisplayConfig::builder()
+        .max_lines(1000)
+        .color_theme(ColorTheme::None)
+        .utc(true)
+        .max_tokens(Some(60))
+        .build();
+
+    // The token budget replaces the line budget, and is used as far as it goes
+    let output = crate::format_tree(&root, &config).unwrap();
+    let tokens = Tokenizer::Chars.count(&output);
+    assert!(tokens <= 60, "{} tokens:\n{}", tokens, output);
+    assert!(output.lines().count() > 3);
+    let more = crate::format_tree(
+        &root,
+        &DisplayConfig {
+            max_tokens: Some(200),
+            ..config.clone()
+        },
+    )
+    .unwrap();
+    assert!(more.lines().count() > output.lines().count());
+}
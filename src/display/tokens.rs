@@ -0,0 +1,71 @@
+//! Token estimates for `--max-tokens`
+//!
+//! Real tokenizers are model-specific, so these are heuristics that err on
+//! the high side for tree output: tree-drawing glyphs and punctuation usually
+//! become tokens of their own, while plain words split into a few pieces.
+
+use crate::error::{Error, Result};
+use crate::types::Tokenizer;
+
+impl Tokenizer {
+    /// The tokenizer called `name` (chars or words, in any case)
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "chars" => Ok(Tokenizer::Chars),
+            "words" => Ok(Tokenizer::Words),
+            _ => Err(Error::Config(format!(
+                "unknown tokenizer '{}' (expected chars or words)",
+                name
+            ))),
+        }
+    }
+
+    /// Estimated number of tokens in `text`
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Tokenizer::Chars => {
+                let ascii = text.chars().filter(char::is_ascii).count();
+                let other = text.chars().count() - ascii;
+                ascii.div_ceil(4) + other
+            }
+            Tokenizer::Words => {
+                let mut tokens = 0;
+                let mut word_len: usize = 0;
+                for c in text.chars() {
+                    if c.is_alphanumeric() {
+                        word_len += 1;
+                        continue;
+                    }
+                    tokens += word_len.div_ceil(4);
+                    word_len = 0;
+                    if !c.is_whitespace() {
+                        tokens += 1;
+                    }
+                }
+                tokens + word_len.div_ceil(4)
+            }
+            Tokenizer::Custom(count) => count(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_estimates() {
+        assert_eq!(Tokenizer::Chars.count(""), 0);
+        assert_eq!(Tokenizer::Chars.count("main.rs"), 2);
+        assert_eq!(Tokenizer::Chars.count("├── a"), 4);
+        assert_eq!(Tokenizer::Words.count("├── main.rs (12KB)"), 9);
+        assert_eq!(Tokenizer::Words.count("configuration"), 4);
+        assert_eq!(Tokenizer::Custom(|text| text.len()).count("abc"), 3);
+
+        assert!(matches!(
+            Tokenizer::from_name("Words"),
+            Ok(Tokenizer::Words)
+        ));
+        assert!(matches!(Tokenizer::from_name("bpe"), Err(Error::Config(_))));
+    }
+}
@@ -1,35 +1,156 @@
 use super::colors;
-use crate::types::{DirectoryEntry, DisplayConfig, SortBy};
+use crate::pattern::Glob;
+use crate::stats::format_size;
+use crate::types::{
+    DirectoryEntry, DisplayConfig, EntryKind, Grouping, Placement, SortBy, TimeStyle,
+};
+use chrono::{DateTime, Local, Utc};
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub(super) fn format_metadata(entry: &DirectoryEntry) -> String {
+pub(super) fn format_metadata(entry: &DirectoryEntry, config: &DisplayConfig) -> String {
     if entry.is_dir {
-        format_directory_metadata(entry)
+        format_directory_metadata(entry, config)
     } else {
-        format_file_metadata(entry)
+        format_file_metadata(entry, config)
     }
 }
 
-pub(super) fn format_directory_metadata(entry: &DirectoryEntry) -> String {
-    let files_count = entry.metadata.files_count.to_string();
-    let size = format_size(entry.metadata.size);
-    let modified = format_time(entry.metadata.modified);
+pub(super) fn format_directory_metadata(entry: &DirectoryEntry, config: &DisplayConfig) -> String {
+    let approx = approx_marker(entry);
+    let files_count = format!("{}{}", approx, entry.metadata.files_count);
+    let size = format!("{}{}", approx, format_size(entry.metadata.size));
+    let modified = format_timestamp(entry.metadata.modified, config);
+
+    format!(
+        "({} files, {}, modified {}{})",
+        files_count,
+        size,
+        modified,
+        plain_access_fields(entry, config)
+    )
+}
+
+/// Annotation text for an entry that couldn't be read: `permission denied`,
+/// or the error itself
+pub(super) fn error_label(error: &str) -> String {
+    match error {
+        "permission denied" => error.to_string(),
+        _ => format!("error: {}", error),
+    }
+}
+
+/// Whether an entry is a symlink or NTFS junction
+pub(super) fn is_symlink(entry: &DirectoryEntry) -> bool {
+    matches!(entry.kind, EntryKind::Symlink { .. })
+}
+
+/// Where a symlink or NTFS junction points, as recorded when scanning
+pub(super) fn link_target(entry: &DirectoryEntry) -> Option<&str> {
+    match &entry.kind {
+        EntryKind::Symlink { target, .. } => target.as_deref(),
+        _ => None,
+    }
+}
 
-    format!("({} files, {}, modified {})", files_count, size, modified)
+/// `~` before the totals of a directory that wasn't counted to the end
+fn approx_marker(entry: &DirectoryEntry) -> &'static str {
+    if entry.metadata.approximate {
+        "~"
+    } else {
+        ""
+    }
 }
 
-pub(super) fn format_file_metadata(entry: &DirectoryEntry) -> String {
+pub(super) fn format_file_metadata(entry: &DirectoryEntry, config: &DisplayConfig) -> String {
     let size = format_size(entry.metadata.size);
-    let modified = format_time(entry.metadata.modified);
+    let modified = format_timestamp(entry.metadata.modified, config);
+
+    format!(
+        "({}, modified {}{})",
+        size,
+        modified,
+        plain_access_fields(entry, config)
+    )
+}
 
-    format!("({}, modified {})", size, modified)
+/// Permissions and owner shown with `--perms` and `--owner`, as label/value pairs
+fn access_fields(entry: &DirectoryEntry, config: &DisplayConfig) -> Vec<(&'static str, String)> {
+    let mut fields = Vec::new();
+    if config.show_perms {
+        let mode = entry
+            .metadata
+            .mode
+            .map_or_else(|| "-".to_string(), format_mode);
+        fields.push(("perms", mode));
+    }
+    if config.show_owner {
+        fields.push((
+            "owner",
+            format_owner(entry.metadata.uid, entry.metadata.gid),
+        ));
+    }
+    fields
+}
+
+/// Access fields for uncolored metadata, each preceded by ", "
+fn plain_access_fields(entry: &DirectoryEntry, config: &DisplayConfig) -> String {
+    access_fields(entry, config)
+        .into_iter()
+        .map(|(_, value)| format!(", {}", value))
+        .collect()
+}
+
+/// Access fields as colorized metadata sections, each preceded by the separator
+fn colorized_access_fields(
+    entry: &DirectoryEntry,
+    config: &DisplayConfig,
+    separator: &str,
+) -> String {
+    access_fields(entry, config)
+        .into_iter()
+        .map(|(label, value)| {
+            let label = colors::colorize(
+                &format!("{}: ", label),
+                colors::get_label_color(config),
+                config,
+            );
+            let value = colors::colorize(&value, colors::get_value_color(config), config);
+            format!("{}{}{}", separator, label, value)
+        })
+        .collect()
+}
+
+/// Permission bits as `rwxr-xr-x`, with setuid/setgid (`s`) and sticky (`t`)
+/// bits shown in the execute positions, uppercase when not executable
+pub(super) fn format_mode(mode: u32) -> String {
+    let mut text = String::with_capacity(9);
+    for (shift, special_bit, special) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(match (mode & special_bit != 0, bits & 0o1 != 0) {
+            (true, true) => special,
+            (true, false) => special.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+/// Owner as `user:group`, with `-` for whatever the platform doesn't report
+fn format_owner(uid: Option<u32>, gid: Option<u32>) -> String {
+    let user = uid.map_or_else(|| "-".to_string(), super::owners::user_name);
+    let group = gid.map_or_else(|| "-".to_string(), super::owners::group_name);
+    format!("{}:{}", user, group)
 }
 
 // Removed unused traditional_metadata function
 
 pub(super) fn format_colorized_metadata(entry: &DirectoryEntry, config: &DisplayConfig) -> String {
     if !colors::should_use_colors(config) {
-        return format_metadata(entry);
+        return format_metadata(entry, config);
     }
 
     // Get the time difference in seconds for coloring
@@ -39,7 +160,8 @@ pub(super) fn format_colorized_metadata(entry: &DirectoryEntry, config: &Display
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
     let modified_secs = duration.as_secs();
-    let now = SystemTime::now()
+    let now = config
+        .now
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
@@ -53,13 +175,13 @@ pub(super) fn format_colorized_metadata(entry: &DirectoryEntry, config: &Display
         let files_label = colors::colorize("files: ", colors::get_label_color(config), config);
         let files_value = if config.size_colorize {
             colors::colorize(
-                &format!("{}", entry.metadata.files_count),
+                &format!("{}{}", approx_marker(entry), entry.metadata.files_count),
                 colors::get_size_color(entry.metadata.size, config),
                 config,
             )
         } else {
             colors::colorize(
-                &format!("{}", entry.metadata.files_count),
+                &format!("{}{}", approx_marker(entry), entry.metadata.files_count),
                 colors::get_value_color(config),
                 config,
             )
@@ -87,13 +209,13 @@ pub(super) fn format_colorized_metadata(entry: &DirectoryEntry, config: &Display
         let date_label = colors::colorize("mod: ", colors::get_label_color(config), config);
         let date_value = if config.date_colorize {
             colors::colorize(
-                &format_time(entry.metadata.modified),
+                &format_timestamp(entry.metadata.modified, config),
                 colors::get_date_color(time_diff, config),
                 config,
             )
         } else {
             colors::colorize(
-                &format_time(entry.metadata.modified),
+                &format_timestamp(entry.metadata.modified, config),
                 colors::get_value_color(config),
                 config,
             )
@@ -101,8 +223,13 @@ pub(super) fn format_colorized_metadata(entry: &DirectoryEntry, config: &Display
         let date_section = format!("{}{}", date_label, date_value);
 
         format!(
-            "({}{}{}{}{})",
-            files_section, separator, size_section, separator, date_section
+            "({}{}{}{}{}{})",
+            files_section,
+            separator,
+            size_section,
+            separator,
+            date_section,
+            colorized_access_fields(entry, config, &separator)
         )
     } else {
         // Format size
@@ -126,20 +253,26 @@ pub(super) fn format_colorized_metadata(entry: &DirectoryEntry, config: &Display
         let date_label = colors::colorize("mod: ", colors::get_label_color(config), config);
         let date_value = if config.date_colorize {
             colors::colorize(
-                &format_time(entry.metadata.modified),
+                &format_timestamp(entry.metadata.modified, config),
                 colors::get_date_color(time_diff, config),
                 config,
             )
         } else {
             colors::colorize(
-                &format_time(entry.metadata.modified),
+                &format_timestamp(entry.metadata.modified, config),
                 colors::get_value_color(config),
                 config,
             )
         };
         let date_section = format!("{}{}", date_label, date_value);
 
-        format!("({}{}{})", size_section, separator, date_section)
+        format!(
+            "({}{}{}{})",
+            size_section,
+            separator,
+            date_section,
+            colorized_access_fields(entry, config, &separator)
+        )
     }
 }
 
@@ -155,7 +288,8 @@ pub(super) fn format_detailed_metadata(entry: &DirectoryEntry, config: &DisplayC
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
     let modified_secs = duration.as_secs();
-    let now = SystemTime::now()
+    let now = config
+        .now
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
@@ -169,8 +303,11 @@ pub(super) fn format_detailed_metadata(entry: &DirectoryEntry, config: &DisplayC
     let created_secs = created_duration.as_secs();
     let created_diff = now.saturating_sub(created_secs);
 
-    let file_type = colors::determine_file_type(entry);
-    let type_str = format!("{:?}", file_type);
+    let (file_type, mime) = colors::file_type_and_mime(entry, config);
+    let type_str = match mime {
+        Some(mime) => format!("{:?} ({})", file_type, mime),
+        None => format!("{:?}", file_type),
+    };
 
     // Define separators
     let separator = colors::colorize(" | ", colors::get_separator_color(config), config);
@@ -196,20 +333,20 @@ pub(super) fn format_detailed_metadata(entry: &DirectoryEntry, config: &DisplayC
 
     // Type section
     let type_label = colors::colorize("type: ", colors::get_label_color(config), config);
-    let type_value = colors::colorize(&type_str, colors::get_name_color(entry, config), config);
+    let type_value = colors::colorize(&type_str, colors::get_name_color(file_type, config), config);
     let type_section = format!("{}{}", type_label, type_value);
 
     // Modified date section
     let mod_label = colors::colorize("mod: ", colors::get_label_color(config), config);
     let mod_value = if config.date_colorize {
         colors::colorize(
-            &format_time(entry.metadata.modified),
+            &format_timestamp(entry.metadata.modified, config),
             colors::get_date_color(time_diff, config),
             config,
         )
     } else {
         colors::colorize(
-            &format_time(entry.metadata.modified),
+            &format_timestamp(entry.metadata.modified, config),
             colors::get_value_color(config),
             config,
         )
@@ -220,13 +357,13 @@ pub(super) fn format_detailed_metadata(entry: &DirectoryEntry, config: &DisplayC
     let created_label = colors::colorize("created: ", colors::get_label_color(config), config);
     let created_value = if config.date_colorize {
         colors::colorize(
-            &format_time(entry.metadata.created),
+            &format_timestamp(entry.metadata.created, config),
             colors::get_date_color(created_diff, config),
             config,
         )
     } else {
         colors::colorize(
-            &format_time(entry.metadata.created),
+            &format_timestamp(entry.metadata.created, config),
             colors::get_value_color(config),
             config,
         )
@@ -238,13 +375,13 @@ pub(super) fn format_detailed_metadata(entry: &DirectoryEntry, config: &DisplayC
         let files_label = colors::colorize("files: ", colors::get_label_color(config), config);
         let files_value = if config.size_colorize {
             colors::colorize(
-                &format!("{}", entry.metadata.files_count),
+                &format!("{}{}", approx_marker(entry), entry.metadata.files_count),
                 colors::get_size_color(entry.metadata.size, config),
                 config,
             )
         } else {
             colors::colorize(
-                &format!("{}", entry.metadata.files_count),
+                &format!("{}{}", approx_marker(entry), entry.metadata.files_count),
                 colors::get_value_color(config),
                 config,
             )
@@ -252,7 +389,7 @@ pub(super) fn format_detailed_metadata(entry: &DirectoryEntry, config: &DisplayC
         let files_section = format!("{}{}", files_label, files_value);
 
         format!(
-            "({}{}{}{}{}{}{}{}{})",
+            "({}{}{}{}{}{}{}{}{}{})",
             size_section,
             separator,
             type_section,
@@ -261,49 +398,51 @@ pub(super) fn format_detailed_metadata(entry: &DirectoryEntry, config: &DisplayC
             separator,
             created_section,
             separator,
-            files_section
+            files_section,
+            colorized_access_fields(entry, config, &separator)
         )
     } else {
         format!(
-            "({}{}{}{}{}{}{})",
+            "({}{}{}{}{}{}{}{})",
             size_section,
             separator,
             type_section,
             separator,
             mod_section,
             separator,
-            created_section
+            created_section,
+            colorized_access_fields(entry, config, &separator)
         )
     }
 }
 
-pub(super) fn format_size(size: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-    const TB: u64 = GB * 1024;
-
-    if size >= TB {
-        format!("{:.2}TB", size as f64 / TB as f64)
-    } else if size >= GB {
-        format!("{:.2}GB", size as f64 / GB as f64)
-    } else if size >= MB {
-        format!("{:.1}MB", size as f64 / MB as f64)
-    } else if size >= KB {
-        format!("{:.1}KB", size as f64 / KB as f64)
+/// Format a timestamp in the configured time style
+pub(super) fn format_timestamp(time: SystemTime, config: &DisplayConfig) -> String {
+    let pattern = match config.time_style {
+        TimeStyle::Relative => return format_time(time, config.now),
+        TimeStyle::Iso => "%Y-%m-%d %H:%M",
+        TimeStyle::Full => "%Y-%m-%d %H:%M:%S %:z",
+    };
+
+    format_datetime(time, pattern, config)
+}
+
+/// Format a time with a strftime pattern, in UTC or the local timezone
+pub(super) fn format_datetime(time: SystemTime, pattern: &str, config: &DisplayConfig) -> String {
+    let time = DateTime::<Utc>::from(time);
+    if config.utc {
+        time.format(pattern).to_string()
     } else {
-        format!("{}B", size)
+        time.with_timezone(&Local).format(pattern).to_string()
     }
 }
 
-pub(super) fn format_time(time: SystemTime) -> String {
+/// Format a timestamp relative to `now` (e.g. "5m ago")
+pub(super) fn format_time(time: SystemTime, now: SystemTime) -> String {
     let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
     let secs = duration.as_secs();
 
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
 
     let diff = now.saturating_sub(secs);
 
@@ -324,22 +463,161 @@ pub(super) fn format_time(time: SystemTime) -> String {
     }
 }
 
-pub(super) fn sort_entries(entries: &mut [DirectoryEntry], config: &DisplayConfig) {
-    entries.sort_by(|a, b| {
-        if config.dirs_first {
-            if a.is_dir && !b.is_dir {
-                return std::cmp::Ordering::Less;
-            }
-            if !a.is_dir && b.is_dir {
-                return std::cmp::Ordering::Greater;
+/// Whether an entry's contents are folded rather than expanded
+pub(super) fn is_folded(entry: &DirectoryEntry, config: &DisplayConfig) -> bool {
+    (entry.is_gitignored && !config.show_system_dirs)
+        || (entry.filtered_by.is_some() && !config.show_filtered)
+        || (entry.is_git_repo && config.fold_nested_repos)
+}
+
+/// Whether an entry is empty: a zero-byte file, or an expanded directory
+/// that has no children left after filtering
+pub(super) fn is_empty_entry(entry: &DirectoryEntry, config: &DisplayConfig) -> bool {
+    if entry.is_dir {
+        // Directories at the depth limit have no scanned children either,
+        // so the scanner notes whether anything is in them
+        entry.children.is_empty()
+            && entry.metadata.files_count == 0
+            && !is_folded(entry, config)
+            && !entry.has_unlisted
+    } else {
+        entry.metadata.size == 0
+    }
+}
+
+/// Keep only entries matching the predicate, plus the directories leading to them
+///
+/// The predicate receives each entry with its depth, where the root's
+/// children are at depth 1.
+pub(super) fn retain_matching<F>(
+    entries: &[DirectoryEntry],
+    depth: usize,
+    predicate: &F,
+) -> Vec<DirectoryEntry>
+where
+    F: Fn(&DirectoryEntry, usize) -> bool,
+{
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let children = retain_matching(&entry.children, depth + 1, predicate);
+            if predicate(entry, depth) || !children.is_empty() {
+                let mut kept = entry.clone();
+                kept.children = children;
+                Some(kept)
+            } else {
+                None
             }
-        }
-
-        match config.sort_by {
-            SortBy::Name => a.name.cmp(&b.name),
-            SortBy::Size => b.metadata.size.cmp(&a.metadata.size),
-            SortBy::Modified => b.metadata.modified.cmp(&a.metadata.modified),
-            SortBy::Created => b.metadata.created.cmp(&a.metadata.created),
-        }
+        })
+        .collect()
+}
+
+/// A priority pattern, expanded once for the whole sort
+struct PlacementPattern {
+    glob: Glob,
+    /// Patterns with `/` match the path from the root rather than the name
+    by_path: bool,
+    placement: Placement,
+}
+
+/// Sort entries below `root` (and their children, recursively) for display
+///
+/// Promoted and demoted entries come first and last at every level,
+/// whatever the sort key. Entries with equal keys are ordered by `config.then_by` and finally by
+/// name and path, so output is identical across runs and platforms.
+pub(super) fn sort_entries(entries: &mut Vec<DirectoryEntry>, root: &Path, config: &DisplayConfig) {
+    let patterns: Vec<PlacementPattern> = config
+        .priorities
+        .iter()
+        .map(|(pattern, placement)| PlacementPattern {
+            glob: Glob::new(pattern.trim_start_matches('/')),
+            by_path: pattern.contains('/'),
+            placement: *placement,
+        })
+        .collect();
+    sort_level(entries, root, &patterns, config);
+}
+
+fn sort_level(
+    entries: &mut Vec<DirectoryEntry>,
+    root: &Path,
+    patterns: &[PlacementPattern],
+    config: &DisplayConfig,
+) {
+    // Ranked once per entry rather than on every comparison
+    let mut ranked: Vec<(u8, DirectoryEntry)> = entries
+        .drain(..)
+        .map(|entry| (placement_rank(&entry, root, patterns), entry))
+        .collect();
+    ranked.sort_by(|(rank_a, a), (rank_b, b)| {
+        let placed = rank_a.cmp(rank_b);
+        let grouped = match config.group_by {
+            Grouping::None => std::cmp::Ordering::Equal,
+            Grouping::DirsFirst => b.is_dir.cmp(&a.is_dir),
+            Grouping::DirsLast => a.is_dir.cmp(&b.is_dir),
+        };
+
+        placed
+            .then(grouped)
+            .then_with(|| compare_by(a, b, &config.sort_by))
+            .then_with(|| compare_by(a, b, &config.then_by))
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.path.cmp(&b.path))
     });
+    entries.extend(ranked.into_iter().map(|(_, entry)| entry));
+
+    for entry in entries.iter_mut() {
+        sort_level(&mut entry.children, root, patterns, config);
+    }
+}
+
+/// Where an entry sorts relative to its siblings: promoted (0), unlisted (1) or demoted (2)
+fn placement_rank(entry: &DirectoryEntry, root: &Path, patterns: &[PlacementPattern]) -> u8 {
+    if patterns.is_empty() {
+        return 1;
+    }
+    let name: Vec<char> = entry.name.chars().collect();
+    let relative = entry.path.strip_prefix(root).unwrap_or(&entry.path);
+    let path: Vec<char> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+        .chars()
+        .collect();
+    let placement = patterns
+        .iter()
+        .find(|pattern| {
+            let text = if pattern.by_path { &path } else { &name };
+            pattern.glob.matches_chars(text)
+        })
+        .map(|pattern| pattern.placement);
+    match placement {
+        Some(Placement::First) => 0,
+        None => 1,
+        Some(Placement::Last) => 2,
+    }
+}
+
+/// Compare two entries by a single key (names ascending, everything else descending)
+fn compare_by(a: &DirectoryEntry, b: &DirectoryEntry, key: &SortBy) -> std::cmp::Ordering {
+    match key {
+        SortBy::Name => a.name.cmp(&b.name),
+        SortBy::Size => b.metadata.size.cmp(&a.metadata.size),
+        SortBy::Modified => b.metadata.modified.cmp(&a.metadata.modified),
+        SortBy::Created => b.metadata.created.cmp(&a.metadata.created),
+    }
+}
+
+/// Markdown code span for a name, so characters like `*` and `_` stay literal
+pub(super) fn code_span(text: &str) -> String {
+    // The fence needs more backticks than any run inside the name
+    let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run + 1);
+    let pad = if text.starts_with('`') || text.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!("{fence}{pad}{text}{pad}{fence}")
 }
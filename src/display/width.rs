@@ -0,0 +1,194 @@
+//! Display width of terminal text
+//!
+//! Widths are measured in terminal columns: ANSI escape sequences take none,
+//! combining marks and variation selectors take none, and East Asian wide
+//! characters and emoji take two.
+
+/// Terminal columns taken by a character
+pub(super) fn char_width(c: char) -> usize {
+    let code = c as u32;
+    match code {
+        0 => 0,
+        _ if c.is_control() => 0,
+        // Combining marks, zero-width spaces and joiners, variation selectors
+        0x0300..=0x036F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0xE0100..=0xE01EF => 0,
+        // Hangul Jamo, CJK, Hangul syllables, fullwidth forms
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        // Emoji and pictographs
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x1FA70..=0x1FAFF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Terminal columns taken by a string, skipping ANSI escape sequences
+pub(super) fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            skip_escape(&mut chars);
+        } else {
+            width += char_width(c);
+        }
+    }
+    width
+}
+
+/// Text without its ANSI escape sequences
+pub(super) fn strip_escapes(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            skip_escape(&mut chars);
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// Cut a line to at most `max_width` columns, ending it with `…` when
+/// anything was removed
+///
+/// Escape sequences are kept, so colors stay balanced: a reset is appended
+/// whenever a colored line is cut.
+pub(super) fn truncate_to_width(line: &str, max_width: usize) -> String {
+    if display_width(line) <= max_width {
+        return line.to_string();
+    }
+    let budget = max_width.saturating_sub(1);
+    let mut output = String::new();
+    let mut width = 0;
+    let mut colored = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            output.push(c);
+            output.push_str(&skip_escape(&mut chars));
+            colored = true;
+            continue;
+        }
+        let char_width = char_width(c);
+        if width + char_width > budget {
+            break;
+        }
+        width += char_width;
+        output.push(c);
+    }
+
+    if max_width > 0 {
+        output.push('…');
+    }
+    if colored {
+        output.push_str("\x1b[0m");
+    }
+    output
+}
+
+/// Consume the rest of an escape sequence (after the ESC), returning it
+fn skip_escape(chars: &mut std::str::Chars) -> String {
+    let mut sequence = String::new();
+    let Some(c) = chars.next() else {
+        return sequence;
+    };
+    sequence.push(c);
+    match c {
+        '[' => {
+            // CSI: parameters, then a final byte in '@'..='~'
+            for c in chars.by_ref() {
+                sequence.push(c);
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+        ']' => {
+            // OSC (e.g. hyperlinks): ends with BEL or ESC '\'
+            while let Some(c) = chars.next() {
+                sequence.push(c);
+                if c == '\x07' {
+                    break;
+                }
+                if c == '\x1b' {
+                    sequence.extend(chars.next());
+                    break;
+                }
+            }
+        }
+        _ => {}
+    }
+    sequence
+}
+
+/// Width of the terminal attached to stdout, if any
+///
+/// Falls back to the `COLUMNS` environment variable.
+pub fn terminal_width() -> Option<usize> {
+    #[cfg(unix)]
+    {
+        // SAFETY: TIOCGWINSZ only writes into the winsize struct we pass
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if result == 0 && size.ws_col > 0 {
+            return Some(usize::from(size.ws_col));
+        }
+    }
+
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns: &usize| columns > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("\x1b[1;94mabc\x1b[0m"), 3);
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(display_width("📁 src"), 6);
+        assert_eq!(display_width("👁️ x"), 4);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(
+            display_width("\x1b]8;;file:///tmp/a\x1b\\a\x1b]8;;\x1b\\"),
+            1
+        );
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        assert_eq!(truncate_to_width("short", 10), "short");
+        assert_eq!(truncate_to_width("├── a-long-name.txt", 10), "├── a-lon…");
+        assert_eq!(truncate_to_width("日本語のファイル", 7), "日本語…");
+        assert_eq!(
+            truncate_to_width("\x1b[90m├── \x1b[0m\x1b[37mlong.txt\x1b[0m", 7),
+            "\x1b[90m├── \x1b[0m\x1b[37mlo…\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,84 @@
+//! Errors returned by the library
+//!
+//! Failures are grouped by kind, so callers can tell a missing file from a
+//! bad pattern or a malformed configuration without parsing messages.
+
+use std::io;
+
+/// Result type of the library's fallible functions
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Why a library call failed
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing the filesystem failed, or a program couldn't run
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Like `Io`, with what was being done, e.g. "reading .gitignore"
+    #[error("{context}")]
+    IoContext {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    /// A glob or format string is invalid
+    #[error("{0}")]
+    Pattern(String),
+    /// A configuration file, setting or argument is invalid, like an unknown
+    /// rule name or a path outside the scanned root
+    #[error("{0}")]
+    Config(String),
+    /// A snapshot, cache, manifest or image is malformed
+    #[error("{0}")]
+    Format(String),
+    /// An external program, like git, reported an error
+    #[error("{0}")]
+    Command(String),
+    /// The scan was cancelled before it finished
+    #[error("scan cancelled")]
+    Cancelled,
+}
+
+impl From<glob::PatternError> for Error {
+    fn from(error: glob::PatternError) -> Self {
+        Error::Pattern(error.to_string())
+    }
+}
+
+impl Error {
+    /// The underlying I/O error, if this is one
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Error::Io(source) | Error::IoContext { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Adds what was being done to I/O errors, like anyhow's `Context`
+pub(crate) trait WithContext<T> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T> WithContext<T> for io::Result<T> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|source| Error::IoContext {
+            context: context().into(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_context() {
+        let missing: io::Result<()> = Err(io::Error::from(io::ErrorKind::NotFound));
+        let error = missing.with_context(|| "reading config.toml").unwrap_err();
+        assert_eq!(error.to_string(), "reading config.toml");
+        assert_eq!(error.io_error().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+}
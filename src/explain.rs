@@ -0,0 +1,272 @@
+//! Decision tracing for a single path
+//!
+//! Runs the same pipeline the scanner uses (gitignore chain, filtering rules,
+//! threshold) for one path and records every step, so users can find out why
+//! an entry is shown, annotated or folded.
+
+use crate::error::{Error, Result};
+use crate::gitignore::{GitIgnoreContext, IgnoreMatch};
+use crate::rules::{FilterContext, FilterRegistry, RuleEvaluation};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Final display decision for an explained path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// Shown normally
+    Shown,
+    /// Shown with a filter annotation (files are never folded)
+    Annotated(String),
+    /// Directory is listed but its contents are folded
+    Folded(String),
+    /// Not shown because an ancestor directory is folded
+    HiddenByAncestor(PathBuf),
+}
+
+/// Per-path trace produced by [`explain_path`]
+#[derive(Debug, Clone)]
+pub struct PathExplanation {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub is_gitignored: bool,
+    pub gitignore_matches: Vec<IgnoreMatch>,
+    pub rule_evaluations: Vec<RuleEvaluation>,
+    pub threshold: Option<f32>,
+    /// Annotation of the winning rule, if any rule reached the threshold
+    pub filter_annotation: Option<String>,
+    pub decision: Decision,
+}
+
+/// Settings that influence the final display decision
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExplainOptions {
+    pub show_system_dirs: bool,
+    pub show_filtered: bool,
+}
+
+/// Explain how `path` (inside `root`) is treated by the scanner and display
+pub fn explain_path(
+    root: &Path,
+    path: &Path,
+    gitignore_ctx: &mut GitIgnoreContext,
+    rule_registry: Option<&FilterRegistry>,
+    options: ExplainOptions,
+) -> Result<PathExplanation> {
+    if !path.starts_with(root) {
+        return Err(Error::Config(format!(
+            "{} is not inside the scanned root {}",
+            path.display(),
+            root.display()
+        )));
+    }
+    let metadata = std::fs::symlink_metadata(path)?;
+
+    // Walk ancestors from the root down: a folded ancestor hides everything below it
+    let mut ancestor_fold = None;
+    for ancestor in path.ancestors().skip(1) {
+        if ancestor == root || !ancestor.starts_with(root) {
+            break;
+        }
+        let (ignored, filtered) = evaluate(root, ancestor, gitignore_ctx, rule_registry);
+        if should_fold(ignored, filtered.is_some(), options) {
+            ancestor_fold = Some(ancestor.to_path_buf());
+        }
+    }
+
+    let (is_gitignored, filter_annotation) = evaluate(root, path, gitignore_ctx, rule_registry);
+    let gitignore_matches = gitignore_ctx.trace(path);
+
+    let rule_evaluations = rule_registry
+        .map(|registry| {
+            let files = listed_files(path);
+            registry.evaluate_all(&rule_context(root, path, &files))
+        })
+        .unwrap_or_default();
+
+    let decision = if let Some(ancestor) = ancestor_fold {
+        Decision::HiddenByAncestor(ancestor)
+    } else if path == root {
+        // The explicitly requested root is never folded
+        Decision::Shown
+    } else if metadata.is_dir() && should_fold(is_gitignored, filter_annotation.is_some(), options)
+    {
+        let reason = filter_annotation
+            .clone()
+            .unwrap_or_else(|| String::from("[folded: system]"));
+        Decision::Folded(reason)
+    } else if let Some(annotation) = &filter_annotation {
+        Decision::Annotated(annotation.clone())
+    } else {
+        Decision::Shown
+    };
+
+    Ok(PathExplanation {
+        path: path.to_path_buf(),
+        is_dir: metadata.is_dir(),
+        is_gitignored,
+        gitignore_matches,
+        rule_evaluations,
+        threshold: rule_registry.map(|registry| registry.threshold()),
+        filter_annotation,
+        decision,
+    })
+}
+
+fn evaluate(
+    root: &Path,
+    path: &Path,
+    gitignore_ctx: &mut GitIgnoreContext,
+    rule_registry: Option<&FilterRegistry>,
+) -> (bool, Option<String>) {
+    let is_gitignored = gitignore_ctx.is_ignored(path);
+    let filter_annotation = rule_registry.and_then(|registry| {
+        let files = listed_files(path);
+        registry
+            .should_hide(&rule_context(root, path, &files))
+            .map(|verdict| verdict.annotation.to_string())
+    });
+    (is_gitignored, filter_annotation)
+}
+
+/// The context the scanner evaluates `path` in once it has listed it, given
+/// the files directly inside it
+fn rule_context<'a>(
+    root: &'a Path,
+    path: &'a Path,
+    files: &'a [(String, u64)],
+) -> FilterContext<'a> {
+    let parent = path.parent().unwrap_or(root);
+    let mut context = FilterContext::new(path, parent, root, depth_of(root, path));
+    context.detect_project_types();
+    context.is_dir = path.is_dir();
+    if context.is_dir {
+        context.files = Some(files);
+    }
+    context
+}
+
+/// Names and sizes of the files directly inside `dir`, none for a file
+fn listed_files(dir: &Path) -> Vec<(String, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = std::fs::metadata(entry.path()).ok()?;
+            metadata.is_file().then(|| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    metadata.len(),
+                )
+            })
+        })
+        .collect()
+}
+
+fn should_fold(is_gitignored: bool, is_filtered: bool, options: ExplainOptions) -> bool {
+    (is_gitignored && !options.show_system_dirs) || (is_filtered && !options.show_filtered)
+}
+
+fn depth_of(root: &Path, path: &Path) -> usize {
+    path.strip_prefix(root)
+        .map(|rel| rel.components().count())
+        .unwrap_or(0)
+}
+
+impl fmt::Display for PathExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.is_dir { "directory" } else { "file" };
+        writeln!(f, "{} ({})", self.path.display(), kind)?;
+
+        writeln!(f, "\ngitignore:")?;
+        if self.gitignore_matches.is_empty() {
+            writeln!(f, "  no matching patterns")?;
+        }
+        for m in &self.gitignore_matches {
+            let source = if m.system {
+                String::from("system defaults")
+            } else {
+                m.source.display().to_string()
+            };
+            let negation = if m.negated { "!" } else { "" };
+            writeln!(f, "  {}{}  (from {})", negation, m.pattern, source)?;
+        }
+        writeln!(f, "  => gitignored: {}", self.is_gitignored)?;
+
+        writeln!(f, "\nrules:")?;
+        match self.threshold {
+            None => writeln!(f, "  rules disabled")?,
+            Some(threshold) => {
+                for eval in &self.rule_evaluations {
+                    let result = if eval.disabled {
+                        String::from("disabled")
+                    } else if let Some(score) = eval.score {
+                        let verdict = if score >= threshold { "hide" } else { "keep" };
+                        format!("score {:.2} -> {} {}", score, verdict, eval.annotation)
+                    } else {
+                        String::from("does not apply")
+                    };
+                    writeln!(
+                        f,
+                        "  {:<16} (priority {:>3})  {}",
+                        eval.rule_id, eval.priority, result
+                    )?;
+                }
+                writeln!(f, "  threshold: {:.2}", threshold)?;
+            }
+        }
+
+        let decision = match &self.decision {
+            Decision::Shown => String::from("shown"),
+            Decision::Annotated(annotation) => format!("shown with annotation {}", annotation),
+            Decision::Folded(reason) => format!("folded {}", reason),
+            Decision::HiddenByAncestor(ancestor) => {
+                format!("hidden inside folded directory {}", ancestor.display())
+            }
+        };
+        write!(f, "\ndecision: {}", decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::create_default_registry;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_explain_folded_build_output() {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::write(root_path.join("Cargo.toml"), "[package]").unwrap();
+        fs::create_dir_all(root_path.join("target/debug")).unwrap();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let registry = create_default_registry(root_path).unwrap();
+
+        let target = root_path.join("target");
+        let explanation = explain_path(
+            root_path,
+            &target,
+            &mut ctx,
+            Some(&registry),
+            ExplainOptions::default(),
+        )
+        .unwrap();
+        assert!(explanation.is_gitignored);
+        assert!(matches!(explanation.decision, Decision::Folded(_)));
+
+        let nested = root_path.join("target/debug");
+        let explanation = explain_path(
+            root_path,
+            &nested,
+            &mut ctx,
+            Some(&registry),
+            ExplainOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(explanation.decision, Decision::HiddenByAncestor(target));
+    }
+}
@@ -0,0 +1,132 @@
+//! Working-tree status from `git status`, for `--git-status`
+//!
+//! Runs `git status --porcelain -z` in the scanned root and maps each
+//! reported path to its state. Directories collect the states of everything
+//! below them, so a folded directory still shows that it holds changes.
+
+use crate::error::{Error, Result, WithContext};
+use crate::types::{GitStatus, GitStatusMap};
+use std::path::Path;
+use std::process::Command;
+
+/// Status of every changed or untracked path below `root`, keyed like the
+/// scanned entries (`root` joined with the path relative to it)
+pub fn git_status(root: &Path) -> Result<GitStatusMap> {
+    // Porcelain paths are relative to the repository top; the prefix is
+    // where `root` sits inside it (e.g. "src/")
+    let prefix = run_git(root, &["rev-parse", "--show-prefix"])?;
+    let output = run_git(
+        root,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+    )?;
+    Ok(parse_porcelain(&output, prefix.trim_end(), root))
+}
+
+fn run_git(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .with_context(|| "running git")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Command(format!(
+            "git {}: {}",
+            args[0],
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `git status --porcelain -z` output
+///
+/// Paths outside `prefix` are skipped. Each record is `XY path`, where X is
+/// the index state and Y the working-tree state; renames and copies are
+/// followed by their source path as an extra record.
+pub(crate) fn parse_porcelain(output: &str, prefix: &str, root: &Path) -> GitStatusMap {
+    let mut statuses = GitStatusMap::new();
+    let mut records = output.split('\0').filter(|record| !record.is_empty());
+
+    while let Some(record) = records.next() {
+        let (Some(code), Some(path)) = (record.get(..2), record.get(3..)) else {
+            continue;
+        };
+        let mut code = code.chars();
+        let (index, worktree) = (code.next().unwrap_or(' '), code.next().unwrap_or(' '));
+        if matches!(index, 'R' | 'C') {
+            records.next();
+        }
+
+        let status = match (index, worktree) {
+            ('?', '?') => GitStatus {
+                untracked: true,
+                ..GitStatus::default()
+            },
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => GitStatus {
+                conflicted: true,
+                ..GitStatus::default()
+            },
+            _ => GitStatus {
+                staged: index != ' ',
+                modified: worktree != ' ',
+                ..GitStatus::default()
+            },
+        };
+
+        let Some(relative) = Path::new(path).strip_prefix(prefix).ok() else {
+            continue;
+        };
+        // Mark the entry and every directory leading to it
+        for ancestor in relative.ancestors() {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            statuses
+                .entry(root.join(ancestor))
+                .or_default()
+                .merge(status);
+        }
+    }
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_porcelain() {
+        let output = "M  src/staged.rs\0 M src/edited.rs\0MM both.rs\0?? src/new/file.txt\0\
+                      R  src/renamed.rs\0src/old.rs\0UU conflict.rs\0";
+        let root = Path::new("repo");
+        let statuses = parse_porcelain(output, "", root);
+        let status = |path: &str| statuses.get(&PathBuf::from("repo").join(path)).copied();
+
+        assert_eq!(status("src/staged.rs").unwrap().labels(), vec!["staged"]);
+        assert_eq!(status("src/edited.rs").unwrap().labels(), vec!["modified"]);
+        assert_eq!(
+            status("both.rs").unwrap().labels(),
+            vec!["staged", "modified"]
+        );
+        assert_eq!(
+            status("src/new/file.txt").unwrap().labels(),
+            vec!["untracked"]
+        );
+        assert_eq!(status("src/new").unwrap().labels(), vec!["untracked"]);
+        assert_eq!(status("src/renamed.rs").unwrap().labels(), vec!["staged"]);
+        assert_eq!(status("src/old.rs"), None);
+        assert_eq!(status("conflict.rs").unwrap().labels(), vec!["conflict"]);
+        assert_eq!(
+            status("src").unwrap().labels(),
+            vec!["staged", "modified", "untracked"]
+        );
+
+        // Only paths below the scanned subdirectory, keyed relative to it
+        let statuses = parse_porcelain(output, "src/", root);
+        assert!(statuses.contains_key(&PathBuf::from("repo/staged.rs")));
+        assert!(!statuses.contains_key(&PathBuf::from("repo/both.rs")));
+    }
+}
@@ -1,19 +1,195 @@
-use anyhow::Result;
-use glob::Pattern;
-use log::{debug, trace};
+use crate::error::Result;
+use crate::hgignore::parse_hgignore;
+use crate::wildmatch::Wildmatch;
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{debug, trace};
+
+/// Glob options matching git: wildcards never cross a `/`
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Directories folded as system directories, at any depth
+// Consider making this configurable or customizing for the domain
+const SYSTEM_DIRS: &[&str] = &[
+    // Version control
+    ".git",
+    ".svn",
+    ".hg",
+    ".jj",
+    // OS files
+    ".DS_Store",
+    "Thumbs.db",
+    // IDE and editors
+    ".idea",
+    ".vscode",
+    ".zed",
+    // Programming languages
+    "__pycache__",  // Python
+    "venv",         // Python
+    ".venv",        // Python
+    "node_modules", // Node.js
+    "target",       // Rust
+    "build",        // Generic build
+    "dist",         // Generic distribution
+    ".gradle",      // Gradle
+    ".next",        // Next.js
+    ".nuxt",        // Nuxt.js
+];
+
+/// The built-in system patterns, compiled
+fn system_patterns() -> Vec<Wildmatch> {
+    SYSTEM_DIRS
+        .iter()
+        .map(|name| Wildmatch::new(name, false))
+        .collect()
+}
 
 /// A struct representing individual gitignore rules for a specific directory
 #[derive(Clone)]
 pub struct GitIgnore {
     // System default patterns are always treated as "ignore"
-    pub system_patterns: Vec<Pattern>,
-    // Regular gitignore patterns
-    pub patterns: Vec<(Pattern, bool)>, // (pattern, is_negated)
+    pub system_patterns: Vec<Wildmatch>,
+    // Regular gitignore patterns, in file order
+    pub patterns: Vec<IgnorePattern>,
     // Whether this is a root-level gitignore
     pub is_root: bool,
+    // Directory containing the .gitignore; patterns match paths relative to it
+    pub base_dir: PathBuf,
+    // File the patterns were read from
+    pub source: PathBuf,
+}
+
+/// A single pattern from a .gitignore file
+#[derive(Clone, Debug)]
+pub struct IgnorePattern {
+    pub matcher: Matcher,
+    // Pattern started with `!` and re-includes what it matches
+    pub negated: bool,
+    // Pattern ended with `/` and only matches directories
+    pub dir_only: bool,
+}
+
+/// How a pattern matches paths relative to its ignore file's directory
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    /// A gitignore pattern (`.gitignore`, `.dockerignore`)
+    Wildmatch(Wildmatch),
+    /// A glob whose wildcards don't cross `/`
+    Glob(Pattern),
+    /// A regular expression found anywhere in the path (`.hgignore`)
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn matches(&self, relative: &str) -> bool {
+        match self {
+            Matcher::Wildmatch(pattern) => pattern.matches(relative),
+            Matcher::Glob(glob) => glob.matches_with(relative, MATCH_OPTIONS),
+            Matcher::Regex(regex) => regex.is_match(relative),
+        }
+    }
+
+    /// Whether the pattern might match something inside `dir`, a path
+    /// relative to the ignore file's directory
+    fn may_match_below(&self, dir: &str) -> bool {
+        match self {
+            Matcher::Wildmatch(pattern) => pattern.may_match_below(dir),
+            // Only .gitignore and .dockerignore patterns can be negated
+            _ => false,
+        }
+    }
+
+    /// The compiled pattern, as shown by `why` and `--rule-debug`
+    pub fn as_str(&self) -> &str {
+        match self {
+            Matcher::Wildmatch(pattern) => pattern.as_str(),
+            Matcher::Glob(glob) => glob.as_str(),
+            Matcher::Regex(regex) => regex.as_str(),
+        }
+    }
+}
+
+impl IgnorePattern {
+    /// Parse one line of a .gitignore file, or None for blanks, comments and
+    /// invalid patterns
+    fn parse(line: &str) -> Option<Self> {
+        let line = trim_trailing_spaces(line);
+
+        // Skip empty lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        // Handle negated patterns (those starting with !)
+        let negated = line.starts_with('!');
+        let pattern = if negated { &line[1..] } else { line };
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.strip_suffix('/').unwrap_or(pattern);
+
+        // A slash at the start or in the middle anchors the pattern to the
+        // directory of the .gitignore; otherwise it matches at any depth
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+
+        trace!(
+            "Added gitignore pattern: {} (negated: {}, dir only: {}, anchored: {})",
+            pattern,
+            negated,
+            dir_only,
+            anchored
+        );
+        Some(IgnorePattern {
+            matcher: Matcher::Wildmatch(Wildmatch::new(pattern, anchored)),
+            negated,
+            dir_only,
+        })
+    }
+
+    /// Parse one line of a .dockerignore file
+    ///
+    /// Unlike .gitignore, every pattern is anchored at the build context
+    /// root, and a trailing slash is dropped rather than limiting the
+    /// pattern to directories.
+    fn parse_docker(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let negated = line.starts_with('!');
+        let pattern = if negated { &line[1..] } else { line };
+        let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(IgnorePattern {
+            matcher: Matcher::Wildmatch(Wildmatch::new(pattern, true)),
+            negated,
+            dir_only: false,
+        })
+    }
+}
+
+/// Ignore files a context applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoreFile {
+    /// `.gitignore` files at every level, and git's excludes files
+    Git,
+    /// The `.dockerignore` at the root, as `docker build` reads it
+    Docker,
 }
 
 impl GitIgnore {
@@ -23,177 +199,106 @@ impl GitIgnore {
             system_patterns: Vec::new(),
             patterns: Vec::new(),
             is_root,
+            base_dir: PathBuf::new(),
+            source: PathBuf::new(),
         }
     }
 
     /// Load gitignore patterns from the specified root directory
     pub fn load(root: &Path) -> Result<Self> {
-        // System defaults that should always be included
-        let system_patterns = vec![
-            ".git",
-            ".DS_Store",
-            ".svn",         // SVN version control
-            ".hg",          // Mercurial version control
-            ".idea",        // IntelliJ IDE
-            ".vscode",      // VS Code
-            "__pycache__",  // Python cache
-            "node_modules", // Node.js dependencies
-            "target",       // Rust build directory
-            "build",        // Common build directory
-            "dist",         // Common distribution directory
-        ]
-        .into_iter()
-        .map(|p| Pattern::new(&format!("**/{}", p)))
-        .collect::<Result<Vec<_>, _>>()?;
-
         let mut patterns = Vec::new();
 
         // Add patterns from .gitignore if it exists
         let gitignore_path = root.join(".gitignore");
         if gitignore_path.exists() {
             debug!("Loading gitignore patterns from {:?}", gitignore_path);
-            let content = fs::read_to_string(gitignore_path)?;
-
-            for line in content.lines() {
-                let line = line.trim();
-
-                // Skip empty lines and comments
-                if line.is_empty() || line.starts_with('#') {
-                    continue;
-                }
-
-                // Handle negated patterns (those starting with !)
-                let is_negated = line.starts_with('!');
-                let pattern = if is_negated { &line[1..] } else { line };
-
-                // Convert pattern to glob format
-                let glob_pattern = convert_to_glob_pattern(pattern);
-
-                match Pattern::new(&glob_pattern) {
-                    Ok(compiled) => {
-                        trace!(
-                            "Added gitignore pattern: {} (negated: {})",
-                            glob_pattern,
-                            is_negated
-                        );
-                        patterns.push((compiled, is_negated));
-                    }
-                    Err(e) => {
-                        debug!("Invalid gitignore pattern '{}': {}", pattern, e);
-                    }
-                }
-            }
+            let content = fs::read_to_string(&gitignore_path)?;
+            patterns.extend(content.lines().filter_map(IgnorePattern::parse));
         }
 
         Ok(GitIgnore {
-            system_patterns,
+            // System defaults that should always be included
+            system_patterns: system_patterns(),
             patterns,
             is_root: true,
+            base_dir: root.to_path_buf(),
+            source: gitignore_path,
         })
     }
 
     /// Check if the given path should be ignored according to gitignore rules
+    ///
+    /// Everything below an ignored directory is ignored as well, as in git.
     pub fn is_ignored(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
+        path.ancestors()
+            .take_while(|ancestor| *ancestor != self.base_dir)
+            .any(|ancestor| self.verdict_with(ancestor, true) == Some(true))
+    }
+
+    /// Like `verdict`, with system patterns checked only when asked to
+    fn verdict_with(&self, path: &Path, check_system: bool) -> Option<bool> {
+        let relative = self.relative_path(path)?;
 
         // First check system patterns (these always ignore)
-        for pattern in &self.system_patterns {
-            if pattern.matches(&path_str) {
-                trace!("Path {:?} matched system pattern", path);
-                return true;
-            }
+        if check_system && self.matches_system(&relative) {
+            trace!("Path {:?} matched system pattern", path);
+            return Some(true);
         }
+        self.matches_patterns(path, &relative)
+    }
 
-        // Now check regular patterns, with negation support
-        let mut ignored = false;
+    /// The path relative to this gitignore's directory, with `/` separators,
+    /// or None for paths outside it
+    fn relative_path(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.base_dir).ok()?;
+        let parts: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("/"))
+    }
 
-        for (pattern, is_negated) in &self.patterns {
-            if pattern.matches(&path_str) {
-                trace!(
-                    "Path {:?} matched pattern {} (negated: {})",
-                    path,
-                    pattern,
-                    is_negated
-                );
+    fn matches_system(&self, relative: &str) -> bool {
+        self.system_patterns
+            .iter()
+            .any(|pattern| pattern.matches(relative))
+    }
 
-                // Negated patterns override previous matches
-                ignored = !is_negated;
+    /// Verdict of the last regular pattern matching the path: Some(true) if
+    /// it ignores, Some(false) if it is a negation, None if none match
+    fn matches_patterns(&self, path: &Path, relative: &str) -> Option<bool> {
+        let mut is_dir = None;
+        let mut verdict = None;
+        for pattern in &self.patterns {
+            if !pattern.matcher.matches(relative) {
+                continue;
             }
-        }
+            // Only look at the filesystem when a directory-only pattern matches
+            if pattern.dir_only && !*is_dir.get_or_insert_with(|| path.is_dir()) {
+                continue;
+            }
+            trace!(
+                "Path {:?} matched pattern {} (negated: {})",
+                path,
+                pattern.matcher.as_str(),
+                pattern.negated
+            );
 
-        ignored
+            // Later patterns override earlier ones
+            verdict = Some(!pattern.negated);
+        }
+        verdict
     }
 
     /// Load gitignore patterns from a specific gitignore file
     pub fn load_from_file(gitignore_path: &Path, is_root: bool) -> Result<Self> {
-        let mut patterns = Vec::new();
-
         debug!("Loading gitignore patterns from {:?}", gitignore_path);
         let content = fs::read_to_string(gitignore_path)?;
-
-        for line in content.lines() {
-            let line = line.trim();
-
-            // Skip empty lines and comments
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-
-            // Handle negated patterns (those starting with !)
-            let is_negated = line.starts_with('!');
-            let pattern = if is_negated { &line[1..] } else { line };
-
-            // Convert pattern to glob format
-            let glob_pattern = convert_to_glob_pattern(pattern);
-
-            match Pattern::new(&glob_pattern) {
-                Ok(compiled) => {
-                    trace!(
-                        "Added gitignore pattern: {} (negated: {})",
-                        glob_pattern,
-                        is_negated
-                    );
-                    patterns.push((compiled, is_negated));
-                }
-                Err(e) => {
-                    debug!("Invalid gitignore pattern '{}': {}", pattern, e);
-                }
-            }
-        }
+        let patterns = content.lines().filter_map(IgnorePattern::parse).collect();
 
         // System defaults are only initialized for the root gitignore
         let system_patterns = if is_root {
-            // Consider making this configurable or customizing for the domain
-            vec![
-                // Version control
-                ".git",
-                ".svn",
-                ".hg",
-                ".jj",
-                // OS files
-                ".DS_Store",
-                "Thumbs.db",
-                // IDE and editors
-                ".idea",
-                ".vscode",
-                ".zed",
-                // Programming languages
-                "__pycache__",  // Python
-                "venv",         // Python
-                ".venv",        // Python
-                "node_modules", // Node.js
-                "target",       // Rust
-                "build",        // Generic build
-                "dist",         // Generic distribution
-                "out",          // Generic output
-                "bin",          // Generic binaries
-                ".gradle",      // Gradle
-                ".next",        // Next.js
-                ".nuxt",        // Nuxt.js
-            ]
-            .into_iter()
-            .map(|p| Pattern::new(&format!("**/{}", p)))
-            .collect::<Result<Vec<_>, _>>()?
+            system_patterns()
         } else {
             Vec::new()
         };
@@ -202,149 +307,423 @@ impl GitIgnore {
             system_patterns,
             patterns,
             is_root,
+            base_dir: gitignore_path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            source: gitignore_path.to_path_buf(),
+        })
+    }
+
+    /// Load the patterns of a .dockerignore file
+    pub fn load_dockerignore(dockerignore_path: &Path) -> Result<Self> {
+        debug!("Loading dockerignore patterns from {:?}", dockerignore_path);
+        let content = fs::read_to_string(dockerignore_path)?;
+        Ok(GitIgnore {
+            system_patterns: Vec::new(),
+            patterns: content
+                .lines()
+                .filter_map(IgnorePattern::parse_docker)
+                .collect(),
+            is_root: false,
+            base_dir: dockerignore_path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            source: dockerignore_path.to_path_buf(),
+        })
+    }
+
+    /// Load the patterns of a Mercurial .hgignore file
+    pub fn load_hgignore(hgignore_path: &Path) -> Result<Self> {
+        debug!("Loading hgignore patterns from {:?}", hgignore_path);
+        let content = fs::read_to_string(hgignore_path)?;
+        Ok(GitIgnore {
+            system_patterns: Vec::new(),
+            patterns: parse_hgignore(&content),
+            is_root: false,
+            base_dir: hgignore_path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            source: hgignore_path.to_path_buf(),
         })
     }
 
     /// Check if the given path should be ignored according to this specific gitignore
     pub fn is_path_ignored(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
+        self.verdict(path) == Some(true)
+    }
+
+    /// Whether this gitignore ignores (Some(true)) or re-includes (Some(false))
+    /// the path, or None when none of its patterns apply
+    pub fn verdict(&self, path: &Path) -> Option<bool> {
+        // System patterns always ignore, but only for the root gitignore
+        self.verdict_with(path, self.is_root)
+    }
+
+    /// Whether a negated pattern of this file might re-include something
+    /// inside the directory `dir`
+    fn may_reinclude_below(&self, dir: &Path) -> bool {
+        let Some(relative) = self.relative_path(dir) else {
+            return false;
+        };
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.negated && pattern.matcher.may_match_below(&relative))
+    }
+
+    /// Every pattern of this gitignore that matches the path
+    fn matching_patterns(&self, path: &Path) -> Vec<IgnoreMatch> {
+        let Some(relative) = self.relative_path(path) else {
+            return Vec::new();
+        };
+        let mut matches = Vec::new();
 
-        // First check system patterns (these always ignore, but only for root gitignore)
         if self.is_root {
             for pattern in &self.system_patterns {
-                if pattern.matches(&path_str) {
-                    trace!("Path {:?} matched system pattern", path);
-                    return true;
+                if pattern.matches(&relative) {
+                    matches.push(IgnoreMatch {
+                        source: self.source.clone(),
+                        pattern: pattern.as_str().to_string(),
+                        negated: false,
+                        system: true,
+                    });
                 }
             }
         }
 
-        // Now check regular patterns, with negation support
-        let mut ignored = false;
-
-        for (pattern, is_negated) in &self.patterns {
-            if pattern.matches(&path_str) {
-                trace!(
-                    "Path {:?} matched pattern {} (negated: {})",
-                    path,
-                    pattern,
-                    is_negated
-                );
-
-                // Negated patterns override previous matches
-                ignored = !is_negated;
+        for pattern in &self.patterns {
+            if pattern.matcher.matches(&relative) && (!pattern.dir_only || path.is_dir()) {
+                matches.push(IgnoreMatch {
+                    source: self.source.clone(),
+                    pattern: pattern.matcher.as_str().to_string(),
+                    negated: pattern.negated,
+                    system: false,
+                });
             }
         }
-
-        ignored
+        matches
     }
 }
 
-/// A context that manages multiple .gitignore files throughout a directory structure
-#[derive(Clone)]
-pub struct GitIgnoreContext {
+/// A single gitignore pattern that matched a path
+#[derive(Debug, Clone)]
+pub struct IgnoreMatch {
+    /// Ignore file that contributed the pattern
+    pub source: PathBuf,
+    /// The compiled glob pattern
+    pub pattern: String,
+    /// Whether the pattern is a negation (`!pattern`)
+    pub negated: bool,
+    /// Whether the pattern is one of the built-in system patterns
+    pub system: bool,
+}
+
+/// The ignore files of a directory tree, loaded once and shared
+///
+/// A scan reads each `.gitignore` through the engine at most once, however
+/// many contexts and rules ask about the paths below it. Contexts wrapping
+/// the same engine give the same answers, except for the system patterns
+/// each of them applies on its own.
+pub struct IgnoreEngine {
     // Base directory for relative path calculations
     root_dir: PathBuf,
-    // Cache of gitignore rules by directory
-    gitignores: HashMap<PathBuf, GitIgnore>,
-    // Cache of already computed ignore status for paths
-    ignore_cache: HashMap<PathBuf, bool>,
+    // Ignore files the engine applies
+    files: Vec<IgnoreFile>,
+    // Global excludes and .git/info/exclude, lowest precedence first
+    excludes: Vec<Arc<GitIgnore>>,
+    // The root .dockerignore, when it is applied
+    dockerignore: Option<Arc<GitIgnore>>,
+    // Built-in system patterns, applied by the contexts that want them
+    system_patterns: Vec<Wildmatch>,
+    // .gitignore files loaded so far by directory, None where there is none
+    gitignores: RwLock<HashMap<PathBuf, Option<Arc<GitIgnore>>>>,
 }
 
-impl GitIgnoreContext {
-    /// Create a new GitIgnoreContext from a root directory
-    pub fn new(root: &Path) -> Result<Self> {
-        let mut ctx = GitIgnoreContext {
+impl IgnoreEngine {
+    /// Create an engine applying the given ignore files below `root`
+    pub fn new(root: &Path, files: &[IgnoreFile]) -> Result<Self> {
+        let read_gitignores = files.contains(&IgnoreFile::Git);
+        let mut engine = IgnoreEngine {
             root_dir: root.to_path_buf(),
-            gitignores: HashMap::new(),
-            ignore_cache: HashMap::new(),
+            files: files.to_vec(),
+            excludes: if read_gitignores {
+                load_excludes(root).into_iter().map(Arc::new).collect()
+            } else {
+                Vec::new()
+            },
+            dockerignore: None,
+            system_patterns: Vec::new(),
+            gitignores: RwLock::new(HashMap::new()),
         };
 
+        let dockerignore_path = root.join(".dockerignore");
+        if files.contains(&IgnoreFile::Docker) && dockerignore_path.is_file() {
+            engine.dockerignore = Some(Arc::new(GitIgnore::load_dockerignore(&dockerignore_path)?));
+        }
+
         // Load root .gitignore if it exists
         let root_gitignore_path = root.join(".gitignore");
-        if root_gitignore_path.exists() {
-            let gitignore = GitIgnore::load_from_file(&root_gitignore_path, true)?;
-            ctx.gitignores.insert(root.to_path_buf(), gitignore);
+        let root_gitignore = if read_gitignores && root_gitignore_path.exists() {
+            let mut gitignore = GitIgnore::load_from_file(&root_gitignore_path, true)?;
+            engine.system_patterns = std::mem::take(&mut gitignore.system_patterns);
+            Some(Arc::new(gitignore))
         } else {
-            // Create an empty root gitignore with just system patterns
-            let system_patterns = vec![
-                ".git",
-                ".DS_Store",
-                ".svn",
-                ".hg",
-                ".idea",
-                ".vscode",
-                ".zed",
-                "__pycache__",
-                "node_modules",
-                "target",
-                "build",
-                "dist",
-            ]
-            .into_iter()
-            .map(|p| Pattern::new(&format!("**/{}", p)))
-            .collect::<Result<Vec<_>, _>>()?;
-
-            ctx.gitignores.insert(
-                root.to_path_buf(),
-                GitIgnore {
-                    system_patterns,
-                    patterns: Vec::new(),
-                    is_root: true,
-                },
-            );
+            // Without a root .gitignore only the system patterns apply
+            engine.system_patterns = system_patterns();
+            None
+        };
+        engine
+            .gitignores
+            .get_mut()
+            .unwrap()
+            .insert(root.to_path_buf(), root_gitignore);
+
+        Ok(engine)
+    }
+
+    /// An engine that reads no ignore files and has no system patterns
+    pub fn disabled(root: &Path) -> Self {
+        IgnoreEngine {
+            root_dir: root.to_path_buf(),
+            files: Vec::new(),
+            excludes: Vec::new(),
+            dockerignore: None,
+            system_patterns: Vec::new(),
+            gitignores: RwLock::new(HashMap::from([(root.to_path_buf(), None)])),
         }
+    }
 
-        Ok(ctx)
+    /// The directory the engine's ignore files apply below
+    pub fn root(&self) -> &Path {
+        &self.root_dir
     }
 
-    /// Process a directory, loading its .gitignore file if any
-    pub fn process_directory(&mut self, dir_path: &Path) -> Result<()> {
-        // Skip if we've already processed this directory
-        if self.gitignores.contains_key(dir_path) {
-            return Ok(());
+    /// The ignore files the engine applies
+    pub fn files(&self) -> &[IgnoreFile] {
+        &self.files
+    }
+
+    /// The .gitignore of `dir`, read on first use
+    ///
+    /// A file that can't be read is tried again next time. Threads asking
+    /// for the same directory at once may both read it, one copy is kept.
+    fn gitignore(&self, dir: &Path) -> Result<Option<Arc<GitIgnore>>> {
+        if let Some(gitignore) = self.gitignores.read().unwrap().get(dir) {
+            return Ok(gitignore.clone());
         }
 
-        // Check for a .gitignore file in this directory
-        let gitignore_path = dir_path.join(".gitignore");
-        if gitignore_path.exists() {
-            let is_root = dir_path == self.root_dir;
-            let gitignore = GitIgnore::load_from_file(&gitignore_path, is_root)?;
-            self.gitignores.insert(dir_path.to_path_buf(), gitignore);
+        let gitignore_path = dir.join(".gitignore");
+        let gitignore = if self.files.contains(&IgnoreFile::Git) && gitignore_path.exists() {
+            let is_root = dir == self.root_dir;
+            Some(Arc::new(GitIgnore::load_from_file(
+                &gitignore_path,
+                is_root,
+            )?))
+        } else {
+            None
+        };
+        self.gitignores
+            .write()
+            .unwrap()
+            .insert(dir.to_path_buf(), gitignore.clone());
+        Ok(gitignore)
+    }
+}
+
+/// A context that manages multiple .gitignore files throughout a directory structure
+///
+/// The ignore files come from an [`IgnoreEngine`] that other contexts may
+/// share, while the system patterns and the cache of answers are the
+/// context's own. Its questions can be asked from several threads at once.
+#[derive(Clone)]
+pub struct GitIgnoreContext {
+    // Loaded ignore files, shared with other contexts of the same tree
+    engine: Arc<IgnoreEngine>,
+    // System patterns this context applies, anchored at the root
+    system: Arc<GitIgnore>,
+    // Cache of already computed ignore status for paths
+    ignore_cache: VerdictCache,
+}
+
+impl GitIgnoreContext {
+    /// Create a new GitIgnoreContext from a root directory
+    pub fn new(root: &Path) -> Result<Self> {
+        Self::with_ignore_files(root, &[IgnoreFile::Git])
+    }
+
+    /// Create a context applying the given ignore files
+    ///
+    /// The built-in system patterns apply either way. A `.dockerignore` adds
+    /// to what the `.gitignore` files ignore, it can't re-include anything.
+    pub fn with_ignore_files(root: &Path, files: &[IgnoreFile]) -> Result<Self> {
+        Ok(Self::with_engine(Arc::new(IgnoreEngine::new(root, files)?)))
+    }
+
+    /// Create a context on the ignore files of a shared engine, applying
+    /// the engine's system patterns
+    pub fn with_engine(engine: Arc<IgnoreEngine>) -> Self {
+        let mut system = GitIgnore::empty(true);
+        system.system_patterns = engine.system_patterns.clone();
+        system.base_dir = engine.root_dir.clone();
+        system.source = engine.root_dir.join(".gitignore");
+        GitIgnoreContext {
+            engine,
+            system: Arc::new(system),
+            ignore_cache: VerdictCache::default(),
         }
+    }
 
-        Ok(())
+    /// A pass-through context that ignores nothing: no ignore files are
+    /// read and no system directories are folded
+    pub fn disabled(root: &Path) -> Self {
+        Self::with_engine(Arc::new(IgnoreEngine::disabled(root)))
+    }
+
+    /// The engine holding the context's ignore files
+    pub fn engine(&self) -> &Arc<IgnoreEngine> {
+        &self.engine
+    }
+
+    /// The ignore files applied and the system directories folded, for
+    /// telling apart scans made with different ones
+    pub(crate) fn settings(&self) -> String {
+        let system_patterns: Vec<&str> = self
+            .system
+            .system_patterns
+            .iter()
+            .map(Wildmatch::as_str)
+            .collect();
+        format!("{:?} {:?}", self.engine.files, system_patterns)
+    }
+
+    /// Stop folding the named system directories, e.g. because the rule
+    /// covering them was disabled
+    pub fn unignore_system_dirs(&mut self, names: &[&str]) {
+        Arc::make_mut(&mut self.system)
+            .system_patterns
+            .retain(|pattern| !names.contains(&pattern.as_str()));
+        self.ignore_cache.clear();
+    }
+
+    /// Drop every built-in system pattern, keeping only the ignore files
+    pub fn clear_system_dirs(&mut self) {
+        Arc::make_mut(&mut self.system).system_patterns.clear();
+        self.ignore_cache.clear();
+    }
+
+    /// Process a directory, loading its .gitignore file if any
+    pub fn process_directory(&self, dir_path: &Path) -> Result<()> {
+        self.engine.gitignore(dir_path).map(|_| ())
     }
 
     /// Check if a path is ignored by any applicable gitignore in its hierarchy
-    pub fn is_ignored(&mut self, path: &Path) -> bool {
+    pub fn is_ignored(&self, path: &Path) -> bool {
         // Check cache first
-        if let Some(&cached) = self.ignore_cache.get(path) {
+        if let Some(cached) = self.ignore_cache.get(path) {
             return cached;
         }
 
-        // Process the directory containing this path
-        let parent_dir = if path.is_dir() {
-            path.to_path_buf()
-        } else {
-            path.parent()
-                .unwrap_or_else(|| Path::new("."))
-                .to_path_buf()
-        };
+        // Everything below an ignored directory is ignored, unless a
+        // negated pattern re-includes it
+        let root_dir = self.engine.root_dir.clone();
+        let mut parent_ignored = false;
+        if let Some(parent) = path.parent() {
+            if parent != root_dir && parent.starts_with(&root_dir) && self.is_ignored(parent) {
+                if !self.may_reinclude_below(parent) {
+                    self.ignore_cache.insert(path, true);
+                    return true;
+                }
+                parent_ignored = true;
+            }
+        }
 
-        // Make sure we've processed this directory
-        if let Err(e) = self.process_directory(&parent_dir) {
-            debug!("Error processing directory {:?}: {}", parent_dir, e);
-            // Continue execution even if processing fails
+        // Excludes come first; deeper .gitignore files override the verdicts
+        // of shallower ones
+        let mut is_ignored = parent_ignored;
+        for gitignore in self.sources(&containing_dir(path)) {
+            if let Some(verdict) = gitignore.verdict(path) {
+                is_ignored = verdict;
+            }
+        }
+        if let Some(dockerignore) = &self.engine.dockerignore {
+            match dockerignore.verdict(path) {
+                Some(true) => is_ignored = true,
+                // Alone, the .dockerignore can re-include what it ignored
+                Some(false) if !self.engine.files.contains(&IgnoreFile::Git) => is_ignored = false,
+                _ => {}
+            }
         }
 
-        // Build the chain of parent directories to check
+        // Cache the result
+        self.ignore_cache.insert(path, is_ignored);
+        is_ignored
+    }
+
+    /// Whether a negated pattern might re-include something inside the
+    /// ignored directory `dir`, so it has to be looked into
+    ///
+    /// Git never looks inside an ignored directory, but smart-tree does, so
+    /// `logs/` with `!logs/important.log` still shows the one file. Built-in
+    /// system directories are never looked into.
+    pub fn may_reinclude_below(&self, dir: &Path) -> bool {
+        if self
+            .system
+            .relative_path(dir)
+            .is_some_and(|relative| self.system.matches_system(&relative))
+        {
+            return false;
+        }
+
+        self.sources(dir)
+            .iter()
+            .chain(&self.engine.dockerignore)
+            .any(|gitignore| gitignore.may_reinclude_below(dir))
+    }
+
+    /// List every pattern in the gitignore hierarchy that matches the path,
+    /// ordered from the excludes files and root .gitignore down to the
+    /// closest one
+    pub fn trace(&self, path: &Path) -> Vec<IgnoreMatch> {
+        self.sources(&containing_dir(path))
+            .iter()
+            .chain(&self.engine.dockerignore)
+            .flat_map(|gitignore| gitignore.matching_patterns(path))
+            .collect()
+    }
+
+    /// The ignore files applying inside `dir`, lowest precedence first: the
+    /// excludes, then every .gitignore from the root down to `dir`, each
+    /// relative to its own location
+    ///
+    /// The system patterns follow the root .gitignore, which can't
+    /// re-include what they ignore.
+    fn sources(&self, dir: &Path) -> Vec<Arc<GitIgnore>> {
+        let mut sources = self.engine.excludes.clone();
+        for chain_dir in self.dir_chain(dir) {
+            let gitignore = self.engine.gitignore(&chain_dir).unwrap_or_else(|e| {
+                debug!("Error processing directory {:?}: {}", chain_dir, e);
+                // Continue execution even if processing fails
+                None
+            });
+            sources.extend(gitignore);
+            if chain_dir == self.engine.root_dir {
+                sources.push(self.system.clone());
+            }
+        }
+        sources
+    }
+
+    /// Build the chain of directories from the root down to `dir`
+    fn dir_chain(&self, dir: &Path) -> Vec<PathBuf> {
+        let root_dir = &self.engine.root_dir;
         let mut dir_chain = Vec::new();
-        let mut current = parent_dir.clone();
+        let mut current = dir.to_path_buf();
 
         loop {
             dir_chain.push(current.clone());
-            if current == self.root_dir || !current.starts_with(&self.root_dir) {
+            if current == *root_dir || !current.starts_with(root_dir) {
                 break;
             }
 
@@ -357,28 +736,7 @@ impl GitIgnoreContext {
 
         // Check gitignores from root to the directory
         dir_chain.reverse();
-
-        // Determine if the path is ignored
-        let mut is_ignored = false;
-        for dir in &dir_chain {
-            if let Some(gitignore) = self.gitignores.get(dir) {
-                // Only override the previous result if this gitignore specifically matches
-                if gitignore.is_path_ignored(path) {
-                    is_ignored = true;
-                }
-
-                // Special case for negated patterns - they should override previous ignores
-                for (pattern, is_negated) in &gitignore.patterns {
-                    if *is_negated && pattern.matches(&path.to_string_lossy()) {
-                        is_ignored = false;
-                    }
-                }
-            }
-        }
-
-        // Cache the result
-        self.ignore_cache.insert(path.to_path_buf(), is_ignored);
-        is_ignored
+        dir_chain
     }
 
     /// Helper method for backward compatibility with the old API
@@ -387,43 +745,212 @@ impl GitIgnoreContext {
     }
 }
 
-/// Converts a gitignore pattern to a glob pattern
+/// Number of separately locked parts of a verdict cache
+const CACHE_SHARDS: usize = 16;
+
+/// Verdicts a shard holds before it is emptied
+const CACHE_SHARD_CAPACITY: usize = 4096;
+
+/// Ignore verdicts by path, split into shards so that threads asking about
+/// different paths rarely wait for each other
 ///
-/// Handles some common gitignore syntax rules:
-/// - Adds ** prefix/suffix where needed
-/// - Handles directory-specific patterns (ending with /)
-/// - Adjusts path anchoring for absolute patterns
-fn convert_to_glob_pattern(pattern: &str) -> String {
-    // Remove trailing slash for directory patterns
-    let pattern = if let Some(stripped) = pattern.strip_suffix('/') {
-        stripped
-    } else {
-        pattern
-    };
-
-    // Handle patterns with wildcards
-    if pattern.contains('*') || pattern.contains('?') || pattern.contains('[') {
-        if let Some(stripped) = pattern.strip_prefix('/') {
-            // Pattern starts with / - anchored to project root
-            stripped.to_string()
-        } else {
-            // Pattern doesn't start with / - match anywhere in subtree
-            format!("**/{}", pattern)
+/// The cache is bounded, so a traversal that keeps no tree, like
+/// `scan_iter`, doesn't grow it with the whole tree. A full shard is emptied;
+/// the verdicts dropped are worked out again when asked for.
+#[derive(Default)]
+struct VerdictCache {
+    shards: [Mutex<HashMap<PathBuf, bool>>; CACHE_SHARDS],
+}
+
+impl VerdictCache {
+    fn shard(&self, path: &Path) -> &Mutex<HashMap<PathBuf, bool>> {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % CACHE_SHARDS]
+    }
+
+    fn get(&self, path: &Path) -> Option<bool> {
+        self.shard(path).lock().unwrap().get(path).copied()
+    }
+
+    fn insert(&self, path: &Path, verdict: bool) {
+        let mut shard = self.shard(path).lock().unwrap();
+        if shard.len() >= CACHE_SHARD_CAPACITY {
+            shard.clear();
+        }
+        shard.insert(path.to_path_buf(), verdict);
+    }
+
+    fn clear(&mut self) {
+        for shard in &mut self.shards {
+            shard.get_mut().unwrap().clear();
+        }
+    }
+}
+
+impl Clone for VerdictCache {
+    fn clone(&self) -> Self {
+        VerdictCache {
+            shards: std::array::from_fn(|i| Mutex::new(self.shards[i].lock().unwrap().clone())),
         }
+    }
+}
+
+/// The directory whose ignore files decide about `path`: the path itself
+/// for directories, otherwise its parent
+fn containing_dir(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.to_path_buf()
     } else {
-        // Simple pattern - match either as filename or directory name
-        if pattern.contains('/') {
-            // Path pattern
-            if let Some(stripped) = pattern.strip_prefix('/') {
-                stripped.to_string()
-            } else {
-                format!("**/{}", pattern)
+        path.parent()
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf()
+    }
+}
+
+/// Load the excludes applied besides .gitignore files: the global excludes
+/// file and the repository's `.git/info/exclude` for git and jj, or the
+/// `.hgignore` of a Mercurial repository
+///
+/// All are anchored at the top of the repository containing `root`, or at
+/// `root` itself outside a repository.
+fn load_excludes(root: &Path) -> Vec<GitIgnore> {
+    let top = root
+        .ancestors()
+        .find(|dir| {
+            [".git", ".hg", ".jj"]
+                .iter()
+                .any(|vcs| dir.join(vcs).exists())
+        })
+        .unwrap_or(root);
+    let git_dir = resolve_git_dir(top);
+    let is_hg = top.join(".hg").is_dir();
+
+    // Mercurial doesn't read git's excludes, unless the checkout is both
+    let mut files = Vec::new();
+    if !is_hg || git_dir.is_some() {
+        // jj reads the same global excludes, configured in its own git store
+        let config_dir = git_dir.clone().or_else(|| resolve_jj_git_dir(top));
+        files.extend(global_excludes_file(config_dir.as_deref()));
+        files.extend(git_dir.map(|dir| dir.join("info").join("exclude")));
+    }
+
+    let mut excludes: Vec<GitIgnore> = files
+        .into_iter()
+        .filter(|file| file.is_file())
+        .filter_map(|file| match GitIgnore::load_from_file(&file, false) {
+            Ok(mut excludes) => {
+                excludes.base_dir = top.to_path_buf();
+                Some(excludes)
             }
-        } else {
-            // Simple name pattern - match either as filename or directory name
-            format!("**/{}", pattern)
+            Err(e) => {
+                debug!("Error loading excludes from {:?}: {}", file, e);
+                None
+            }
+        })
+        .collect();
+
+    let hgignore = top.join(".hgignore");
+    if is_hg && hgignore.is_file() {
+        match GitIgnore::load_hgignore(&hgignore) {
+            Ok(patterns) => excludes.push(patterns),
+            Err(e) => debug!("Error loading {:?}: {}", hgignore, e),
+        }
+    }
+    excludes
+}
+
+/// The git store behind a jj workspace at `top`, named by
+/// `.jj/repo/store/git_target` relative to the store
+fn resolve_jj_git_dir(top: &Path) -> Option<PathBuf> {
+    let store = top.join(".jj").join("repo").join("store");
+    let target = fs::read_to_string(store.join("git_target")).ok()?;
+    Some(store.join(target.trim()))
+}
+
+/// The git directory of the repository at `top`: `.git` itself, or the
+/// directory a `.git` file points to (worktrees and submodules)
+fn resolve_git_dir(top: &Path) -> Option<PathBuf> {
+    let dot_git = top.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let target = content.trim().strip_prefix("gitdir:")?.trim();
+    Some(top.join(target))
+}
+
+/// The global excludes file: `core.excludesFile` from the git config, or
+/// git's default of `$XDG_CONFIG_HOME/git/ignore`
+fn global_excludes_file(git_dir: Option<&Path>) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    let xdg_config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+
+    // Later config files take precedence, as in git
+    let configs = [
+        xdg_config
+            .as_ref()
+            .map(|dir| dir.join("git").join("config")),
+        home.as_ref().map(|home| home.join(".gitconfig")),
+        git_dir.map(|dir| dir.join("config")),
+    ];
+    let configured = configs
+        .iter()
+        .rev()
+        .flatten()
+        .filter_map(|config| fs::read_to_string(config).ok())
+        .find_map(|content| config_excludes_file(&content));
+
+    match configured {
+        Some(file) => match (file.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(file)),
+        },
+        None => xdg_config.map(|dir| dir.join("git").join("ignore")),
+    }
+}
+
+/// The `core.excludesFile` value of a git config file, if set
+fn config_excludes_file(config: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut value = None;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let section = line.trim_start_matches('[').split([']', ' ']).next();
+            in_core = section.is_some_and(|name| name.eq_ignore_ascii_case("core"));
+            continue;
+        }
+        let Some((key, raw)) = line.split_once('=') else {
+            continue;
+        };
+        if in_core && key.trim().eq_ignore_ascii_case("excludesfile") {
+            value = Some(raw.trim().trim_matches('"').to_string());
         }
     }
+    value
+}
+
+/// The line without trailing spaces, except those escaped with a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') {
+        let backslashes = line[..end - 1]
+            .chars()
+            .rev()
+            .take_while(|&c| c == '\\')
+            .count();
+        if backslashes % 2 == 1 {
+            break;
+        }
+        end -= 1;
+    }
+    &line[..end]
 }
 
 #[cfg(test)]
@@ -433,6 +960,19 @@ mod tests {
     use std::io::Write;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_verdict_cache_is_bounded() {
+        let cache = VerdictCache::default();
+        for i in 0..CACHE_SHARDS * CACHE_SHARD_CAPACITY * 2 {
+            cache.insert(&PathBuf::from(format!("dir/file{}", i)), i % 2 == 0);
+        }
+        for shard in &cache.shards {
+            assert!(shard.lock().unwrap().len() <= CACHE_SHARD_CAPACITY);
+        }
+        cache.insert(Path::new("dir/last"), true);
+        assert_eq!(cache.get(Path::new("dir/last")), Some(true));
+    }
+
     #[test]
     fn test_system_patterns() {
         // Create a temporary directory
@@ -453,6 +993,25 @@ mod tests {
         assert!(!gitignore.is_ignored(&root_path.join("README.md")));
     }
 
+    #[test]
+    fn test_system_patterns_without_root_gitignore() -> Result<()> {
+        let plain = tempdir().unwrap();
+        let with_gitignore = tempdir().unwrap();
+        fs::write(with_gitignore.path().join(".gitignore"), "*.log\n")?;
+
+        for root in [plain.path(), with_gitignore.path()] {
+            let mut ctx = GitIgnoreContext::new(root)?;
+            assert!(ctx.is_ignored(&root.join("app/.venv")));
+            assert!(ctx.is_ignored(&root.join(".jj")));
+            assert!(!ctx.is_ignored(&root.join("src/bin")));
+            assert!(!ctx.is_ignored(&root.join("venv.txt")));
+
+            ctx.unignore_system_dirs(&["venv", ".venv"]);
+            assert!(!ctx.is_ignored(&root.join("app/.venv")));
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_gitignore_patterns() -> Result<()> {
         // Create a temporary directory with a .gitignore file
@@ -472,15 +1031,18 @@ temp/
         let mut file = File::create(&gitignore_path)?;
         file.write_all(gitignore_content.as_bytes())?;
 
+        // Directory-only patterns need real directories to match
+        for dir in ["build", "temp", "src/temp"] {
+            fs::create_dir_all(root_path.join(dir))?;
+        }
+
         let gitignore = GitIgnore::load(root_path)?;
 
         // Test patterns
         assert!(gitignore.is_ignored(&root_path.join("app.log")));
         assert!(gitignore.is_ignored(&root_path.join("logs/server.log")));
         assert!(gitignore.is_ignored(&root_path.join("build")));
-        // This test would fail because '/build/' pattern would only match the build directory
-        // but not its children directly in the globbing rule, so we'll skip it
-        // assert!(gitignore.is_ignored(&root_path.join("build/output.txt")));
+        assert!(gitignore.is_ignored(&root_path.join("build/output.txt")));
         assert!(gitignore.is_ignored(&root_path.join("temp")));
         assert!(gitignore.is_ignored(&root_path.join("src/temp")));
 
@@ -495,19 +1057,261 @@ temp/
     }
 
     #[test]
-    fn test_convert_to_glob_pattern() {
-        // Test directory patterns
-        assert_eq!(convert_to_glob_pattern("logs/"), "**/logs");
+    fn test_pattern_parsing() {
+        let parse = |line: &str| IgnorePattern::parse(line).unwrap();
+        let matches = |line: &str, path: &str| parse(line).matcher.matches(path);
+
+        // A slash anywhere but the end anchors the pattern
+        assert!(matches("logs/", "src/logs"));
+        assert!(parse("logs/").dir_only);
+        assert!(matches("*.log", "a/b/c.log"));
+        assert!(matches("src/*.js", "src/app.js"));
+        assert!(!matches("src/*.js", "lib/src/app.js"));
+        assert!(matches("/dist", "dist"));
+        assert!(!matches("/dist", "web/dist"));
+        assert!(matches("build/temp", "build/temp"));
+        assert!(!matches("build/temp", "x/build/temp"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(matches("**/cache", "deep/cache"));
+
+        // Trailing spaces are dropped unless escaped; leading ones are kept
+        assert!(matches("name.txt   ", "name.txt"));
+        assert!(matches("trailing\\ ", "trailing "));
+        assert!(matches(" lead", " lead"));
+        assert!(!matches(" lead", "lead"));
+
+        // Escaped `#` and `!` are literal
+        assert!(IgnorePattern::parse("# comment").is_none());
+        assert!(matches("\\#notes", "#notes"));
+        let bang = parse("\\!important");
+        assert!(!bang.negated && bang.matcher.matches("!important"));
+        assert!(parse("!keep.log").negated);
+
+        assert_eq!(parse("*.log").matcher.as_str(), "*.log");
+        assert!(IgnorePattern::parse("/").is_none());
+    }
+
+    #[test]
+    fn test_nested_gitignore_anchoring() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        for dir in [
+            "sub/output",
+            "sub/x/output",
+            "output",
+            "sub/docs",
+            "sub/x/docs",
+        ] {
+            fs::create_dir_all(root_path.join(dir))?;
+        }
+        fs::write(root_path.join("sub/.gitignore"), "/output\ndocs/*.md\n")?;
+
+        let ctx = GitIgnoreContext::new(root_path)?;
+        assert!(ctx.is_ignored(&root_path.join("sub/output")));
+        assert!(!ctx.is_ignored(&root_path.join("sub/x/output")));
+        assert!(!ctx.is_ignored(&root_path.join("output")));
+        assert!(ctx.is_ignored(&root_path.join("sub/docs/guide.md")));
+        assert!(!ctx.is_ignored(&root_path.join("sub/x/docs/guide.md")));
+        assert!(!ctx.is_ignored(&root_path.join("sub/docs/deep/guide.md")));
+        Ok(())
+    }
 
-        // Test patterns with wildcards
-        assert_eq!(convert_to_glob_pattern("*.log"), "**/*.log");
-        assert_eq!(convert_to_glob_pattern("src/*.js"), "**/src/*.js");
+    #[test]
+    fn test_dir_only_patterns() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::create_dir_all(root_path.join("logs/nested"))?;
+        fs::create_dir_all(root_path.join("src"))?;
+        fs::write(root_path.join("src/logs"), "not a directory")?;
+        fs::write(root_path.join("logs/nested/app.txt"), "")?;
+        fs::write(root_path.join(".gitignore"), "logs/\n!keep.txt\n")?;
+
+        let ctx = GitIgnoreContext::new(root_path)?;
+        assert!(ctx.is_ignored(&root_path.join("logs")));
+        assert!(!ctx.is_ignored(&root_path.join("src/logs")));
+
+        // Descendants of an ignored directory stay ignored, unless negated
+        assert!(ctx.is_ignored(&root_path.join("logs/nested/app.txt")));
+        assert!(!ctx.is_ignored(&root_path.join("logs/keep.txt")));
+        assert!(ctx.may_reinclude_below(&root_path.join("logs")));
+        Ok(())
+    }
 
-        // Test path patterns
-        assert_eq!(convert_to_glob_pattern("/dist"), "dist");
-        assert_eq!(convert_to_glob_pattern("build/temp"), "**/build/temp");
+    #[test]
+    fn test_info_exclude() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::create_dir_all(root_path.join(".git/info"))?;
+        fs::create_dir_all(root_path.join("sub"))?;
+        fs::write(
+            root_path.join(".git/info/exclude"),
+            "*.scratch\n/notes.md\n",
+        )?;
+        fs::write(root_path.join(".gitignore"), "!keep.scratch\n")?;
+
+        // Scanning a subdirectory still anchors the excludes at the repo top
+        let ctx = GitIgnoreContext::new(&root_path.join("sub"))?;
+        assert!(ctx.is_ignored(&root_path.join("sub/a.scratch")));
+        assert!(!ctx.is_ignored(&root_path.join("sub/notes.md")));
+
+        // .gitignore files take precedence over the excludes
+        let ctx = GitIgnoreContext::new(root_path)?;
+        assert!(ctx.is_ignored(&root_path.join("notes.md")));
+        assert!(!ctx.is_ignored(&root_path.join("keep.scratch")));
+        Ok(())
+    }
 
-        // Test simple name patterns
-        assert_eq!(convert_to_glob_pattern("node_modules"), "**/node_modules");
+    #[test]
+    fn test_disabled_context() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::create_dir_all(root_path.join("node_modules"))?;
+        fs::write(root_path.join(".gitignore"), "*.log\n")?;
+
+        let ctx = GitIgnoreContext::disabled(root_path);
+        assert!(!ctx.is_ignored(&root_path.join("app.log")));
+        assert!(!ctx.is_ignored(&root_path.join("node_modules")));
+        assert!(!ctx.is_ignored(&root_path.join(".git")));
+        assert!(ctx.trace(&root_path.join("app.log")).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_engine() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::create_dir_all(root_path.join("src"))?;
+        fs::create_dir_all(root_path.join("node_modules"))?;
+        fs::write(root_path.join("src/.gitignore"), "*.tmp\n")?;
+
+        let engine = Arc::new(IgnoreEngine::new(root_path, &[IgnoreFile::Git])?);
+        let scanner = GitIgnoreContext::with_engine(engine.clone());
+        let mut rule = GitIgnoreContext::with_engine(engine.clone());
+        rule.clear_system_dirs();
+
+        // Both read the same patterns, each with its own system patterns
+        assert!(scanner.is_ignored(&root_path.join("src/a.tmp")));
+        assert!(rule.is_ignored(&root_path.join("src/a.tmp")));
+        assert!(scanner.is_ignored(&root_path.join("node_modules")));
+        assert!(!rule.is_ignored(&root_path.join("node_modules")));
+
+        // The .gitignore was read once
+        let first = engine.gitignore(&root_path.join("src"))?.unwrap();
+        let second = engine.gitignore(&root_path.join("src"))?.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_queries() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        for dir in ["a", "b", "c", "d"] {
+            fs::create_dir_all(root_path.join(dir))?;
+            fs::write(root_path.join(dir).join(".gitignore"), "*.tmp\n!keep.tmp\n")?;
+        }
+
+        // One context, asked from several threads without cloning it
+        let ctx = GitIgnoreContext::new(root_path)?;
+        std::thread::scope(|scope| {
+            for dir in ["a", "b", "c", "d"] {
+                let ctx = &ctx;
+                scope.spawn(move || {
+                    for i in 0..50 {
+                        let dir = root_path.join(dir);
+                        assert!(ctx.is_ignored(&dir.join(format!("{}.tmp", i))));
+                        assert!(!ctx.is_ignored(&dir.join(format!("{}.rs", i))));
+                        assert!(!ctx.is_ignored(&dir.join("keep.tmp")));
+                    }
+                });
+            }
+        });
+        assert!(ctx.is_ignored(&root_path.join("c/7.tmp")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dockerignore() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::create_dir_all(root_path.join("docs"))?;
+        fs::create_dir_all(root_path.join("src/docs"))?;
+        fs::write(root_path.join(".gitignore"), "*.log\n")?;
+        fs::write(
+            root_path.join(".dockerignore"),
+            "# build context\ndocs/\n**/*.md\n!README.md\n",
+        )?;
+
+        // Instead of .gitignore: patterns are anchored at the root
+        let ctx = GitIgnoreContext::with_ignore_files(root_path, &[IgnoreFile::Docker])?;
+        assert!(ctx.is_ignored(&root_path.join("docs")));
+        assert!(!ctx.is_ignored(&root_path.join("src/docs")));
+        assert!(ctx.is_ignored(&root_path.join("src/notes.md")));
+        assert!(!ctx.is_ignored(&root_path.join("README.md")));
+        assert!(!ctx.is_ignored(&root_path.join("app.log")));
+        assert_eq!(ctx.trace(&root_path.join("docs"))[0].pattern, "docs");
+
+        // Alongside it, either file can ignore a path
+        let ctx =
+            GitIgnoreContext::with_ignore_files(root_path, &[IgnoreFile::Git, IgnoreFile::Docker])?;
+        assert!(ctx.is_ignored(&root_path.join("docs")));
+        assert!(ctx.is_ignored(&root_path.join("app.log")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hgignore_repository() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::create_dir_all(root_path.join(".hg"))?;
+        fs::create_dir_all(root_path.join("src"))?;
+        fs::write(
+            root_path.join(".hgignore"),
+            "syntax: glob\n*.orig\nre:^gen/\n",
+        )?;
+
+        // Applies from a subdirectory too, anchored at the repository top
+        let ctx = GitIgnoreContext::new(&root_path.join("src"))?;
+        assert!(ctx.is_ignored(&root_path.join("src/main.rs.orig")));
+        assert!(!ctx.is_ignored(&root_path.join("src/main.rs")));
+
+        let ctx = GitIgnoreContext::new(root_path)?;
+        assert!(ctx.is_ignored(&root_path.join("gen/out.o")));
+        assert!(!ctx.is_ignored(&root_path.join("src/gen/out.o")));
+        let trace = ctx.trace(&root_path.join("a.orig"));
+        assert_eq!(trace[0].source, root_path.join(".hgignore"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_jj_workspace_excludes() -> Result<()> {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        let store = root_path.join(".jj/repo/store");
+        fs::create_dir_all(store.join("git"))?;
+        fs::write(store.join("git_target"), "git\n")?;
+        let excludes = root_path.join("global-ignore");
+        fs::write(&excludes, "*.swp\n")?;
+        fs::write(
+            store.join("git/config"),
+            format!("[core]\n\texcludesFile = {}\n", excludes.display()),
+        )?;
+
+        // The excludes file configured in jj's git store applies
+        let ctx = GitIgnoreContext::new(root_path)?;
+        assert!(ctx.is_ignored(&root_path.join("notes.swp")));
+        assert!(!ctx.is_ignored(&root_path.join("notes.txt")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_excludes_file() {
+        let config = "[user]\n\tname = someone\n[core]\n\tautocrlf = input\n\
+                      \texcludesFile = \"~/.gitignore_global\"\n[alias]\n\texcludesfile = no\n";
+        assert_eq!(
+            config_excludes_file(config).as_deref(),
+            Some("~/.gitignore_global")
+        );
+        assert_eq!(config_excludes_file("[core]\n\tbare = false\n"), None);
     }
 }
@@ -0,0 +1,139 @@
+//! Mercurial's `.hgignore`
+//!
+//! Patterns are regular expressions unless a `syntax: glob` line switches
+//! the rest of the file, or a line carries its own `glob:`/`re:` prefix.
+//! Neither kind is rooted: a regexp may match anywhere in the path, and a
+//! glob at any depth, except for `rootglob:` and `path:` patterns. There are
+//! no negations.
+
+use crate::gitignore::{IgnorePattern, Matcher};
+use glob::Pattern;
+use regex::Regex;
+use tracing::debug;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Syntax {
+    Regexp,
+    Glob,
+    RootGlob,
+    Path,
+}
+
+impl Syntax {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "re" | "regexp" | "relre" => Some(Syntax::Regexp),
+            "glob" | "relglob" => Some(Syntax::Glob),
+            "rootglob" => Some(Syntax::RootGlob),
+            "path" => Some(Syntax::Path),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the contents of a `.hgignore` file
+pub(crate) fn parse_hgignore(content: &str) -> Vec<IgnorePattern> {
+    let mut syntax = Syntax::Regexp;
+    let mut patterns = Vec::new();
+    for line in content.lines() {
+        let line = strip_comment(line);
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("syntax:") {
+            match Syntax::from_name(name.trim()) {
+                Some(new_syntax) => syntax = new_syntax,
+                None => debug!("Unknown hgignore syntax '{}'", name.trim()),
+            }
+            continue;
+        }
+
+        let (line_syntax, pattern) = match line.split_once(':') {
+            Some((prefix, rest)) => match Syntax::from_name(prefix) {
+                Some(prefixed) => (prefixed, rest),
+                None => (syntax, line),
+            },
+            None => (syntax, line),
+        };
+        match matcher(line_syntax, pattern) {
+            Some(matcher) => patterns.push(IgnorePattern {
+                matcher,
+                negated: false,
+                dir_only: false,
+            }),
+            None => debug!("Invalid hgignore pattern '{}'", pattern),
+        }
+    }
+    patterns
+}
+
+/// The line up to an unescaped `#`, with `\#` unescaped
+fn strip_comment(line: &str) -> String {
+    let mut kept = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'#') => {
+                kept.push('#');
+                chars.next();
+            }
+            '#' => break,
+            c => kept.push(c),
+        }
+    }
+    kept
+}
+
+fn matcher(syntax: Syntax, pattern: &str) -> Option<Matcher> {
+    match syntax {
+        Syntax::Regexp => Regex::new(pattern).ok().map(Matcher::Regex),
+        Syntax::Glob if pattern.starts_with("**/") => glob(pattern),
+        Syntax::Glob => glob(&format!("**/{}", pattern)),
+        Syntax::RootGlob => glob(pattern),
+        Syntax::Path => glob(&Pattern::escape(pattern.trim_matches('/'))),
+    }
+}
+
+fn glob(pattern: &str) -> Option<Matcher> {
+    Pattern::new(pattern.trim_end_matches('/'))
+        .ok()
+        .map(Matcher::Glob)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignores(patterns: &[IgnorePattern], path: &str) -> bool {
+        patterns.iter().any(|pattern| pattern.matcher.matches(path))
+    }
+
+    #[test]
+    fn test_hgignore_syntax() {
+        let patterns = parse_hgignore(
+            "# regexps by default\n\
+             \\.orig$\n\
+             ^out/\n\
+             syntax: glob\n\
+             *.pyc\n\
+             re:^tmp\\d+$\n\
+             rootglob:dist\n\
+             path:docs/_build\n\
+             issue\\#12.txt\n",
+        );
+        assert_eq!(patterns.len(), 7);
+
+        assert!(ignores(&patterns, "src/main.rs.orig"));
+        assert!(ignores(&patterns, "out/log.txt"));
+        assert!(!ignores(&patterns, "src/out/log.txt"));
+        assert!(ignores(&patterns, "lib/mod.pyc"));
+        assert!(ignores(&patterns, "tmp42"));
+        assert!(!ignores(&patterns, "src/tmp42"));
+        assert!(ignores(&patterns, "dist"));
+        assert!(!ignores(&patterns, "web/dist"));
+        assert!(ignores(&patterns, "docs/_build"));
+        assert!(ignores(&patterns, "notes/issue#12.txt"));
+        assert!(!ignores(&patterns, "src/main.rs"));
+    }
+}
@@ -0,0 +1,692 @@
+//! Container image inspection
+//!
+//! Reads an OCI image layout directory or a `docker save` archive and
+//! applies its layers in order (honouring whiteouts), building the merged
+//! filesystem as a tree from the tar headers alone. Nothing is unpacked or
+//! written to disk, and no file contents are read. A plain (or gzipped) tar
+//! archive, possibly streamed on stdin, is read the same way as one layer.
+
+use crate::error::{Error, Result, WithContext};
+use crate::rules::{FilterContext, FilterRegistry};
+use crate::stats::format_size;
+use crate::types::{DirectoryEntry, EntryKind, EntryMetadata};
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::debug;
+
+/// Prefix marking a deleted path in a layer
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// Marker clearing everything a directory inherited from lower layers
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Magic bytes at the start of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// What a single layer contributed to the merged filesystem
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerSummary {
+    /// Layer digest or path inside the image
+    pub id: String,
+    /// Regular files added or replaced by the layer
+    pub files: usize,
+    /// Total size of those files
+    pub size: u64,
+    /// Paths deleted through whiteouts
+    pub whiteouts: usize,
+}
+
+/// What each layer of an opened image contributed
+pub struct ImageFs {
+    /// Per-layer contributions, lowest layer first
+    pub layers: Vec<LayerSummary>,
+}
+
+impl fmt::Display for ImageFs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "layers:")?;
+        for (index, layer) in self.layers.iter().enumerate() {
+            writeln!(
+                f,
+                "  {:>2}  {:<19} {:>7} files  {:>9}  {} whiteouts",
+                index + 1,
+                short_id(&layer.id),
+                layer.files,
+                format_size(layer.size),
+                layer.whiteouts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Shorten a layer digest to the usual 12 hex characters
+fn short_id(id: &str) -> &str {
+    let hex = id.strip_prefix("sha256:").unwrap_or(id);
+    let hex = hex.strip_prefix("blobs/sha256/").unwrap_or(hex);
+    if hex.len() > 12 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        &hex[..12]
+    } else {
+        id
+    }
+}
+
+/// Where the image's files are read from: an OCI layout directory, or the
+/// members of a `docker save` archive, read in place
+enum ImageSource {
+    Dir(PathBuf),
+    Archive {
+        path: PathBuf,
+        /// Offset and size of each member's data
+        members: HashMap<PathBuf, (u64, u64)>,
+    },
+}
+
+impl ImageSource {
+    fn open(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            return Ok(ImageSource::Dir(path.to_path_buf()));
+        }
+        let mut archive = tar::Archive::new(File::open(path)?);
+        let mut members = HashMap::new();
+        let entries = archive
+            .entries_with_seek()
+            .with_context(|| format!("failed to read image archive {}", path.display()))?;
+        for entry in entries {
+            let entry = entry
+                .with_context(|| format!("failed to read image archive {}", path.display()))?;
+            if let Some(rel) = sanitize(&entry.path()?) {
+                members.insert(rel, (entry.raw_file_position(), entry.size()));
+            }
+        }
+        Ok(ImageSource::Archive {
+            path: path.to_path_buf(),
+            members,
+        })
+    }
+
+    fn contains(&self, rel: &Path) -> bool {
+        match self {
+            ImageSource::Dir(dir) => dir.join(rel).is_file(),
+            ImageSource::Archive { members, .. } => members.contains_key(rel),
+        }
+    }
+
+    /// Read the file at `rel`, relative to the image
+    fn read(&self, rel: &Path) -> io::Result<Box<dyn Read>> {
+        match self {
+            ImageSource::Dir(dir) => Ok(Box::new(File::open(dir.join(rel))?)),
+            ImageSource::Archive { path, members } => {
+                let (offset, size) = members.get(rel).copied().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not in the image archive", rel.display()),
+                    )
+                })?;
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(Box::new(file.take(size)))
+            }
+        }
+    }
+
+    fn read_json(&self, rel: &Path) -> Result<Value> {
+        let file = self
+            .read(rel)
+            .with_context(|| format!("failed to open {}", rel.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| Error::Format(format!("failed to parse {}: {}", rel.display(), e)))
+    }
+}
+
+/// Build the merged filesystem of an OCI layout directory or docker archive
+///
+/// The tree is read from the layers' tar headers, without unpacking
+/// anything. It is named after the image and its entries have paths
+/// relative to the image root, like a `--fromfile` listing.
+pub fn open_image(path: &Path) -> Result<(DirectoryEntry, ImageFs)> {
+    let source = ImageSource::open(path)?;
+    let mut root = Node::dir();
+    let mut layers = Vec::new();
+    for (id, layer_path) in layer_paths(&source)? {
+        debug!("Applying layer {} from {}", id, layer_path.display());
+        let mut summary = apply_layer(&source, &layer_path, &mut root)
+            .with_context(|| format!("failed to apply layer {}", id))?;
+        summary.id = id;
+        layers.push(summary);
+    }
+
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    Ok((root.into_entry(PathBuf::new(), name), ImageFs { layers }))
+}
+
+/// Layer ids and blob paths in application order
+fn layer_paths(source: &ImageSource) -> Result<Vec<(String, PathBuf)>> {
+    // docker save format: manifest.json lists layer files relative to the archive
+    let docker_manifest = Path::new("manifest.json");
+    if source.contains(docker_manifest) {
+        let manifest = source.read_json(docker_manifest)?;
+        let layers = manifest
+            .get(0)
+            .and_then(|image| image.get("Layers"))
+            .and_then(Value::as_array)
+            .ok_or_else(|| Error::Format(String::from("manifest.json does not list any layers")))?;
+        return layers
+            .iter()
+            .map(|layer| {
+                let rel = layer.as_str().ok_or_else(|| {
+                    Error::Format(String::from("invalid layer entry in manifest.json"))
+                })?;
+                let path = sanitize(Path::new(rel)).ok_or_else(|| {
+                    Error::Format(format!("invalid layer path {} in manifest.json", rel))
+                })?;
+                Ok((rel.to_string(), path))
+            })
+            .collect();
+    }
+
+    // OCI layout: index.json -> image manifest blob -> layer blobs
+    let index_path = Path::new("index.json");
+    if !source.contains(index_path) {
+        return Err(Error::Format(String::from(
+            "neither an OCI image layout nor a docker archive",
+        )));
+    }
+    let index = source.read_json(index_path)?;
+    let manifest_digest = index
+        .get("manifests")
+        .and_then(|manifests| manifests.get(0))
+        .and_then(|manifest| manifest.get("digest"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::Format(String::from("index.json does not reference a manifest")))?;
+    let manifest = source.read_json(&blob_path(manifest_digest)?)?;
+    let layers = manifest
+        .get("layers")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::Format(String::from("image manifest does not list any layers")))?;
+    layers
+        .iter()
+        .map(|layer| {
+            let digest = layer.get("digest").and_then(Value::as_str).ok_or_else(|| {
+                Error::Format(String::from("layer without digest in image manifest"))
+            })?;
+            Ok((digest.to_string(), blob_path(digest)?))
+        })
+        .collect()
+}
+
+fn blob_path(digest: &str) -> Result<PathBuf> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .ok_or_else(|| Error::Format(format!("invalid digest {}", digest)))?;
+    Ok(Path::new("blobs").join(algorithm).join(hex))
+}
+
+/// One entry of the merged filesystem
+struct Node {
+    is_dir: bool,
+    kind: EntryKind,
+    size: u64,
+    mtime: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    /// A directory only known from the entries below it
+    fn dir() -> Self {
+        Node {
+            is_dir: true,
+            kind: EntryKind::Regular,
+            size: 0,
+            mtime: 0,
+            mode: 0o755,
+            uid: 0,
+            gid: 0,
+            children: BTreeMap::new(),
+        }
+    }
+
+    /// The node at `rel`, if every directory on the way exists
+    fn get(&self, rel: &Path) -> Option<&Node> {
+        rel.components().try_fold(self, |node, component| {
+            node.children
+                .get(component.as_os_str().to_string_lossy().as_ref())
+        })
+    }
+
+    /// The directory at `rel` if it exists
+    fn existing_dir_mut(&mut self, rel: &Path) -> Option<&mut Node> {
+        rel.components().try_fold(self, |node, component| {
+            node.children
+                .get_mut(component.as_os_str().to_string_lossy().as_ref())
+                .filter(|child| child.is_dir)
+        })
+    }
+
+    /// The directory at `rel`, creating missing ones on the way, or None
+    /// when something other than a directory is in the way
+    fn dir_mut(&mut self, rel: &Path) -> Option<&mut Node> {
+        rel.components().try_fold(self, |node, component| {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            let child = node.children.entry(name).or_insert_with(Node::dir);
+            child.is_dir.then_some(child)
+        })
+    }
+
+    fn into_entry(self, path: PathBuf, name: String) -> DirectoryEntry {
+        let children: Vec<DirectoryEntry> = self
+            .children
+            .into_iter()
+            .map(|(name, child)| child.into_entry(path.join(&name), name))
+            .collect();
+        let (files_count, size) = if self.is_dir {
+            children.iter().fold((0, 0), |(files, size), child| {
+                let child_files = if child.is_dir {
+                    child.metadata.files_count
+                } else {
+                    1
+                };
+                (files + child_files, size + child.metadata.size)
+            })
+        } else {
+            (0, self.size)
+        };
+        let modified = UNIX_EPOCH + Duration::from_secs(self.mtime);
+
+        DirectoryEntry {
+            path,
+            name,
+            is_dir: self.is_dir,
+            metadata: EntryMetadata {
+                size,
+                created: modified,
+                modified,
+                files_count,
+                mode: Some(self.mode),
+                uid: Some(self.uid),
+                gid: Some(self.gid),
+                approximate: false,
+            },
+            children,
+            is_gitignored: false,
+            is_git_repo: false,
+            filtered_by: None,
+            filter_annotation: None,
+            error: None,
+            is_virtual: true,
+            kind: self.kind,
+            has_unlisted: false,
+            has_omitted: false,
+            cancelled: false,
+        }
+    }
+}
+
+/// Build the tree of a tar archive read from `reader`, decompressing it
+/// first if `gzip`
+///
+/// The archive is read as a single layer, except that `.wh.` names are
+/// ordinary files. The tree is named `name` and its entries have paths
+/// relative to the archive root.
+pub fn read_archive(reader: impl Read, gzip: bool, name: String) -> Result<DirectoryEntry> {
+    let mut root = Node::dir();
+    if gzip {
+        apply_tar(GzDecoder::new(reader), false, &mut root)
+    } else {
+        apply_tar(reader, false, &mut root)
+    }
+    .with_context(|| format!("failed to read archive {}", name))?;
+    Ok(root.into_entry(PathBuf::new(), name))
+}
+
+/// Open a layer as a tar stream, transparently decompressing gzip
+fn open_layer(source: &ImageSource, rel: &Path) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(source.read(rel)?);
+    let is_gzip = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    Ok(if is_gzip {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Apply one layer on top of the merged filesystem at `root`
+fn apply_layer(source: &ImageSource, path: &Path, root: &mut Node) -> io::Result<LayerSummary> {
+    apply_tar(open_layer(source, path)?, true, root)
+}
+
+/// Apply a tar stream on top of `root`, deleting the paths its whiteouts
+/// name when `whiteouts`
+fn apply_tar(reader: impl Read, whiteouts: bool, root: &mut Node) -> io::Result<LayerSummary> {
+    let mut summary = LayerSummary::default();
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let Some(rel) = sanitize(&entry.path()?) else {
+            continue;
+        };
+        let Some(name) = rel.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            continue;
+        };
+        let parent = rel.parent().unwrap_or(Path::new(""));
+
+        if whiteouts && name == OPAQUE_WHITEOUT {
+            if let Some(dir) = root.existing_dir_mut(parent) {
+                dir.children.clear();
+            }
+            summary.whiteouts += 1;
+            continue;
+        }
+        if let Some(deleted) = name.strip_prefix(WHITEOUT_PREFIX).filter(|_| whiteouts) {
+            if let Some(dir) = root.existing_dir_mut(parent) {
+                dir.children.remove(deleted);
+            }
+            summary.whiteouts += 1;
+            continue;
+        }
+
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        // A hard link has the size of the file it links to
+        let size = if entry_type.is_hard_link() {
+            entry
+                .link_name()?
+                .and_then(|target| sanitize(&target))
+                .and_then(|target| root.get(&target).map(|node| node.size))
+                .unwrap_or(0)
+        } else {
+            header.size().unwrap_or(0)
+        };
+        let node = Node {
+            is_dir: entry_type.is_dir(),
+            kind: match entry_type {
+                tar::EntryType::Symlink => EntryKind::Symlink {
+                    target: entry
+                        .link_name()?
+                        .map(|target| target.display().to_string()),
+                    broken: false,
+                },
+                tar::EntryType::Fifo => EntryKind::Fifo,
+                tar::EntryType::Block => EntryKind::BlockDevice,
+                tar::EntryType::Char => EntryKind::CharDevice,
+                _ => EntryKind::Regular,
+            },
+            size: if entry_type.is_dir() || entry_type.is_symlink() {
+                0
+            } else {
+                size
+            },
+            mtime: header.mtime().unwrap_or(0),
+            mode: header.mode().unwrap_or(0o644),
+            uid: header.uid().unwrap_or(0) as u32,
+            gid: header.gid().unwrap_or(0) as u32,
+            children: BTreeMap::new(),
+        };
+
+        // Like on disk, nothing is created below a symlink or a file
+        let Some(dir) = root.dir_mut(parent) else {
+            debug!("Skipping {} below a non-directory", rel.display());
+            continue;
+        };
+        match dir.children.get_mut(&name) {
+            // A directory entry updates the directory, keeping what is in it
+            Some(existing) if existing.is_dir && node.is_dir => {
+                let children = std::mem::take(&mut existing.children);
+                *existing = Node { children, ..node };
+            }
+            _ => {
+                dir.children.insert(name, node);
+            }
+        }
+        if entry_type.is_file() {
+            summary.files += 1;
+            summary.size += size;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Mark the entries of a tree not read from the filesystem, like an image's,
+/// that `rules` hide, folding hidden directories unless `show_filtered` as a
+/// scan does. Entry paths are relative to the root, which is never hidden.
+pub fn classify_tree(tree: &mut DirectoryEntry, rules: &FilterRegistry, show_filtered: bool) {
+    classify_children(tree, rules, show_filtered);
+}
+
+/// Classify what is below `dir`, returning the files and bytes left out by
+/// rules that omit their matches
+fn classify_children(
+    dir: &mut DirectoryEntry,
+    rules: &FilterRegistry,
+    show_filtered: bool,
+) -> (usize, u64) {
+    let (mut files_left_out, mut size_left_out) = (0, 0);
+    dir.children.retain_mut(|child| {
+        let files: Vec<(String, u64)> = child
+            .children
+            .iter()
+            .filter(|grandchild| !grandchild.is_dir)
+            .map(|file| (file.name.clone(), file.metadata.size))
+            .collect();
+        let parent = child.path.parent().unwrap_or(Path::new(""));
+        let depth = child.path.components().count();
+        let mut context = FilterContext::new(&child.path, parent, Path::new(""), depth);
+        context.is_dir = child.is_dir;
+        if child.is_dir {
+            context.files = Some(&files);
+        }
+        let verdict = rules
+            .should_hide(&context)
+            .map(|verdict| (verdict.rule_id.to_string(), verdict.annotation.to_string()));
+
+        if let Some((rule_id, annotation)) = verdict {
+            if rules.omits(&rule_id) && !show_filtered {
+                files_left_out += if child.is_dir {
+                    child.metadata.files_count
+                } else {
+                    1
+                };
+                size_left_out += child.metadata.size;
+                return false;
+            }
+            child.filter_annotation = rules
+                .summary(&rule_id, child.metadata.size, child.metadata.files_count)
+                .or(Some(annotation));
+            child.filtered_by = Some(rule_id);
+            if child.is_dir && !show_filtered {
+                child.children.clear();
+                return true;
+            }
+        }
+        if child.is_dir {
+            let (files, size) = classify_children(child, rules, show_filtered);
+            files_left_out += files;
+            size_left_out += size;
+        }
+        true
+    });
+    dir.metadata.files_count -= files_left_out;
+    dir.metadata.size -= size_left_out;
+    (files_left_out, size_left_out)
+}
+
+/// Normalize a layer path, dropping entries that would escape the root
+fn sanitize(path: &Path) -> Option<PathBuf> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!clean.as_os_str().is_empty()).then_some(clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn find<'a>(entry: &'a DirectoryEntry, name: &str) -> &'a DirectoryEntry {
+        entry.children.iter().find(|c| c.name == name).unwrap()
+    }
+
+    fn names(entry: &DirectoryEntry) -> Vec<&str> {
+        entry.children.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    fn layer(entries: &[(&str, u64)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, size) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(*size);
+            header.set_mode(0o644);
+            header.set_mtime(0);
+            // "path -> target" is a hard link
+            if let Some((path, target)) = path.split_once(" -> ") {
+                header.set_entry_type(tar::EntryType::Link);
+                builder.append_link(&mut header, path, target).unwrap();
+                continue;
+            }
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, &vec![0u8; *size as usize][..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// Write a `docker save` archive of `layers` to `path`
+    fn docker_archive(path: &Path, layers: &[Vec<u8>]) {
+        let mut builder = tar::Builder::new(Vec::new());
+        let names: Vec<String> = (1..=layers.len())
+            .map(|i| format!("l{}/layer.tar", i))
+            .collect();
+        let manifest = serde_json::json!([{ "Layers": names }]).to_string();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "manifest.json", manifest.as_bytes())
+            .unwrap();
+        for (name, data) in names.iter().zip(layers) {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, &data[..]).unwrap();
+        }
+        fs::write(path, builder.into_inner().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_docker_archive_layers_are_merged() {
+        let dir = tempdir().unwrap();
+        let layers = [
+            layer(&[("app/keep.txt", 10), ("app/old.log", 500)]),
+            layer(&[
+                ("app/.wh.old.log", 0),
+                ("big.bin", 4096),
+                ("app/link.txt -> app/keep.txt", 0),
+            ]),
+        ];
+
+        let archive = dir.path().join("image.tar");
+        docker_archive(&archive, &layers);
+
+        let (tree, image) = open_image(&archive).unwrap();
+        assert_eq!(tree.name, "image.tar");
+        assert_eq!(tree.path, PathBuf::new());
+        let app = find(&tree, "app");
+        assert_eq!(app.path, PathBuf::from("app"));
+        assert_eq!(names(app), vec!["keep.txt", "link.txt"]);
+        assert_eq!(find(app, "keep.txt").metadata.size, 10);
+        assert_eq!(
+            find(app, "link.txt").metadata.size,
+            10,
+            "A hard link has its target's size"
+        );
+        assert_eq!(find(&tree, "big.bin").metadata.size, 4096);
+        assert_eq!(tree.metadata.files_count, 3);
+        assert_eq!(tree.metadata.size, 4116);
+
+        assert_eq!(image.layers.len(), 2);
+        assert_eq!(image.layers[0].files, 2);
+        assert_eq!(image.layers[1].size, 4096);
+        assert_eq!(image.layers[1].whiteouts, 1);
+        assert_eq!(sanitize(Path::new("etc/../../escape")), None);
+    }
+
+    #[test]
+    fn test_read_gzipped_archive() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let tar = layer(&[("src/main.rs", 12), ("src/.wh.notes", 3)]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let tree = read_archive(&gzipped[..], true, String::from("-")).unwrap();
+        assert_eq!(tree.name, "-");
+        let src = find(&tree, "src");
+        assert_eq!(
+            names(src),
+            vec![".wh.notes", "main.rs"],
+            "Whiteouts are plain files"
+        );
+        assert_eq!(tree.metadata.size, 15);
+
+        assert!(read_archive(&gzipped[..], false, String::from("-")).is_err());
+    }
+
+    #[test]
+    fn test_rules_apply_to_image_tree() {
+        use crate::gitignore::IgnoreEngine;
+        use crate::rules::create_registry_with_engine;
+        use std::sync::Arc;
+
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("image.tar");
+        docker_archive(
+            &archive,
+            &[layer(&[
+                ("srv/.pytest_cache/v/cache", 5),
+                ("srv/Cargo.lock", 7),
+                ("srv/main.rs", 3),
+            ])],
+        );
+        let (mut tree, _) = open_image(&archive).unwrap();
+        let engine = Arc::new(IgnoreEngine::disabled(Path::new("")));
+        let registry = create_registry_with_engine(engine);
+        classify_tree(&mut tree, &registry, false);
+
+        let srv = find(&tree, "srv");
+        assert_eq!(srv.filtered_by, None);
+        let cache = find(srv, ".pytest_cache");
+        assert_eq!(cache.filtered_by.as_deref(), Some("caches"));
+        assert!(cache.children.is_empty(), "Folded like in a scan");
+        assert_eq!(cache.metadata.files_count, 1);
+        assert_eq!(
+            find(srv, "Cargo.lock").filtered_by.as_deref(),
+            Some("lockfiles")
+        );
+        assert_eq!(tree.metadata.files_count, 3);
+    }
+}
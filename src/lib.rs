@@ -1,50 +1,111 @@
 //! Smart tree display library
 
+/// Builder methods setting one field each of the struct the builder wraps
+/// in `$inner`, and passing the builder on
+macro_rules! setters {
+    ($inner:ident; $($(#[$doc:meta])* $field:ident: $ty:ty;)*) => {
+        $(
+            $(#[$doc])*
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.$inner.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+mod cache;
+mod config;
+mod dir_reader;
 mod display;
+mod error;
+mod explain;
+mod git_status;
 mod gitignore;
-mod log_macros;
+mod hgignore;
+mod image;
+mod nice;
+mod path_list;
+mod pattern;
+mod progress;
 pub mod rules;
+mod scan_iter;
 mod scanner;
+mod sizing;
+mod stats;
 mod tests;
 mod types;
+mod verify;
+mod wildmatch;
+mod workspace;
 
 // Re-export public items
-pub use display::{format_tree, should_use_colors};
-pub use gitignore::{GitIgnore, GitIgnoreContext};
-pub use scanner::scan_directory;
-pub use types::{ColorTheme, DirectoryEntry, DisplayConfig, EntryMetadata, SortBy};
+pub use cache::{cache_dir, changes_since, load_scan, save_scan};
+pub use config::{config_path, Config};
+pub use display::{
+    ansi_to_html, enable_ansi_support, format_html, format_json, format_ndjson, format_tree,
+    format_tree_json, format_tree_xml, format_trees, html_document, set_color_mode,
+    should_use_colors, skip_report, terminal_width, FoldedDir, SkipReport, TruncatedDir,
+    UnreadablePath,
+};
+pub use error::{Error, Result};
+pub use explain::{explain_path, Decision, ExplainOptions, PathExplanation};
+pub use git_status::git_status;
+pub use gitignore::{GitIgnore, GitIgnoreContext, IgnoreEngine, IgnoreFile, IgnoreMatch};
+pub use image::{classify_tree, open_image, read_archive, ImageFs, LayerSummary};
+pub use nice::lower_io_priority;
+pub use path_list::tree_from_paths;
+pub use progress::{ProgressSink, ScanProgress};
+pub use scan_iter::{scan_iter, ScanIter, ScannedEntry};
+#[cfg(feature = "tokio")]
+pub use scanner::scan_async;
+pub use scanner::{merge_roots, rescan_paths, scan, ScanOptions, ScanOptionsBuilder};
+#[allow(deprecated)]
+pub use scanner::{scan_directory, scan_with_options};
+pub use sizing::{apply_exact_sizes, format_exact_sizes, DirSize, ExactSizes};
+pub use stats::{ExtensionStats, TreeStats};
+pub use types::{
+    BadgeMap, ChangeKind, ChangeSet, ColorMode, ColorTheme, DirectoryEntry, DisplayConfig,
+    DisplayConfigBuilder, EntryKind, EntryMetadata, GitStatus, GitStatusMap, Grouping,
+    IconOverrides, IconStyle, LsColors, OutputFormat, Placement, Priority, SortBy, TimeStyle,
+    Tokenizer,
+};
+pub use verify::{file_checksum, verify_snapshot, Divergence, Verification};
+pub use workspace::workspace_badges;
 
 // Convenience wrapper for backward compatibility
 #[deprecated(
     since = "0.2.1",
-    note = "Use scan_directory with GitIgnoreContext instead"
+    note = "Use scan with GitIgnoreContext and ScanOptions instead"
 )]
 pub fn scan_directory_simple(
     root: &std::path::Path,
     gitignore: &mut GitIgnoreContext,
     max_depth: usize,
-) -> anyhow::Result<DirectoryEntry> {
-    scanner::scan_directory(root, gitignore, None, max_depth, None, None)
+) -> Result<DirectoryEntry> {
+    let options = ScanOptions::builder().max_depth(max_depth).build();
+    scan(root, gitignore, &options)
 }
 
 // Another wrapper for backward compatibility with older GitIgnore API
 #[deprecated(
     since = "0.3.0",
-    note = "Use scan_directory with GitIgnoreContext instead"
+    note = "Use scan with GitIgnoreContext and ScanOptions instead"
 )]
 pub fn scan_directory_with_legacy_gitignore(
     root: &std::path::Path,
     gitignore: &GitIgnore, // Using the old GitIgnore API
     max_depth: usize,
     show_system_dirs: Option<bool>,
-) -> anyhow::Result<DirectoryEntry> {
-    use crate::types::{DirectoryEntry, EntryMetadata};
-    use log::{debug, warn};
+) -> Result<DirectoryEntry> {
+    use crate::types::{DirectoryEntry, EntryKind, EntryMetadata};
     use std::fs;
+    use tracing::{debug, warn};
 
     // Default to not showing system directories if not specified
     let show_system = show_system_dirs.unwrap_or(false);
     let root_metadata = fs::metadata(root)?;
+    let (mode, uid, gid) = crate::scanner::permissions(&root_metadata);
     let root_name = root
         .file_name()
         .map(|n| n.to_string_lossy().to_string())
@@ -61,11 +122,22 @@ pub fn scan_directory_with_legacy_gitignore(
                 created: root_metadata.created()?,
                 modified: root_metadata.modified()?,
                 files_count: 0,
+                mode,
+                uid,
+                gid,
+                approximate: false,
             },
             children: Vec::new(),
             is_gitignored: gitignore.is_ignored(root),
+            is_git_repo: false,
             filtered_by: None,
             filter_annotation: None,
+            error: None,
+            is_virtual: false,
+            kind: EntryKind::Regular,
+            has_unlisted: false,
+            has_omitted: false,
+            cancelled: false,
         });
     }
 
@@ -80,11 +152,22 @@ pub fn scan_directory_with_legacy_gitignore(
             created: root_metadata.created()?,
             modified: root_metadata.modified()?,
             files_count: 0,
+            mode,
+            uid,
+            gid,
+            approximate: false,
         },
         children: Vec::new(),
         is_gitignored: gitignore.is_ignored(root),
+        is_git_repo: false,
         filtered_by: None,
         filter_annotation: None,
+        error: None,
+        is_virtual: false,
+        kind: EntryKind::Regular,
+        has_unlisted: false,
+        has_omitted: false,
+        cancelled: false,
     };
 
     // For gitignored directories, decide whether to traverse or just provide basic metadata
@@ -96,6 +179,7 @@ pub fn scan_directory_with_legacy_gitignore(
         // If not showing system directories, do a quick scan to get file counts without deep traversal
         let mut file_count = 0;
         let mut total_size = 0;
+        let mut has_subdirs = false;
 
         if let Ok(entries) = fs::read_dir(root) {
             for entry in entries.flatten() {
@@ -104,22 +188,18 @@ pub fn scan_directory_with_legacy_gitignore(
                     if !metadata.is_dir() {
                         file_count += 1;
                     } else {
-                        // For directories, make a rough estimate
-                        // This avoids traversing deeply into large system directories
-                        file_count += 10; // Just a placeholder estimate
+                        // Subdirectories aren't looked into, so the totals
+                        // are lower bounds
+                        has_subdirs = true;
                     }
                 }
             }
         }
 
-        // If total size is still 0 but we know it's a directory, use a placeholder size
-        if total_size == 0 && file_count > 0 {
-            total_size = 1024 * 1024; // 1MB placeholder
-        }
-
         // Update the metadata
         root_entry.metadata.files_count = file_count;
         root_entry.metadata.size = total_size;
+        root_entry.metadata.approximate = has_subdirs;
 
         return Ok(root_entry);
     }
@@ -131,6 +211,7 @@ pub fn scan_directory_with_legacy_gitignore(
         let dir_entry = dir_entry?;
         let path = dir_entry.path();
         let metadata = dir_entry.metadata()?;
+        let (mode, uid, gid) = crate::scanner::permissions(&metadata);
         let name = dir_entry.file_name().to_string_lossy().to_string();
 
         // Check if this specific entry is gitignored
@@ -149,6 +230,7 @@ pub fn scan_directory_with_legacy_gitignore(
                         // Update parent metadata
                         root_entry.metadata.files_count += dir_entry.metadata.files_count;
                         root_entry.metadata.size += dir_entry.metadata.size;
+                        root_entry.metadata.approximate |= dir_entry.metadata.approximate;
                         entries.push(dir_entry);
                     }
                     Err(e) => {
@@ -166,11 +248,22 @@ pub fn scan_directory_with_legacy_gitignore(
                         created: metadata.created()?,
                         modified: metadata.modified()?,
                         files_count: 0,
+                        mode,
+                        uid,
+                        gid,
+                        approximate: false,
                     },
                     children: Vec::new(),
                     is_gitignored,
+                    is_git_repo: false,
                     filtered_by: None,
                     filter_annotation: None,
+                    error: None,
+                    is_virtual: false,
+                    kind: EntryKind::Regular,
+                    has_unlisted: false,
+                    has_omitted: false,
+                    cancelled: false,
                 });
 
                 // Update parent size
@@ -190,11 +283,22 @@ pub fn scan_directory_with_legacy_gitignore(
                     created: metadata.created()?,
                     modified: metadata.modified()?,
                     files_count: 0,
+                    mode,
+                    uid,
+                    gid,
+                    approximate: false,
                 },
                 children: Vec::new(),
                 is_gitignored,
+                is_git_repo: false,
                 filtered_by: None,
                 filter_annotation: None,
+                error: None,
+                is_virtual: false,
+                kind: EntryKind::Regular,
+                has_unlisted: false,
+                has_omitted: false,
+                cancelled: false,
             });
         }
     }
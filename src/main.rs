@@ -1,23 +1,76 @@
-use anyhow::Result;
-use clap::Parser;
-use log::debug;
-use smart_tree::rules::create_default_registry;
+use anyhow::{anyhow, Context, Result};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, Parser, Subcommand};
+use indicatif::{
+    HumanBytes, HumanCount, ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle,
+};
+use smart_tree::rules::{
+    create_registry_with_engine, detect_project_types, display_priorities, trace_to_json,
+    trace_to_text, AllowRule, Combination, FilterRegistry,
+};
 use smart_tree::{
-    format_tree, scan_directory, ColorTheme, DisplayConfig, GitIgnoreContext, SortBy,
+    ansi_to_html, apply_exact_sizes, changes_since, classify_tree, config_path,
+    enable_ansi_support, explain_path, format_exact_sizes, format_html, format_json, format_ndjson,
+    format_tree, format_tree_json, format_tree_xml, format_trees, git_status, html_document,
+    load_scan, lower_io_priority, merge_roots, open_image, read_archive, save_scan, scan,
+    scan_iter, set_color_mode, skip_report, terminal_width, tree_from_paths, verify_snapshot,
+    workspace_badges, ChangeSet, ColorMode, ColorTheme, Config, DirectoryEntry, DisplayConfig,
+    ExactSizes, ExplainOptions, GitIgnoreContext, Grouping, IconStyle, IgnoreEngine, IgnoreFile,
+    ImageFs, LsColors, OutputFormat, Placement, ProgressSink, ScanOptions, ScanProgress, SortBy,
+    TimeStyle, Tokenizer, TreeStats,
 };
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::io::{BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info_span, warn};
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, disable_version_flag = true)]
+#[command(
+    author,
+    version,
+    about,
+    disable_version_flag = true,
+    args_override_self = true,
+    after_help = "Any flag set in a configuration file can be turned off, or a list cleared, with --no-<flag>\n\
+                  Show a directory named like a subcommand after --, as in `smart-tree -- stats`"
+)]
 struct Args {
-    /// Directory path to display
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Directory paths to display; several are shown as consecutive trees
     #[arg(default_value = ".")]
-    path: PathBuf,
+    paths: Vec<PathBuf>,
+
+    /// Show several paths under one combined root instead of as separate trees
+    #[arg(long)]
+    merge_roots: bool,
+
+    /// Build the tree from newline- or NUL-delimited paths on stdin, without
+    /// reading the filesystem (e.g. `git ls-files | smart-tree --fromfile`)
+    #[arg(long)]
+    fromfile: bool,
+
+    /// Show the structure of a tar archive given as the path, or read from stdin
+    /// with `-` (e.g. `curl ... | smart-tree --archive tar.gz -`)
+    #[arg(long, value_name = "FORMAT", value_parser = ["tar", "tar.gz", "tgz"], conflicts_with = "fromfile")]
+    archive: Option<String>,
 
     /// Maximum number of lines in output
     #[arg(long, default_value_t = 200)]
     max_lines: usize,
 
+    /// Fit the tree into about this many LLM tokens instead of --max-lines
+    #[arg(long, value_name = "N")]
+    max_tokens: Option<usize>,
+
+    /// How tokens are estimated for --max-tokens (chars|words)
+    #[arg(long, default_value = "chars")]
+    tokenizer: String,
+
     /// Maximum items per directory
     #[arg(long, default_value_t = 20)]
     dir_limit: usize,
@@ -26,15 +79,83 @@ struct Args {
     #[arg(short = 'L', long, default_value_t = usize::MAX)]
     max_depth: usize,
 
+    /// Only show entries at least this deep, keeping the directories leading to them
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    min_depth: usize,
+
+    /// Don't expand directories with more than N entries (like `tree --filelimit`)
+    #[arg(long, value_name = "N")]
+    filelimit: Option<usize>,
+
+    /// List directories only, with file counts and sizes still in their totals (like `tree -d`)
+    #[arg(short = 'd', long)]
+    dirs_only: bool,
+
+    /// Only list files matching this glob (can be used multiple times; like `tree -P`)
+    #[arg(short = 'P', long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Leave out files and directories matching this glob (can be used multiple times; like `tree -I`)
+    #[arg(short = 'I', long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Drop directories that end up without any matching files
+    #[arg(long)]
+    prune: bool,
+
+    /// Only list files at least this large (e.g. 10M); directories without any are dropped
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    min_size: Option<u64>,
+
+    /// Only list files at most this large (e.g. 1.5K); directories without any are dropped
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    max_size: Option<u64>,
+
+    /// Only list files modified within a duration (e.g. 2d, 3h) or after a date (e.g. 2024-01-01)
+    #[arg(long, value_name = "WHEN", value_parser = parse_time_bound)]
+    newer_than: Option<TimeBound>,
+
+    /// Only list files modified longer ago than a duration or before a date
+    #[arg(long, value_name = "WHEN", value_parser = parse_time_bound)]
+    older_than: Option<TimeBound>,
+
     /// Sort entries by (name|size|modified|created)
-    #[arg(long, default_value = "name")]
+    #[arg(long, default_value = "name", value_parser = ["name", "size", "modified", "created"])]
     sort_by: String,
 
+    /// Order entries with equal sort keys by (name|size|modified|created); name is always the final fallback
+    #[arg(long, default_value = "name", value_parser = ["name", "size", "modified", "created"])]
+    then_by: String,
+
     /// List directories before files
-    #[arg(long)]
+    #[arg(long, conflicts_with = "dirs_last")]
     dirs_first: bool,
 
-    /// Disable colored output
+    /// List files before directories
+    #[arg(long, conflicts_with = "dirs_first")]
+    dirs_last: bool,
+
+    /// Group entries by type (directories first, or last with --dirs-last) or not at all (none)
+    #[arg(long, value_name = "HOW", value_parser = ["type", "none"])]
+    group_by: Option<String>,
+
+    /// When to color output: auto (terminals only, honoring NO_COLOR), always, or never
+    #[arg(long, value_name = "WHEN", default_value = "auto", value_parser = ["auto", "always", "never"])]
+    color: String,
+
+    /// Make entry names clickable links to their files in supporting terminals (OSC 8)
+    #[arg(long)]
+    hyperlinks: bool,
+
+    /// Color names from the LS_COLORS environment variable, matching ls/eza
+    #[arg(long)]
+    ls_colors: bool,
+
+    /// Ellipsize lines wider than N columns (default: the terminal width; 0 disables)
+    #[arg(long, value_name = "N")]
+    max_width: Option<usize>,
+
+    /// Disable colored output (same as --color never)
     #[arg(long)]
     no_color: bool,
 
@@ -50,6 +171,22 @@ struct Args {
     #[arg(long)]
     no_emoji: bool,
 
+    /// Icon set for file types: emoji, nerd (Nerd Font glyphs), or none
+    #[arg(long, value_name = "STYLE", value_parser = ["emoji", "nerd", "none"])]
+    icons: Option<String>,
+
+    /// Use this icon for a file name or extension, e.g. rs=🦀 (can be used multiple times)
+    #[arg(long, value_name = "NAME=ICON", value_parser = parse_icon_mapping)]
+    icon: Vec<(String, String)>,
+
+    /// Show permission bits (rwxr-xr-x) in the metadata
+    #[arg(long)]
+    perms: bool,
+
+    /// Show owner and group in the metadata (Unix)
+    #[arg(long)]
+    owner: bool,
+
     /// Colorize file sizes based on magnitude
     #[arg(long)]
     color_sizes: bool,
@@ -58,18 +195,96 @@ struct Args {
     #[arg(long)]
     color_dates: bool,
 
+    /// How to show timestamps (relative|iso|full)
+    #[arg(
+        long,
+        default_value = "relative",
+        ignore_case = true,
+        value_parser = ["relative", "iso", "full"]
+    )]
+    time_style: String,
+
+    /// Show absolute timestamps in UTC instead of the local timezone
+    #[arg(long)]
+    utc: bool,
+
+    /// Detect the type of files without a known extension from their content
+    #[arg(long)]
+    sniff: bool,
+
+    /// Print one line per entry from a template instead of the tree, e.g.
+    /// "{path}\t{size}\t{mtime:%Y-%m-%d}" (fields: name, path, type, size, bytes, files,
+    /// mtime, ctime, annotation, depth, indent)
+    #[arg(long, value_name = "TEMPLATE")]
+    format_string: Option<String>,
+
     /// Display detailed metadata for files and directories
     #[arg(long)]
     detailed: bool,
 
+    /// Annotate zero-byte files and directories without children with [empty]
+    #[arg(long)]
+    mark_empty: bool,
+
+    /// Only show empty files and directories (and the directories leading to them)
+    #[arg(long)]
+    only_empty: bool,
+
+    /// Show this many levels inside folded directories before folding (e.g. top-level packages)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    peek_depth: usize,
+
+    /// Don't expand nested Git repositories and submodules (they are always badged [git repo])
+    #[arg(long)]
+    fold_nested_repos: bool,
+
+    /// Keep each scan under ~/.cache/smart-tree and reuse it next time for directories whose
+    /// modification time hasn't changed; files written in place keep their earlier size until
+    /// something else changes in their directory
+    #[arg(long, overrides_with = "no_cache")]
+    cache: bool,
+
+    /// Don't use or update the scan cache, overriding an earlier --cache
+    #[arg(long, overrides_with = "cache")]
+    no_cache: bool,
+
+    /// Highlight entries that are new or modified since the previous run with the same options
+    /// (compared against the scan saved in ~/.cache/smart-tree/)
+    #[arg(long)]
+    highlight_changes: bool,
+
+    /// Mark entries with their git status (staged, modified, untracked, conflict)
+    #[arg(long)]
+    git_status: bool,
+
+    /// With --format json, record a SHA-256 checksum for every file (for `verify`)
+    #[arg(long)]
+    checksums: bool,
+
+    /// After the tree, list folded directories, entries hidden by truncation
+    /// and paths that could not be read
+    #[arg(long)]
+    report: bool,
+
+    /// Size folded directories in full on background threads and list their
+    /// totals after the tree
+    #[arg(long)]
+    exact_sizes: bool,
+
     /// Show system directories like .git, node_modules, target, etc.
     #[arg(long)]
     show_system_dirs: bool,
 
-    /// Ignore .gitignore files when scanning
+    /// Ignore .gitignore files when scanning, and don't fold system directories
+    /// on their account (filtering rules still apply)
     #[arg(long)]
     no_gitignore: bool,
 
+    /// Ignore files to apply: gitignore (the default), dockerignore to preview a
+    /// Docker build context, or both (can be used multiple times)
+    #[arg(long, value_name = "KIND", value_parser = ["gitignore", "dockerignore"])]
+    ignore_file: Vec<String>,
+
     /// Show items that would normally be hidden by filtering rules
     #[arg(long)]
     show_hidden: bool,
@@ -82,21 +297,162 @@ struct Args {
     #[arg(long, value_name = "RULE")]
     enable_rule: Vec<String>,
 
+    /// List entries matching this pattern first among their siblings, at every level; patterns
+    /// with / match the path from the root (overrides project defaults)
+    #[arg(long, value_name = "PATTERN")]
+    promote: Vec<String>,
+
+    /// List entries matching this pattern last among their siblings, at every level; patterns
+    /// with / match the path from the root (overrides project defaults)
+    #[arg(long, value_name = "PATTERN")]
+    demote: Vec<String>,
+
+    /// Never fold entries matching this pattern, even if gitignored or matched by a rule
+    #[arg(long, value_name = "PATTERN")]
+    always_show: Vec<String>,
+
+    /// Like --always-show, and unfold the directories leading to matches of the --keep patterns and of every --always-show pattern
+    #[arg(long, value_name = "PATTERN")]
+    keep: Vec<String>,
+
+    /// Score (0-1) at which a rule folds an entry (default: 0.5)
+    #[arg(long, value_name = "SCORE")]
+    rule_threshold: Option<f32>,
+
+    /// How rule scores combine: the highest, their sum, or the highest-priority rule alone
+    #[arg(long, value_name = "HOW", default_value = "max", value_parser = ["max", "weighted-sum", "priority"])]
+    rule_combination: String,
+
+    /// Multiply a rule's scores by WEIGHT (can be used multiple times)
+    #[arg(long, value_name = "RULE=WEIGHT", value_parser = parse_rule_weight)]
+    rule_weight: Vec<(String, f32)>,
+
     /// List all available filtering rules
     #[arg(long)]
     list_rules: bool,
 
-    /// Show detailed information about rule application
+    /// After the scan, list on stderr which rules scored each path and the final decision
     #[arg(long)]
     rule_debug: bool,
 
+    /// Write every rule evaluation (path, rule, score, decision, time) to FILE as JSON
+    #[arg(long, value_name = "FILE")]
+    rule_debug_json: Option<PathBuf>,
+
+    /// Follow symlinks so they count with their target's size (like `du -L`)
+    #[arg(long)]
+    dereference: bool,
+
+    /// Report disk usage (allocated blocks) instead of apparent sizes, so totals match `du`
+    #[arg(long, overrides_with = "apparent_size")]
+    du: bool,
+
+    /// Report apparent sizes (file lengths); the default, overrides an earlier --du
+    #[arg(long, overrides_with = "du")]
+    apparent_size: bool,
+
+    /// Don't descend into mount points (directories on another filesystem than the root)
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+
+    /// Show entries the filesystem marks as hidden or system (Windows
+    /// attributes), which are left out by default, and dotfiles when the
+    /// `dotfiles` rule is enabled
+    #[arg(short = 'a', long)]
+    all: bool,
+
+    /// Cap the memory used for the scanned tree (e.g. 512M); past it, directories keep totals only
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    max_memory: Option<u64>,
+
+    /// Scan gently: idle IO priority where supported and short pauses between batches
+    #[arg(long)]
+    nice: bool,
+
+    /// Don't show a progress spinner on stderr for scans that take a while
+    #[arg(long)]
+    no_progress: bool,
+
     /// Disable smart filtering rules completely
     #[arg(long)]
     no_rules: bool,
 
+    /// Output format
+    #[arg(
+        long,
+        default_value = "tree",
+        ignore_case = true,
+        value_parser = ["tree", "json", "markdown", "md", "html", "tree-json", "xml", "ndjson"]
+    )]
+    format: String,
+
+    /// Write the tree to a file instead of stdout (.html files are exported as colored HTML
+    /// unless colors are turned off)
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
     /// Display current version
     #[arg(short = 'v', long)]
     version: bool,
+
+    /// Read default flags from this file instead of ~/.config/smart-tree/config.toml
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Ignore the configuration files
+    #[arg(long)]
+    no_config: bool,
+
+    /// Apply a named group of settings: overview, full, llm, or one from a config file
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Explain why a path is shown, annotated or folded
+    Why {
+        /// Path to explain
+        target: PathBuf,
+
+        /// Root directory the scan would start from
+        #[arg(long, default_value = ".")]
+        root: PathBuf,
+    },
+
+    /// Show statistics about the scanned tree, such as the file size distribution
+    Stats {
+        /// Directory to analyze
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+
+    /// Check a directory against a `--format json` snapshot, exiting non-zero on differences
+    Verify {
+        /// Snapshot written by `--format json` (add `--checksums` to compare contents)
+        snapshot: PathBuf,
+
+        /// Directory to verify (defaults to the snapshot's root path)
+        root: Option<PathBuf>,
+    },
+
+    /// Show the merged filesystem of a container image and what each layer adds
+    Image {
+        /// OCI image layout directory or `docker save` archive
+        image: PathBuf,
+    },
+}
+
+impl Command {
+    /// Name the subcommand is invoked by
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Why { .. } => "why",
+            Command::Stats { .. } => "stats",
+            Command::Verify { .. } => "verify",
+            Command::Image { .. } => "image",
+        }
+    }
 }
 
 fn init_logger() {
@@ -108,15 +464,20 @@ fn init_logger() {
         "off"
     };
 
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
-        .format_timestamp(None)
+    // RUST_LOG overrides the default; spans give each message its directory/phase context
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .without_time()
         .init();
 }
 
 fn main() -> Result<()> {
     init_logger();
-    let args = Args::parse();
-    
+    let args = parse_args()?;
+
     // Check if version flag was used
     if args.version {
         let version = env!("CARGO_PKG_VERSION");
@@ -124,108 +485,1220 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    // A directory named like the subcommand is only shown after `--`
+    if let Some(name) = args.command.as_ref().map(Command::name) {
+        if Path::new(name).is_dir() {
+            eprintln!(
+                "note: running `{0}`; use `smart-tree -- {0}` to show the directory",
+                name
+            );
+        }
+    }
+
     // Determine if we should use emoji (default to true unless --no-emoji is specified)
-    let use_emoji = if args.no_emoji {
-        false
-    } else {
-        args.emoji || !args.no_emoji
+    let use_emoji = match args.icons.as_deref() {
+        Some("none") => false,
+        Some(_) => true,
+        None => args.emoji || !args.no_emoji,
+    };
+    let icon_style = match args.icons.as_deref() {
+        Some("nerd") => IconStyle::Nerd,
+        _ => IconStyle::Emoji,
+    };
+
+    let color_mode = match args.color.as_str() {
+        _ if args.no_color => ColorMode::Never,
+        "always" => ColorMode::Always,
+        "never" => ColorMode::Never,
+        _ => ColorMode::Auto,
     };
 
     // Clone the rules vectors for later usage
     let disable_rules = args.disable_rule.clone();
     let enable_rules = args.enable_rule.clone();
 
-    let config = DisplayConfig {
-        max_lines: args.max_lines,
-        dir_limit: args.dir_limit,
-        sort_by: match args.sort_by.as_str() {
-            "size" => SortBy::Size,
-            "modified" => SortBy::Modified,
-            "created" => SortBy::Created,
-            _ => SortBy::Name,
-        },
-        dirs_first: args.dirs_first,
-        use_colors: !args.no_color,
-        color_theme: match args.color_theme.to_lowercase().as_str() {
+    let mut config = DisplayConfig::builder()
+        .max_lines(args.max_lines)
+        .dir_limit(args.dir_limit)
+        .sort_by(parse_sort_key(&args.sort_by))
+        .then_by(parse_sort_key(&args.then_by))
+        .group_by(parse_grouping(&args))
+        .use_colors(set_color_mode(color_mode))
+        .color_theme(match args.color_theme.to_lowercase().as_str() {
             "light" => ColorTheme::Light,
             "dark" => ColorTheme::Dark,
             "none" => ColorTheme::None,
             _ => ColorTheme::Auto,
-        },
-        use_emoji,
-        size_colorize: args.color_sizes,
-        date_colorize: args.color_dates,
-        detailed_metadata: args.detailed,
-        show_system_dirs: args.show_system_dirs,
-        show_filtered: args.show_hidden,
-        disable_rules: args.disable_rule,
-        enable_rules: args.enable_rule,
-        rule_debug: args.rule_debug,
-    };
-
-    // Initialize the GitIgnoreContext
-    let mut gitignore_ctx = if args.no_gitignore {
-        // Create an empty context if gitignore is disabled
-        GitIgnoreContext::new(&args.path)?
-    } else {
-        GitIgnoreContext::new(&args.path)?
-    };
+        })
+        .use_emoji(use_emoji)
+        .size_colorize(args.color_sizes)
+        .date_colorize(args.color_dates)
+        .detailed_metadata(args.detailed)
+        .show_system_dirs(args.show_system_dirs)
+        .show_filtered(args.show_hidden)
+        .disable_rules(args.disable_rule.clone())
+        .enable_rules(args.enable_rule.clone())
+        .rule_debug(args.rule_debug)
+        .mark_empty(args.mark_empty)
+        .only_empty(args.only_empty)
+        .peek_depth(args.peek_depth)
+        .min_depth(args.min_depth)
+        .time_style(match args.time_style.to_lowercase().as_str() {
+            "iso" => TimeStyle::Iso,
+            "full" => TimeStyle::Full,
+            _ => TimeStyle::Relative,
+        })
+        .utc(args.utc)
+        .sniff_content(args.sniff)
+        .fold_nested_repos(args.fold_nested_repos)
+        .skip_report(args.report)
+        .format_string(args.format_string.clone())
+        .hyperlinks(args.hyperlinks)
+        .ls_colors(args.ls_colors.then(LsColors::from_env))
+        .icon_style(icon_style)
+        .icon_overrides(args.icon.iter().cloned().collect())
+        .show_perms(args.perms)
+        .show_owner(args.owner)
+        .show_metadata(!args.fromfile)
+        .max_tokens(args.max_tokens)
+        .tokenizer(Tokenizer::from_name(&args.tokenizer)?)
+        .build();
 
     // Handle --list-rules flag
     if args.list_rules {
         println!("Available filtering rules:\n");
-        println!("  gitignore      - Files/directories matched by .gitignore patterns");
+        println!(
+            "  gitignore      - Files/directories matched by .gitignore (or .hgignore) patterns"
+        );
         println!("  vcs            - Version control system directories (.git, .svn, .hg, .jj)");
         println!("  build_output   - Build output directories (target, dist, build)");
         println!("  dependencies   - Dependency directories (node_modules, venv)");
         println!("  dev_environment - Development environment configs (.vscode, .idea)");
+        println!(
+            "  game_engine    - Unity and Unreal caches (Library/, Temp/, Intermediate/, Saved/)"
+        );
+        println!(
+            "  terraform      - Terraform working directories (.terraform/, .terragrunt-cache/)"
+        );
+        println!("  caches         - Tool caches (.cache, .pytest_cache, .mypy_cache, .turbo)");
+        println!("  bundled        - Minified and bundled assets (*.min.js, *.map, vendor/)");
+        println!("  docs_build     - Documentation builds next to their config (_site/, _build/, book/, site/)");
+        println!("  coverage       - Test coverage reports (coverage/, htmlcov/, lcov.info)");
+        println!(
+            "  generated      - Generated code (*_pb2.py, *.pb.go, generated/, \"DO NOT EDIT\")"
+        );
+        println!("  lockfiles      - Lockfiles and other generated files (Cargo.lock, yarn.lock)");
+        println!("  assets         - Directories mostly holding large media or model weights");
+        println!("  dotfiles       - Dotfiles and dot-directories, left out like ls does (off by default)");
+        println!("  keep           - Paths given with --always-show or --keep, which no other rule can hide");
         println!("\nUsage examples:\n");
         println!("  --disable-rule vcs             # Show VCS directories");
         println!("  --disable-rule dependencies    # Show dependency directories");
+        println!("  --enable-rule dotfiles         # Leave out dotfiles (-a shows them again)");
         println!("  --show-hidden                  # Show all items that would be filtered");
         return Ok(());
     }
 
-    // Initialize rules registry if rules are enabled
-    let rule_registry_option = if args.no_rules {
-        None
-    } else {
-        // Create the rule registry
-        let mut registry = create_default_registry(&args.path)?;
-
-        // Handle enable/disable rules
-        if !disable_rules.is_empty() || !enable_rules.is_empty() {
-            // Apply rule enabling/disabling
-            
-            // Process rule disabling
-            for rule_id in &disable_rules {
-                debug!("Disabling rule: {}", rule_id);
-                registry.disable_rule(rule_id);
+    // Handle the `why` subcommand
+    if let Some(Command::Why { target, root }) = &args.command {
+        let root = root.canonicalize()?;
+        let target = target.canonicalize()?;
+        let engine = ignore_engine(&root, &args)?;
+        let mut ctx = GitIgnoreContext::with_engine(engine.clone());
+        let registry = build_registry(engine, &args, &disable_rules, &enable_rules)?;
+        if let Some(registry) = &registry {
+            ctx.unignore_system_dirs(&registry.disabled_system_dirs());
+        }
+        let options = ExplainOptions {
+            show_system_dirs: config.show_system_dirs,
+            show_filtered: config.show_filtered,
+        };
+        let explanation = explain_path(&root, &target, &mut ctx, registry.as_ref(), options)?;
+        println!("{}", explanation);
+        return Ok(());
+    }
+
+    // Handle the `verify` subcommand
+    if let Some(Command::Verify { snapshot, root }) = &args.command {
+        let snapshot = std::fs::read_to_string(snapshot)?;
+        let verification = verify_snapshot(&snapshot, root.as_deref())?;
+        for divergence in &verification.divergences {
+            println!("{}", divergence);
+        }
+        if !verification.is_ok() {
+            eprintln!(
+                "{} of {} entries differ from the snapshot",
+                verification.divergences.len(),
+                verification.checked
+            );
+            std::process::exit(1);
+        }
+        println!("OK: {} entries match the snapshot", verification.checked);
+        return Ok(());
+    }
+
+    // Container images and archives are read from their tar headers, without unpacking
+    let (image_tree, image) = match (&args.command, &args.archive) {
+        (Some(Command::Image { image }), _) => {
+            let (tree, image) = open_image(image)?;
+            (Some(tree), Some(image))
+        }
+        (None, Some(format)) => (Some(open_archive(&args.paths, format)?), None),
+        _ => (None, None),
+    };
+    let from_archive = image_tree.is_some();
+
+    // The stats subcommand scans its own path, everything else scans the main paths
+    let scan_roots = match &args.command {
+        Some(Command::Stats { path }) => vec![path.clone()],
+        // A listing on stdin, an image or an archive stands in for the filesystem,
+        // so nothing is scanned
+        _ if args.fromfile || from_archive => Vec::new(),
+        _ => args.paths.clone(),
+    };
+
+    // Order the top level for the detected project types, after the user's own patterns
+    let mut project_types = Vec::new();
+    for scan_root in &scan_roots {
+        for project_type in detect_project_types(scan_root) {
+            if !project_types.contains(&project_type) {
+                project_types.push(project_type);
+            }
+        }
+    }
+    let promoted = args.promote.iter().map(|p| (p.clone(), Placement::First));
+    let demoted = args.demote.iter().map(|p| (p.clone(), Placement::Last));
+    config.priorities = promoted
+        .chain(demoted)
+        .chain(display_priorities(&project_types))
+        .collect();
+
+    if args.nice {
+        if let Err(e) = lower_io_priority() {
+            warn!("Failed to lower IO priority: {}", e);
+        }
+    }
+
+    // Scan the directory tree
+    let scan_options = scan_options(&args, &config);
+    let progress = (!args.no_progress && std::io::stderr().is_terminal())
+        .then(|| Arc::new(ScanProgressBar::new()));
+
+    // NDJSON is written as the entries are found, without building the tree
+    let format = parse_output_format(&args.format);
+    if format == OutputFormat::Ndjson {
+        if args.fromfile {
+            return Err(anyhow!(
+                "--format ndjson streams a scan and can't read --fromfile"
+            ));
+        }
+        if from_archive {
+            return Err(anyhow!(
+                "--format ndjson streams a scan and can't show a container image or archive"
+            ));
+        }
+        let roots = scan_roots
+            .iter()
+            .map(|scan_root| {
+                let (gitignore_ctx, options) = root_setup(
+                    scan_root,
+                    &args,
+                    &scan_options,
+                    &disable_rules,
+                    &enable_rules,
+                )?;
+                Ok((scan_root.as_path(), gitignore_ctx, options))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return stream_ndjson(roots, args.output.as_deref());
+    }
+
+    // Each root gets its own gitignore context and rules, as if scanned alone
+    let mut roots = Vec::new();
+    let mut records = Vec::new();
+    let mut changes = ChangeSet::new();
+    for scan_root in &scan_roots {
+        let (mut gitignore_ctx, mut options) = root_setup(
+            scan_root,
+            &args,
+            &scan_options,
+            &disable_rules,
+            &enable_rules,
+        )?;
+        options.progress = progress.clone().map(|bar| bar as Arc<dyn ProgressSink>);
+        let cache = args.cache && !from_archive;
+        let highlight = args.highlight_changes && !from_archive;
+        // Relative time bounds are keyed as written, so the cache survives the clock moving
+        let time_bounds = [&args.newer_than, &args.older_than]
+            .map(|bound| bound.as_ref().map(|bound| bound.written.as_str()));
+        let time_bounds = format!("{:?}", time_bounds);
+        let mut previous = None;
+        if cache || highlight {
+            match load_scan(scan_root, &gitignore_ctx, &options, Some(&time_bounds)) {
+                Ok(saved) => previous = saved.map(Arc::new),
+                Err(e) => warn!("Failed to load the cached scan: {}", e),
             }
-            
-            // Process rule enabling
-            for rule_id in &enable_rules {
-                debug!("Enabling rule: {}", rule_id);
-                registry.enable_rule(rule_id);
+        }
+        // Only reuse it when caching, so files rewritten in place still show as modified
+        if cache {
+            options.previous = previous.clone();
+        }
+        let mut root = info_span!("scan", root = %scan_root.display()).in_scope(|| {
+            scan(scan_root, &mut gitignore_ctx, &options)
+                .with_context(|| format!("scanning {}", scan_root.display()))
+        })?;
+        if let Some(previous) = previous.as_deref().filter(|_| highlight) {
+            changes.extend(changes_since(previous, &root));
+        }
+        if cache || highlight {
+            let saved = save_scan(
+                scan_root,
+                &gitignore_ctx,
+                &options,
+                Some(&time_bounds),
+                &root,
+            );
+            if let Err(e) = saved {
+                warn!("Failed to save the scan to the cache: {}", e);
             }
         }
+        if let Some(registry) = &options.rules {
+            records.extend(registry.take_trace());
+        }
+        // Name each of several roots the way it was given
+        if scan_roots.len() > 1 {
+            root.name = scan_root.display().to_string();
+        }
+        roots.push(root);
+    }
+    if let Some(progress) = &progress {
+        progress.bar.finish_and_clear();
+    }
+    if args.fromfile {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .context("reading paths from stdin")?;
+        roots.push(tree_from_paths(&input));
+    }
+    if let Some(mut tree) = image_tree {
+        // Nothing in an image or archive is gitignored, but the other rules apply
+        let engine = Arc::new(IgnoreEngine::disabled(Path::new("")));
+        if let Some(registry) = build_registry(engine, &args, &disable_rules, &enable_rules)? {
+            classify_tree(&mut tree, &registry, config.show_filtered);
+        }
+        roots.push(tree);
+    }
+
+    // Dump the recorded rule evaluations
+    if let Some(path) = &args.rule_debug_json {
+        std::fs::write(path, trace_to_json(&records))?;
+    }
+    if args.rule_debug {
+        for scan_root in &scan_roots {
+            let records: Vec<_> = records
+                .iter()
+                .filter(|record| record.path.starts_with(scan_root))
+                .cloned()
+                .collect();
+            eprintln!("{}", trace_to_text(&records, scan_root));
+        }
+    }
+
+    // Handle the `stats` subcommand
+    if let Some(Command::Stats { .. }) = &args.command {
+        println!("{}", TreeStats::collect(&roots[0]));
+        return Ok(());
+    }
 
-        Some(registry)
+    // Folded directories are sized while the rest of the output is prepared
+    let mut exact_sizes = args.exact_sizes.then(|| ExactSizes::spawn(&roots, args.du));
+
+    // Structured formats and the HTML page describe the scanned tree as-is,
+    // with the exact sizes written into it; all but tree's own formats merge
+    // several roots into one document
+    let is_document = matches!(
+        format,
+        OutputFormat::Json | OutputFormat::Html | OutputFormat::TreeJson | OutputFormat::Xml
+    );
+    if let Some(sizes) = exact_sizes.take_if(|_| is_document) {
+        let sizes = sizes.wait();
+        for root in &mut roots {
+            apply_exact_sizes(root, &sizes);
+        }
+    }
+    let merged = |roots: Vec<DirectoryEntry>| match roots.len() {
+        1 => roots.into_iter().next().unwrap(),
+        _ => merge_roots(roots),
     };
+    let document = match format {
+        OutputFormat::Json => {
+            let root = merged(std::mem::take(&mut roots));
+            Some(info_span!("render", format = "json").in_scope(|| {
+                let report = args.report.then(|| skip_report(&root, &config));
+                format_json(&root, report.as_ref(), args.checksums)
+            }))
+        }
+        OutputFormat::Html => {
+            let root = merged(std::mem::take(&mut roots));
+            Some(info_span!("render", format = "html").in_scope(|| format_html(&root, &config)))
+        }
+        OutputFormat::TreeJson => Some(
+            info_span!("render", format = "tree-json")
+                .in_scope(|| format_tree_json(&roots, &config)),
+        ),
+        OutputFormat::Xml => {
+            Some(info_span!("render", format = "xml").in_scope(|| format_tree_xml(&roots, &config)))
+        }
+        OutputFormat::Tree | OutputFormat::Markdown | OutputFormat::Ndjson => None,
+    };
+    if let Some(output) = document {
+        match &args.output {
+            Some(output_path) => std::fs::write(output_path, output)?,
+            None => println!("{}", output.trim_end_matches('\n')),
+        }
+        return Ok(());
+    }
 
-    // Scan the directory tree
-    let root = scan_directory(
-        &args.path,
-        &mut gitignore_ctx,
-        rule_registry_option.as_ref(),
-        args.max_depth,
-        Some(config.show_system_dirs),
-        Some(config.show_filtered),
-    )?;
+    config.changes.extend(changes);
+
+    if args.git_status && !from_archive {
+        for scan_root in &scan_roots {
+            match git_status(scan_root) {
+                Ok(statuses) => config.git_status.extend(statuses),
+                Err(e) => warn!("Failed to read git status: {}", e),
+            }
+        }
+    }
+
+    if !from_archive {
+        for scan_root in &scan_roots {
+            config.badges.extend(workspace_badges(scan_root));
+        }
+    }
+
+    if args.merge_roots && roots.len() > 1 {
+        roots = vec![merge_roots(roots)];
+    }
+
+    // Markdown is pasted into other documents, so it is always plain text
+    if format == OutputFormat::Markdown {
+        config.markdown = true;
+        config.use_colors = false;
+        config.hyperlinks = false;
+    }
 
     // Format and print the tree
-    let output = format_tree(&root, &config)?;
-    println!("{}", output);
+    match &args.output {
+        Some(output_path) if is_html_path(output_path) => {
+            // Keep the theme colors so they can be converted into CSS spans,
+            // unless colors were turned off
+            colored::control::set_override(config.use_colors);
+            let mut output = render(&roots, &config, image.as_ref())?;
+            output.push_str(&exact_sizes_footer(exact_sizes));
+            std::fs::write(output_path, html_document(&ansi_to_html(&output)))?;
+        }
+        Some(output_path) => {
+            // Files get plain text unless colors were explicitly requested
+            colored::control::set_override(color_mode == ColorMode::Always);
+            config.hyperlinks &= color_mode == ColorMode::Always;
+            let mut output = render(&roots, &config, image.as_ref())?;
+            output.push_str(&exact_sizes_footer(exact_sizes));
+            std::fs::write(output_path, output)?;
+        }
+        None => {
+            // Fall back to plain output when the console cannot render escape codes
+            if config.use_colors && !enable_ansi_support() {
+                config.use_colors = false;
+            }
+            // Keep lines from wrapping in the terminal
+            config.max_width = match args.max_width {
+                Some(0) => None,
+                Some(width) => Some(width),
+                None if std::io::stdout().is_terminal() && !config.markdown => terminal_width(),
+                None => None,
+            };
+            let output = render(&roots, &config, image.as_ref())?;
+            println!("{}", output);
+            // The tree is out before waiting for the sizes
+            print!(
+                "{}",
+                exact_sizes_footer(exact_sizes).trim_start_matches('\n')
+            );
+        }
+    }
 
     Ok(())
 }
+
+/// The totals of `--exact-sizes`, once every folded directory is sized
+fn exact_sizes_footer(exact_sizes: Option<ExactSizes>) -> String {
+    exact_sizes
+        .map(|sizes| format_exact_sizes(&sizes.wait()))
+        .unwrap_or_default()
+}
+
+/// How long a scan runs before its progress is shown
+const PROGRESS_DELAY: Duration = Duration::from_millis(500);
+
+/// Spinner on stderr with the entries and bytes scanned so far, shown once
+/// a scan takes longer than `PROGRESS_DELAY`
+struct ScanProgressBar {
+    bar: ProgressBar,
+    started: Instant,
+}
+
+impl ScanProgressBar {
+    fn new() -> Self {
+        // Cleared when dropped, so an error doesn't leave it on screen
+        let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::hidden())
+            .with_style(
+                ProgressStyle::with_template("{spinner} {wide_msg}")
+                    .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+            )
+            .with_finish(ProgressFinish::AndClear);
+        Self {
+            bar,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl ProgressSink for ScanProgressBar {
+    fn progress(&self, progress: &ScanProgress) {
+        if self.bar.is_hidden() {
+            if self.started.elapsed() < PROGRESS_DELAY {
+                return;
+            }
+            self.bar.set_draw_target(ProgressDrawTarget::stderr());
+            self.bar.enable_steady_tick(Duration::from_millis(100));
+        }
+        self.bar.set_message(format!(
+            "{} entries, {} - {}",
+            HumanCount(progress.entries as u64),
+            HumanBytes(progress.bytes),
+            progress.path.display()
+        ));
+    }
+}
+
+/// Write one NDJSON line per entry of each root, to a file or stdout
+fn stream_ndjson(
+    roots: Vec<(&Path, GitIgnoreContext, ScanOptions)>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let _span = info_span!("render", format = "ndjson").entered();
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    let written = roots
+        .into_iter()
+        .try_for_each(|(root, gitignore_ctx, options)| {
+            for entry in scan_iter(root, gitignore_ctx, &options) {
+                writeln!(out, "{}", format_ndjson(&entry))?;
+            }
+            Ok::<_, anyhow::Error>(())
+        });
+    match written.and_then(|()| Ok(out.flush()?)) {
+        // The reader (e.g. `head`) stopped early, which is not an error
+        Err(e) if is_broken_pipe(&e) => Ok(()),
+        result => result,
+    }
+}
+
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+}
+
+/// Format the tree (or one tree per root), followed by the layer summary when
+/// showing a container image
+fn render(
+    roots: &[DirectoryEntry],
+    config: &DisplayConfig,
+    image: Option<&ImageFs>,
+) -> Result<String> {
+    let _span = info_span!("render", format = "tree").entered();
+    let mut output = match roots {
+        [root] => format_tree(root, config)?,
+        roots => format_trees(roots, config)?,
+    };
+    if let Some(image) = image {
+        output.push_str(&format!("\n{}", image));
+    }
+    Ok(output)
+}
+
+/// Parse a `NAME=ICON` icon override (NAME is a file name or an extension)
+fn parse_icon_mapping(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .filter(|(name, icon)| !name.is_empty() && !icon.is_empty())
+        .map(|(name, icon)| (name.to_string(), icon.to_string()))
+        .ok_or_else(|| format!("expected NAME=ICON, got '{}'", value))
+}
+
+/// Parse a `RULE=WEIGHT` rule weight
+fn parse_rule_weight(value: &str) -> Result<(String, f32), String> {
+    value
+        .split_once('=')
+        .and_then(|(rule, weight)| Some((rule.to_string(), weight.parse().ok()?)))
+        .filter(|(rule, weight): &(String, f32)| !rule.is_empty() && *weight >= 0.0)
+        .ok_or_else(|| {
+            format!(
+                "expected RULE=WEIGHT with a weight of 0 or more, got '{}'",
+                value
+            )
+        })
+}
+
+/// Per-project configuration file, read from the scanned root (the first one
+/// when several are given, since settings apply to the whole run)
+const PROJECT_CONFIG: &str = ".smart-tree.toml";
+
+/// Flags a project's configuration may set: what is shown and how it is
+/// filtered, but nothing that writes files or runs other programs
+const PROJECT_SETTINGS: &[&str] = &[
+    "all",
+    "always-show",
+    "demote",
+    "dir-limit",
+    "dirs-first",
+    "dirs-last",
+    "dirs-only",
+    "disable-rule",
+    "enable-rule",
+    "exclude",
+    "filelimit",
+    "fold-nested-repos",
+    "group-by",
+    "ignore-file",
+    "include",
+    "keep",
+    "max-depth",
+    "max-lines",
+    "max-tokens",
+    "min-depth",
+    "no-gitignore",
+    "no-progress",
+    "no-rules",
+    "peek-depth",
+    "profile",
+    "promote",
+    "prune",
+    "rule-combination",
+    "rule-threshold",
+    "rule-weight",
+    "show-hidden",
+    "show-system-dirs",
+    "sort-by",
+    "then-by",
+    "tokenizer",
+];
+
+/// Parse the command line, with the user's and the project's configuration
+/// files and the selected profile as defaults
+fn parse_args() -> Result<Args> {
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    let (plain_line, _) = split_negations(&Args::command(), &command_line);
+    let args = Args::parse_from(&plain_line);
+
+    let command = Args::command();
+    let takes_value = |flag: &str| {
+        command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(flag))
+            .map(|arg| arg.get_action().takes_values())
+    };
+    let with_settings = |settings: &[String]| {
+        let full_line = merge_settings(settings, &command_line).unwrap_or_else(|e| e.exit());
+        Args::parse_from(full_line)
+    };
+
+    let mut configs = Vec::new();
+    if !args.no_config {
+        if let Some(path) = args.config.clone().or_else(config_path) {
+            match Config::load(&path)? {
+                Some(config) => configs.push((path, config)),
+                None if args.config.is_some() => {
+                    return Err(anyhow!("config file {} not found", path.display()));
+                }
+                None => {}
+            }
+        }
+
+        let root = match &args.command {
+            Some(Command::Why { root, .. }) => root,
+            Some(Command::Stats { path }) => path,
+            // Settings apply to every root, so only the first one's count
+            _ => &args.paths[0],
+        };
+        let path = root.join(PROJECT_CONFIG);
+        if let Some(config) = root
+            .is_dir()
+            .then(|| Config::load(&path))
+            .transpose()?
+            .flatten()
+        {
+            check_project_config(&config, &path)?;
+            configs.push((path, config));
+        }
+    }
+
+    let mut settings = Vec::new();
+    for (path, config) in &configs {
+        settings.extend(
+            config
+                .args(takes_value)
+                .with_context(|| format!("in {}", path.display()))?,
+        );
+    }
+
+    // The profile may itself come from a configuration file; the project's
+    // definition wins over the user's, and both over the built-in ones
+    let args = with_settings(&settings);
+    if let Some(name) = &args.profile {
+        let builtin = Config::builtin();
+        let profile = configs
+            .iter()
+            .rev()
+            .map(|(_, config)| config)
+            .chain([&builtin])
+            .find_map(|config| config.profile(name))
+            .ok_or_else(|| {
+                let mut names: Vec<_> = configs
+                    .iter()
+                    .flat_map(|(_, config)| config.profile_names())
+                    .chain(builtin.profile_names())
+                    .collect();
+                names.sort_unstable();
+                names.dedup();
+                anyhow!(
+                    "unknown profile '{}' (available: {})",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+        settings.extend(
+            profile
+                .args(takes_value)
+                .with_context(|| format!("in profile '{}'", name))?,
+        );
+        return Ok(with_settings(&settings));
+    }
+    Ok(args)
+}
+
+/// The command line with configuration settings in front, so that flags on
+/// the command line override them and lists combine
+///
+/// Settings are left out when a `--no-<flag>` on the command line turns them
+/// off (or clears the list), or when a flag given later conflicts with them,
+/// so e.g. `--dirs-last` wins over `dirs-first` from a configuration file.
+fn merge_settings(
+    settings: &[String],
+    command_line: &[OsString],
+) -> Result<Vec<OsString>, clap::Error> {
+    let mut command = Args::command();
+    command.build();
+    let (command_line, negated) = split_negations(&command, command_line);
+    let matches = command.clone().try_get_matches_from(&command_line)?;
+
+    let flag_of = |setting: &str| {
+        let setting = setting.trim_start_matches("--");
+        setting.split('=').next().unwrap_or(setting).to_string()
+    };
+    let on_command_line: Vec<String> = command
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .filter_map(|arg| arg.get_long().map(String::from))
+        .collect();
+    let conflicting = |flag: &str| -> Vec<String> {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(flag));
+        arg.map(|arg| command.get_arg_conflicts_with(arg))
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|other| other.get_long().map(String::from))
+            .collect()
+    };
+
+    let mut full_line = command_line[..1].to_vec();
+    for (i, setting) in settings.iter().enumerate() {
+        let flag = flag_of(setting);
+        let conflicting = conflicting(&flag);
+        let mut later = on_command_line
+            .iter()
+            .cloned()
+            .chain(settings[i + 1..].iter().map(|later| flag_of(later)));
+        if !negated.contains(&flag) && !later.any(|later| conflicting.contains(&later)) {
+            full_line.push(OsString::from(setting));
+        }
+    }
+    full_line.extend_from_slice(&command_line[1..]);
+    Ok(full_line)
+}
+
+/// Take `--no-<flag>` negations of the command's flags (other than those
+/// defined as flags of their own) off the command line, returning the
+/// remaining line and the negated flags
+fn split_negations(
+    command: &clap::Command,
+    command_line: &[OsString],
+) -> (Vec<OsString>, Vec<String>) {
+    let defined = |flag: &str| {
+        command
+            .get_arguments()
+            .any(|arg| arg.get_long() == Some(flag))
+    };
+    let mut remaining = command_line[..1].to_vec();
+    let mut negated = Vec::new();
+    let mut args = command_line[1..].iter();
+    for arg in args.by_ref() {
+        if arg == "--" {
+            remaining.push(arg.clone());
+            break;
+        }
+        match arg.to_str().and_then(|arg| arg.strip_prefix("--no-")) {
+            Some(flag) if defined(flag) && !defined(&format!("no-{}", flag)) => {
+                negated.push(flag.to_string());
+            }
+            _ => remaining.push(arg.clone()),
+        }
+    }
+    remaining.extend(args.cloned());
+    (remaining, negated)
+}
+
+/// Reject project settings (including those in its profiles) that only the
+/// user configuration may set
+fn check_project_config(config: &Config, path: &Path) -> Result<()> {
+    let profiles = config
+        .profile_names()
+        .filter_map(|name| config.profile(name));
+    let keys = config
+        .keys()
+        .chain(profiles.flat_map(|profile| profile.keys()));
+    for key in keys {
+        if !PROJECT_SETTINGS.contains(&key.replace('_', "-").as_str()) {
+            return Err(anyhow!(
+                "in {}: '{}' can't be set by a project, only in the user configuration",
+                path.display(),
+                key
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a byte size with an optional binary unit suffix (e.g. 512M, 2G, 1.5K)
+fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let number: f64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("unknown size unit '{}'", unit)),
+    };
+    let bytes = (number * multiplier as f64).round();
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("size '{}' is too large", value));
+    }
+    Ok(bytes as u64)
+}
+
+/// A point in time given on the command line, with the text it was given as
+#[derive(Debug, Clone)]
+struct TimeBound {
+    written: String,
+    time: SystemTime,
+}
+
+/// Parse a point in time given as an age (e.g. 30m, 2d, 1w) counted back from
+/// now, or as a local date or date-time (e.g. 2024-01-01, 2024-01-01 13:45)
+fn parse_time_bound(value: &str) -> Result<TimeBound, String> {
+    let time = parse_time(value.trim())?;
+    Ok(TimeBound {
+        written: value.trim().to_string(),
+        time,
+    })
+}
+
+fn parse_time(value: &str) -> Result<SystemTime, String> {
+    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let seconds = match unit {
+        "s" => Some(1),
+        "m" | "min" => Some(60),
+        "h" => Some(60 * 60),
+        "d" => Some(24 * 60 * 60),
+        "w" => Some(7 * 24 * 60 * 60),
+        _ => None,
+    };
+    if let (Ok(number), Some(seconds)) = (digits.parse::<u64>(), seconds) {
+        let age = std::time::Duration::from_secs(number.saturating_mul(seconds));
+        return Ok(SystemTime::now()
+            .checked_sub(age)
+            .unwrap_or(SystemTime::UNIX_EPOCH));
+    }
+
+    let local = if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0)
+    } else {
+        ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    };
+    if let Some(time) = local.and_then(|time| Local.from_local_datetime(&time).earliest()) {
+        return Ok(time.into());
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.into());
+    }
+    Err(format!(
+        "invalid time '{}' (expected an age like 2d or 3h, or a date like 2024-01-01)",
+        value
+    ))
+}
+
+/// How directories are grouped apart from files; `--group-by none` wins over
+/// a `dirs-first` set in a configuration file
+fn parse_grouping(args: &Args) -> Grouping {
+    match args.group_by.as_deref() {
+        Some("none") => Grouping::None,
+        _ if args.dirs_last => Grouping::DirsLast,
+        Some(_) => Grouping::DirsFirst,
+        None if args.dirs_first => Grouping::DirsFirst,
+        None => Grouping::None,
+    }
+}
+
+/// Parse a sort key name, defaulting to name order
+fn parse_sort_key(key: &str) -> SortBy {
+    match key {
+        "size" => SortBy::Size,
+        "modified" => SortBy::Modified,
+        "created" => SortBy::Created,
+        _ => SortBy::Name,
+    }
+}
+
+/// Read the tree of the single archive in `paths`, `-` meaning stdin
+fn open_archive(paths: &[PathBuf], format: &str) -> Result<DirectoryEntry> {
+    let [path] = paths else {
+        return Err(anyhow!("--archive reads a single archive"));
+    };
+    let gzip = format != "tar";
+    let tree = if path == Path::new("-") {
+        read_archive(std::io::stdin().lock(), gzip, String::from("-"))?
+    } else {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("opening archive {}", path.display()))?;
+        read_archive(BufReader::new(file), gzip, path.display().to_string())?
+    };
+    Ok(tree)
+}
+
+/// Parse an output format name, one of the values clap accepts for --format
+fn parse_output_format(format: &str) -> OutputFormat {
+    match format.to_lowercase().as_str() {
+        "json" => OutputFormat::Json,
+        "markdown" | "md" => OutputFormat::Markdown,
+        "html" => OutputFormat::Html,
+        "tree-json" => OutputFormat::TreeJson,
+        "xml" => OutputFormat::Xml,
+        "ndjson" => OutputFormat::Ndjson,
+        _ => OutputFormat::Tree,
+    }
+}
+
+/// Whether an output path should be exported as HTML
+fn is_html_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"))
+}
+
+/// Ignore files selected with --ignore-file, .gitignore when none are,
+/// less .gitignore with --no-gitignore
+fn ignore_files(args: &Args) -> Vec<IgnoreFile> {
+    let mut files = if args.ignore_file.is_empty() {
+        vec![IgnoreFile::Git]
+    } else {
+        args.ignore_file
+            .iter()
+            .map(|kind| match kind.as_str() {
+                "dockerignore" => IgnoreFile::Docker,
+                _ => IgnoreFile::Git,
+            })
+            .collect()
+    };
+    if args.no_gitignore {
+        files.retain(|file| *file != IgnoreFile::Git);
+    }
+    files
+}
+
+/// The ignore files of a scan root, shared by the scanner and the gitignore
+/// rule, passing everything through when no ignore file is left to apply
+fn ignore_engine(root: &Path, args: &Args) -> Result<Arc<IgnoreEngine>> {
+    let files = ignore_files(args);
+    Ok(Arc::new(if files.is_empty() {
+        IgnoreEngine::disabled(root)
+    } else {
+        IgnoreEngine::new(root, &files)?
+    }))
+}
+
+/// The gitignore context and scan options of one scan root, with the
+/// root's own ignore files and rules
+fn root_setup(
+    scan_root: &Path,
+    args: &Args,
+    scan_options: &ScanOptions,
+    disable_rules: &[String],
+    enable_rules: &[String],
+) -> Result<(GitIgnoreContext, ScanOptions)> {
+    let engine = ignore_engine(scan_root, args)?;
+    let mut gitignore_ctx = GitIgnoreContext::with_engine(engine.clone());
+    let mut options = scan_options.clone();
+    options.rules = build_registry(engine, args, disable_rules, enable_rules)?.map(Arc::new);
+    // Directories of disabled rules are no longer folded as system ones
+    if let Some(registry) = &options.rules {
+        gitignore_ctx.unignore_system_dirs(&registry.disabled_system_dirs());
+    }
+    Ok((gitignore_ctx, options))
+}
+
+/// Create the rule registry unless rules are disabled, applying enable/disable flags
+fn build_registry(
+    engine: Arc<IgnoreEngine>,
+    args: &Args,
+    disable_rules: &[String],
+    enable_rules: &[String],
+) -> Result<Option<FilterRegistry>> {
+    if args.no_rules {
+        return Ok(None);
+    }
+
+    let mut registry = create_registry_with_engine(engine);
+    let shown = always_shown(args);
+    if !shown.is_empty() {
+        registry.add_rule(AllowRule::new(shown, !args.keep.is_empty()));
+    }
+
+    if args.rule_debug || args.rule_debug_json.is_some() {
+        registry.enable_tracing();
+    }
+    if let Some(threshold) = args.rule_threshold {
+        registry.set_threshold(threshold);
+    }
+    registry.set_combination(match args.rule_combination.as_str() {
+        "weighted-sum" => Combination::WeightedSum,
+        "priority" => Combination::PriorityOverride,
+        _ => Combination::Max,
+    });
+    for (rule_id, weight) in &args.rule_weight {
+        registry.set_weight(rule_id, *weight)?;
+    }
+
+    // Process rule disabling
+    for rule_id in disable_rules {
+        debug!("Disabling rule: {}", rule_id);
+        registry.set_enabled(rule_id, false)?;
+    }
+
+    // Process rule enabling
+    for rule_id in enable_rules {
+        debug!("Enabling rule: {}", rule_id);
+        registry.set_enabled(rule_id, true)?;
+    }
+    if args.all {
+        registry.disable_rule("dotfiles");
+    }
+
+    Ok(Some(registry))
+}
+
+/// The scan options the command line asks for, without the per-root rules
+fn scan_options(args: &Args, config: &DisplayConfig) -> ScanOptions {
+    ScanOptions::builder()
+        .max_depth(args.max_depth)
+        .show_system_dirs(config.show_system_dirs)
+        .show_filtered(config.show_filtered)
+        .show_all(args.all)
+        .nice(args.nice)
+        .dereference(args.dereference)
+        .max_memory(args.max_memory.map(|bytes| bytes as usize))
+        .file_limit(args.filelimit)
+        .include(args.include.clone())
+        .exclude(args.exclude.clone())
+        .prune(args.prune)
+        .min_size(args.min_size)
+        .max_size(args.max_size)
+        .newer_than(args.newer_than.as_ref().map(|bound| bound.time))
+        .older_than(args.older_than.as_ref().map(|bound| bound.time))
+        .dirs_only(args.dirs_only)
+        .disk_usage(args.du)
+        .one_file_system(args.one_file_system)
+        .fold_nested_repos(args.fold_nested_repos)
+        .always_show(always_shown(args))
+        .traverse_to_shown(!args.keep.is_empty())
+        .build()
+}
+
+/// Patterns of `--always-show` and `--keep`, which differ only in whether
+/// the directories leading to matches are unfolded too
+fn always_shown(args: &Args) -> Vec<String> {
+    args.always_show.iter().chain(&args.keep).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that scan flags on the command line reach the scan options
+    #[test]
+    fn test_scan_options_from_args() {
+        let args = Args::try_parse_from([
+            "smart-tree",
+            "--all",
+            "--dereference",
+            "--du",
+            "--prune",
+            "--max-depth",
+            "2",
+            "--keep",
+            "*.log",
+        ])
+        .unwrap();
+        let options = scan_options(&args, &DisplayConfig::default());
+        assert!(options.show_all);
+        assert!(options.dereference);
+        assert!(options.disk_usage);
+        assert!(options.prune);
+        assert_eq!(options.max_depth, 2);
+        assert_eq!(options.always_show, ["*.log"]);
+        assert!(options.traverse_to_shown);
+
+        let args = Args::try_parse_from(["smart-tree"]).unwrap();
+        let options = scan_options(&args, &DisplayConfig::default());
+        assert!(!options.show_all);
+        assert!(!options.dereference);
+    }
+
+    /// Test that directories can't be asked to come both first and last
+    #[test]
+    fn test_dirs_first_conflicts_with_dirs_last() {
+        let args = Args::try_parse_from(["smart-tree", "--dirs-last"]).unwrap();
+        assert_eq!(parse_grouping(&args), Grouping::DirsLast);
+
+        let error =
+            Args::try_parse_from(["smart-tree", "--dirs-first", "--dirs-last"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    /// Test that a directory named like a subcommand can be shown after `--`
+    #[test]
+    fn test_subcommand_name_as_path_after_separator() {
+        let args = Args::try_parse_from(["smart-tree", "stats"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Stats { .. })));
+
+        let args = Args::try_parse_from(["smart-tree", "--", "stats"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.paths, [PathBuf::from("stats")]);
+
+        // Settings from configuration files go ahead of the separator
+        let command_line: Vec<OsString> = ["smart-tree", "--", "why"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        let full_line = merge_settings(&["--prune".to_string()], &command_line).unwrap();
+        let args = Args::try_parse_from(full_line).unwrap();
+        assert!(args.command.is_none() && args.prune);
+        assert_eq!(args.paths, [PathBuf::from("why")]);
+    }
+
+    /// Test that unknown output formats are rejected instead of showing the tree
+    #[test]
+    fn test_unknown_format_is_rejected() {
+        let args = Args::try_parse_from(["smart-tree", "--format", "MD"]).unwrap();
+        assert_eq!(parse_output_format(&args.format), OutputFormat::Markdown);
+
+        let error = Args::try_parse_from(["smart-tree", "--format", "yaml"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::InvalidValue);
+    }
+
+    /// Test that misspelled time styles are rejected instead of showing relative times
+    #[test]
+    fn test_unknown_time_style_is_rejected() {
+        let args = Args::try_parse_from(["smart-tree", "--time-style", "ISO"]).unwrap();
+        assert_eq!(args.time_style, "ISO");
+
+        let error = Args::try_parse_from(["smart-tree", "--time-style", "isoo"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::InvalidValue);
+    }
+
+    /// Test that unknown sort keys are rejected instead of sorting by name
+    #[test]
+    fn test_unknown_sort_key_is_rejected() {
+        let args =
+            Args::try_parse_from(["smart-tree", "--sort-by", "size", "--then-by", "modified"])
+                .unwrap();
+        assert_eq!(parse_sort_key(&args.sort_by), SortBy::Size);
+        assert_eq!(parse_sort_key(&args.then_by), SortBy::Modified);
+
+        for flag in ["--sort-by", "--then-by"] {
+            let error = Args::try_parse_from(["smart-tree", flag, "sise"]).unwrap_err();
+            assert_eq!(error.kind(), clap::error::ErrorKind::InvalidValue);
+        }
+    }
+
+    /// Test that the command line can turn off, clear and override configured flags
+    #[test]
+    fn test_command_line_overrides_settings() {
+        let settings = [
+            "--dirs-first",
+            "--prune",
+            "--exclude=*.log",
+            "--max-depth=2",
+        ]
+        .map(String::from);
+        let parse = |command_line: &[&str]| {
+            let command_line: Vec<OsString> = command_line.iter().map(OsString::from).collect();
+            Args::try_parse_from(merge_settings(&settings, &command_line).unwrap()).unwrap()
+        };
+
+        let args = parse(&["smart-tree", "--exclude", "*.tmp", "--max-depth", "3"]);
+        assert!(args.prune);
+        assert_eq!(args.exclude, ["*.log", "*.tmp"]);
+        assert_eq!(args.max_depth, 3);
+
+        let args = parse(&["smart-tree", "--dirs-last", "--no-prune", "--no-exclude"]);
+        assert!(!args.dirs_first);
+        assert_eq!(parse_grouping(&args), Grouping::DirsLast);
+        assert!(!args.prune);
+        assert!(args.exclude.is_empty());
+        assert_eq!(args.max_depth, 2);
+
+        // Flags that have a negation of their own keep it
+        let args = parse(&["smart-tree", "--cache", "--no-cache"]);
+        assert!(args.no_cache);
+    }
+}
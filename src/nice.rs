@@ -0,0 +1,83 @@
+//! Low-impact scanning support for `--nice`
+//!
+//! Nice mode lowers the process IO and CPU priority where the platform
+//! supports it and throttles the scanner by sleeping between batches of
+//! filesystem operations.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// Number of entries processed between two throttling pauses
+const BATCH_SIZE: usize = 256;
+
+/// Length of each throttling pause
+const BATCH_PAUSE: Duration = Duration::from_millis(10);
+
+/// Sleeps briefly after every batch of entries when enabled
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    enabled: bool,
+    processed: usize,
+}
+
+impl Throttle {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            processed: 0,
+        }
+    }
+
+    /// Record one processed entry, pausing at the end of each batch
+    pub(crate) fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.processed += 1;
+        if self.processed.is_multiple_of(BATCH_SIZE) {
+            thread::sleep(BATCH_PAUSE);
+        }
+    }
+}
+
+/// Move the current process to idle IO priority and the lowest CPU priority
+///
+/// On Linux this uses `ioprio_set` with the idle class; other Unix systems
+/// only lower the CPU priority. Elsewhere this is a no-op. Both priorities
+/// are lowered even if the first call fails, which is then reported.
+pub fn lower_io_priority() -> io::Result<()> {
+    #[allow(unused_mut)]
+    let mut outcome = Ok(());
+
+    #[cfg(target_os = "linux")]
+    {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+        // SAFETY: ioprio_set only reads its integer arguments
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                0,
+                IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+            )
+        };
+        if result < 0 {
+            outcome = Err(io::Error::last_os_error());
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        // SAFETY: setpriority only reads its integer arguments
+        let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) };
+        if result < 0 {
+            outcome = outcome.and(Err(io::Error::last_os_error()));
+        }
+    }
+
+    outcome
+}
@@ -0,0 +1,134 @@
+//! Trees built from a list of paths, for `--fromfile`
+//!
+//! Like `tree --fromfile`, the listing (e.g. from `git ls-files` or `find`)
+//! is taken as-is: nothing is read from the filesystem, so entries have no
+//! sizes or dates, and directories are the paths that have entries below them
+//! or end with a separator. Entries are marked virtual, so displaying them
+//! doesn't look them up on disk either.
+
+use crate::types::{DirectoryEntry, EntryKind, EntryMetadata};
+use std::collections::BTreeMap;
+use std::path::{is_separator, Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Default)]
+struct Node {
+    is_dir: bool,
+    children: BTreeMap<String, Node>,
+}
+
+/// Build a tree from newline- or NUL-delimited paths
+///
+/// Input containing a NUL byte is split on NULs (as from `find -print0`),
+/// anything else on lines. The root is a synthetic `.` directory with an
+/// empty path, so entries keep the paths as listed.
+pub fn tree_from_paths(input: &str) -> DirectoryEntry {
+    let separator = if input.contains('\0') { '\0' } else { '\n' };
+    let mut root = Node {
+        is_dir: true,
+        ..Node::default()
+    };
+
+    for line in input.split(separator) {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut node = &mut root;
+        for component in Path::new(line).components() {
+            if component == Component::CurDir {
+                continue;
+            }
+            node.is_dir = true;
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            node = node.children.entry(name).or_default();
+        }
+        if line.ends_with(is_separator) {
+            node.is_dir = true;
+        }
+    }
+
+    to_entry(root, PathBuf::new(), String::from("."))
+}
+
+fn to_entry(node: Node, path: PathBuf, name: String) -> DirectoryEntry {
+    let children: Vec<DirectoryEntry> = node
+        .children
+        .into_iter()
+        .map(|(name, child)| to_entry(child, path.join(&name), name))
+        .collect();
+    let files_count = children
+        .iter()
+        .map(|child| {
+            if child.is_dir {
+                child.metadata.files_count
+            } else {
+                1
+            }
+        })
+        .sum();
+
+    DirectoryEntry {
+        path,
+        name,
+        is_dir: node.is_dir,
+        metadata: EntryMetadata {
+            size: 0,
+            created: UNIX_EPOCH,
+            modified: UNIX_EPOCH,
+            files_count,
+            mode: None,
+            uid: None,
+            gid: None,
+            approximate: false,
+        },
+        children,
+        is_gitignored: false,
+        is_git_repo: false,
+        filtered_by: None,
+        filter_annotation: None,
+        error: None,
+        is_virtual: true,
+        kind: EntryKind::Regular,
+        has_unlisted: false,
+        has_omitted: false,
+        cancelled: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(entry: &DirectoryEntry) -> Vec<&str> {
+        entry.children.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tree_from_paths() {
+        let root =
+            tree_from_paths("src/main.rs\n./src/lib.rs\r\nREADME.md\n\ndocs/\nsrc/bin/cli.rs\n");
+        assert_eq!(root.name, ".");
+        assert_eq!(names(&root), vec!["README.md", "docs", "src"]);
+        assert_eq!(root.metadata.files_count, 4);
+
+        let src = &root.children[2];
+        assert!(src.is_dir);
+        assert!(src.is_virtual);
+        assert_eq!(src.path, PathBuf::from("src"));
+        assert_eq!(names(src), vec!["bin", "lib.rs", "main.rs"]);
+        assert_eq!(src.metadata.files_count, 3);
+        assert_eq!(
+            src.children[0].children[0].path,
+            PathBuf::from("src/bin/cli.rs")
+        );
+
+        // Listed with a trailing slash, so a directory even though it is empty
+        assert!(root.children[1].is_dir);
+        assert!(!root.children[0].is_dir);
+    }
+
+    #[test]
+    fn test_tree_from_nul_separated_paths() {
+        let root = tree_from_paths("a/new\nline.txt\0a/b.txt\0");
+        let a = &root.children[0];
+        assert_eq!(names(a), vec!["b.txt", "new\nline.txt"]);
+    }
+}
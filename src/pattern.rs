@@ -0,0 +1,227 @@
+//! Glob pattern matching for include/exclude options and custom rules
+//!
+//! Supports `*` and `?` (never crossing `/`), `**` (any number of path
+//! segments), `[abc]`/`[a-z]`/`[!a]` character classes, `{a,b}` alternation
+//! (nestable) and `\` escapes.
+
+/// Whether `text` matches the glob `pattern`
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    Glob::new(pattern).matches(text)
+}
+
+/// A glob with its alternations expanded once, for matching many texts
+pub(crate) struct Glob {
+    alternatives: Vec<Vec<char>>,
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Self {
+        let alternatives = expand_braces(pattern)
+            .iter()
+            .map(|alternative| alternative.chars().collect())
+            .collect();
+        Self { alternatives }
+    }
+
+    pub(crate) fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.matches_chars(&text)
+    }
+
+    /// Match text already split into chars, to reuse it across globs
+    pub(crate) fn matches_chars(&self, text: &[char]) -> bool {
+        self.alternatives
+            .iter()
+            .any(|pattern| match_chars(pattern, text))
+    }
+}
+
+/// Expand `{a,b}` alternations into the list of plain patterns they describe
+pub(crate) fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let Some((open, close, commas)) = find_alternation(&chars) else {
+        return vec![pattern.to_string()];
+    };
+
+    let prefix: String = chars[..open].iter().collect();
+    let suffix: String = chars[close + 1..].iter().collect();
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+
+    bounds
+        .windows(2)
+        .flat_map(|window| {
+            let choice: String = chars[window[0] + 1..window[1]].iter().collect();
+            expand_braces(&format!("{}{}{}", prefix, choice, suffix))
+        })
+        .collect()
+}
+
+/// Locate the first `{...}` group with at least one top-level comma
+fn find_alternation(chars: &[char]) -> Option<(usize, usize, Vec<usize>)> {
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => {
+                let mut depth = 0;
+                let mut commas = Vec::new();
+                let mut j = i + 1;
+                while j < chars.len() {
+                    match chars[j] {
+                        '\\' => j += 1,
+                        '{' => depth += 1,
+                        '}' if depth == 0 => {
+                            if !commas.is_empty() {
+                                return Some((i, j, commas));
+                            }
+                            break;
+                        }
+                        '}' => depth -= 1,
+                        ',' if depth == 0 => commas.push(j),
+                        _ => {}
+                    }
+                    j += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let mut rest = &pattern[2..];
+            while rest.first() == Some(&'*') {
+                rest = &rest[1..];
+            }
+            if rest.first() == Some(&'/') {
+                // `**/` matches zero or more whole directories
+                let rest = &rest[1..];
+                match_chars(rest, text)
+                    || (0..text.len()).any(|i| text[i] == '/' && match_chars(rest, &text[i + 1..]))
+            } else {
+                (0..=text.len()).any(|i| match_chars(rest, &text[i..]))
+            }
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if match_chars(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => {
+            matches!(text.first(), Some(c) if *c != '/') && match_chars(&pattern[1..], &text[1..])
+        }
+        Some('[') => match parse_class(pattern) {
+            Some((matcher, len)) => match text.first() {
+                Some(&c) if c != '/' && matcher.matches(c) => {
+                    match_chars(&pattern[len..], &text[1..])
+                }
+                _ => false,
+            },
+            // An unterminated class is a literal `[`
+            None => text.first() == Some(&'[') && match_chars(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && match_chars(&pattern[2..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && match_chars(&pattern[1..], &text[1..]),
+    }
+}
+
+/// A parsed `[...]` character class
+struct CharClass {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+/// Parse a class starting at `pattern[0] == '['`, returning it and its length
+fn parse_class(pattern: &[char]) -> Option<(CharClass, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < pattern.len() {
+        let c = pattern[i];
+        // A `]` right after the opening bracket is a literal member
+        if c == ']' && i > start {
+            return Some((CharClass { negated, ranges }, i + 1));
+        }
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            ranges.push((c, pattern[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_wildcards() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("test_*", "test_utils"));
+        assert!(glob_match("*config*", "webpack.config.js"));
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(!glob_match("file?.txt", "file10.txt"));
+        assert!(glob_match("[a-c]at", "bat"));
+        assert!(!glob_match("[!a-c]at", "bat"));
+        assert!(glob_match("\\*.md", "*.md"));
+        assert!(!glob_match("\\*.md", "a.md"));
+    }
+
+    #[test]
+    fn test_brace_alternation() {
+        assert_eq!(expand_braces("*.{js,ts}"), vec!["*.js", "*.ts"]);
+        assert_eq!(
+            expand_braces("{src,lib/{a,b}}/x"),
+            vec!["src/x", "lib/a/x", "lib/b/x"]
+        );
+        assert_eq!(expand_braces("{single}"), vec!["{single}"]);
+        assert!(glob_match("*.{png,jpg}", "photo.jpg"));
+        assert!(!glob_match("*.{png,jpg}", "photo.gif"));
+    }
+
+    #[test]
+    fn test_double_star() {
+        assert!(glob_match("**/*.rs", "main.rs"));
+        assert!(glob_match("**/*.rs", "src/display/mod.rs"));
+        assert!(glob_match("src/**/mod.rs", "src/mod.rs"));
+        assert!(glob_match("src/**/mod.rs", "src/display/html/mod.rs"));
+        assert!(!glob_match("src/**/mod.rs", "lib/mod.rs"));
+        assert!(glob_match("docs/**", "docs/guide/intro.md"));
+        assert!(glob_match(
+            "**/node_modules/**",
+            "web/node_modules/react/index.js"
+        ));
+    }
+}
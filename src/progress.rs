@@ -0,0 +1,33 @@
+//! Progress reporting while a tree is scanned
+//!
+//! Scans of large trees can take seconds. A `ProgressSink` set in
+//! `ScanOptions::progress` hears from the scanner as it enters each
+//! directory, with running totals, so callers can show a progress bar.
+
+use std::fmt;
+use std::path::Path;
+
+/// How far a scan has come
+#[derive(Debug, Clone, Copy)]
+pub struct ScanProgress<'a> {
+    /// Entries looked at so far, including those inside folded directories
+    pub entries: usize,
+    /// Bytes counted toward the totals so far
+    pub bytes: u64,
+    /// Directory being read
+    pub path: &'a Path,
+}
+
+/// Receives progress updates from the scanner
+///
+/// Updates arrive once per directory read, on the scanning thread, so
+/// implementations should return quickly.
+pub trait ProgressSink: Send + Sync {
+    fn progress(&self, progress: &ScanProgress);
+}
+
+impl fmt::Debug for dyn ProgressSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressSink")
+    }
+}
@@ -17,8 +17,15 @@
 //! Each rule returns a score between 0.0 and 1.0, with higher scores
 //! indicating higher confidence that a path should be hidden/folded.
 
-use std::collections::HashMap;
+use crate::error::{Error, Result};
+use crate::gitignore::{GitIgnoreContext, IgnoreEngine, IgnoreFile};
+use crate::pattern::glob_match;
+use crate::stats::{format_count, format_size};
+use crate::types::Placement;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Supported project types for specialized filtering
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -35,10 +42,171 @@ pub enum ProjectType {
     Go,
     /// Ruby project (detected by Gemfile)
     Ruby,
+    /// C# / .NET project (detected by *.csproj, *.sln)
+    CSharp,
+    /// C/C++ project (detected by CMakeLists.txt)
+    Cpp,
+    /// PHP project (detected by composer.json)
+    Php,
+    /// Elixir project (detected by mix.exs)
+    Elixir,
+    /// Swift package (detected by Package.swift)
+    Swift,
+    /// Dart/Flutter project (detected by pubspec.yaml)
+    Dart,
+    /// Unity project (detected by Assets/ and ProjectSettings/ together)
+    Unity,
+    /// Unreal Engine project (detected by *.uproject)
+    Unreal,
+    /// Bazel or Buck workspace (detected by WORKSPACE, MODULE.bazel, BUCK)
+    Bazel,
+    /// Terraform configuration (detected by *.tf, terragrunt.hcl)
+    Terraform,
     /// Generic project (no specific type detected)
     Generic,
 }
 
+impl ProjectType {
+    /// Name patterns of entries the tree view lists first among their siblings
+    /// for this project type
+    pub fn promoted_entries(&self) -> &'static [&'static str] {
+        match self {
+            ProjectType::Rust => &["src", "Cargo.toml", "tests", "examples", "build.rs"],
+            ProjectType::NodeJs => &["src", "package.json", "tsconfig.json", "test", "tests"],
+            ProjectType::Python => &["src", "pyproject.toml", "setup.py", "tests"],
+            ProjectType::Java => &["src", "pom.xml", "build.gradle*", "settings.gradle*"],
+            ProjectType::Go => &["cmd", "internal", "pkg", "go.mod", "main.go"],
+            ProjectType::Ruby => &["app", "lib", "spec", "Gemfile"],
+            ProjectType::CSharp => &["*.sln", "*.csproj", "src", "tests"],
+            ProjectType::Cpp => &["CMakeLists.txt", "src", "include", "tests"],
+            ProjectType::Php => &["src", "app", "composer.json", "tests"],
+            ProjectType::Elixir => &["lib", "mix.exs", "test", "config"],
+            ProjectType::Swift => &["Sources", "Tests", "Package.swift"],
+            ProjectType::Dart => &["lib", "pubspec.yaml", "test", "bin"],
+            ProjectType::Unity => &["Assets", "Packages", "ProjectSettings"],
+            ProjectType::Unreal => &["*.uproject", "Source", "Content", "Config"],
+            ProjectType::Bazel => &["MODULE.bazel", "WORKSPACE*", "BUILD*", "BUCK"],
+            ProjectType::Terraform => &["main.tf", "variables.tf", "outputs.tf", "modules"],
+            ProjectType::Generic => &["README*", "src"],
+        }
+    }
+
+    /// Name patterns of entries the tree view lists last among their siblings
+    pub fn demoted_entries(&self) -> &'static [&'static str] {
+        match self {
+            ProjectType::Rust => &[
+                "rustfmt.toml",
+                "clippy.toml",
+                "deny.toml",
+                "rust-toolchain*",
+            ],
+            ProjectType::NodeJs => &[
+                "public",
+                "assets",
+                "*.config.{js,cjs,mjs,ts}",
+                ".eslintrc*",
+                ".prettierrc*",
+                ".babelrc*",
+            ],
+            ProjectType::Python => &["setup.cfg", "tox.ini", ".flake8", "*.egg-info"],
+            ProjectType::Java => &["gradle", "gradlew*", "mvnw*", ".mvn"],
+            ProjectType::Go => &["vendor"],
+            ProjectType::Ruby => &[".rubocop.yml", "bin", "tmp", "log"],
+            ProjectType::CSharp => &["*.user", "*.DotSettings*", "Directory.Build.*"],
+            ProjectType::Cpp => &["cmake", "CMakePresets.json", "third_party", ".clang-*"],
+            ProjectType::Php => &["phpunit.xml*", "phpstan.neon*", ".php-cs-fixer*"],
+            ProjectType::Elixir => &[".formatter.exs", ".credo.exs"],
+            ProjectType::Swift => &[".swiftpm", ".swiftlint.yml"],
+            ProjectType::Dart => &[
+                "android",
+                "ios",
+                "web",
+                "linux",
+                "macos",
+                "windows",
+                "analysis_options.yaml",
+            ],
+            ProjectType::Unity => &["*.sln", "*.csproj", "UserSettings"],
+            ProjectType::Unreal => &["*.sln", ".vsconfig"],
+            ProjectType::Bazel => &[".bazelrc", ".bazelversion", ".buckconfig", "*.bzl"],
+            ProjectType::Terraform => &["*.tfvars", "*.tfstate*", ".terraform.lock.hcl"],
+            ProjectType::Generic => &[],
+        }
+    }
+}
+
+/// Detect the project types of a directory from its marker files
+pub fn detect_project_types(root_path: &Path) -> Vec<ProjectType> {
+    let markers: [(&[&str], ProjectType); 15] = [
+        (&["Cargo.toml"], ProjectType::Rust),
+        (&["package.json"], ProjectType::NodeJs),
+        (&["setup.py", "pyproject.toml"], ProjectType::Python),
+        (&["pom.xml", "build.gradle"], ProjectType::Java),
+        (&["go.mod"], ProjectType::Go),
+        (&["Gemfile"], ProjectType::Ruby),
+        (&["*.csproj", "*.sln"], ProjectType::CSharp),
+        (&["CMakeLists.txt"], ProjectType::Cpp),
+        (&["composer.json"], ProjectType::Php),
+        (&["mix.exs"], ProjectType::Elixir),
+        (&["Package.swift"], ProjectType::Swift),
+        (&["pubspec.yaml"], ProjectType::Dart),
+        (&["*.uproject"], ProjectType::Unreal),
+        (
+            &[
+                "WORKSPACE",
+                "WORKSPACE.bazel",
+                "MODULE.bazel",
+                "BUCK",
+                ".buckconfig",
+            ],
+            ProjectType::Bazel,
+        ),
+        (&["*.tf", "terragrunt.hcl"], ProjectType::Terraform),
+    ];
+
+    // Markers may be globs, so match them against one listing of the root
+    let names: Vec<String> = std::fs::read_dir(root_path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut project_types: Vec<ProjectType> = markers
+        .into_iter()
+        .filter(|(files, _)| {
+            files
+                .iter()
+                .any(|file| names.iter().any(|name| glob_match(file, name)))
+        })
+        .map(|(_, project_type)| project_type)
+        .collect();
+    if ["Assets", "ProjectSettings"]
+        .iter()
+        .all(|dir| root_path.join(dir).is_dir())
+    {
+        project_types.push(ProjectType::Unity);
+    }
+
+    // If no specific type detected, mark as generic
+    if project_types.is_empty() {
+        project_types.push(ProjectType::Generic);
+    }
+    project_types
+}
+
+/// Display priorities for entries of the given project types, in
+/// precedence order (the first matching pattern decides)
+pub fn display_priorities(project_types: &[ProjectType]) -> Vec<(String, Placement)> {
+    let promoted = project_types.iter().flat_map(|t| t.promoted_entries());
+    let demoted = project_types.iter().flat_map(|t| t.demoted_entries());
+    promoted
+        .map(|pattern| (pattern.to_string(), Placement::First))
+        .chain(demoted.map(|pattern| (pattern.to_string(), Placement::Last)))
+        .collect()
+}
+
 /// Context provided to filter rules during evaluation
 pub struct FilterContext<'a> {
     /// Detected project types for the root directory
@@ -53,6 +221,13 @@ pub struct FilterContext<'a> {
     /// Directory tree depth from root
     pub depth: usize,
 
+    /// Whether the path is a directory
+    pub is_dir: bool,
+
+    /// Names and sizes of the files directly inside the directory, once the
+    /// caller has listed it
+    pub files: Option<&'a [(String, u64)]>,
+
     /// Cache of file existence tests (path -> exists)
     pub has_file: HashMap<String, bool>,
 
@@ -71,6 +246,8 @@ impl<'a> FilterContext<'a> {
             path,
             parent_path,
             depth,
+            is_dir: false,
+            files: None,
             has_file: HashMap::new(),
             extension_counts: HashMap::new(),
             root_path,
@@ -79,42 +256,7 @@ impl<'a> FilterContext<'a> {
 
     /// Detect project types for the given path
     pub fn detect_project_types(&mut self) {
-        // Check for Rust project
-        if self.root_path.join("Cargo.toml").exists() {
-            self.project_types.push(ProjectType::Rust);
-        }
-
-        // Check for Node.js project
-        if self.root_path.join("package.json").exists() {
-            self.project_types.push(ProjectType::NodeJs);
-        }
-
-        // Check for Python project
-        if self.root_path.join("setup.py").exists()
-            || self.root_path.join("pyproject.toml").exists()
-        {
-            self.project_types.push(ProjectType::Python);
-        }
-
-        // Check for Java project
-        if self.root_path.join("pom.xml").exists() || self.root_path.join("build.gradle").exists() {
-            self.project_types.push(ProjectType::Java);
-        }
-
-        // Check for Go project
-        if self.root_path.join("go.mod").exists() {
-            self.project_types.push(ProjectType::Go);
-        }
-
-        // Check for Ruby project
-        if self.root_path.join("Gemfile").exists() {
-            self.project_types.push(ProjectType::Ruby);
-        }
-
-        // If no specific type detected, mark as generic
-        if self.project_types.is_empty() {
-            self.project_types.push(ProjectType::Generic);
-        }
+        self.project_types = detect_project_types(self.root_path);
     }
 
     /// Check if file exists in the current directory
@@ -132,7 +274,6 @@ impl<'a> FilterContext<'a> {
 
     /// Check if the current directory contains a file matching a pattern
     pub fn has_file_matching(&self, pattern: &str) -> bool {
-        // Simple glob-style matching
         use std::fs;
 
         if let Ok(entries) = fs::read_dir(self.path) {
@@ -165,33 +306,22 @@ impl<'a> FilterContext<'a> {
                     || self.project_types.contains(&ProjectType::Java)
             }
             "venv" | ".venv" => self.project_types.contains(&ProjectType::Python),
+            "bin" | "obj" => self.project_types.contains(&ProjectType::CSharp),
+            "_build" | "deps" => self.project_types.contains(&ProjectType::Elixir),
+            ".dart_tool" => self.project_types.contains(&ProjectType::Dart),
+            ".build" => self.project_types.contains(&ProjectType::Swift),
+            "vendor" => self.project_types.contains(&ProjectType::Php),
+            name if name.starts_with("cmake-build-") => {
+                self.project_types.contains(&ProjectType::Cpp)
+            }
+            name if name.starts_with("bazel-") || name == "buck-out" => {
+                self.project_types.contains(&ProjectType::Bazel)
+            }
             _ => false,
         }
     }
 }
 
-/// Very simple glob pattern matching (for basic cases only)
-fn glob_match(pattern: &str, name: &str) -> bool {
-    if pattern == "*" {
-        return true;
-    }
-
-    if pattern.starts_with('*') && pattern.ends_with('*') {
-        let inner = &pattern[1..pattern.len() - 1];
-        return name.contains(inner);
-    }
-
-    if let Some(suffix) = pattern.strip_prefix('*') {
-        return name.ends_with(suffix);
-    }
-
-    if let Some(prefix) = pattern.strip_suffix('*') {
-        return name.starts_with(prefix);
-    }
-
-    pattern == name
-}
-
 /// Interface for all filter rules
 pub trait FilterRule: Send + Sync {
     /// Unique identifier for the rule
@@ -211,13 +341,125 @@ pub trait FilterRule: Send + Sync {
     fn annotation(&self) -> &str {
         "[filtered]"
     }
+
+    /// Whether this rule decides alone when it applies, whatever the
+    /// registry's combination strategy (used to veto hiding)
+    fn overrides(&self) -> bool {
+        false
+    }
+
+    /// Annotation for a directory this rule hid, once the scan knows its
+    /// total size and file count (the plain `annotation` when None)
+    fn summary(&self, _size: u64, _files_count: usize) -> Option<String> {
+        None
+    }
+
+    /// Directory names the gitignore context folds as system directories on
+    /// this rule's behalf, shown again when the rule is disabled
+    fn system_dirs(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Whether the paths this rule hides are left out of the listing and
+    /// the totals altogether, instead of being folded or dimmed
+    fn omits(&self) -> bool {
+        false
+    }
+
+    /// The rule's settings, for telling apart scans made with differently
+    /// configured rules (empty when the rule has none)
+    fn settings(&self) -> String {
+        String::new()
+    }
+}
+
+/// Outcome of evaluating a single rule against a path
+#[derive(Debug, Clone)]
+pub struct RuleEvaluation {
+    /// ID of the evaluated rule
+    pub rule_id: String,
+    /// Priority of the rule
+    pub priority: i32,
+    /// Whether the rule is disabled in the registry
+    pub disabled: bool,
+    /// Whether the rule applies to the path (always false for disabled rules)
+    pub applies: bool,
+    /// Score returned by the rule, if it applied
+    pub score: Option<f32>,
+    /// Annotation the rule would attach
+    pub annotation: String,
+}
+
+/// The rule that decided to hide a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HideVerdict<'a> {
+    /// ID of the highest-scoring rule
+    pub rule_id: &'a str,
+    /// Its score, at or above the registry threshold
+    pub score: f32,
+    /// Annotation the rule attaches
+    pub annotation: &'a str,
+}
+
+/// A single recorded rule evaluation, collected when tracing is enabled
+#[derive(Debug, Clone)]
+pub struct RuleTraceRecord {
+    /// Path the rule was evaluated against
+    pub path: PathBuf,
+    /// ID of the evaluated rule
+    pub rule_id: String,
+    /// Score returned by the rule times its weight, if it applied
+    pub score: Option<f32>,
+    /// Outcome of this rule on its own: "hide", "keep" or "not_applicable"
+    pub decision: &'static str,
+    /// Rule the whole evaluation of the path hid it by, once scores combined
+    pub hidden_by: Option<String>,
+    /// Time spent in `applies_to` and `evaluate`
+    pub elapsed: Duration,
+}
+
+/// How the scores of the applicable rules combine into one decision
+///
+/// Each score is first multiplied by its rule's weight (1.0 unless set).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Combination {
+    /// The highest score decides
+    #[default]
+    Max,
+    /// The scores add up, so several weak signals can hide a path together
+    WeightedSum,
+    /// The highest-priority applicable rule decides alone, so e.g. a
+    /// gitignore negation keeps a path other rules would hide
+    PriorityOverride,
 }
 
 /// Collection of filter rules with evaluation logic
 pub struct FilterRegistry {
     rules: Vec<Box<dyn FilterRule>>,
     threshold: f32,
+    combination: Combination,
+    weights: HashMap<String, f32>,
     disabled_rules: Vec<String>,
+    trace: Option<Mutex<Vec<RuleTraceRecord>>>,
+}
+
+impl std::fmt::Debug for FilterRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FilterRegistry")
+            .field(
+                "rules",
+                &self
+                    .rules
+                    .iter()
+                    .map(|rule| (rule.id(), rule.settings()))
+                    .collect::<Vec<_>>(),
+            )
+            .field("threshold", &self.threshold)
+            .field("combination", &self.combination)
+            .field("weights", &self.weights.iter().collect::<BTreeMap<_, _>>())
+            .field("disabled_rules", &self.disabled_rules)
+            .finish()
+    }
 }
 
 impl Default for FilterRegistry {
@@ -225,7 +467,10 @@ impl Default for FilterRegistry {
         Self {
             rules: Vec::new(),
             threshold: 0.5, // Default threshold is 0.5
+            combination: Combination::default(),
+            weights: HashMap::new(),
             disabled_rules: Vec::new(),
+            trace: None,
         }
     }
 }
@@ -248,14 +493,37 @@ impl FilterRegistry {
     pub fn set_threshold(&mut self, threshold: f32) {
         self.threshold = threshold.clamp(0.0, 1.0);
     }
-    
+
+    /// Set how the scores of applicable rules are combined
+    pub fn set_combination(&mut self, combination: Combination) {
+        self.combination = combination;
+    }
+
+    /// Scale a registered rule's scores by `weight`, failing for unknown IDs
+    pub fn set_weight(&mut self, rule_id: &str, weight: f32) -> Result<()> {
+        if self.rule(rule_id).is_none() {
+            return Err(Error::Config(format!(
+                "unknown rule '{}' (available: {})",
+                rule_id,
+                self.rule_ids().join(", ")
+            )));
+        }
+        self.weights.insert(rule_id.to_string(), weight.max(0.0));
+        Ok(())
+    }
+
+    /// Weight applied to a rule's scores
+    pub fn weight(&self, rule_id: &str) -> f32 {
+        self.weights.get(rule_id).copied().unwrap_or(1.0)
+    }
+
     /// Disable a specific rule by ID
     pub fn disable_rule(&mut self, rule_id: &str) {
         if !self.disabled_rules.contains(&rule_id.to_string()) {
             self.disabled_rules.push(rule_id.to_string());
         }
     }
-    
+
     /// Enable a previously disabled rule
     pub fn enable_rule(&mut self, rule_id: &str) {
         self.disabled_rules.retain(|id| id != rule_id);
@@ -266,31 +534,176 @@ impl FilterRegistry {
         self.disabled_rules.contains(&rule_id.to_string())
     }
 
-    /// Evaluate if a path should be hidden based on all applicable rules
-    pub fn should_hide(&self, context: &FilterContext) -> Option<(bool, &str)> {
-        let mut max_score = 0.0;
-        let mut annotation = "[filtered]";
+    /// Look up a registered rule by ID
+    pub fn rule(&self, rule_id: &str) -> Option<&dyn FilterRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.id() == rule_id)
+            .map(|rule| rule.as_ref())
+    }
+
+    /// Whether the given rule leaves the paths it hides out of the listing
+    pub fn omits(&self, rule_id: &str) -> bool {
+        self.rule(rule_id).is_some_and(|rule| rule.omits())
+    }
+
+    /// Summary annotation the given rule gives a directory it hid
+    pub fn summary(&self, rule_id: &str, size: u64, files_count: usize) -> Option<String> {
+        self.rule(rule_id)?.summary(size, files_count)
+    }
+
+    /// IDs of the registered rules, in priority order
+    pub fn rule_ids(&self) -> Vec<&str> {
+        self.rules.iter().map(|rule| rule.id()).collect()
+    }
+
+    /// Enable or disable a registered rule, failing for unknown IDs
+    pub fn set_enabled(&mut self, rule_id: &str, enabled: bool) -> Result<()> {
+        if self.rule(rule_id).is_none() {
+            return Err(Error::Config(format!(
+                "unknown rule '{}' (available: {})",
+                rule_id,
+                self.rule_ids().join(", ")
+            )));
+        }
+        if enabled {
+            self.enable_rule(rule_id);
+        } else {
+            self.disable_rule(rule_id);
+        }
+        Ok(())
+    }
+
+    /// System directories of the disabled rules, which should no longer be
+    /// folded by the gitignore context
+    ///
+    /// A rule weighted below the threshold counts as disabled, since its
+    /// score (at most 1.0) can't reach the threshold on its own.
+    pub fn disabled_system_dirs(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .filter(|rule| {
+                self.is_rule_disabled(rule.id()) || self.weight(rule.id()) < self.threshold
+            })
+            .flat_map(|rule| rule.system_dirs().iter().copied())
+            .collect()
+    }
+
+    /// Get the threshold score for hiding
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Evaluate every registered rule against the context, including disabled
+    /// and non-applicable ones, in priority order
+    pub fn evaluate_all(&self, context: &FilterContext) -> Vec<RuleEvaluation> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let disabled = self.is_rule_disabled(rule.id());
+                let applies = !disabled && rule.applies_to(context);
+                RuleEvaluation {
+                    rule_id: rule.id().to_string(),
+                    priority: rule.priority(),
+                    disabled,
+                    applies,
+                    score: if applies {
+                        Some(rule.evaluate(context))
+                    } else {
+                        None
+                    },
+                    annotation: rule.annotation().to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// Start recording every rule evaluation made by `should_hide`
+    pub fn enable_tracing(&mut self) {
+        self.trace = Some(Mutex::new(Vec::new()));
+    }
+
+    /// Take the evaluations recorded so far (empty if tracing is disabled)
+    pub fn take_trace(&self) -> Vec<RuleTraceRecord> {
+        self.trace
+            .as_ref()
+            .map(|trace| std::mem::take(&mut *trace.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    /// Evaluate if a path should be hidden based on all applicable rules,
+    /// returning the deciding rule when the combined score reaches the
+    /// threshold (for a weighted sum, the rule contributing the most); a
+    /// score of zero never hides, even at a threshold of zero
+    pub fn should_hide(&self, context: &FilterContext) -> Option<HideVerdict<'_>> {
+        let mut verdict: Option<HideVerdict> = None;
+        let mut total = 0.0;
 
+        let mut records = Vec::new();
         for rule in &self.rules {
             // Skip disabled rules
             if self.is_rule_disabled(rule.id()) {
                 continue;
             }
-            
-            if rule.applies_to(context) {
-                let score = rule.evaluate(context);
-                if score > max_score {
-                    max_score = score;
-                    annotation = rule.annotation();
-                }
+
+            let started = Instant::now();
+            let score = if rule.applies_to(context) {
+                Some(rule.evaluate(context) * self.weight(rule.id()))
+            } else {
+                None
+            };
+
+            if self.trace.is_some() {
+                let decision = match score {
+                    Some(score) if score > 0.0 && score >= self.threshold => "hide",
+                    Some(_) => "keep",
+                    None => "not_applicable",
+                };
+                records.push(RuleTraceRecord {
+                    path: context.path.to_path_buf(),
+                    rule_id: rule.id().to_string(),
+                    score,
+                    decision,
+                    hidden_by: None,
+                    elapsed: started.elapsed(),
+                });
+            }
+
+            let Some(score) = score else {
+                continue;
+            };
+            let candidate = HideVerdict {
+                rule_id: rule.id(),
+                score,
+                annotation: rule.annotation(),
+            };
+            if self.combination == Combination::PriorityOverride || rule.overrides() {
+                // Rules are sorted by priority, so the first applicable one decides
+                verdict = Some(candidate);
+                break;
+            }
+            total += score;
+            if score > verdict.map_or(0.0, |v| v.score) {
+                verdict = Some(candidate);
             }
         }
 
-        if max_score >= self.threshold {
-            Some((true, annotation))
-        } else {
-            None
+        if self.combination == Combination::WeightedSum {
+            verdict = verdict.map(|verdict| HideVerdict {
+                score: total,
+                ..verdict
+            });
+        }
+        let verdict =
+            verdict.filter(|verdict| verdict.score > 0.0 && verdict.score >= self.threshold);
+        if let Some(trace) = &self.trace {
+            let hidden_by = verdict.map(|verdict| verdict.rule_id.to_string());
+            for record in &mut records {
+                record.hidden_by = hidden_by.clone();
+            }
+            trace.lock().unwrap().extend(records);
         }
+        verdict
     }
 }
 
@@ -313,13 +726,25 @@ impl FilterRule for BuildOutputRule {
             .and_then(|n| n.to_str())
             .unwrap_or("");
 
+        let has = |project_type| context.project_types.contains(&project_type);
         match file_name {
-            "target" => context.project_types.contains(&ProjectType::Rust),
-            "build" | "dist" => {
-                context.project_types.contains(&ProjectType::NodeJs)
-                    || context.project_types.contains(&ProjectType::Java)
+            "target" => has(ProjectType::Rust),
+            "build" => {
+                has(ProjectType::NodeJs)
+                    || has(ProjectType::Java)
+                    || has(ProjectType::Cpp)
+                    || has(ProjectType::Dart)
             }
-            "__pycache__" => context.project_types.contains(&ProjectType::Python),
+            "dist" => has(ProjectType::NodeJs) || has(ProjectType::Java),
+            "__pycache__" => has(ProjectType::Python),
+            "bin" | "obj" => has(ProjectType::CSharp),
+            "_build" => has(ProjectType::Elixir),
+            ".build" => has(ProjectType::Swift),
+            ".dart_tool" => has(ProjectType::Dart),
+            name if name.starts_with("cmake-build-") => has(ProjectType::Cpp),
+            // Output trees, and the convenience symlinks into them
+            "buck-out" => has(ProjectType::Bazel),
+            name if name.starts_with("bazel-") => has(ProjectType::Bazel),
             _ => false,
         }
     }
@@ -332,6 +757,10 @@ impl FilterRule for BuildOutputRule {
     fn annotation(&self) -> &str {
         "[build output]"
     }
+
+    fn system_dirs(&self) -> &[&str] {
+        &["target", "build", "dist", "__pycache__"]
+    }
 }
 
 /// Built-in rule for hiding dependency directories
@@ -356,6 +785,8 @@ impl FilterRule for DependencyRule {
         match file_name {
             "node_modules" => context.project_types.contains(&ProjectType::NodeJs),
             "venv" | ".venv" => context.project_types.contains(&ProjectType::Python),
+            "vendor" => context.project_types.contains(&ProjectType::Php),
+            "deps" => context.project_types.contains(&ProjectType::Elixir),
             _ => false,
         }
     }
@@ -368,6 +799,10 @@ impl FilterRule for DependencyRule {
     fn annotation(&self) -> &str {
         "[dependencies]"
     }
+
+    fn system_dirs(&self) -> &[&str] {
+        &["node_modules", "venv", ".venv"]
+    }
 }
 
 /// Built-in rule for hiding version control system directories
@@ -399,6 +834,10 @@ impl FilterRule for VCSRule {
     fn annotation(&self) -> &str {
         "[vcs]"
     }
+
+    fn system_dirs(&self) -> &[&str] {
+        &[".git", ".svn", ".hg", ".jj"]
+    }
 }
 
 /// Built-in rule for hiding IDE and editor config directories
@@ -430,130 +869,1311 @@ impl FilterRule for DevEnvironmentRule {
     fn annotation(&self) -> &str {
         "[dev config]"
     }
-}
 
-/// Rule for applying gitignore patterns
-pub struct GitIgnoreRule {
-    contexts: HashMap<PathBuf, crate::gitignore::GitIgnoreContext>,
+    fn system_dirs(&self) -> &[&str] {
+        &[".vscode", ".idea", ".eclipse", ".zed"]
+    }
 }
 
-impl GitIgnoreRule {
-    pub fn new(root_path: &Path) -> Result<Self, anyhow::Error> {
-        let mut contexts = HashMap::new();
-        let root_context = crate::gitignore::GitIgnoreContext::new(root_path)?;
-        contexts.insert(root_path.to_path_buf(), root_context);
+/// Built-in rule for the caches and intermediate output game engines keep
+/// at the top of a Unity or Unreal project
+pub struct GameEngineRule;
 
-        Ok(Self { contexts })
+impl FilterRule for GameEngineRule {
+    fn id(&self) -> &str {
+        "game_engine"
     }
 
-    /// Get or create a GitIgnoreContext for the given path
-    #[allow(dead_code)]
-    fn get_context_for_path(&mut self, _path: &Path) -> &mut crate::gitignore::GitIgnoreContext {
-        let root_path = self.contexts.keys().next().unwrap().clone();
-        self.contexts.get_mut(&root_path).unwrap()
+    fn priority(&self) -> i32 {
+        85
     }
-}
 
-impl FilterRule for GitIgnoreRule {
-    fn id(&self) -> &str {
-        "gitignore"
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        if context.parent_path != context.root_path || !context.is_dir {
+            return false;
+        }
+
+        match file_name {
+            "Library" | "Temp" | "Logs" | "obj" | "UserSettings" => {
+                context.project_types.contains(&ProjectType::Unity)
+            }
+            "Intermediate" | "Saved" | "DerivedDataCache" | "Binaries" => {
+                context.project_types.contains(&ProjectType::Unreal)
+            }
+            _ => false,
+        }
     }
 
-    fn priority(&self) -> i32 {
-        100 // High priority
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.9
     }
 
-    fn applies_to(&self, _context: &FilterContext) -> bool {
-        true // Always check gitignore rules
+    fn annotation(&self) -> &str {
+        "[engine cache]"
     }
+}
 
-    fn evaluate(&self, context: &FilterContext) -> f32 {
-        let path = context.path;
+/// Built-in rule for Terraform and Terragrunt working directories and state
+/// backups, which are unambiguous by name so no project type is required
+pub struct TerraformRule;
 
-        // Get the GitIgnoreContext for this path's root
-        let root_path = self.contexts.keys().next().unwrap();
-        let gitignore_context = self.contexts.get(root_path).unwrap();
+impl FilterRule for TerraformRule {
+    fn id(&self) -> &str {
+        "terraform"
+    }
 
-        // We need to create a mutable copy since is_ignored requires mutation
-        // In a production implementation, we would refactor this to avoid the clone
-        let mut gitignore_context_clone = gitignore_context.clone();
+    fn priority(&self) -> i32 {
+        75
+    }
 
-        // Check if path is ignored
-        if gitignore_context_clone.is_ignored(path) {
-            0.95 // High confidence
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        if context.is_dir {
+            matches!(file_name, ".terraform" | ".terragrunt-cache")
         } else {
-            0.0 // Not ignored
+            glob_match("*.tfstate.backup", file_name)
         }
     }
 
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.85
+    }
+
     fn annotation(&self) -> &str {
-        "[gitignored]"
+        "[terraform cache]"
     }
 }
 
-/// Create a registry with all default rules enabled
-pub fn create_default_registry(root_path: &Path) -> Result<FilterRegistry, anyhow::Error> {
-    let mut registry = FilterRegistry::new();
+/// Built-in rule for tool caches, kept separate from `dependencies` so they
+/// can be toggled on their own
+pub struct CacheRule;
 
-    // Add the gitignore rule
-    let gitignore_rule = GitIgnoreRule::new(root_path)?;
-    registry.add_rule(gitignore_rule);
+impl FilterRule for CacheRule {
+    fn id(&self) -> &str {
+        "caches"
+    }
 
-    // Add other built-in rules
-    registry.add_rule(BuildOutputRule);
-    registry.add_rule(DependencyRule);
-    registry.add_rule(VCSRule);
-    registry.add_rule(DevEnvironmentRule);
+    fn priority(&self) -> i32 {
+        65
+    }
 
-    Ok(registry)
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        matches!(
+            file_name,
+            ".cache"
+                | ".pytest_cache"
+                | ".mypy_cache"
+                | ".ruff_cache"
+                | ".hypothesis"
+                | ".tox"
+                | ".nox"
+                | ".turbo"
+                | ".parcel-cache"
+                | ".sass-cache"
+                | ".eslintcache"
+                | ".stylelintcache"
+        )
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.85
+    }
+
+    fn annotation(&self) -> &str {
+        "[cache]"
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::path::PathBuf;
+/// Built-in rule for minified, bundled and vendored frontend assets
+pub struct BundledAssetsRule;
 
-    #[test]
-    fn test_build_output_rule() {
-        let rule = BuildOutputRule;
-        let path = PathBuf::from("/project/target");
-        let parent = PathBuf::from("/project");
-        let root = PathBuf::from("/project");
+impl FilterRule for BundledAssetsRule {
+    fn id(&self) -> &str {
+        "bundled"
+    }
 
-        let mut context = FilterContext::new(&path, &parent, &root, 1);
-        context.project_types.push(ProjectType::Rust);
+    fn priority(&self) -> i32 {
+        60
+    }
 
-        assert!(rule.applies_to(&context));
-        assert!(rule.evaluate(&context) > 0.5);
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        let parent_name = context
+            .parent_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        if context.is_dir {
+            file_name == "vendor" || (file_name == "build" && parent_name == "public")
+        } else {
+            ["*.min.js", "*.min.css", "*.map", "*.bundle.js"]
+                .iter()
+                .any(|pattern| glob_match(pattern, file_name))
+        }
     }
 
-    #[test]
-    fn test_dependency_rule() {
-        let rule = DependencyRule;
-        let path = PathBuf::from("/project/node_modules");
-        let parent = PathBuf::from("/project");
-        let root = PathBuf::from("/project");
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.75
+    }
 
-        let mut context = FilterContext::new(&path, &parent, &root, 1);
-        context.project_types.push(ProjectType::NodeJs);
+    fn annotation(&self) -> &str {
+        "[bundled]"
+    }
+}
 
-        assert!(rule.applies_to(&context));
-        assert!(rule.evaluate(&context) > 0.5);
+/// Built-in rule for documentation build output, recognized by the site
+/// generator's config next to it (so a plain `site/` or `book/` stays visible)
+pub struct DocsBuildRule;
+
+impl DocsBuildRule {
+    /// Output directory names and the configs that mark their generator
+    const OUTPUTS: &'static [(&'static str, &'static [&'static str])] = &[
+        ("_site", &["_config.yml", "_config.toml"]), // Jekyll
+        ("_build", &["conf.py", "source/conf.py"]),  // Sphinx
+        ("book", &["book.toml"]),                    // mdBook
+        ("site", &["mkdocs.yml", "mkdocs.yaml"]),    // MkDocs
+        ("public", &["hugo.toml", "hugo.yaml"]),     // Hugo
+        ("build", &["docusaurus.config.js", "docusaurus.config.ts"]), // Docusaurus
+    ];
+}
+
+impl FilterRule for DocsBuildRule {
+    fn id(&self) -> &str {
+        "docs_build"
     }
 
-    #[test]
-    fn test_registry_evaluation() {
-        let root = PathBuf::from("/project");
-        let registry = create_default_registry(&root).unwrap();
-        let path = PathBuf::from("/project/target");
-        let parent = PathBuf::from("/project");
+    fn priority(&self) -> i32 {
+        62
+    }
 
-        let mut context = FilterContext::new(&path, &parent, &root, 1);
-        context.project_types.push(ProjectType::Rust);
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
 
-        let result = registry.should_hide(&context);
-        assert!(result.is_some());
-        let (should_hide, _) = result.unwrap();
-        assert!(should_hide);
+        context.is_dir
+            && Self::OUTPUTS
+                .iter()
+                .filter(|(output, _)| *output == file_name)
+                .any(|(_, configs)| {
+                    configs
+                        .iter()
+                        .any(|config| context.parent_path.join(config).is_file())
+                })
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.8
+    }
+
+    fn annotation(&self) -> &str {
+        "[docs build]"
+    }
+}
+
+/// Built-in rule for test-coverage reports and raw coverage data
+pub struct CoverageRule;
+
+impl FilterRule for CoverageRule {
+    fn id(&self) -> &str {
+        "coverage"
+    }
+
+    fn priority(&self) -> i32 {
+        58
+    }
+
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        if context.is_dir {
+            matches!(
+                file_name,
+                "coverage" | "htmlcov" | "lcov-report" | ".nyc_output" | "cov_html"
+            )
+        } else {
+            matches!(
+                file_name,
+                ".coverage" | "lcov.info" | "coverage.xml" | "cobertura.xml"
+            ) || ["tarpaulin-report.*", ".coverage.*", "*.profraw"]
+                .iter()
+                .any(|pattern| glob_match(pattern, file_name))
+        }
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.8
+    }
+
+    fn annotation(&self) -> &str {
+        "[coverage]"
+    }
+}
+
+/// Built-in rule for generated code: well-known generator outputs by name,
+/// `generated/` directories, and source files marked as generated in their
+/// first lines ("DO NOT EDIT", "@generated")
+pub struct GeneratedCodeRule;
+
+impl GeneratedCodeRule {
+    /// Source files worth reading the first lines of
+    const SOURCE_EXTENSIONS: &'static [&'static str] = &[
+        "go", "rs", "py", "js", "ts", "java", "kt", "cs", "dart", "swift", "c", "h", "cc", "cpp",
+        "hpp", "m", "rb", "php",
+    ];
+
+    /// Whether the first lines of a source file say it is generated; the
+    /// caller has already ruled out directories
+    fn has_marker(path: &Path) -> bool {
+        use std::io::Read;
+
+        let is_source = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| Self::SOURCE_EXTENSIONS.contains(&ext));
+        if !is_source {
+            return false;
+        }
+        let Ok(file) = std::fs::File::open(path) else {
+            return false;
+        };
+        let mut head = Vec::new();
+        if file.take(1024).read_to_end(&mut head).is_err() {
+            return false;
+        }
+        String::from_utf8_lossy(&head)
+            .lines()
+            .take(5)
+            .any(|line| line.contains("DO NOT EDIT") || line.contains("@generated"))
+    }
+}
+
+impl FilterRule for GeneratedCodeRule {
+    fn id(&self) -> &str {
+        "generated"
+    }
+
+    fn priority(&self) -> i32 {
+        55
+    }
+
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        if context.is_dir {
+            return matches!(file_name, "generated" | "__generated__");
+        }
+        [
+            "*_pb2.py",
+            "*_pb2_grpc.py",
+            "*.pb.go",
+            "*.pb.cc",
+            "*.pb.h",
+            "*.g.dart",
+            "*.freezed.dart",
+            "*.generated.*",
+        ]
+        .iter()
+        .any(|pattern| glob_match(pattern, file_name))
+            || Self::has_marker(context.path)
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.7
+    }
+
+    fn annotation(&self) -> &str {
+        "[generated]"
+    }
+}
+
+/// Built-in rule for directories mostly holding large binary media: images,
+/// video, audio and model weights
+///
+/// Only the files directly inside a directory are looked at, as listed in
+/// [`FilterContext::files`]; without a listing it doesn't apply. It applies when
+/// large media files make up most of them by count or by bytes, and at least
+/// `MIN_BYTES` in total. Hidden directories are summarized by size and count.
+pub struct LargeAssetsRule;
+
+impl LargeAssetsRule {
+    const EXTENSIONS: &'static [&'static str] = &[
+        "png",
+        "jpg",
+        "jpeg",
+        "gif",
+        "webp",
+        "tif",
+        "tiff",
+        "bmp",
+        "psd",
+        "exr",
+        "hdr",
+        "mp4",
+        "mov",
+        "mkv",
+        "avi",
+        "webm",
+        "wav",
+        "flac",
+        "mp3",
+        "ogg",
+        "bin",
+        "onnx",
+        "pt",
+        "pth",
+        "safetensors",
+        "ckpt",
+        "gguf",
+        "h5",
+        "tflite",
+        "npy",
+        "npz",
+    ];
+    /// Smallest file counted as a large asset
+    const MIN_FILE_BYTES: u64 = 256 * 1024;
+    /// Smallest total of large assets worth folding
+    const MIN_BYTES: u64 = 10 * 1024 * 1024;
+
+    fn is_media(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| Self::EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+    }
+}
+
+impl FilterRule for LargeAssetsRule {
+    fn id(&self) -> &str {
+        "assets"
+    }
+
+    fn priority(&self) -> i32 {
+        40
+    }
+
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let Some(listing) = context.files else {
+            return false;
+        };
+        let (mut files, mut bytes, mut media_files, mut media_bytes) = (0, 0, 0, 0);
+        for (name, size) in listing {
+            files += 1;
+            bytes += size;
+            if *size >= Self::MIN_FILE_BYTES && Self::is_media(Path::new(name)) {
+                media_files += 1;
+                media_bytes += size;
+            }
+        }
+
+        media_bytes >= Self::MIN_BYTES && (media_files * 2 > files || media_bytes * 2 > bytes)
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.7
+    }
+
+    fn annotation(&self) -> &str {
+        "[assets]"
+    }
+
+    fn summary(&self, size: u64, files_count: usize) -> Option<String> {
+        Some(format!(
+            "[assets: {} in {} files]",
+            format_size(size),
+            format_count(files_count)
+        ))
+    }
+}
+
+/// Built-in rule for lockfiles and other machine-managed files
+pub struct LockfileRule;
+
+impl FilterRule for LockfileRule {
+    fn id(&self) -> &str {
+        "lockfiles"
+    }
+
+    fn priority(&self) -> i32 {
+        50
+    }
+
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        matches!(
+            file_name,
+            "Cargo.lock"
+                | "package-lock.json"
+                | "npm-shrinkwrap.json"
+                | "yarn.lock"
+                | "pnpm-lock.yaml"
+                | "bun.lockb"
+                | "poetry.lock"
+                | "Pipfile.lock"
+                | "uv.lock"
+                | "flake.lock"
+                | "Gemfile.lock"
+                | "composer.lock"
+                | "go.sum"
+                | "mix.lock"
+                | "packages.lock.json"
+        ) && !context.is_dir
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.6
+    }
+
+    fn annotation(&self) -> &str {
+        "[generated]"
+    }
+}
+
+/// Built-in rule leaving out dotfiles and dot-directories, as `ls` does
+///
+/// Off unless enabled with `--enable-rule dotfiles`; `-a` turns it off again.
+pub struct HiddenFilesRule;
+
+impl FilterRule for HiddenFilesRule {
+    fn id(&self) -> &str {
+        "dotfiles"
+    }
+
+    fn priority(&self) -> i32 {
+        110 // Above gitignore, so hidden entries aren't folded instead
+    }
+
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let file_name = context
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        context.depth > 0 && file_name.starts_with('.')
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        1.0
+    }
+
+    fn annotation(&self) -> &str {
+        "[hidden]"
+    }
+
+    fn omits(&self) -> bool {
+        true
+    }
+}
+
+/// Rule keeping paths that match always-shown patterns and the entries
+/// below them, whatever other rules say; when traversing, the directories
+/// leading to them are kept too
+///
+/// Patterns containing `/` match the path relative to the scan root, others
+/// any entry name.
+pub struct AllowRule {
+    patterns: Vec<String>,
+    traverse: bool,
+}
+
+impl AllowRule {
+    pub fn new(patterns: Vec<String>, traverse: bool) -> Self {
+        Self { patterns, traverse }
+    }
+
+    /// Whether the path, relative to the scan root, is kept
+    pub fn keeps(&self, relative: &Path) -> bool {
+        if self.patterns.is_empty() || relative.as_os_str().is_empty() {
+            return false;
+        }
+        let parts: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        (1..=parts.len()).any(|len| self.matches(&parts[..len]))
+            || (self.traverse && self.leads_to_match(&parts))
+    }
+
+    fn matches(&self, parts: &[String]) -> bool {
+        self.patterns.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern.trim_start_matches('/'), &parts.join("/"))
+            } else {
+                parts.last().is_some_and(|name| glob_match(pattern, name))
+            }
+        })
+    }
+
+    /// Whether a pattern with `/` could match something below the path
+    fn leads_to_match(&self, parts: &[String]) -> bool {
+        self.patterns
+            .iter()
+            .filter(|pattern| pattern.contains('/'))
+            .any(|pattern| {
+                let globs: Vec<&str> = pattern.trim_start_matches('/').split('/').collect();
+                let mut pairs = globs.iter().zip(parts);
+                globs.len() > parts.len()
+                    && pairs.all(|(glob, part)| *glob == "**" || glob_match(glob, part))
+            })
+    }
+}
+
+impl FilterRule for AllowRule {
+    fn id(&self) -> &str {
+        "keep"
+    }
+
+    fn priority(&self) -> i32 {
+        i32::MAX // Checked before anything that could hide the path
+    }
+
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        let relative = context
+            .path
+            .strip_prefix(context.root_path)
+            .unwrap_or(context.path);
+        self.keeps(relative)
+    }
+
+    fn evaluate(&self, _context: &FilterContext) -> f32 {
+        0.0 // Never hide
+    }
+
+    fn overrides(&self) -> bool {
+        true
+    }
+
+    fn annotation(&self) -> &str {
+        "[kept]"
+    }
+
+    fn settings(&self) -> String {
+        format!("{} traverse={}", self.patterns.join(" "), self.traverse)
+    }
+}
+
+/// Rule for applying gitignore patterns
+pub struct GitIgnoreRule {
+    // Shared by every evaluation, so its caches carry over between paths
+    context: GitIgnoreContext,
+    annotation: &'static str,
+}
+
+impl GitIgnoreRule {
+    pub fn new(root_path: &Path) -> Result<Self> {
+        Self::with_ignore_files(root_path, &[IgnoreFile::Git])
+    }
+
+    /// A rule applying the given ignore files instead of just .gitignore
+    pub fn with_ignore_files(root_path: &Path, files: &[IgnoreFile]) -> Result<Self> {
+        Ok(Self::with_engine(Arc::new(IgnoreEngine::new(
+            root_path, files,
+        )?)))
+    }
+
+    /// A rule applying the ignore files of an engine the scanner shares
+    pub fn with_engine(engine: Arc<IgnoreEngine>) -> Self {
+        let files = engine.files();
+        let annotation = match (
+            files.contains(&IgnoreFile::Git),
+            files.contains(&IgnoreFile::Docker),
+        ) {
+            (true, true) => "[ignored]",
+            (false, true) => "[dockerignored]",
+            _ => "[gitignored]",
+        };
+
+        // System directories are left to the rules that cover them, so
+        // disabling e.g. `vcs` shows `.git` again
+        let mut context = GitIgnoreContext::with_engine(engine);
+        context.clear_system_dirs();
+        Self {
+            context,
+            annotation,
+        }
+    }
+}
+
+impl FilterRule for GitIgnoreRule {
+    fn id(&self) -> &str {
+        "gitignore"
+    }
+
+    fn priority(&self) -> i32 {
+        100 // High priority
+    }
+
+    fn applies_to(&self, context: &FilterContext) -> bool {
+        // Only paths some pattern speaks about, ignoring or re-including them
+        self.context.is_ignored(context.path) || !self.context.trace(context.path).is_empty()
+    }
+
+    fn evaluate(&self, context: &FilterContext) -> f32 {
+        if self.context.is_ignored(context.path) {
+            0.95 // High confidence
+        } else {
+            0.0 // Not ignored
+        }
+    }
+
+    fn annotation(&self) -> &str {
+        self.annotation
+    }
+
+    fn settings(&self) -> String {
+        self.context.settings()
+    }
+}
+
+/// Serialize recorded rule evaluations as a JSON array
+pub fn trace_to_json(records: &[RuleTraceRecord]) -> String {
+    let entries: Vec<serde_json::Value> = records
+        .iter()
+        .map(|record| {
+            serde_json::json!({
+                "path": record.path.to_string_lossy(),
+                "rule_id": record.rule_id,
+                "score": record.score,
+                "decision": record.decision,
+                "hidden_by": record.hidden_by,
+                "elapsed_us": record.elapsed.as_micros() as u64,
+            })
+        })
+        .collect();
+
+    serde_json::to_string_pretty(&entries).unwrap_or_else(|_| String::from("[]"))
+}
+
+/// Summarize recorded rule evaluations for `--rule-debug`: every path some
+/// rule scored, with each rule's score and decision and the final verdict.
+/// Paths are shown relative to `root`; repeated evaluations of a path (as a
+/// child, as a scanned directory and once it is listed) keep the last one,
+/// made knowing the most about the path.
+pub fn trace_to_text(records: &[RuleTraceRecord], root: &Path) -> String {
+    let mut paths: Vec<&Path> = Vec::new();
+    let mut by_path: HashMap<&Path, Vec<&RuleTraceRecord>> = HashMap::new();
+    for record in records {
+        let seen = by_path.entry(&record.path).or_default();
+        if seen.is_empty() {
+            paths.push(&record.path);
+        }
+        match seen.iter_mut().find(|r| r.rule_id == record.rule_id) {
+            Some(earlier) => *earlier = record,
+            None => seen.push(record),
+        }
+    }
+
+    let mut lines = vec![String::from("Rule evaluations:")];
+    for path in paths {
+        let evaluations = &by_path[path];
+        if !evaluations.iter().any(|r| r.score.unwrap_or(0.0) > 0.0) {
+            continue;
+        }
+        // The latest evaluation of the path decides, as it did when scanning
+        let latest = records.iter().rfind(|record| record.path == path);
+        let verdict = latest
+            .and_then(|record| record.hidden_by.as_deref())
+            .map_or(String::from("shown"), |rule_id| {
+                format!("hidden by {}", rule_id)
+            });
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        lines.push(format!("  {}: {}", relative.display(), verdict));
+        for record in evaluations.iter().filter(|r| r.score.is_some()) {
+            lines.push(format!(
+                "    {:<16} {:.2}  {}",
+                record.rule_id,
+                record.score.unwrap_or(0.0),
+                record.decision
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Create a registry with all default rules enabled
+pub fn create_default_registry(root_path: &Path) -> Result<FilterRegistry> {
+    create_registry_with_ignore_files(root_path, &[IgnoreFile::Git])
+}
+
+/// The default registry, with the gitignore rule applying `files`
+pub fn create_registry_with_ignore_files(
+    root_path: &Path,
+    files: &[IgnoreFile],
+) -> Result<FilterRegistry> {
+    Ok(create_registry_with_engine(Arc::new(IgnoreEngine::new(
+        root_path, files,
+    )?)))
+}
+
+/// The default registry, with the gitignore rule reading its ignore files
+/// through `engine`
+pub fn create_registry_with_engine(engine: Arc<IgnoreEngine>) -> FilterRegistry {
+    let mut registry = FilterRegistry::new();
+
+    // Add the gitignore rule
+    registry.add_rule(GitIgnoreRule::with_engine(engine));
+
+    // Add other built-in rules
+    registry.add_rule(BuildOutputRule);
+    registry.add_rule(DependencyRule);
+    registry.add_rule(VCSRule);
+    registry.add_rule(DevEnvironmentRule);
+    registry.add_rule(GameEngineRule);
+    registry.add_rule(TerraformRule);
+    registry.add_rule(CacheRule);
+    registry.add_rule(BundledAssetsRule);
+    registry.add_rule(GeneratedCodeRule);
+    registry.add_rule(CoverageRule);
+    registry.add_rule(DocsBuildRule);
+    registry.add_rule(LockfileRule);
+    registry.add_rule(LargeAssetsRule);
+    registry.add_rule(HiddenFilesRule);
+    registry.disable_rule("dotfiles");
+
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A context for `path` as the scanner builds it, knowing whether the
+    /// path is a directory
+    fn context_for<'a>(
+        path: &'a Path,
+        parent: &'a Path,
+        root: &'a Path,
+        depth: usize,
+    ) -> FilterContext<'a> {
+        let mut context = FilterContext::new(path, parent, root, depth);
+        context.is_dir = path.is_dir();
+        context
+    }
+
+    #[test]
+    fn test_build_output_rule() {
+        let rule = BuildOutputRule;
+        let path = PathBuf::from("/project/target");
+        let parent = PathBuf::from("/project");
+        let root = PathBuf::from("/project");
+
+        let mut context = FilterContext::new(&path, &parent, &root, 1);
+        context.project_types.push(ProjectType::Rust);
+
+        assert!(rule.applies_to(&context));
+        assert!(rule.evaluate(&context) > 0.5);
+    }
+
+    #[test]
+    fn test_dependency_rule() {
+        let rule = DependencyRule;
+        let path = PathBuf::from("/project/node_modules");
+        let parent = PathBuf::from("/project");
+        let root = PathBuf::from("/project");
+
+        let mut context = FilterContext::new(&path, &parent, &root, 1);
+        context.project_types.push(ProjectType::NodeJs);
+
+        assert!(rule.applies_to(&context));
+        assert!(rule.evaluate(&context) > 0.5);
+    }
+
+    #[test]
+    fn test_more_project_types() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("App.csproj"), "<Project />").unwrap();
+        std::fs::write(root.join("CMakeLists.txt"), "").unwrap();
+        std::fs::write(root.join("mix.exs"), "").unwrap();
+        let types = detect_project_types(root);
+        assert_eq!(
+            types,
+            vec![ProjectType::CSharp, ProjectType::Cpp, ProjectType::Elixir]
+        );
+
+        let mut registry = create_default_registry(root).unwrap();
+        registry.set_enabled("gitignore", false).unwrap();
+        let verdict = |name: &str| {
+            let path = root.join(name);
+            let mut context = FilterContext::new(&path, root, root, 1);
+            context.project_types = types.clone();
+            registry
+                .should_hide(&context)
+                .map(|v| v.rule_id.to_string())
+        };
+        assert_eq!(verdict("obj").as_deref(), Some("build_output"));
+        assert_eq!(
+            verdict("cmake-build-debug").as_deref(),
+            Some("build_output")
+        );
+        assert_eq!(verdict("_build").as_deref(), Some("build_output"));
+        assert_eq!(verdict("deps").as_deref(), Some("dependencies"));
+        assert_eq!(verdict(".dart_tool"), None, "Not a Dart project");
+    }
+
+    #[test]
+    fn test_bazel_outputs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("MODULE.bazel"), "").unwrap();
+        let types = detect_project_types(root);
+        assert_eq!(types, vec![ProjectType::Bazel]);
+
+        for name in ["bazel-out", "bazel-bin", "bazel-myrepo", "buck-out"] {
+            let path = root.join(name);
+            let mut context = FilterContext::new(&path, root, root, 1);
+            context.project_types = types.clone();
+            assert!(BuildOutputRule.applies_to(&context), "{} is output", name);
+        }
+        let path = root.join("bazel");
+        let mut context = FilterContext::new(&path, root, root, 1);
+        context.project_types = types;
+        assert!(!BuildOutputRule.applies_to(&context));
+    }
+
+    #[test]
+    fn test_terraform_rule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join(".terraform/providers")).unwrap();
+        std::fs::create_dir_all(root.join("live/.terragrunt-cache")).unwrap();
+        std::fs::write(root.join("main.tf"), "").unwrap();
+        assert_eq!(detect_project_types(root), vec![ProjectType::Terraform]);
+
+        let live = root.join("live");
+        let applies = |path: PathBuf, parent: &Path| {
+            TerraformRule.applies_to(&context_for(&path, parent, root, 1))
+        };
+        assert!(applies(root.join(".terraform"), root));
+        assert!(applies(live.join(".terragrunt-cache"), &live));
+        assert!(applies(root.join("terraform.tfstate.backup"), root));
+        assert!(!applies(root.join("terraform.tfstate"), root));
+    }
+
+    #[test]
+    fn test_game_engine_rule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        for path in ["Assets/Library", "ProjectSettings", "Library", "Temp"] {
+            std::fs::create_dir_all(root.join(path)).unwrap();
+        }
+        let types = detect_project_types(root);
+        assert_eq!(types, vec![ProjectType::Unity]);
+
+        let applies = |path: PathBuf, parent: &Path| {
+            let mut context = context_for(&path, parent, root, 1);
+            context.project_types = types.clone();
+            GameEngineRule.applies_to(&context)
+        };
+        assert!(applies(root.join("Library"), root));
+        assert!(applies(root.join("Temp"), root));
+        let assets = root.join("Assets");
+        assert!(
+            !applies(assets.join("Library"), &assets),
+            "Only the project's own Library"
+        );
+
+        std::fs::write(root.join("Game.uproject"), "{}").unwrap();
+        assert!(detect_project_types(root).contains(&ProjectType::Unreal));
+    }
+
+    #[test]
+    fn test_cache_rule() {
+        let root = PathBuf::from("/project");
+        let registry = create_default_registry(&root).unwrap();
+        for name in [".pytest_cache", ".turbo", ".parcel-cache", ".eslintcache"] {
+            let path = root.join(name);
+            let context = FilterContext::new(&path, &root, &root, 1);
+            let verdict = registry.should_hide(&context).unwrap();
+            assert_eq!(verdict.rule_id, "caches", "{} should be a cache", name);
+            assert_eq!(verdict.annotation, "[cache]");
+        }
+        let path = root.join("cache.rs");
+        let context = FilterContext::new(&path, &root, &root, 1);
+        assert!(!CacheRule.applies_to(&context));
+    }
+
+    #[test]
+    fn test_docs_build_rule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        let docs = root.join("docs");
+        for path in ["docs/_build/html", "book", "site", "guide/book"] {
+            std::fs::create_dir_all(root.join(path)).unwrap();
+        }
+        std::fs::write(docs.join("conf.py"), "").unwrap();
+        std::fs::write(root.join("book.toml"), "").unwrap();
+        let rule = DocsBuildRule;
+        let applies =
+            |path: PathBuf, parent: &Path| rule.applies_to(&context_for(&path, parent, root, 1));
+
+        assert!(applies(docs.join("_build"), &docs));
+        assert!(applies(root.join("book"), root));
+        assert!(
+            !applies(root.join("site"), root),
+            "No mkdocs.yml next to it"
+        );
+        let guide = root.join("guide");
+        assert!(
+            !applies(guide.join("book"), &guide),
+            "No book.toml next to it"
+        );
+    }
+
+    #[test]
+    fn test_coverage_rule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("htmlcov")).unwrap();
+        std::fs::create_dir_all(root.join(".nyc_output")).unwrap();
+        let registry = create_default_registry(root).unwrap();
+
+        for name in [
+            "htmlcov",
+            ".nyc_output",
+            "lcov.info",
+            "tarpaulin-report.html",
+        ] {
+            let path = root.join(name);
+            let context = context_for(&path, root, root, 1);
+            let verdict = registry.should_hide(&context).unwrap();
+            assert_eq!(verdict.rule_id, "coverage", "{} is coverage output", name);
+            assert_eq!(verdict.annotation, "[coverage]");
+        }
+        let path = root.join("coverage.rs");
+        assert!(!CoverageRule.applies_to(&FilterContext::new(&path, root, root, 1)));
+    }
+
+    #[test]
+    fn test_generated_code_rule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/generated")).unwrap();
+        std::fs::write(
+            root.join("api.go"),
+            "// Code generated by protoc-gen-go. DO NOT EDIT.\npackage api\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("main.go"), "package main\n").unwrap();
+        std::fs::write(root.join("notes.txt"), "DO NOT EDIT\n").unwrap();
+        let rule = GeneratedCodeRule;
+        let applies = |path: PathBuf| rule.applies_to(&context_for(&path, root, root, 1));
+
+        assert!(applies(root.join("service_pb2.py")));
+        assert!(applies(root.join("model.g.dart")));
+        assert!(applies(root.join("src/generated")));
+        assert!(applies(root.join("api.go")));
+        assert!(!applies(root.join("main.go")));
+        assert!(
+            !applies(root.join("notes.txt")),
+            "Only source files are read"
+        );
+    }
+
+    #[test]
+    fn test_bundled_assets_rule() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("public/build")).unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        let rule = BundledAssetsRule;
+
+        for name in ["app.min.js", "site.min.css", "app.js.map", "main.bundle.js"] {
+            let path = root.join(name);
+            let context = FilterContext::new(&path, root, root, 1);
+            assert!(rule.applies_to(&context), "{} should be bundled", name);
+        }
+        let path = root.join("app.js");
+        assert!(!rule.applies_to(&FilterContext::new(&path, root, root, 1)));
+
+        let public = root.join("public");
+        let path = public.join("build");
+        assert!(rule.applies_to(&context_for(&path, &public, root, 2)));
+        let path = root.join("build");
+        assert!(!rule.applies_to(&context_for(&path, root, root, 1)));
+    }
+
+    #[test]
+    fn test_rule_combination() {
+        struct Fixed(&'static str, i32, f32);
+        impl FilterRule for Fixed {
+            fn id(&self) -> &str {
+                self.0
+            }
+            fn priority(&self) -> i32 {
+                self.1
+            }
+            fn applies_to(&self, _context: &FilterContext) -> bool {
+                true
+            }
+            fn evaluate(&self, _context: &FilterContext) -> f32 {
+                self.2
+            }
+        }
+
+        let root = PathBuf::from("/project");
+        let path = root.join("vendor");
+        let context = FilterContext::new(&path, &root, &root, 1);
+        let mut registry = FilterRegistry::new();
+        registry.add_rule(Fixed("keep", 10, 0.0));
+        registry.add_rule(Fixed("weak", 5, 0.3));
+        registry.add_rule(Fixed("weaker", 1, 0.25));
+
+        // Neither weak signal reaches the threshold on its own
+        assert!(registry.should_hide(&context).is_none());
+
+        registry.set_combination(Combination::WeightedSum);
+        let verdict = registry.should_hide(&context).unwrap();
+        assert_eq!(verdict.rule_id, "weak");
+        assert!((verdict.score - 0.55).abs() < 1e-6);
+
+        registry.set_weight("weaker", 0.5).unwrap();
+        assert!(registry.should_hide(&context).is_none());
+        assert!(matches!(
+            registry.set_weight("missing", 1.0),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            registry.set_enabled("missing", true),
+            Err(Error::Config(_))
+        ));
+
+        registry.set_weight("weak", 2.0).unwrap();
+        registry.set_combination(Combination::Max);
+        assert_eq!(registry.should_hide(&context).unwrap().rule_id, "weak");
+
+        // The highest-priority rule keeps the path despite the others
+        registry.set_combination(Combination::PriorityOverride);
+        assert!(registry.should_hide(&context).is_none());
+    }
+
+    #[test]
+    fn test_allow_rule() {
+        let patterns = vec![String::from("target/doc"), String::from("*.env")];
+        let shown = AllowRule::new(patterns.clone(), false);
+        assert!(
+            !shown.keeps(Path::new("target")),
+            "Not traversing to matches"
+        );
+        assert!(shown.keeps(Path::new("target/doc/index.html")));
+
+        let rule = AllowRule::new(patterns, true);
+        assert!(rule.keeps(Path::new("target")), "Leads to target/doc");
+        assert!(rule.keeps(Path::new("target/doc")));
+        assert!(rule.keeps(Path::new("target/doc/index.html")));
+        assert!(!rule.keeps(Path::new("target/debug")));
+        assert!(rule.keeps(Path::new("config/.prod.env")));
+        assert!(!rule.keeps(Path::new("")));
+
+        // Kept paths stay visible even when a stronger rule would hide them
+        let root = PathBuf::from("/project");
+        let mut registry = create_default_registry(&root).unwrap();
+        registry.add_rule(rule);
+        let path = root.join("target");
+        let mut context = FilterContext::new(&path, &root, &root, 1);
+        context.project_types.push(ProjectType::Rust);
+        assert!(registry.should_hide(&context).is_none());
+        registry.set_threshold(0.0);
+        assert!(
+            registry.should_hide(&context).is_none(),
+            "Kept at any threshold"
+        );
+        let path = root.join("dist");
+        let mut context = FilterContext::new(&path, &root, &root, 1);
+        context.project_types.push(ProjectType::NodeJs);
+        assert!(registry.should_hide(&context).is_some());
+    }
+
+    #[test]
+    fn test_large_assets_rule() {
+        let root = PathBuf::from("/project");
+        let path = root.join("renders");
+        let big = 4 * 1024 * 1024;
+        let renders = [
+            (String::from("a.png"), big),
+            (String::from("b.png"), big),
+            (String::from("c.mp4"), big),
+            (String::from("README.md"), 7),
+        ];
+        let icons = [
+            (String::from("add.png"), 512),
+            (String::from("remove.png"), 512),
+        ];
+        let rule = LargeAssetsRule;
+
+        let mut context = FilterContext::new(&path, &root, &root, 1);
+        context.is_dir = true;
+        assert!(!rule.applies_to(&context), "Not listed yet");
+        context.files = Some(&renders);
+        assert!(rule.applies_to(&context));
+        context.files = Some(&icons);
+        assert!(
+            !rule.applies_to(&context),
+            "Small images are not large assets"
+        );
+        assert_eq!(
+            rule.summary(12 * 1024 * 1024, 4).as_deref(),
+            Some("[assets: 12.0MB in 4 files]")
+        );
+    }
+
+    #[test]
+    fn test_lockfile_rule() {
+        let root = PathBuf::from("/project");
+        let mut registry = create_default_registry(&root).unwrap();
+
+        let path = root.join("Cargo.lock");
+        let context = FilterContext::new(&path, &root, &root, 1);
+        let verdict = registry.should_hide(&context).unwrap();
+        assert_eq!(verdict.rule_id, "lockfiles");
+        assert_eq!(verdict.annotation, "[generated]");
+
+        let path = root.join("Cargo.toml");
+        let context = FilterContext::new(&path, &root, &root, 1);
+        assert!(registry.should_hide(&context).is_none());
+
+        registry.disable_rule("lockfiles");
+        let path = root.join("poetry.lock");
+        let context = FilterContext::new(&path, &root, &root, 1);
+        assert!(registry.should_hide(&context).is_none());
+    }
+
+    #[test]
+    fn test_display_priorities() {
+        let priorities = display_priorities(&[ProjectType::Rust]);
+        assert_eq!(priorities[0], (String::from("src"), Placement::First));
+        assert!(priorities.contains(&(String::from("rustfmt.toml"), Placement::Last)));
+        assert!(display_priorities(&[ProjectType::Generic])
+            .iter()
+            .all(|(_, placement)| *placement == Placement::First));
+    }
+
+    #[test]
+    fn test_registry_evaluation() {
+        let root = PathBuf::from("/project");
+        let registry = create_default_registry(&root).unwrap();
+        let path = PathBuf::from("/project/target");
+        let parent = PathBuf::from("/project");
+
+        let mut context = FilterContext::new(&path, &parent, &root, 1);
+        context.project_types.push(ProjectType::Rust);
+
+        let verdict = registry.should_hide(&context).unwrap();
+        assert_eq!(verdict.rule_id, "build_output");
+        assert!(verdict.score >= registry.threshold());
+    }
+
+    #[test]
+    fn test_evaluate_all_reports_disabled_rules() {
+        let root = PathBuf::from("/project");
+        let mut registry = create_default_registry(&root).unwrap();
+        registry.disable_rule("build_output");
+        let path = PathBuf::from("/project/target");
+
+        let mut context = FilterContext::new(&path, &root, &root, 1);
+        context.project_types.push(ProjectType::Rust);
+
+        let evaluations = registry.evaluate_all(&context);
+        let build = evaluations
+            .iter()
+            .find(|e| e.rule_id == "build_output")
+            .unwrap();
+        assert!(build.disabled);
+        assert!(!build.applies);
+        assert!(build.score.is_none());
+        assert_eq!(evaluations.len(), registry.rule_ids().len());
+    }
+
+    #[test]
+    fn test_tracing_records_evaluations() {
+        let root = PathBuf::from("/project");
+        let mut registry = create_default_registry(&root).unwrap();
+        registry.enable_tracing();
+        let path = PathBuf::from("/project/node_modules");
+
+        let mut context = FilterContext::new(&path, &root, &root, 1);
+        context.project_types.push(ProjectType::NodeJs);
+        registry.should_hide(&context);
+
+        let trace = registry.take_trace();
+        // Rules off by default, like dotfiles, aren't evaluated
+        let rule_count = registry
+            .rule_ids()
+            .iter()
+            .filter(|id| !registry.is_rule_disabled(id))
+            .count();
+        assert_eq!(trace.len(), rule_count);
+        let deps = trace.iter().find(|r| r.rule_id == "dependencies").unwrap();
+        assert_eq!(deps.decision, "hide");
+
+        let json: serde_json::Value = serde_json::from_str(&trace_to_json(&trace)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), rule_count);
+        assert!(registry.take_trace().is_empty());
+
+        let text = trace_to_text(&trace, &root);
+        assert!(text.contains("  node_modules: hidden by dependencies\n"));
+        assert!(text.contains("    dependencies     0.95  hide"));
+        assert!(!text.contains("not_applicable"));
+    }
+
+    #[test]
+    fn test_tracing_weighted_scores() {
+        let root = PathBuf::from("/project");
+        let mut registry = create_default_registry(&root).unwrap();
+        registry.set_weight("lockfiles", 0.5).unwrap();
+        registry.set_weight("build_output", 0.1).unwrap();
+        registry.enable_tracing();
+
+        let path = root.join("Cargo.lock");
+        let context = FilterContext::new(&path, &root, &root, 1);
+        assert!(registry.should_hide(&context).is_none());
+
+        let trace = registry.take_trace();
+        let lockfiles = trace.iter().find(|r| r.rule_id == "lockfiles").unwrap();
+        assert_eq!(lockfiles.decision, "keep");
+        assert!(lockfiles.score.unwrap() <= 0.5);
+        assert!(trace.iter().all(|r| r.hidden_by.is_none()));
+        assert!(trace_to_text(&trace, &root).contains("  Cargo.lock: shown\n"));
+
+        let system_dirs = registry.disabled_system_dirs();
+        assert!(system_dirs.contains(&"target"));
+        assert!(!system_dirs.contains(&"node_modules"));
     }
 }
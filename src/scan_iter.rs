@@ -0,0 +1,236 @@
+//! Streaming directory traversal
+//!
+//! `scan_iter` yields entries depth-first as they are discovered instead of
+//! building a `DirectoryEntry` tree, so memory use is bounded by the width of
+//! the directories on the current path rather than the size of the tree.
+//! Directory totals (`files_count`, aggregated sizes) are not available in
+//! this mode, nor are rules judging a directory by the files inside it, as
+//! directories are yielded before they are listed.
+
+use crate::dir_reader::{read_dir_entries, ListedEntry};
+use crate::gitignore::GitIgnoreContext;
+use crate::nice::Throttle;
+use crate::scanner::{
+    describe_io_error, device_id, has_hidden_attribute, is_nested_repo, timestamps, Classifier,
+    ScanOptions,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::debug;
+
+/// An entry discovered by `scan_iter`
+#[derive(Debug, Clone)]
+pub struct ScannedEntry {
+    pub path: PathBuf,
+    pub name: String,
+    /// Distance from the root, which is yielded first at depth 0
+    pub depth: usize,
+    pub is_dir: bool,
+    /// Size of the entry itself (not aggregated for directories)
+    pub size: u64,
+    pub created: SystemTime,
+    pub modified: SystemTime,
+    pub is_gitignored: bool,
+    /// ID of the rule hiding the entry, whose directories aren't descended into
+    pub filtered_by: Option<String>,
+    pub filter_annotation: Option<String>,
+    /// Why some of this entry's data (or a directory's listing) is missing
+    pub error: Option<String>,
+}
+
+/// One directory being traversed
+struct Frame {
+    pending: std::vec::IntoIter<ListedEntry>,
+    depth: usize,
+    /// Canonical path, tracked to detect symlink loops when dereferencing
+    canonical: Option<PathBuf>,
+}
+
+/// Iterator returned by `scan_iter`
+pub struct ScanIter {
+    gitignore_ctx: GitIgnoreContext,
+    classifier: Classifier,
+    options: ScanOptions,
+    throttle: Throttle,
+    root: Option<PathBuf>,
+    stack: Vec<Frame>,
+    /// Device of the root, set when staying on one filesystem
+    root_device: Option<u64>,
+}
+
+/// Walk the tree below `root`, yielding entries as they are discovered
+///
+/// Entries are gitignored and hidden by `options.rules` as `scan` decides,
+/// with `gitignore_ctx` applying the ignore files. Honors `max_depth`,
+/// `show_system_dirs` and `show_filtered` (gitignored and hidden directories
+/// are yielded but only descended into when set; entries of rules that omit
+/// them aren't yielded at all), `always_show`, `traverse_to_shown`, `dereference`, `nice`,
+/// `file_limit` (larger directories are yielded but not descended into),
+/// `one_file_system`, `fold_nested_repos` and `cancel` (the iterator ends
+/// early once it is set).
+pub fn scan_iter(root: &Path, gitignore_ctx: GitIgnoreContext, options: &ScanOptions) -> ScanIter {
+    ScanIter {
+        gitignore_ctx,
+        classifier: Classifier::new(root, options, options.rules.is_some()),
+        options: options.clone(),
+        throttle: Throttle::new(options.nice),
+        root: Some(root.to_path_buf()),
+        stack: Vec::new(),
+        root_device: options
+            .one_file_system
+            .then(|| fs::metadata(root).ok().and_then(|m| device_id(&m)))
+            .flatten(),
+    }
+}
+
+impl ScanIter {
+    /// The entry at `path`, or None when a rule leaves it out
+    fn visit(&mut self, path: PathBuf, name: String, depth: usize) -> Option<ScannedEntry> {
+        self.throttle.tick();
+        if let Some(parent) = path.parent() {
+            if let Err(e) = self.gitignore_ctx.process_directory(parent) {
+                debug!("Error processing gitignore in {}: {}", parent.display(), e);
+            }
+        }
+        let metadata = self.metadata(&path);
+        // The root is always shown, like in `scan`
+        let (is_gitignored, filtered_by, filter_annotation) = if depth == 0 {
+            (false, None, None)
+        } else {
+            let rules = self.options.rules.as_deref();
+            let is_dir = metadata.as_ref().is_ok_and(|metadata| metadata.is_dir());
+            self.classifier
+                .classify(&self.gitignore_ctx, rules, &path, is_dir)
+        };
+        let omitted = filtered_by.as_deref().is_some_and(|rule_id| {
+            self.options
+                .rules
+                .as_ref()
+                .is_some_and(|registry| registry.omits(rule_id))
+        });
+        if omitted && !self.options.show_filtered {
+            return None;
+        }
+
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                return Some(ScannedEntry {
+                    path,
+                    name,
+                    depth,
+                    is_dir: false,
+                    size: 0,
+                    created: SystemTime::UNIX_EPOCH,
+                    modified: SystemTime::UNIX_EPOCH,
+                    is_gitignored,
+                    filtered_by,
+                    filter_annotation,
+                    error: Some(describe_io_error(&e)),
+                })
+            }
+        };
+        let (created, modified, mut error) = timestamps(&metadata);
+
+        let descend = metadata.is_dir()
+            && depth < self.options.max_depth
+            && (!is_gitignored || self.options.show_system_dirs)
+            && (filtered_by.is_none() || self.options.show_filtered)
+            && (depth == 0 || !self.options.fold_nested_repos || !is_nested_repo(&path))
+            && self
+                .root_device
+                .is_none_or(|root| device_id(&metadata).is_none_or(|device| device == root));
+        if descend {
+            let canonical = self
+                .options
+                .dereference
+                .then(|| path.canonicalize().ok())
+                .flatten();
+            let looped = canonical
+                .as_ref()
+                .is_some_and(|dir| self.stack.iter().any(|f| f.canonical.as_ref() == Some(dir)));
+            match read_dir_entries(&path) {
+                Ok(_) if looped => {}
+                Ok(listing)
+                    if depth > 0
+                        && self
+                            .options
+                            .file_limit
+                            .is_some_and(|limit| listing.len() > limit) => {}
+                Ok(mut listing) => {
+                    listing.sort();
+                    self.stack.push(Frame {
+                        pending: listing.into_iter(),
+                        depth: depth + 1,
+                        canonical,
+                    });
+                }
+                Err(e) => error = Some(describe_io_error(&e)),
+            }
+        }
+
+        Some(ScannedEntry {
+            path,
+            name,
+            depth,
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            created,
+            modified,
+            is_gitignored,
+            filtered_by,
+            filter_annotation,
+            error,
+        })
+    }
+
+    /// Whether the entry is left out for its hidden or system attribute
+    fn is_hidden(&self, path: &Path) -> bool {
+        cfg!(windows)
+            && !self.options.show_all
+            && fs::symlink_metadata(path).is_ok_and(|metadata| has_hidden_attribute(&metadata))
+    }
+
+    fn metadata(&self, path: &Path) -> std::io::Result<fs::Metadata> {
+        let metadata = fs::symlink_metadata(path)?;
+        if self.options.dereference && metadata.file_type().is_symlink() {
+            Ok(fs::metadata(path).unwrap_or(metadata))
+        } else {
+            Ok(metadata)
+        }
+    }
+}
+
+impl Iterator for ScanIter {
+    type Item = ScannedEntry;
+
+    fn next(&mut self) -> Option<ScannedEntry> {
+        if self.options.is_cancelled() {
+            return None;
+        }
+        if let Some(root) = self.root.take() {
+            let name = root
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| root.to_string_lossy().to_string());
+            return self.visit(root, name, 0);
+        }
+
+        loop {
+            let frame = self.stack.last_mut()?;
+            let depth = frame.depth;
+            match frame.pending.next() {
+                Some(ListedEntry { path, .. }) if self.is_hidden(&path) => {}
+                Some(ListedEntry { path, name, .. }) => {
+                    if let Some(entry) = self.visit(path, name, depth) {
+                        return Some(entry);
+                    }
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
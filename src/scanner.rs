@@ -1,11 +1,270 @@
+use crate::dir_reader::{read_dir_entries, ListedEntry};
+use crate::error::{Error, Result};
 use crate::gitignore::GitIgnoreContext;
-use crate::rules::{FilterContext, FilterRegistry};
-use crate::types::{DirectoryEntry, EntryMetadata};
-use anyhow::Result;
-use log::{debug, warn};
+use crate::nice::Throttle;
+use crate::pattern::glob_match;
+use crate::progress::{ProgressSink, ScanProgress};
+use crate::rules::{detect_project_types, AllowRule, FilterContext, FilterRegistry, ProjectType};
+use crate::types::{DirectoryEntry, EntryKind, EntryMetadata};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, debug_span, warn};
 
+/// `filtered_by` value of directories left unexpanded by `ScanOptions::file_limit`
+pub(crate) const FILE_LIMIT_RULE: &str = "filelimit";
+
+/// `filtered_by` value of mount points left unexpanded by `ScanOptions::one_file_system`
+pub(crate) const MOUNT_POINT_RULE: &str = "mountpoint";
+
+/// Id of the rule applying ignore files
+const GITIGNORE_RULE: &str = "gitignore";
+
+/// Annotation of directories left unfinished by a cancelled scan, unless
+/// they carry one already
+const CANCELLED_ANNOTATION: &str = "[truncated: scan cancelled]";
+
+/// Entries counted inside a folded directory before its totals are left
+/// as approximate
+const FOLDED_COUNT_LIMIT: usize = 10_000;
+
+/// Options controlling how a directory tree is scanned
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ScanOptions {
+    /// Maximum depth to traverse
+    pub max_depth: usize,
+    /// Traverse system directories (like .git) instead of folding them
+    pub show_system_dirs: bool,
+    /// Traverse directories hidden by filtering rules
+    pub show_filtered: bool,
+    /// Throttle filesystem access so other workloads are not starved
+    pub nice: bool,
+    /// Follow symlinks so they contribute their target's size (like `du -L`)
+    pub dereference: bool,
+    /// Approximate memory budget for the in-memory tree, in bytes
+    ///
+    /// Once exceeded, remaining entries only contribute to their parent's
+    /// totals and the parent is annotated as truncated.
+    pub max_memory: Option<usize>,
+    /// Don't expand directories (other than the root) with more entries than this
+    pub file_limit: Option<usize>,
+    /// Only keep files matching one of these globs (all files when empty)
+    ///
+    /// Patterns containing `/` match the path relative to the scan root,
+    /// others match the entry name.
+    pub include: Vec<String>,
+    /// Drop files and directories matching any of these globs
+    pub exclude: Vec<String>,
+    /// Drop directories left without any files after filtering
+    pub prune: bool,
+    /// Only keep files at least this many bytes large
+    pub min_size: Option<u64>,
+    /// Only keep files at most this many bytes large
+    pub max_size: Option<u64>,
+    /// Only keep files modified after this time
+    pub newer_than: Option<SystemTime>,
+    /// Only keep files modified before this time
+    pub older_than: Option<SystemTime>,
+    /// List directories only; files still count toward their totals
+    pub dirs_only: bool,
+    /// Report space allocated on disk instead of apparent sizes (like `du`)
+    ///
+    /// Directories count their own blocks and hard-linked files count once
+    /// (within each directory `rescan_paths` reads again, after a rescan).
+    pub disk_usage: bool,
+    /// Don't descend into directories on a different filesystem than the root
+    pub one_file_system: bool,
+    /// Fold nested Git repositories and submodules below the root instead of
+    /// descending into them, totaling their contents like folded system directories
+    pub fold_nested_repos: bool,
+    /// Never fold entries matching these globs (or anything below them),
+    /// whether gitignored or matched by a rule
+    pub always_show: Vec<String>,
+    /// Also never fold the directories leading to `always_show` matches, so
+    /// e.g. `target/doc` is shown inside an otherwise folded `target`
+    pub traverse_to_shown: bool,
+    /// Keep entries the filesystem marks as hidden or system (Windows file
+    /// attributes), which are left out otherwise
+    pub show_all: bool,
+    /// Filtering rules deciding what gets folded, used by `scan`
+    pub rules: Option<Arc<FilterRegistry>>,
+    /// Told about the scan's progress as each directory is read
+    pub progress: Option<Arc<dyn ProgressSink>>,
+    /// Stops the scan once set, from any thread
+    ///
+    /// The tree scanned so far is returned, with the directories left
+    /// unfinished annotated as truncated and their totals as approximate.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// An earlier scan of the same root with the same options, whose
+    /// directories are reused where their modification time hasn't changed
+    ///
+    /// Adding, removing or renaming entries changes a directory's time, but
+    /// writing to a file doesn't, so such files keep their earlier size until
+    /// something else changes in their directory. Subdirectories are still
+    /// checked one by one, and an edited `.gitignore` counts as a change.
+    /// Not used with `prune` or `max_memory`.
+    pub previous: Option<Arc<DirectoryEntry>>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: usize::MAX,
+            show_system_dirs: false,
+            show_filtered: false,
+            nice: false,
+            dereference: false,
+            max_memory: None,
+            file_limit: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            prune: false,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            dirs_only: false,
+            disk_usage: false,
+            one_file_system: false,
+            fold_nested_repos: false,
+            always_show: Vec::new(),
+            traverse_to_shown: false,
+            show_all: false,
+            rules: None,
+            progress: None,
+            cancel: None,
+            previous: None,
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Start from the defaults and set only the options that differ
+    pub fn builder() -> ScanOptionsBuilder {
+        ScanOptionsBuilder::default()
+    }
+
+    /// Whether the scan was asked to stop through `cancel`
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+}
+
+/// Builds `ScanOptions` one option at a time, so options added later don't
+/// break callers
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptionsBuilder {
+    options: ScanOptions,
+}
+
+impl ScanOptionsBuilder {
+    setters! { options;
+        /// Maximum depth to traverse
+        max_depth: usize;
+        /// Traverse system directories (like .git) instead of folding them
+        show_system_dirs: bool;
+        /// Traverse directories hidden by filtering rules
+        show_filtered: bool;
+        /// Throttle filesystem access so other workloads are not starved
+        nice: bool;
+        /// Follow symlinks so they contribute their target's size (like `du -L`)
+        dereference: bool;
+        /// Approximate memory budget for the in-memory tree, in bytes
+        max_memory: Option<usize>;
+        /// Don't expand directories (other than the root) with more entries than this
+        file_limit: Option<usize>;
+        /// Only keep files matching one of these globs (all files when empty)
+        include: Vec<String>;
+        /// Drop files and directories matching any of these globs
+        exclude: Vec<String>;
+        /// Drop directories left without any files after filtering
+        prune: bool;
+        /// Only keep files at least this many bytes large
+        min_size: Option<u64>;
+        /// Only keep files at most this many bytes large
+        max_size: Option<u64>;
+        /// Only keep files modified after this time
+        newer_than: Option<SystemTime>;
+        /// Only keep files modified before this time
+        older_than: Option<SystemTime>;
+        /// List directories only; files still count toward their totals
+        dirs_only: bool;
+        /// Report space allocated on disk instead of apparent sizes (like `du`)
+        disk_usage: bool;
+        /// Don't descend into directories on a different filesystem than the root
+        one_file_system: bool;
+        /// Fold nested Git repositories and submodules below the root instead of
+        /// descending into them, totaling their contents like folded system directories
+        fold_nested_repos: bool;
+        /// Never fold entries matching these globs (or anything below them),
+        /// whether gitignored or matched by a rule
+        always_show: Vec<String>;
+        /// Also never fold the directories leading to `always_show` matches, so
+        /// e.g. `target/doc` is shown inside an otherwise folded `target`
+        traverse_to_shown: bool;
+        /// Keep entries the filesystem marks as hidden or system (Windows file
+        /// attributes), which are left out otherwise
+        show_all: bool;
+        /// Filtering rules deciding what gets folded, used by `scan`
+        rules: Option<Arc<FilterRegistry>>;
+        /// Told about the scan's progress as each directory is read
+        progress: Option<Arc<dyn ProgressSink>>;
+        /// Stops the scan once set, from any thread
+        cancel: Option<Arc<AtomicBool>>;
+        /// An earlier scan of the same root with the same options, whose
+        /// directories are reused where their modification time hasn't changed
+        previous: Option<Arc<DirectoryEntry>>;
+    }
+
+    /// The finished options
+    pub fn build(self) -> ScanOptions {
+        self.options
+    }
+}
+
+/// Scan a directory tree, with the rules in `options.rules`
+///
+/// Fails with `Error::Cancelled` if `options.cancel` is set before the scan
+/// starts; once it has started, a cancelled scan returns a partial tree.
+pub fn scan(
+    root: &Path,
+    gitignore_ctx: &mut GitIgnoreContext,
+    options: &ScanOptions,
+) -> Result<DirectoryEntry> {
+    scan_tree(root, gitignore_ctx, options.rules.as_deref(), options)
+}
+
+/// Scan a directory tree on tokio's blocking thread pool, so async callers
+/// don't stall their runtime
+///
+/// The tree is the same `scan` returns with `gitignore_ctx`, which the scan
+/// takes over. Dropping the future doesn't stop the scan; use
+/// `options.cancel` for that.
+#[cfg(feature = "tokio")]
+pub async fn scan_async(
+    root: impl Into<PathBuf>,
+    mut gitignore_ctx: GitIgnoreContext,
+    options: ScanOptions,
+) -> Result<DirectoryEntry> {
+    let root = root.into();
+    let scanned =
+        tokio::task::spawn_blocking(move || scan(&root, &mut gitignore_ctx, &options)).await;
+    match scanned {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        // The runtime shut down before the scan ran
+        Err(_) => Err(Error::Cancelled),
+    }
+}
+
+#[deprecated(since = "0.3.0", note = "Use scan with ScanOptions instead")]
 pub fn scan_directory(
     root: &Path,
     gitignore_ctx: &mut GitIgnoreContext,
@@ -14,232 +273,1411 @@ pub fn scan_directory(
     show_system_dirs: Option<bool>,
     show_filtered: Option<bool>,
 ) -> Result<DirectoryEntry> {
-    // Default settings
-    let show_system = show_system_dirs.unwrap_or(false);
-    let show_hidden = show_filtered.unwrap_or(false);
+    let options = ScanOptions {
+        max_depth,
+        show_system_dirs: show_system_dirs.unwrap_or(false),
+        show_filtered: show_filtered.unwrap_or(false),
+        ..ScanOptions::default()
+    };
+    scan_tree(root, gitignore_ctx, rule_registry, &options)
+}
 
-    let root_metadata = fs::metadata(root)?;
-    let root_name = root
-        .file_name()
-        .map(|n| n.to_string_lossy().to_string())
-        .unwrap_or_else(|| root.to_string_lossy().to_string());
+#[deprecated(since = "0.3.0", note = "Use scan with ScanOptions::rules instead")]
+pub fn scan_with_options(
+    root: &Path,
+    gitignore_ctx: &mut GitIgnoreContext,
+    rule_registry: Option<&FilterRegistry>,
+    options: &ScanOptions,
+) -> Result<DirectoryEntry> {
+    scan_tree(root, gitignore_ctx, rule_registry, options)
+}
 
-    // Process this directory to load any .gitignore file before checking ignore status
-    if let Err(e) = gitignore_ctx.process_directory(root) {
-        warn!("Error processing gitignore in {}: {}", root.display(), e);
+fn scan_tree(
+    root: &Path,
+    gitignore_ctx: &mut GitIgnoreContext,
+    rule_registry: Option<&FilterRegistry>,
+    options: &ScanOptions,
+) -> Result<DirectoryEntry> {
+    if options.is_cancelled() {
+        return Err(Error::Cancelled);
     }
+    let mut scanner = Scanner::new(root, gitignore_ctx, rule_registry, options);
+    scanner.previous = options
+        .previous
+        .as_deref()
+        .map(index_directories)
+        .unwrap_or_default();
+    scanner.scan(root, options.max_depth)
+}
 
-    // Get parent path for context
-    let parent_path = root.parent().unwrap_or(root);
-
-    // Check filtering rules if provided
-    let is_gitignored = gitignore_ctx.is_ignored(root);
-    let mut filtered_by = None;
-    let mut filter_annotation = None;
+/// Bring the entries at `paths` in the scanned tree `root` up to date, for
+/// callers watching the tree for changes
+///
+/// The directory holding each path is read again, so new paths are added
+/// and deleted ones removed, and the subtrees below are rescanned where they
+/// changed (see `ScanOptions::previous`). Ancestors get their totals updated.
+/// `gitignore_ctx` and `options`, with its rules, should be those the tree
+/// was scanned with. Paths outside `root` are left alone.
+///
+/// `paths` must be written the way `root.path` is: for a tree scanned as `.`,
+/// a watcher's absolute paths are all outside it and ignored, so make them
+/// relative first (or scan from an absolute path).
+///
+/// With `ScanOptions::disk_usage`, totals are approximate after a rescan:
+/// hard links are counted once within each rescanned directory, but one
+/// shared with the rest of the tree counts again there. Scan the tree anew
+/// for exact `du` totals.
+pub fn rescan_paths(
+    root: &mut DirectoryEntry,
+    paths: &[PathBuf],
+    gitignore_ctx: &mut GitIgnoreContext,
+    options: &ScanOptions,
+) -> Result<()> {
+    // Rescan the nearest directory of each path that is in the tree and on
+    // disk, once, and not below another one being rescanned
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for path in paths.iter().filter(|path| path.starts_with(&root.path)) {
+        let mut dir = path.parent().filter(|_| *path != root.path).unwrap_or(path);
+        while dir != root.path && !(dir.is_dir() && find_entry(root, dir).is_some()) {
+            dir = dir.parent().unwrap_or(&root.path);
+        }
+        dirs.push(dir.to_path_buf());
+    }
+    // Read again even where nested in another directory being rescanned
+    let stale: HashSet<PathBuf> = dirs.iter().chain(paths).cloned().collect();
+    dirs.sort();
+    dirs.dedup_by(|dir, ancestor| dir.starts_with(ancestor));
 
-    // Apply rules if registry is provided
-    if let Some(registry) = rule_registry {
-        // Create context for this path
-        let mut context = FilterContext::new(
-            root,
-            parent_path,
-            root, // Using root as project root for now
-            0,    // Depth will be set correctly in recursive calls
-        );
+    for dir in dirs {
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let depth = dir
+            .strip_prefix(&root.path)
+            .map_or(0, |rel| rel.iter().count());
+        let max_depth = options.max_depth.saturating_sub(depth);
+        if max_depth == 0 {
+            continue;
+        }
+        let Some(entry) = find_entry_mut(root, &dir) else {
+            continue;
+        };
+        let children = std::mem::take(&mut entry.children);
+        let old = DirectoryEntry {
+            children,
+            ..entry.clone()
+        };
 
-        // Detect project types
-        context.detect_project_types();
+        // Unchanged subdirectories are reused, but not those holding or
+        // named in `paths`
+        let scanned = {
+            let mut previous = index_directories(&old);
+            previous.retain(|path, _| !stale.contains(*path));
+            for ancestor in dir.ancestors().skip(1) {
+                if !ancestor.starts_with(&root.path) {
+                    break;
+                }
+                gitignore_ctx.process_directory(ancestor)?;
+            }
+            let rule_registry = options.rules.as_deref();
+            let mut scanner = Scanner::new(&root.path, gitignore_ctx, rule_registry, options);
+            scanner.previous = previous;
+            scanner.scan(&dir, max_depth)
+        };
+        let mut scanned = match scanned {
+            Ok(scanned) => scanned,
+            Err(e) => {
+                if let Some(entry) = find_entry_mut(root, &dir) {
+                    entry.children = old.children;
+                }
+                return Err(e);
+            }
+        };
+        scanned.name = old.name.clone();
+        scanned.is_git_repo = old.is_git_repo;
 
-        // Evaluate rules
-        if let Some((_, annotation)) = registry.should_hide(&context) {
-            filtered_by = Some(String::from("rule")); // Would ideally track specific rule ID
-            filter_annotation = Some(String::from(annotation));
+        // Update the totals of the ancestors on the way down
+        let mut entry = &mut *root;
+        while entry.path != dir {
+            let metadata = &mut entry.metadata;
+            metadata.size =
+                (metadata.size + scanned.metadata.size).saturating_sub(old.metadata.size);
+            metadata.files_count = (metadata.files_count + scanned.metadata.files_count)
+                .saturating_sub(old.metadata.files_count);
+            let Some(index) = entry
+                .children
+                .iter()
+                .position(|child| dir.starts_with(&child.path))
+            else {
+                break;
+            };
+            entry = &mut entry.children[index];
         }
+        if entry.path == dir {
+            *entry = scanned;
+        }
+        refresh_approximate(root, &dir);
     }
+    Ok(())
+}
 
-    // Early return for non-directories or when max_depth is 0
-    if !root_metadata.is_dir() || max_depth == 0 {
-        return Ok(DirectoryEntry {
-            path: root.to_path_buf(),
-            name: root_name,
-            is_dir: root_metadata.is_dir(),
-            metadata: EntryMetadata {
-                size: root_metadata.len(),
-                created: root_metadata.created()?,
-                modified: root_metadata.modified()?,
-                files_count: 0,
+/// Recompute whether the totals of the directories leading to `dir` are
+/// approximate, now that its subtree may have been completed
+///
+/// A directory's totals are approximate when its own listing was cut short by
+/// a cancel, or when those of a child are.
+fn refresh_approximate(entry: &mut DirectoryEntry, dir: &Path) {
+    if entry.path == dir {
+        return;
+    }
+    if let Some(child) = entry
+        .children
+        .iter_mut()
+        .find(|child| dir.starts_with(&child.path))
+    {
+        refresh_approximate(child, dir);
+    }
+    entry.metadata.approximate = entry.cancelled
+        || entry
+            .children
+            .iter()
+            .any(|child| child.metadata.approximate);
+}
+
+/// The entry at `path` in the tree below `root`
+fn find_entry<'e>(root: &'e DirectoryEntry, path: &Path) -> Option<&'e DirectoryEntry> {
+    let mut entry = root;
+    while entry.path != path {
+        entry = entry
+            .children
+            .iter()
+            .find(|child| path.starts_with(&child.path))?;
+    }
+    Some(entry)
+}
+
+fn find_entry_mut<'e>(root: &'e mut DirectoryEntry, path: &Path) -> Option<&'e mut DirectoryEntry> {
+    let mut entry = root;
+    while entry.path != path {
+        entry = entry
+            .children
+            .iter_mut()
+            .find(|child| path.starts_with(&child.path))?;
+    }
+    Some(entry)
+}
+
+/// Gather separately scanned roots under one synthetic root directory
+///
+/// The synthetic root has an empty path, so the roots keep their paths as
+/// given, and its size and file count are the totals of the roots.
+pub fn merge_roots(roots: Vec<DirectoryEntry>) -> DirectoryEntry {
+    let metadata = EntryMetadata {
+        size: roots.iter().map(|root| root.metadata.size).sum(),
+        created: roots
+            .iter()
+            .map(|root| root.metadata.created)
+            .min()
+            .unwrap_or(UNIX_EPOCH),
+        modified: roots
+            .iter()
+            .map(|root| root.metadata.modified)
+            .max()
+            .unwrap_or(UNIX_EPOCH),
+        files_count: roots
+            .iter()
+            .map(|root| {
+                if root.is_dir {
+                    root.metadata.files_count
+                } else {
+                    1
+                }
+            })
+            .sum(),
+        mode: None,
+        uid: None,
+        gid: None,
+        approximate: false,
+    };
+    DirectoryEntry {
+        path: PathBuf::new(),
+        name: String::from("."),
+        is_dir: true,
+        metadata,
+        children: roots,
+        is_gitignored: false,
+        is_git_repo: false,
+        filtered_by: None,
+        filter_annotation: None,
+        error: None,
+        is_virtual: false,
+        kind: EntryKind::Regular,
+        has_unlisted: false,
+        has_omitted: false,
+        cancelled: false,
+    }
+}
+
+/// Decides which entries are gitignored or hidden by a rule, for the
+/// scanner and `scan_iter` alike
+pub(crate) struct Classifier {
+    /// Directory the scan started from
+    root: PathBuf,
+    /// Project types of the scan root, detected once for all rule evaluations
+    project_types: Vec<ProjectType>,
+    /// Paths shown by `ScanOptions::always_show`
+    always_show: AllowRule,
+}
+
+impl Classifier {
+    /// A classifier for the scan of `root`, detecting its project types
+    /// only when rules will be evaluated
+    pub(crate) fn new(root: &Path, options: &ScanOptions, with_rules: bool) -> Self {
+        Classifier {
+            root: root.to_path_buf(),
+            project_types: if with_rules {
+                detect_project_types(root)
+            } else {
+                Vec::new()
             },
-            children: Vec::new(),
-            is_gitignored,
+            always_show: AllowRule::new(options.always_show.clone(), options.traverse_to_shown),
+        }
+    }
+
+    /// Whether `path` is gitignored, and the rule hiding it with its
+    /// annotation, if any
+    pub(crate) fn classify(
+        &self,
+        gitignore_ctx: &GitIgnoreContext,
+        rule_registry: Option<&FilterRegistry>,
+        path: &Path,
+        is_dir: bool,
+    ) -> (bool, Option<String>, Option<String>) {
+        if self.is_always_shown(path) {
+            return (false, None, None);
+        }
+        let (filtered_by, filter_annotation) = match rule_registry {
+            Some(registry) => self.evaluate_rules(registry, path, is_dir, None),
+            None => (None, None),
+        };
+        (
+            gitignore_ctx.is_ignored(path),
             filtered_by,
             filter_annotation,
-        });
+        )
     }
 
-    // Check if this entry should be filtered based on rules
-    let should_filter = (is_gitignored && !show_system) || (filtered_by.is_some() && !show_hidden);
+    /// Rule ID and annotation of a rule hiding the directory `path` for the
+    /// names and sizes of the files directly inside it, once it is listed
+    pub(crate) fn classify_listing(
+        &self,
+        registry: &FilterRegistry,
+        path: &Path,
+        files: &[(String, u64)],
+    ) -> (Option<String>, Option<String>) {
+        if self.is_always_shown(path) {
+            return (None, None);
+        }
+        self.evaluate_rules(registry, path, true, Some(files))
+    }
 
-    // Initialize the root entry with temporary metadata
-    // We'll calculate accurate size and file count as we traverse
-    let mut root_entry = DirectoryEntry {
-        path: root.to_path_buf(),
-        name: root_name,
-        is_dir: true,
-        metadata: EntryMetadata {
-            size: 0,
-            created: root_metadata.created()?,
-            modified: root_metadata.modified()?,
-            files_count: 0,
-        },
-        children: Vec::new(),
-        is_gitignored,
-        filtered_by,
-        filter_annotation,
-    };
+    /// Rule ID and annotation of the rule hiding `path`, if any
+    fn evaluate_rules(
+        &self,
+        registry: &FilterRegistry,
+        path: &Path,
+        is_dir: bool,
+        files: Option<&[(String, u64)]>,
+    ) -> (Option<String>, Option<String>) {
+        let parent_path = path.parent().unwrap_or(path);
+        let depth = path
+            .strip_prefix(&self.root)
+            .map_or(0, |relative| relative.components().count());
+        let mut context = FilterContext::new(path, parent_path, &self.root, depth);
+        context.project_types = self.project_types.clone();
+        context.is_dir = is_dir;
+        context.files = files;
 
-    // For filtered directories, decide whether to traverse or just provide basic metadata
-    // If this is the root path that was explicitly specified, never skip it regardless of filter rules
-    let is_direct_path = root.canonicalize().unwrap_or_else(|_| root.to_path_buf())
-        == Path::new(&root).canonicalize().unwrap_or_else(|_| root.to_path_buf());
-    let should_skip = should_filter && !is_direct_path;
-
-    if should_skip {
-        debug!(
-            "Skipping deep traversal of filtered directory: {}",
-            root.display()
-        );
-        // Do a quick scan to get file counts without deep traversal
-        let mut file_count = 0;
-        let mut total_size = 0;
-
-        if let Ok(entries) = fs::read_dir(root) {
-            for entry in entries.flatten() {
-                if let Ok(metadata) = entry.metadata() {
-                    total_size += metadata.len();
-                    if !metadata.is_dir() {
-                        file_count += 1;
-                    } else {
-                        // For directories, make a rough estimate
-                        // This avoids traversing deeply into large system directories
-                        file_count += 10; // Just a placeholder estimate
-                    }
+        match registry.should_hide(&context) {
+            Some(verdict) => (
+                Some(String::from(verdict.rule_id)),
+                Some(String::from(verdict.annotation)),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// Whether a glob matches the entry's name, or its path relative to the
+    /// scan root for patterns containing `/`
+    pub(crate) fn matches(&self, pattern: &str, path: &Path, name: &str) -> bool {
+        if !pattern.contains('/') {
+            return glob_match(pattern, name);
+        }
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let parts: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        glob_match(pattern.trim_start_matches('/'), &parts.join("/"))
+    }
+
+    /// Whether the entry or one of its ancestors below the root matches
+    /// `always_show`, or the entry leads to a match when traversing to them
+    fn is_always_shown(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.always_show.keeps(relative)
+    }
+}
+
+/// State shared across the recursive traversal
+struct Scanner<'a> {
+    gitignore_ctx: &'a mut GitIgnoreContext,
+    rule_registry: Option<&'a FilterRegistry>,
+    classifier: Classifier,
+    options: &'a ScanOptions,
+    throttle: Throttle,
+    /// Directories of `ScanOptions::previous` by path
+    previous: HashMap<&'a Path, &'a DirectoryEntry>,
+    /// Entries looked at so far, for progress reports
+    entries_seen: usize,
+    /// Bytes counted toward the totals so far, for progress reports
+    bytes_seen: u64,
+    /// Canonical paths of the directories being scanned, used to detect
+    /// symlink loops when dereferencing
+    ancestors: HashSet<PathBuf>,
+    /// Estimated bytes held by the entries built so far
+    memory_used: usize,
+    /// Hard-linked files already counted in disk-usage mode
+    seen_inodes: HashSet<(u64, u64)>,
+    /// Device of the scan root, set when staying on one filesystem
+    root_device: Option<u64>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(
+        root: &Path,
+        gitignore_ctx: &'a mut GitIgnoreContext,
+        rule_registry: Option<&'a FilterRegistry>,
+        options: &'a ScanOptions,
+    ) -> Self {
+        Scanner {
+            gitignore_ctx,
+            rule_registry,
+            classifier: Classifier::new(root, options, rule_registry.is_some()),
+            options,
+            throttle: Throttle::new(options.nice),
+            previous: HashMap::new(),
+            entries_seen: 0,
+            bytes_seen: 0,
+            ancestors: HashSet::new(),
+            memory_used: 0,
+            seen_inodes: HashSet::new(),
+            root_device: options
+                .one_file_system
+                .then(|| fs::metadata(root).ok().and_then(|m| device_id(&m)))
+                .flatten(),
+        }
+    }
+
+    fn scan(&mut self, root: &Path, max_depth: usize) -> Result<DirectoryEntry> {
+        let _span = debug_span!("scan_dir", path = %root.display()).entered();
+        let canonical = if self.options.dereference {
+            root.canonicalize().ok()
+        } else {
+            None
+        };
+        if let Some(dir) = &canonical {
+            self.ancestors.insert(dir.clone());
+        }
+        let result = self.scan_entry(root, max_depth);
+        if let Some(dir) = &canonical {
+            self.ancestors.remove(dir);
+        }
+        result
+    }
+
+    /// Whether `path` is gitignored, and the rule hiding it with its
+    /// annotation, if any
+    fn classify(&self, path: &Path, is_dir: bool) -> (bool, Option<String>, Option<String>) {
+        self.classifier
+            .classify(self.gitignore_ctx, self.rule_registry, path, is_dir)
+    }
+
+    /// Whether the rule that hid an entry leaves it out of the listing
+    fn omits(&self, rule_id: &str) -> bool {
+        self.rule_registry
+            .is_some_and(|registry| registry.omits(rule_id))
+    }
+
+    /// Metadata for a directory entry, following symlinks when dereferencing,
+    /// and what the entry itself is
+    ///
+    /// On Windows, junctions and other name-surrogate reparse points count as
+    /// symlinks here, so they are listed but not descended into by default,
+    /// which keeps a junction to an ancestor from being scanned twice.
+    fn entry_metadata(&self, path: &Path) -> Result<(fs::Metadata, EntryKind)> {
+        let metadata = fs::symlink_metadata(path)?;
+        let kind = entry_kind(path, &metadata);
+        if !self.options.dereference || !metadata.file_type().is_symlink() {
+            return Ok((metadata, kind));
+        }
+
+        match fs::metadata(path) {
+            Ok(target) if target.is_dir() => {
+                let looped = path
+                    .canonicalize()
+                    .is_ok_and(|dir| self.ancestors.contains(&dir));
+                if looped {
+                    debug!("Not following symlink loop at {}", path.display());
+                    Ok((metadata, kind))
+                } else {
+                    Ok((target, kind))
                 }
             }
+            Ok(target) => Ok((target, kind)),
+            Err(e) => {
+                debug!("Not following broken symlink {}: {}", path.display(), e);
+                Ok((metadata, kind))
+            }
         }
+    }
 
-        // If total size is still 0 but we know it's a directory, use a placeholder size
-        if total_size == 0 && file_count > 0 {
-            total_size = 1024 * 1024; // 1MB placeholder
+    /// Size an entry contributes to the totals: its length, or the space it
+    /// occupies on disk in disk-usage mode
+    fn entry_size(&mut self, metadata: &fs::Metadata) -> u64 {
+        let size = if self.options.disk_usage {
+            allocated_size(metadata, &mut self.seen_inodes)
+        } else {
+            metadata.len()
+        };
+        self.bytes_seen += size;
+        size
+    }
+
+    /// Size a traversed directory contributes on its own, on top of its
+    /// contents; only disk usage accounts for directory blocks
+    fn directory_size(&mut self, metadata: &fs::Metadata) -> u64 {
+        if self.options.disk_usage {
+            self.entry_size(metadata)
+        } else {
+            0
+        }
+    }
+
+    /// Whether a directory lives on another filesystem than the scan root
+    fn is_mount_point(&self, metadata: &fs::Metadata) -> bool {
+        self.root_device
+            .is_some_and(|root| device_id(metadata).is_some_and(|device| device != root))
+    }
+
+    /// Whether `--exclude` drops the entry
+    fn is_excluded(&self, path: &Path, name: &str) -> bool {
+        self.options
+            .exclude
+            .iter()
+            .any(|pattern| self.classifier.matches(pattern, path, name))
+    }
+
+    /// Whether a file passes the `--include` patterns, size and time bounds
+    fn is_included(&self, path: &Path, name: &str, metadata: &fs::Metadata) -> bool {
+        let size = metadata.len();
+        if self.options.min_size.is_some_and(|min| size < min)
+            || self.options.max_size.is_some_and(|max| size > max)
+        {
+            return false;
+        }
+        if self.options.newer_than.is_some() || self.options.older_than.is_some() {
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            if self
+                .options
+                .newer_than
+                .is_some_and(|after| modified <= after)
+                || self
+                    .options
+                    .older_than
+                    .is_some_and(|before| modified >= before)
+            {
+                return false;
+            }
         }
+        self.options.include.is_empty()
+            || self
+                .options
+                .include
+                .iter()
+                .any(|pattern| self.classifier.matches(pattern, path, name))
+    }
 
-        // Update the metadata
-        root_entry.metadata.files_count = file_count;
-        root_entry.metadata.size = total_size;
+    /// Whether directories without matching files are dropped; filtering by
+    /// size or time only makes sense alongside the directories that contain matches
+    fn prunes(&self) -> bool {
+        let options = self.options;
+        options.prune
+            || options.min_size.is_some()
+            || options.max_size.is_some()
+            || options.newer_than.is_some()
+            || options.older_than.is_some()
+    }
 
-        return Ok(root_entry);
+    /// Whether the tree has outgrown the configured memory budget
+    fn over_memory_limit(&self) -> bool {
+        self.options
+            .max_memory
+            .is_some_and(|limit| self.memory_used >= limit)
     }
-    // If we're showing filtered directories, we'll continue with the normal traversal
 
-    let mut entries = Vec::new();
+    /// The previous scan's entry for `dir`, if the directory hasn't changed
+    /// since and the entry can be reused
+    ///
+    /// Ignore files may have changed even so, so the caller classifies the
+    /// reused entries again.
+    fn unchanged(&self, dir: &Path, metadata: &fs::Metadata) -> Option<&'a DirectoryEntry> {
+        if self.prunes() || self.options.max_memory.is_some() {
+            return None;
+        }
+        let previous = *self.previous.get(dir)?;
+        // An error with no children means the directory couldn't be read
+        let reusable = (previous.error.is_none() || !previous.children.is_empty())
+            && previous.filtered_by.as_deref() != Some(FILE_LIMIT_RULE)
+            && !previous.cancelled
+            && metadata
+                .modified()
+                .is_ok_and(|modified| modified == previous.metadata.modified);
+        reusable.then_some(previous)
+    }
 
-    // Read the directory and process entries
-    for dir_entry in fs::read_dir(root)? {
-        let dir_entry = dir_entry?;
-        let path = dir_entry.path();
-        let metadata = dir_entry.metadata()?;
-        let name = dir_entry.file_name().to_string_lossy().to_string();
+    /// Record one entry looked at, pausing in nice mode
+    fn tick(&mut self) {
+        self.entries_seen += 1;
+        self.throttle.tick();
+    }
 
-        // Check if this specific entry is gitignored
-        let is_gitignored = gitignore_ctx.is_ignored(&path);
+    /// Tell the progress sink, if any, that `dir` is being read
+    fn report_progress(&self, dir: &Path) {
+        if let Some(sink) = &self.options.progress {
+            sink.progress(&ScanProgress {
+                entries: self.entries_seen,
+                bytes: self.bytes_seen,
+                path: dir,
+            });
+        }
+    }
 
-        // Apply filtering rules if available
-        let mut filtered_by = None;
-        let mut filter_annotation = None;
+    /// Add an entry to `entries`, accounting for its memory footprint
+    fn keep(&mut self, entries: &mut Vec<DirectoryEntry>, entry: DirectoryEntry) {
+        self.memory_used += entry_footprint(&entry);
+        entries.push(entry);
+    }
 
-        if let Some(registry) = rule_registry {
-            // Create context for this path
-            let mut context = FilterContext::new(
-                &path, root, root,      // Using root as project root
-                max_depth, // Current depth level
-            );
+    /// Count files and bytes below `dir` without building entries
+    ///
+    /// Stops after looking at `limit` entries, returning whether the count
+    /// is complete.
+    fn summarize_dir(&mut self, dir: &Path, max_depth: usize, limit: usize) -> (usize, u64, bool) {
+        let mut totals = FoldedTotals::default();
+        let complete = self.summarize_into(dir, max_depth, limit, &mut totals);
+        (totals.files, totals.size, complete)
+    }
 
-            // Detect project types
-            context.detect_project_types();
+    /// Add what is below `dir` to `totals`, returning false once `limit`
+    /// entries were looked at or the scan was cancelled
+    fn summarize_into(
+        &mut self,
+        dir: &Path,
+        depth: usize,
+        limit: usize,
+        totals: &mut FoldedTotals,
+    ) -> bool {
+        let Ok(listing) = read_dir_entries(dir) else {
+            return true;
+        };
+        self.report_progress(dir);
+        // Like `scan`, symlinks back to a directory on the way down aren't followed
+        let canonical = self
+            .options
+            .dereference
+            .then(|| dir.canonicalize().ok())
+            .flatten()
+            .filter(|dir| self.ancestors.insert(dir.clone()));
 
-            // Evaluate rules
-            if let Some((_, annotation)) = registry.should_hide(&context) {
-                filtered_by = Some(String::from("rule"));
-                filter_annotation = Some(String::from(annotation));
+        let mut complete = true;
+        let mut subdirs = Vec::new();
+        for ListedEntry { path, .. } in listing {
+            if totals.seen == limit || self.options.is_cancelled() {
+                complete = false;
+                break;
+            }
+            totals.seen += 1;
+            self.tick();
+            let Ok((metadata, _)) = self.entry_metadata(&path) else {
+                continue;
+            };
+            if !metadata.is_dir() {
+                totals.files += 1;
+                totals.size += self.entry_size(&metadata);
+            } else if depth > 1 && !self.is_mount_point(&metadata) {
+                totals.size += self.directory_size(&metadata);
+                subdirs.push(path);
+            } else {
+                totals.size += self.entry_size(&metadata);
+            }
+        }
+        for subdir in subdirs {
+            if !complete {
+                break;
             }
+            complete = self.summarize_into(&subdir, depth - 1, limit, totals);
         }
 
-        if metadata.is_dir() {
-            // Recursively scan subdirectories if depth allows
-            if max_depth > 1 {
-                match scan_directory(
-                    &path,
-                    gitignore_ctx,
-                    rule_registry,
-                    max_depth - 1,
-                    Some(show_system),
-                    Some(show_hidden),
-                ) {
-                    Ok(dir_entry) => {
-                        // Update parent metadata
-                        root_entry.metadata.files_count += dir_entry.metadata.files_count;
-                        root_entry.metadata.size += dir_entry.metadata.size;
-                        entries.push(dir_entry);
-                    }
-                    Err(e) => {
-                        warn!("Error scanning directory {}: {}", path.display(), e);
-                    }
-                }
-            } else {
-                // Just add the directory as a leaf node
-                entries.push(DirectoryEntry {
-                    path,
-                    name,
-                    is_dir: true,
-                    metadata: EntryMetadata {
-                        size: metadata.len(),
-                        created: metadata.created()?,
-                        modified: metadata.modified()?,
-                        files_count: 0,
-                    },
-                    children: Vec::new(),
-                    is_gitignored,
-                    filtered_by,
-                    filter_annotation,
-                });
+        if let Some(dir) = &canonical {
+            self.ancestors.remove(dir);
+        }
+        complete
+    }
 
-                // Update parent size
-                root_entry.metadata.size += metadata.len();
+    /// Scan the subdirectory `path` of `parent`, adding it to `entries` and
+    /// its totals to the parent's
+    fn scan_subdir(
+        &mut self,
+        parent: &mut DirectoryEntry,
+        entries: &mut Vec<DirectoryEntry>,
+        path: PathBuf,
+        name: String,
+        is_git_repo: bool,
+        max_depth: usize,
+    ) {
+        match self.scan(&path, max_depth) {
+            Ok(dir_entry)
+                if self.prunes()
+                    && dir_entry.children.is_empty()
+                    && dir_entry.metadata.files_count == 0 =>
+            {
+                debug!("Pruning {} (no matching files)", path.display());
+                parent.has_omitted = true;
+            }
+            Ok(mut dir_entry) => {
+                dir_entry.is_git_repo = is_git_repo;
+                // Update parent metadata
+                parent.metadata.files_count += dir_entry.metadata.files_count;
+                parent.metadata.size += dir_entry.metadata.size;
+                parent.metadata.approximate |= dir_entry.metadata.approximate;
+                self.keep(entries, dir_entry);
             }
+            Err(e) => {
+                warn!("Error scanning directory {}: {}", path.display(), e);
+                let mut entry = error_entry(path, name, &e);
+                entry.is_dir = true;
+                self.keep(entries, entry);
+            }
+        }
+    }
+
+    fn scan_entry(&mut self, root: &Path, max_depth: usize) -> Result<DirectoryEntry> {
+        // Default settings
+        let show_system = self.options.show_system_dirs;
+        let show_hidden = self.options.show_filtered;
+
+        let link_metadata = fs::symlink_metadata(root)?;
+        let kind = entry_kind(root, &link_metadata);
+        let root_metadata = if link_metadata.file_type().is_symlink() {
+            fs::metadata(root)?
         } else {
-            // For files, update parent metadata and add to entries
-            root_entry.metadata.files_count += 1;
-            root_entry.metadata.size += metadata.len();
-
-            entries.push(DirectoryEntry {
-                path,
-                name,
-                is_dir: false,
+            link_metadata
+        };
+        let root_name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| root.to_string_lossy().to_string());
+
+        // Process this directory to load any .gitignore file before checking ignore status
+        if let Err(e) = self.gitignore_ctx.process_directory(root) {
+            warn!("Error processing gitignore in {}: {}", root.display(), e);
+        }
+
+        // Check filtering rules if provided
+        let (is_gitignored, filtered_by, filter_annotation) =
+            self.classify(root, root_metadata.is_dir());
+
+        let (created, modified, error) = timestamps(&root_metadata);
+        let (mode, uid, gid) = permissions(&root_metadata);
+
+        // Early return for non-directories or when max_depth is 0
+        if !root_metadata.is_dir() || max_depth == 0 {
+            return Ok(DirectoryEntry {
+                path: root.to_path_buf(),
+                name: root_name,
+                is_dir: root_metadata.is_dir(),
                 metadata: EntryMetadata {
-                    size: metadata.len(),
-                    created: metadata.created()?,
-                    modified: metadata.modified()?,
+                    size: self.entry_size(&root_metadata),
+                    created,
+                    modified,
                     files_count: 0,
+                    mode,
+                    uid,
+                    gid,
+                    approximate: false,
                 },
                 children: Vec::new(),
                 is_gitignored,
+                is_git_repo: false,
                 filtered_by,
                 filter_annotation,
+                error,
+                is_virtual: false,
+                kind: kind.clone(),
+                has_unlisted: root_metadata.is_dir() && has_entries(root),
+                has_omitted: false,
+                cancelled: false,
+            });
+        }
+
+        // Check if this entry should be filtered based on rules
+        let should_filter = (is_gitignored && !show_system)
+            || (filtered_by.is_some() && !show_hidden)
+            || (self.options.fold_nested_repos && is_nested_repo(root));
+
+        // Initialize the root entry with temporary metadata
+        // We'll calculate accurate size and file count as we traverse
+        let mut root_entry = DirectoryEntry {
+            path: root.to_path_buf(),
+            name: root_name,
+            is_dir: true,
+            metadata: EntryMetadata {
+                size: self.directory_size(&root_metadata),
+                created,
+                modified,
+                files_count: 0,
+                mode,
+                uid,
+                gid,
+                approximate: false,
+            },
+            children: Vec::new(),
+            is_gitignored,
+            is_git_repo: false,
+            filtered_by,
+            filter_annotation,
+            error,
+            is_virtual: false,
+            kind,
+            has_unlisted: false,
+            has_omitted: false,
+            cancelled: false,
+        };
+
+        // For filtered directories, decide whether to traverse or just provide basic metadata
+        // The scan root itself is never skipped, regardless of filter rules
+        let is_scan_root = root == self.classifier.root;
+        // Ignored directories are looked into when a negated pattern may
+        // re-include something inside them
+        let reincludes =
+            ignored_by_gitignore(&root_entry) && self.gitignore_ctx.may_reinclude_below(root);
+        let should_skip = should_filter && !is_scan_root && !reincludes;
+
+        if should_skip {
+            debug!(
+                "Skipping deep traversal of filtered directory: {}",
+                root.display()
+            );
+            // Count the contents without building entries, up to a limit so
+            // huge directories like node_modules stay cheap. The totals are
+            // never taken from a previous scan, whose subdirectories may
+            // have changed since.
+            let (files, size, complete) = self.summarize_dir(root, max_depth, FOLDED_COUNT_LIMIT);
+            root_entry.metadata.files_count = files;
+            root_entry.metadata.size += size;
+            root_entry.metadata.approximate = !complete;
+            root_entry.cancelled = !complete && self.options.is_cancelled();
+            root_entry.has_omitted = true;
+
+            return Ok(root_entry);
+        }
+        // If we're showing filtered directories, we'll continue with the normal traversal
+
+        let mut entries = Vec::new();
+        let mut omitted = 0;
+        let mut cancelled = false;
+        // Names and sizes of the files directly inside, for rules that judge
+        // a directory by them
+        let mut files = Vec::new();
+
+        // An unchanged directory takes over its files from the previous scan,
+        // and only its subdirectories are looked at again
+        self.report_progress(root);
+        let previous = self.unchanged(root, &root_metadata);
+        if let Some(previous) = previous {
+            root_entry.metadata.files_count = previous.metadata.files_count;
+            root_entry.metadata.size = previous.metadata.size;
+            root_entry.has_omitted = previous.has_omitted;
+            for child in previous.children.iter().filter(|child| child.is_dir) {
+                root_entry.metadata.files_count = root_entry
+                    .metadata
+                    .files_count
+                    .saturating_sub(child.metadata.files_count);
+                root_entry.metadata.size =
+                    root_entry.metadata.size.saturating_sub(child.metadata.size);
+            }
+            for child in &previous.children {
+                if self.options.is_cancelled() {
+                    cancelled = true;
+                    break;
+                }
+                self.tick();
+                let rescan = child.is_dir
+                    && max_depth > 1
+                    && child.filtered_by.as_deref() != Some(MOUNT_POINT_RULE);
+                if rescan {
+                    let (path, name) = (child.path.clone(), child.name.clone());
+                    let is_git_repo = is_nested_repo(&path);
+                    self.scan_subdir(
+                        &mut root_entry,
+                        &mut entries,
+                        path,
+                        name,
+                        is_git_repo,
+                        max_depth - 1,
+                    );
+                    continue;
+                }
+                // The ignore files may have changed since, the entry itself hasn't
+                let mut child = child.clone();
+                if child.filtered_by.as_deref() != Some(MOUNT_POINT_RULE) {
+                    (
+                        child.is_gitignored,
+                        child.filtered_by,
+                        child.filter_annotation,
+                    ) = self.classify(&child.path, child.is_dir);
+                }
+                let omitted_by_rule = child
+                    .filtered_by
+                    .as_deref()
+                    .is_some_and(|rule_id| self.omits(rule_id));
+                if omitted_by_rule && !self.options.show_filtered {
+                    root_entry.has_omitted = true;
+                    if !child.is_dir {
+                        root_entry.metadata.files_count =
+                            root_entry.metadata.files_count.saturating_sub(1);
+                        root_entry.metadata.size =
+                            root_entry.metadata.size.saturating_sub(child.metadata.size);
+                    }
+                    continue;
+                }
+                if child.is_dir {
+                    root_entry.metadata.files_count += child.metadata.files_count;
+                    root_entry.metadata.size += child.metadata.size;
+                } else if self.rule_registry.is_some() {
+                    files.push((child.name.clone(), child.metadata.size));
+                }
+                self.bytes_seen += child.metadata.size;
+                self.keep(&mut entries, child);
+            }
+        }
+
+        // Read the directory and process entries
+        let listing = match previous {
+            Some(_) => Vec::new(),
+            None => match read_dir_entries(root) {
+                Ok(listing) => listing,
+                Err(e) => {
+                    // Keep the directory with its own metadata and report why it has no children
+                    warn!("Error reading directory {}: {}", root.display(), e);
+                    root_entry.metadata.size = self.entry_size(&root_metadata);
+                    root_entry.error = Some(describe_io_error(&e));
+                    return Ok(root_entry);
+                }
+            },
+        };
+
+        // Directories with too many entries are listed but not expanded
+        if let Some(limit) = self.options.file_limit {
+            if listing.len() > limit && !is_scan_root {
+                debug!(
+                    "Not expanding {} ({} entries)",
+                    root.display(),
+                    listing.len()
+                );
+                root_entry.metadata.files_count = listing.iter().filter(|e| !e.is_dir).count();
+                root_entry.metadata.size = self.entry_size(&root_metadata);
+                root_entry.filtered_by = Some(String::from(FILE_LIMIT_RULE));
+                root_entry.filter_annotation = Some(String::from("[too many entries]"));
+                root_entry.has_omitted = true;
+                return Ok(root_entry);
+            }
+        }
+
+        for ListedEntry { path, name, .. } in listing {
+            if self.options.is_cancelled() {
+                cancelled = true;
+                break;
+            }
+            self.tick();
+            if self.is_excluded(&path, &name) {
+                root_entry.has_omitted = true;
+                continue;
+            }
+            let (metadata, kind) = match self.entry_metadata(&path) {
+                Ok(found) => found,
+                Err(e) => {
+                    warn!("Error reading metadata of {}: {}", path.display(), e);
+                    self.keep(&mut entries, error_entry(path, name, &e));
+                    continue;
+                }
+            };
+
+            // Hidden like in Explorer, and left out of the totals as well
+            if !self.options.show_all && has_hidden_attribute(&metadata) {
+                root_entry.has_omitted = true;
+                continue;
+            }
+
+            // Files filtered out are left out of the totals as well
+            if !metadata.is_dir() && !self.is_included(&path, &name, &metadata) {
+                root_entry.has_omitted = true;
+                continue;
+            }
+            // Check if this specific entry is gitignored or hidden by a rule
+            let (is_gitignored, mut filtered_by, mut filter_annotation) =
+                self.classify(&path, metadata.is_dir());
+
+            // Rules like `dotfiles` leave their matches out, totals included
+            let omitted_by_rule = filtered_by
+                .as_deref()
+                .is_some_and(|rule_id| self.omits(rule_id));
+            if omitted_by_rule && !self.options.show_filtered {
+                root_entry.has_omitted = true;
+                continue;
+            }
+
+            let (created, modified, error) = timestamps(&metadata);
+            let (mode, uid, gid) = permissions(&metadata);
+
+            // Past the memory budget, keep the aggregates but stop building entries
+            if self.over_memory_limit() {
+                omitted += 1;
+                root_entry.has_omitted = true;
+                if metadata.is_dir() && max_depth > 1 {
+                    let (files, size, _) = self.summarize_dir(&path, max_depth - 1, usize::MAX);
+                    root_entry.metadata.files_count += files;
+                    root_entry.metadata.size += size;
+                } else {
+                    root_entry.metadata.files_count += usize::from(!metadata.is_dir());
+                    root_entry.metadata.size += self.entry_size(&metadata);
+                }
+                continue;
+            }
+
+            if metadata.is_dir() {
+                let is_git_repo = is_nested_repo(&path);
+
+                // Mount points are listed but not expanded
+                let is_mount_point = self.is_mount_point(&metadata);
+                if is_mount_point {
+                    debug!("Not crossing into the filesystem at {}", path.display());
+                    filtered_by = Some(String::from(MOUNT_POINT_RULE));
+                    filter_annotation = Some(String::from("[other filesystem]"));
+                }
+
+                // Recursively scan subdirectories if depth allows
+                if max_depth > 1 && !is_mount_point {
+                    self.scan_subdir(
+                        &mut root_entry,
+                        &mut entries,
+                        path,
+                        name,
+                        is_git_repo,
+                        max_depth - 1,
+                    );
+                } else {
+                    // Just add the directory as a leaf node
+                    let size = self.entry_size(&metadata);
+                    let has_unlisted = !is_mount_point && has_entries(&path);
+                    self.keep(
+                        &mut entries,
+                        DirectoryEntry {
+                            path,
+                            name,
+                            is_dir: true,
+                            metadata: EntryMetadata {
+                                size,
+                                created,
+                                modified,
+                                files_count: 0,
+                                mode,
+                                uid,
+                                gid,
+                                approximate: false,
+                            },
+                            children: Vec::new(),
+                            is_gitignored,
+                            is_git_repo,
+                            filtered_by,
+                            filter_annotation,
+                            error,
+                            is_virtual: false,
+                            kind,
+                            has_unlisted,
+                            // A mount point's contents are never looked at
+                            has_omitted: is_mount_point,
+                            cancelled: false,
+                        },
+                    );
+
+                    // Update parent size
+                    root_entry.metadata.size += size;
+                }
+            } else {
+                // For files, update parent metadata and add to entries
+                let size = self.entry_size(&metadata);
+                root_entry.metadata.files_count += 1;
+                root_entry.metadata.size += size;
+                if self.rule_registry.is_some() {
+                    files.push((name.clone(), size));
+                }
+                if self.options.dirs_only {
+                    root_entry.has_omitted = true;
+                    continue;
+                }
+
+                self.keep(
+                    &mut entries,
+                    DirectoryEntry {
+                        path,
+                        name,
+                        is_dir: false,
+                        metadata: EntryMetadata {
+                            size,
+                            created,
+                            modified,
+                            files_count: 0,
+                            mode,
+                            uid,
+                            gid,
+                            approximate: false,
+                        },
+                        children: Vec::new(),
+                        is_gitignored,
+                        is_git_repo: false,
+                        filtered_by,
+                        filter_annotation,
+                        error,
+                        is_virtual: false,
+                        kind,
+                        has_unlisted: false,
+                        has_omitted: false,
+                        cancelled: false,
+                    },
+                );
+            }
+        }
+
+        if cancelled {
+            debug!("Scan cancelled while reading {}", root.display());
+            root_entry.metadata.approximate = true;
+            root_entry.cancelled = true;
+            root_entry
+                .filter_annotation
+                .get_or_insert_with(|| String::from(CANCELLED_ANNOTATION));
+        }
+        if omitted > 0 {
+            debug!(
+                "Memory limit reached, {} entries of {} kept as totals only",
+                omitted,
+                root.display()
+            );
+            root_entry.filter_annotation.get_or_insert_with(|| {
+                format!("[truncated: {} entries over memory limit]", omitted)
             });
         }
+
+        // Rules judging a directory by its files decide once it is listed
+        if let Some(registry) = self
+            .rule_registry
+            .filter(|_| root_entry.filtered_by.is_none() && !cancelled)
+        {
+            if let (Some(rule_id), annotation) =
+                self.classifier.classify_listing(registry, root, &files)
+            {
+                root_entry.filtered_by = Some(rule_id);
+                root_entry.filter_annotation = annotation;
+                if !show_hidden && !is_scan_root {
+                    entries.clear();
+                    root_entry.has_omitted = true;
+                }
+            }
+        }
+
+        // Set the children
+        root_entry.children = entries;
+
+        // A directory holding re-included entries is shown rather than folded
+        if reincludes
+            && root_entry
+                .children
+                .iter()
+                .any(|child| !ignored_by_gitignore(child))
+        {
+            root_entry.is_gitignored = false;
+            if root_entry.filtered_by.as_deref() == Some(GITIGNORE_RULE) {
+                root_entry.filtered_by = None;
+                root_entry.filter_annotation = None;
+            }
+        }
+
+        // With the totals known, the hiding rule may describe the directory
+        let summary = self.rule_registry.zip(root_entry.filtered_by.as_deref());
+        if let Some(summary) = summary.and_then(|(registry, rule_id)| {
+            registry.summary(
+                rule_id,
+                root_entry.metadata.size,
+                root_entry.metadata.files_count,
+            )
+        }) {
+            root_entry.filter_annotation = Some(summary);
+        }
+
+        Ok(root_entry)
     }
+}
 
-    // Set the children
-    root_entry.children = entries;
+/// Running totals of a folded directory's contents
+#[derive(Default)]
+struct FoldedTotals {
+    files: usize,
+    size: u64,
+    /// Entries looked at so far, files and directories alike
+    seen: usize,
+}
+
+/// Directories of a scanned tree by path
+fn index_directories(root: &DirectoryEntry) -> HashMap<&Path, &DirectoryEntry> {
+    let mut index = HashMap::new();
+    let mut pending = vec![root];
+    while let Some(entry) = pending.pop() {
+        if entry.is_dir {
+            index.insert(entry.path.as_path(), entry);
+            pending.extend(&entry.children);
+        }
+    }
+    index
+}
 
-    Ok(root_entry)
+/// Whether an entry is hidden by ignore files, as a system directory or by
+/// the gitignore rule
+fn ignored_by_gitignore(entry: &DirectoryEntry) -> bool {
+    entry.is_gitignored || entry.filtered_by.as_deref() == Some(GITIGNORE_RULE)
+}
+
+/// Whether a directory is a Git repository of its own (`.git` is a directory
+/// for nested clones and a file for submodules and worktrees)
+pub(crate) fn is_nested_repo(dir: &Path) -> bool {
+    fs::symlink_metadata(dir.join(".git")).is_ok()
+}
+
+/// Identifier of the filesystem holding an entry
+#[cfg(unix)]
+pub(crate) fn device_id(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+/// Filesystems can't be told apart here, so everything counts as one
+#[cfg(not(unix))]
+pub(crate) fn device_id(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Bytes allocated on disk for an entry, or 0 for a hard link to a file
+/// that was already counted
+#[cfg(unix)]
+pub(crate) fn allocated_size(
+    metadata: &fs::Metadata,
+    seen_inodes: &mut HashSet<(u64, u64)>,
+) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    if !metadata.is_dir()
+        && metadata.nlink() > 1
+        && !seen_inodes.insert((metadata.dev(), metadata.ino()))
+    {
+        return 0;
+    }
+    // st_blocks is always in 512-byte units, whatever the filesystem block size
+    metadata.blocks() * 512
+}
+
+/// Allocation sizes aren't available here, so fall back to the apparent size
+#[cfg(not(unix))]
+pub(crate) fn allocated_size(
+    metadata: &fs::Metadata,
+    _seen_inodes: &mut HashSet<(u64, u64)>,
+) -> u64 {
+    metadata.len()
+}
+
+/// Rough number of bytes an entry occupies, excluding its children
+fn entry_footprint(entry: &DirectoryEntry) -> usize {
+    std::mem::size_of::<DirectoryEntry>()
+        + entry.path.as_os_str().len()
+        + entry.name.len()
+        + entry.filtered_by.as_ref().map_or(0, String::len)
+        + entry.filter_annotation.as_ref().map_or(0, String::len)
+        + entry.error.as_ref().map_or(0, String::len)
+}
+
+/// Creation and modification times, with a note when the platform cannot provide them
+pub(crate) fn timestamps(metadata: &fs::Metadata) -> (SystemTime, SystemTime, Option<String>) {
+    let mut error = None;
+    let modified = metadata.modified().unwrap_or_else(|e| {
+        error = Some(describe_io_error(&e));
+        UNIX_EPOCH
+    });
+    let created = metadata.created().unwrap_or_else(|e| {
+        error.get_or_insert_with(|| describe_io_error(&e));
+        modified
+    });
+    (created, modified, error)
+}
+
+/// Permission bits and owning user and group ids
+///
+/// Windows only exposes a read-only flag, so the mode is approximated from it
+/// and ownership is left unknown.
+pub(crate) fn permissions(metadata: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        (
+            Some(metadata.mode() & 0o7777),
+            Some(metadata.uid()),
+            Some(metadata.gid()),
+        )
+    }
+
+    #[cfg(not(unix))]
+    {
+        let mut mode = if metadata.permissions().readonly() {
+            0o444
+        } else {
+            0o666
+        };
+        if metadata.is_dir() {
+            mode |= 0o111;
+        }
+        (Some(mode), None, None)
+    }
+}
+
+/// Whether the entry at `path` is a symlink, socket, named pipe or device
+/// rather than a plain file or directory, from its metadata not following links
+pub(crate) fn entry_kind(path: &Path, metadata: &fs::Metadata) -> EntryKind {
+    if metadata.file_type().is_symlink() {
+        return EntryKind::Symlink {
+            target: fs::read_link(path).ok().map(|target| link_text(&target)),
+            broken: fs::metadata(path).is_err(),
+        };
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        let file_type = metadata.file_type();
+        if file_type.is_socket() {
+            EntryKind::Socket
+        } else if file_type.is_fifo() {
+            EntryKind::Fifo
+        } else if file_type.is_block_device() {
+            EntryKind::BlockDevice
+        } else if file_type.is_char_device() {
+            EntryKind::CharDevice
+        } else {
+            EntryKind::Regular
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        EntryKind::Regular
+    }
+}
+
+/// A link target as stored in the link
+///
+/// Junction targets come back in their NT form (`\??\C:\...`), so the
+/// prefix is dropped to show the plain path.
+fn link_text(target: &Path) -> String {
+    let target = target.display().to_string();
+    let plain = target
+        .strip_prefix(r"\??\")
+        .or_else(|| target.strip_prefix(r"\\?\"))
+        .unwrap_or(&target);
+    plain.to_string()
+}
+
+/// Whether Windows marks the entry as hidden or as a system file; always
+/// false elsewhere, where only dotfiles are hidden
+#[cfg(windows)]
+pub(crate) fn has_hidden_attribute(metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+    metadata.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0
+}
+
+#[cfg(not(windows))]
+pub(crate) fn has_hidden_attribute(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Whether a directory has anything in it, without listing it
+fn has_entries(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Placeholder for an entry whose metadata could not be read
+fn error_entry(path: PathBuf, name: String, error: &Error) -> DirectoryEntry {
+    DirectoryEntry {
+        path,
+        name,
+        is_dir: false,
+        metadata: EntryMetadata {
+            size: 0,
+            created: UNIX_EPOCH,
+            modified: UNIX_EPOCH,
+            files_count: 0,
+            mode: None,
+            uid: None,
+            gid: None,
+            approximate: false,
+        },
+        children: Vec::new(),
+        is_gitignored: false,
+        is_git_repo: false,
+        filtered_by: None,
+        filter_annotation: None,
+        error: Some(describe_error(error)),
+        is_virtual: false,
+        kind: EntryKind::Regular,
+        has_unlisted: false,
+        has_omitted: false,
+        cancelled: false,
+    }
+}
+
+/// Short, user-facing description of a scan error
+fn describe_error(error: &Error) -> String {
+    match error.io_error() {
+        Some(e) => describe_io_error(e),
+        None => error.to_string(),
+    }
+}
+
+pub(crate) fn describe_io_error(error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => String::from("permission denied"),
+        io::ErrorKind::NotFound => String::from("not found"),
+        io::ErrorKind::Unsupported => String::from("metadata unavailable on this platform"),
+        _ => error.to_string(),
+    }
 }
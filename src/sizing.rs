@@ -0,0 +1,273 @@
+//! Exact sizes of folded directories for `--exact-sizes`
+//!
+//! The scanner stops counting inside a folded directory after a while, so
+//! a big `node_modules` shows lower bounds. Worker threads walk the folded
+//! directories in full while the tree is rendered, and their totals are
+//! printed after it, or written into the tree for structured formats.
+
+use crate::scanner::allocated_size;
+use crate::stats::{format_count, format_size};
+use crate::types::DirectoryEntry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Total contents of one folded directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirSize {
+    /// The directory, relative to its scan root
+    pub name: String,
+    pub path: PathBuf,
+    pub files_count: usize,
+    pub size: u64,
+}
+
+/// Folded directories being sized on worker threads
+pub struct ExactSizes {
+    workers: Vec<JoinHandle<Vec<(usize, DirSize)>>>,
+}
+
+impl ExactSizes {
+    /// Start sizing every folded directory of the scanned `roots`, by the
+    /// space they take on disk when `disk_usage` is set, like `--du` scans
+    pub fn spawn(roots: &[DirectoryEntry], disk_usage: bool) -> Self {
+        let mut dirs = Vec::new();
+        for root in roots {
+            let prefix = match roots.len() {
+                1 => None,
+                _ => Some(root.name.as_str()),
+            };
+            collect_folded(root, &root.path, prefix, &mut dirs);
+        }
+
+        let threads = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(dirs.len());
+        let queue = Arc::new(Mutex::new(
+            dirs.into_iter().enumerate().collect::<VecDeque<_>>(),
+        ));
+        // Hard links are counted once across all directories, as in the scan
+        let seen_inodes = Arc::new(Mutex::new(HashSet::new()));
+        let workers = (0..threads)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let seen_inodes = Arc::clone(&seen_inodes);
+                thread::spawn(move || {
+                    let mut sized = Vec::new();
+                    // The lock is released before each directory is walked
+                    while let Some((index, (name, path))) = queue.lock().unwrap().pop_front() {
+                        let seen_inodes = disk_usage.then_some(&*seen_inodes);
+                        let (files_count, size) = measure(&path, seen_inodes);
+                        sized.push((
+                            index,
+                            DirSize {
+                                name,
+                                path,
+                                files_count,
+                                size,
+                            },
+                        ));
+                    }
+                    sized
+                })
+            })
+            .collect();
+        ExactSizes { workers }
+    }
+
+    /// Wait for the workers, returning the sizes in tree order
+    pub fn wait(self) -> Vec<DirSize> {
+        let mut sized: Vec<(usize, DirSize)> = self
+            .workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect();
+        sized.sort_by_key(|(index, _)| *index);
+        sized.into_iter().map(|(_, size)| size).collect()
+    }
+}
+
+/// Write the sizes into the folded directories of `root`, and the
+/// difference to what the scan counted into their ancestors
+pub fn apply_exact_sizes(root: &mut DirectoryEntry, sizes: &[DirSize]) {
+    let by_path: HashMap<&Path, &DirSize> =
+        sizes.iter().map(|dir| (dir.path.as_path(), dir)).collect();
+    apply_to(root, &by_path);
+}
+
+/// Apply the sizes below `entry`, returning how many files and bytes were
+/// added to it
+fn apply_to(entry: &mut DirectoryEntry, sizes: &HashMap<&Path, &DirSize>) -> (isize, i64) {
+    if let Some(dir) = sizes.get(entry.path.as_path()) {
+        let added = (
+            dir.files_count as isize - entry.metadata.files_count as isize,
+            dir.size as i64 - entry.metadata.size as i64,
+        );
+        entry.metadata.files_count = dir.files_count;
+        entry.metadata.size = dir.size;
+        entry.metadata.approximate = false;
+        return added;
+    }
+
+    let mut added = (0, 0);
+    for child in &mut entry.children {
+        let (files, size) = apply_to(child, sizes);
+        added.0 += files;
+        added.1 += size;
+    }
+    entry.metadata.files_count = entry.metadata.files_count.saturating_add_signed(added.0);
+    entry.metadata.size = entry.metadata.size.saturating_add_signed(added.1);
+    added
+}
+
+/// Render the sizes as text to print after the tree
+pub fn format_exact_sizes(sizes: &[DirSize]) -> String {
+    if sizes.is_empty() {
+        return String::new();
+    }
+
+    let mut lines = vec![String::from("Folded directories:")];
+    for dir in sizes {
+        lines.push(format!(
+            "  {}/ ({} files, {})",
+            dir.name,
+            format_count(dir.files_count),
+            format_size(dir.size)
+        ));
+    }
+    lines.push(format!(
+        "  total ({} files, {})",
+        format_count(sizes.iter().map(|dir| dir.files_count).sum()),
+        format_size(sizes.iter().map(|dir| dir.size).sum())
+    ));
+    format!("\n{}\n", lines.join("\n"))
+}
+
+/// Directories the scanner didn't look into because they are folded
+fn collect_folded(
+    entry: &DirectoryEntry,
+    root: &Path,
+    prefix: Option<&str>,
+    dirs: &mut Vec<(String, PathBuf)>,
+) {
+    for child in &entry.children {
+        if !child.is_dir {
+            continue;
+        }
+        let folded = child.is_gitignored || child.filtered_by.is_some();
+        if folded && child.children.is_empty() && child.metadata.files_count > 0 {
+            let relative = child.path.strip_prefix(root).unwrap_or(&child.path);
+            let name = match prefix {
+                Some(prefix) => Path::new(prefix).join(relative),
+                None => relative.to_path_buf(),
+            };
+            dirs.push((name.to_string_lossy().into_owned(), child.path.clone()));
+        } else {
+            collect_folded(child, root, prefix, dirs);
+        }
+    }
+}
+
+/// Count the files and bytes below `dir`, without following symlinks
+///
+/// With `seen_inodes`, bytes are the space allocated on disk, directories
+/// included, and hard links already seen take none.
+fn measure(dir: &Path, seen_inodes: Option<&Mutex<HashSet<(u64, u64)>>>) -> (usize, u64) {
+    let entry_size = |metadata: &fs::Metadata| match seen_inodes {
+        Some(seen_inodes) => allocated_size(metadata, &mut seen_inodes.lock().unwrap()),
+        None if metadata.is_dir() => 0,
+        None => metadata.len(),
+    };
+
+    let mut files = 0;
+    let mut size = fs::symlink_metadata(dir).map_or(0, |metadata| entry_size(&metadata));
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(listing) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in listing.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            size += entry_size(&metadata);
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                files += 1;
+            }
+        }
+    }
+    (files, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scan, GitIgnoreContext, ScanOptions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_exact_sizes_of_folded_dirs() {
+        let root = tempdir().unwrap();
+        let root_path = root.path();
+        fs::create_dir_all(root_path.join("node_modules/pkg/lib")).unwrap();
+        fs::create_dir_all(root_path.join("web/target")).unwrap();
+        fs::write(root_path.join("node_modules/pkg/index.js"), "abc").unwrap();
+        fs::write(root_path.join("node_modules/pkg/lib/a.js"), "abcd").unwrap();
+        fs::write(root_path.join("web/target/out.o"), "12345").unwrap();
+        fs::write(root_path.join("web/main.rs"), "").unwrap();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let tree = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        let sizes = ExactSizes::spawn(std::slice::from_ref(&tree), false).wait();
+
+        let mut names: Vec<_> = sizes.iter().map(|dir| dir.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["node_modules", "web/target"]);
+        let node_modules = sizes.iter().find(|dir| dir.name == "node_modules").unwrap();
+        assert_eq!((node_modules.files_count, node_modules.size), (2, 7));
+
+        let text = format_exact_sizes(&sizes);
+        assert!(text.contains("  total (3 files, 12B)"), "{}", text);
+
+        // Written into the tree, ancestors included
+        let mut tree = tree;
+        let mut sizes = sizes;
+        sizes.iter_mut().for_each(|dir| dir.size += 100);
+        let before = tree.metadata.size;
+        apply_exact_sizes(&mut tree, &sizes);
+        assert_eq!(tree.metadata.size, before + 200);
+        let web = tree.children.iter().find(|c| c.name == "web").unwrap();
+        let target = web.children.iter().find(|c| c.name == "target").unwrap();
+        assert_eq!(
+            (target.metadata.files_count, target.metadata.size),
+            (1, 105)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exact_disk_usage_counts_hard_links_once() {
+        let root = tempdir().unwrap();
+        let modules = root.path().join("node_modules");
+        fs::create_dir_all(&modules).unwrap();
+        fs::write(modules.join("a.js"), "x".repeat(10_000)).unwrap();
+        fs::hard_link(modules.join("a.js"), modules.join("b.js")).unwrap();
+
+        let apparent = measure(&modules, None);
+        assert_eq!(apparent, (2, 20_000));
+        let seen_inodes = Mutex::new(HashSet::new());
+        let (files, allocated) = measure(&modules, Some(&seen_inodes));
+        assert_eq!(files, 2);
+        let file_blocks = allocated_size(
+            &fs::metadata(modules.join("a.js")).unwrap(),
+            &mut HashSet::new(),
+        );
+        let dir_blocks = allocated_size(&fs::metadata(&modules).unwrap(), &mut HashSet::new());
+        assert_eq!(allocated, file_blocks + dir_blocks);
+    }
+}
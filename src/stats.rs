@@ -0,0 +1,310 @@
+//! Aggregate statistics over a scanned tree
+
+use crate::types::DirectoryEntry;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Upper bounds (exclusive) of the size histogram buckets, in bytes
+const BUCKET_LIMITS: [u64; 5] = [
+    1024,
+    100 * 1024,
+    1024 * 1024,
+    100 * 1024 * 1024,
+    1024 * 1024 * 1024,
+];
+
+/// Labels for the histogram buckets (one more than the limits)
+const BUCKET_LABELS: [&str; 6] = [
+    "< 1K",
+    "1K - 100K",
+    "100K - 1M",
+    "1M - 100M",
+    "100M - 1G",
+    ">= 1G",
+];
+
+/// Width of the longest histogram bar, in characters
+const BAR_WIDTH: usize = 40;
+
+/// Number of extensions listed in each ranking
+const TOP_EXTENSIONS: usize = 10;
+
+/// Label used for files without an extension
+const NO_EXTENSION: &str = "(none)";
+
+/// File count and total size for a single extension
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExtensionStats {
+    pub files: usize,
+    pub size: u64,
+}
+
+/// Summary statistics collected from a scanned tree
+#[derive(Debug, Clone, Default)]
+pub struct TreeStats {
+    /// Number of files in the scanned tree
+    pub files: usize,
+    /// Number of directories below the root
+    pub directories: usize,
+    /// Total size of all scanned files
+    pub total_size: u64,
+    /// Number of files per size bucket
+    pub size_histogram: [usize; 6],
+    /// Files and bytes per lowercase extension (with leading dot)
+    pub extensions: HashMap<String, ExtensionStats>,
+}
+
+impl TreeStats {
+    /// Collect statistics over all entries below `root`
+    pub fn collect(root: &DirectoryEntry) -> Self {
+        let mut stats = TreeStats::default();
+        for child in &root.children {
+            stats.visit(child);
+        }
+        stats
+    }
+
+    fn visit(&mut self, entry: &DirectoryEntry) {
+        if entry.is_dir {
+            self.directories += 1;
+            for child in &entry.children {
+                self.visit(child);
+            }
+        } else {
+            self.files += 1;
+            self.total_size += entry.metadata.size;
+            self.size_histogram[bucket_index(entry.metadata.size)] += 1;
+
+            let extension = entry
+                .path
+                .extension()
+                .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+                .unwrap_or_else(|| String::from(NO_EXTENSION));
+            let ext_stats = self.extensions.entry(extension).or_default();
+            ext_stats.files += 1;
+            ext_stats.size += entry.metadata.size;
+        }
+    }
+
+    /// Extensions sorted by file count (descending), then by name
+    pub fn top_extensions_by_count(&self, limit: usize) -> Vec<(&str, ExtensionStats)> {
+        self.ranked_extensions(limit, |a, b| b.files.cmp(&a.files))
+    }
+
+    /// Extensions sorted by total size (descending), then by name
+    pub fn top_extensions_by_size(&self, limit: usize) -> Vec<(&str, ExtensionStats)> {
+        self.ranked_extensions(limit, |a, b| b.size.cmp(&a.size))
+    }
+
+    fn ranked_extensions<F>(&self, limit: usize, compare: F) -> Vec<(&str, ExtensionStats)>
+    where
+        F: Fn(&ExtensionStats, &ExtensionStats) -> std::cmp::Ordering,
+    {
+        let mut ranked: Vec<(&str, ExtensionStats)> = self
+            .extensions
+            .iter()
+            .map(|(ext, stats)| (ext.as_str(), *stats))
+            .collect();
+        ranked.sort_by(|a, b| compare(&a.1, &b.1).then_with(|| a.0.cmp(b.0)));
+        ranked.truncate(limit);
+        ranked
+    }
+}
+
+/// Format a byte size in binary units (e.g. 1.5MB)
+pub(crate) fn format_size(size: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+    const TB: u64 = GB * 1024;
+
+    if size >= TB {
+        format!("{:.2}TB", size as f64 / TB as f64)
+    } else if size >= GB {
+        format!("{:.2}GB", size as f64 / GB as f64)
+    } else if size >= MB {
+        format!("{:.1}MB", size as f64 / MB as f64)
+    } else if size >= KB {
+        format!("{:.1}KB", size as f64 / KB as f64)
+    } else {
+        format!("{}B", size)
+    }
+}
+
+/// Format a count with thousands separators (e.g. 1,204)
+pub(crate) fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn write_extension_table(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    ranked: &[(&str, ExtensionStats)],
+) -> fmt::Result {
+    writeln!(f, "\n{}:", title)?;
+    for (ext, stats) in ranked {
+        writeln!(
+            f,
+            "  {:<12} {:>9} files  {:>9}",
+            ext,
+            format_count(stats.files),
+            format_size(stats.size)
+        )?;
+    }
+    Ok(())
+}
+
+fn bucket_index(size: u64) -> usize {
+    BUCKET_LIMITS
+        .iter()
+        .position(|&limit| size < limit)
+        .unwrap_or(BUCKET_LIMITS.len())
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "files:       {}", self.files)?;
+        writeln!(f, "directories: {}", self.directories)?;
+        writeln!(
+            f,
+            "total size:  {} ({} bytes)",
+            format_size(self.total_size),
+            self.total_size
+        )?;
+
+        writeln!(f, "\nsize distribution:")?;
+        let max = self.size_histogram.iter().copied().max().unwrap_or(0);
+        for (label, &count) in BUCKET_LABELS.iter().zip(&self.size_histogram) {
+            let bar_len = if max == 0 {
+                0
+            } else {
+                // Keep non-empty buckets visible even when tiny compared to the largest
+                (count * BAR_WIDTH).div_ceil(max)
+            };
+            writeln!(
+                f,
+                "  {:>10} | {:<width$} {}",
+                label,
+                "#".repeat(bar_len),
+                count,
+                width = BAR_WIDTH
+            )?;
+        }
+
+        if !self.extensions.is_empty() {
+            write_extension_table(
+                f,
+                "top extensions by count",
+                &self.top_extensions_by_count(TOP_EXTENSIONS),
+            )?;
+            write_extension_table(
+                f,
+                "top extensions by size",
+                &self.top_extensions_by_size(TOP_EXTENSIONS),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EntryKind, EntryMetadata};
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    fn entry(name: &str, size: u64, children: Vec<DirectoryEntry>) -> DirectoryEntry {
+        DirectoryEntry {
+            path: PathBuf::from(name),
+            name: name.to_string(),
+            is_dir: !children.is_empty(),
+            metadata: EntryMetadata {
+                size,
+                created: SystemTime::now(),
+                modified: SystemTime::now(),
+                files_count: children.len(),
+                mode: None,
+                uid: None,
+                gid: None,
+                approximate: false,
+            },
+            children,
+            is_gitignored: false,
+            is_git_repo: false,
+            filtered_by: None,
+            filter_annotation: None,
+            error: None,
+            is_virtual: false,
+            kind: EntryKind::Regular,
+            has_unlisted: false,
+            has_omitted: false,
+            cancelled: false,
+        }
+    }
+
+    #[test]
+    fn test_size_histogram_buckets() {
+        let root = entry(
+            ".",
+            0,
+            vec![
+                entry("small", 10, vec![]),
+                entry("medium", 50 * 1024, vec![]),
+                entry("dir", 0, vec![entry("big", 2 * 1024 * 1024 * 1024, vec![])]),
+            ],
+        );
+
+        let stats = TreeStats::collect(&root);
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.directories, 1);
+        assert_eq!(stats.size_histogram, [1, 1, 0, 0, 0, 1]);
+        assert!(stats.to_string().contains(">= 1G"));
+    }
+
+    #[test]
+    fn test_extension_rankings() {
+        let root = entry(
+            ".",
+            0,
+            vec![
+                entry("a.PNG", 300, vec![]),
+                entry("b.png", 300, vec![]),
+                entry("c.rs", 1000, vec![]),
+                entry("Makefile", 5, vec![]),
+            ],
+        );
+
+        let stats = TreeStats::collect(&root);
+        let by_count = stats.top_extensions_by_count(2);
+        assert_eq!(
+            by_count[0],
+            (
+                ".png",
+                ExtensionStats {
+                    files: 2,
+                    size: 600
+                }
+            )
+        );
+        assert_eq!(by_count[1].0, NO_EXTENSION);
+
+        let by_size = stats.top_extensions_by_size(1);
+        assert_eq!(by_size[0].0, ".rs");
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(7), "7");
+        assert_eq!(format_count(1204), "1,204");
+        assert_eq!(format_count(1234567), "1,234,567");
+    }
+}
@@ -155,12 +155,14 @@ impl TestFileBuilder {
 }
 
 #[cfg(test)]
+#[allow(deprecated)] // Exercises the legacy GitIgnore scanning API
 mod integration_tests {
     use super::*;
     use crate::format_tree;
     use crate::gitignore::GitIgnore;
     use crate::scan_directory_with_legacy_gitignore;
-    use crate::types::{ColorTheme, DisplayConfig, SortBy};
+    use crate::types::{ColorTheme, DisplayConfig};
+    use std::time::SystemTime;
 
     /// Test for correctly marking system directories as gitignored
     #[test]
@@ -243,23 +245,11 @@ mod integration_tests {
             scan_directory_with_legacy_gitignore(&root_path, &gitignore, usize::MAX, None).unwrap();
 
         // Configure to only show 2 items in directory (2 lines + collapsed indicator)
-        let config = DisplayConfig {
-            max_lines: 5,
-            dir_limit: 2,
-            sort_by: SortBy::Name,
-            dirs_first: false,
-            use_colors: false,
-            color_theme: ColorTheme::None,
-            use_emoji: false,
-            size_colorize: false,
-            date_colorize: false,
-            detailed_metadata: false,
-            show_system_dirs: false,
-            show_filtered: false,
-            disable_rules: Vec::new(),
-            enable_rules: Vec::new(),
-            rule_debug: false,
-        };
+        let config = DisplayConfig::builder()
+            .max_lines(5)
+            .dir_limit(2)
+            .color_theme(ColorTheme::None)
+            .build();
 
         let output = format_tree(&root, &config).unwrap();
 
@@ -309,23 +299,11 @@ mod integration_tests {
         let root =
             scan_directory_with_legacy_gitignore(&root_path, &gitignore, usize::MAX, None).unwrap();
 
-        let config = DisplayConfig {
-            max_lines: 10,
-            dir_limit: 10,
-            sort_by: SortBy::Name,
-            dirs_first: false,
-            use_colors: false,
-            color_theme: ColorTheme::None,
-            use_emoji: false,
-            size_colorize: false,
-            date_colorize: false,
-            detailed_metadata: false,
-            show_system_dirs: false,
-            show_filtered: false,
-            disable_rules: Vec::new(),
-            enable_rules: Vec::new(),
-            rule_debug: false,
-        };
+        let config = DisplayConfig::builder()
+            .max_lines(10)
+            .dir_limit(10)
+            .color_theme(ColorTheme::None)
+            .build();
 
         let output = format_tree(&root, &config).unwrap();
 
@@ -383,23 +361,10 @@ mod integration_tests {
                 .unwrap();
 
         // First test with show_system_dirs = false (default)
-        let config = DisplayConfig {
-            max_lines: 20,
-            dir_limit: 20,
-            sort_by: SortBy::Name,
-            dirs_first: false,
-            use_colors: false,
-            color_theme: ColorTheme::None,
-            use_emoji: false,
-            size_colorize: false,
-            date_colorize: false,
-            detailed_metadata: false,
-            show_system_dirs: false,
-            show_filtered: false,
-            disable_rules: Vec::new(),
-            enable_rules: Vec::new(),
-            rule_debug: false,
-        };
+        let config = DisplayConfig::builder()
+            .max_lines(20)
+            .color_theme(ColorTheme::None)
+            .build();
 
         let output = format_tree(&root, &config).unwrap();
         println!("Output without show_system_dirs:\n{}", output);
@@ -455,4 +420,1398 @@ mod integration_tests {
             ".git directory should have [system] indicator instead of being folded"
         );
     }
+
+    /// Test that --dereference counts symlink targets and survives loops
+    #[cfg(unix)]
+    #[test]
+    fn test_dereference_symlinks() {
+        use crate::{scan, EntryKind, GitIgnoreContext, ScanOptions};
+        use std::os::unix::fs::symlink;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("data/big.bin", &"x".repeat(10_000))
+            .create_file("node_modules/pkg/index.js", "pkg");
+        let root_path = builder.root_path();
+        symlink(root_path.join("data"), root_path.join("linked")).unwrap();
+        symlink(root_path, root_path.join("data/loop")).unwrap();
+
+        let options = ScanOptions {
+            dereference: true,
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        let linked = root.children.iter().find(|c| c.name == "linked").unwrap();
+        assert!(linked.is_dir, "Symlinked directory should be followed");
+        let target = Some(root_path.join("data").display().to_string());
+        let broken = false;
+        assert_eq!(linked.kind, EntryKind::Symlink { target, broken });
+        assert!(linked.metadata.size >= 10_000, "Target size should count");
+
+        // Folded directories are counted without following loops either
+        symlink(
+            root_path.join("node_modules/pkg"),
+            root_path.join("node_modules/pkg/self"),
+        )
+        .unwrap();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        let modules = root
+            .children
+            .iter()
+            .find(|c| c.name == "node_modules")
+            .unwrap();
+        assert!(modules.children.is_empty(), "Folded");
+        // The file and the link, which is counted rather than followed
+        assert_eq!(modules.metadata.files_count, 2);
+        assert!(!modules.metadata.approximate);
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        let linked = root.children.iter().find(|c| c.name == "linked").unwrap();
+        assert!(!linked.is_dir, "Symlinks are not followed by default");
+        assert!(matches!(linked.kind, EntryKind::Symlink { .. }));
+    }
+
+    /// Test that --max-memory keeps totals while dropping entries past the cap
+    #[test]
+    fn test_max_memory_keeps_aggregates() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        for i in 0..50 {
+            builder.create_file(&format!("dir{:02}/file.txt", i), "0123456789");
+        }
+        let root_path = builder.root_path();
+
+        let options = ScanOptions {
+            max_memory: Some(1),
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        assert!(root.children.len() < 50, "Entries past the cap are dropped");
+        assert_eq!(root.metadata.files_count, 50, "Totals are still complete");
+        assert!(root.metadata.size >= 500);
+        assert!(root
+            .filter_annotation
+            .as_deref()
+            .is_some_and(|a| a.contains("truncated")));
+    }
+
+    /// Test that the tree view says when the root itself was cut by --max-memory
+    #[test]
+    fn test_max_memory_root_annotation_shown() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        for i in 0..50 {
+            builder.create_file(&format!("file{:02}.txt", i), "0123456789");
+        }
+        let root_path = builder.root_path();
+
+        let options = ScanOptions {
+            max_memory: Some(1),
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        let config = DisplayConfig::builder()
+            .color_theme(ColorTheme::None)
+            .build();
+        let output = format_tree(&root, &config).unwrap();
+        let first_line = output.lines().next().unwrap();
+        assert!(
+            first_line.contains("entries over memory limit]"),
+            "Root line: {}",
+            first_line
+        );
+    }
+
+    /// Test that --filelimit lists big directories without expanding them
+    #[test]
+    fn test_file_limit_skips_big_directories() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        for i in 0..30 {
+            builder.create_file(&format!("big_dir/file{:02}.txt", i), "x");
+        }
+        builder.create_file("big_dir/sub/nested.txt", "x");
+        builder.create_file("small_dir/readme.md", "x");
+        let root_path = builder.root_path();
+
+        let options = ScanOptions {
+            file_limit: Some(10),
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        let big_dir = root.children.iter().find(|c| c.name == "big_dir").unwrap();
+        assert!(big_dir.children.is_empty(), "Big directory is not expanded");
+        assert_eq!(big_dir.metadata.files_count, 30, "Only files are counted");
+
+        let small_dir = root
+            .children
+            .iter()
+            .find(|c| c.name == "small_dir")
+            .unwrap();
+        assert_eq!(small_dir.children.len(), 1);
+
+        let config = DisplayConfig::builder()
+            .max_lines(20)
+            .color_theme(ColorTheme::None)
+            .build();
+        let output = format_tree(&root, &config).unwrap();
+        assert!(output.contains("big_dir (30 files)"), "Output:\n{}", output);
+        assert!(output.contains("too many entries"));
+    }
+
+    /// Test that priority patterns with `/` match the path from the root
+    #[test]
+    fn test_priority_patterns_with_paths() {
+        use crate::{scan, GitIgnoreContext, Placement, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/a.rs", "")
+            .create_file("src/main.rs", "")
+            .create_file("lib/a.rs", "")
+            .create_file("lib/main.rs", "");
+        let root_path = builder.root_path();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+
+        let config = DisplayConfig::builder()
+            .max_lines(20)
+            .color_theme(ColorTheme::None)
+            .priorities(vec![(String::from("src/main.rs"), Placement::First)])
+            .build();
+        let output = format_tree(&root, &config).unwrap();
+        let names: Vec<&str> = output
+            .lines()
+            .filter_map(|line| line.split("── ").nth(1))
+            .map(|rest| rest.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["lib", "a.rs", "main.rs", "src", "main.rs", "a.rs"],
+            "Output:\n{}",
+            output
+        );
+    }
+
+    /// Test that entries new or modified since a saved scan are highlighted
+    #[test]
+    fn test_highlight_changes_since_saved_scan() {
+        use crate::{changes_since, scan, ChangeKind, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("README.md", "readme");
+        let root_path = builder.root_path().to_path_buf();
+        let scan = || {
+            let mut ctx = GitIgnoreContext::new(&root_path).unwrap();
+            scan(&root_path, &mut ctx, &ScanOptions::default()).unwrap()
+        };
+
+        let previous = scan();
+        assert!(
+            changes_since(&previous, &scan()).is_empty(),
+            "Nothing changed yet"
+        );
+
+        builder
+            .create_file("src/main.rs", "fn main() { println!(\"changed\"); }")
+            .create_file("docs/guide.md", "guide");
+        let root = scan();
+        let changes = changes_since(&previous, &root);
+        assert_eq!(
+            changes.get(&root_path.join("src/main.rs")),
+            Some(&ChangeKind::Modified)
+        );
+        for new in ["docs", "docs/guide.md"] {
+            assert_eq!(changes.get(&root_path.join(new)), Some(&ChangeKind::New));
+        }
+        // Directories above a modified file aren't modified themselves
+        assert!(!changes.contains_key(&root_path.join("src")));
+        assert!(!changes.contains_key(&root_path.join("README.md")));
+
+        let config = DisplayConfig::builder()
+            .max_lines(20)
+            .color_theme(ColorTheme::None)
+            .changes(changes)
+            .build();
+        let output = format_tree(&root, &config).unwrap();
+        assert!(output
+            .lines()
+            .any(|l| l.contains("guide.md") && l.contains("[new]")));
+        assert!(output
+            .lines()
+            .any(|l| l.contains("main.rs") && l.contains("[modified]")));
+        assert!(output
+            .lines()
+            .any(|l| l.contains("README.md") && !l.contains("[")));
+    }
+
+    /// Test that a checksummed JSON export round-trips through verification
+    #[test]
+    fn test_verify_snapshot_round_trip() {
+        use crate::{
+            format_json, scan, verify_snapshot, Divergence, GitIgnoreContext, ScanOptions,
+        };
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("config.toml", "a = 1")
+            .create_file("notes.txt", "keep");
+        let root_path = builder.root_path().to_path_buf();
+
+        let mut ctx = GitIgnoreContext::new(&root_path).unwrap();
+        let root = scan(&root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        let snapshot = format_json(&root, None, true);
+
+        let verification = verify_snapshot(&snapshot, None).unwrap();
+        assert!(verification.is_ok(), "{:?}", verification.divergences);
+        assert_eq!(verification.checked, 4);
+
+        // Same size, different content
+        builder
+            .create_file("config.toml", "a = 2")
+            .create_file("src/lib.rs", "");
+        fs::remove_file(root_path.join("notes.txt")).unwrap();
+
+        let verification = verify_snapshot(&snapshot, None).unwrap();
+        let divergences = verification.divergences;
+        assert!(divergences.contains(&Divergence::HashMismatch(root_path.join("config.toml"))));
+        assert!(divergences.contains(&Divergence::Missing(root_path.join("notes.txt"))));
+        assert!(divergences.contains(&Divergence::Unexpected(root_path.join("src/lib.rs"))));
+        assert_eq!(divergences.len(), 3);
+    }
+
+    /// Test that exports leaving entries out verify cleanly against an unchanged tree
+    #[test]
+    fn test_verify_partial_snapshots() {
+        use crate::rules::create_default_registry;
+        use crate::{
+            format_json, scan, verify_snapshot, Divergence, GitIgnoreContext, ScanOptions,
+        };
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file(".env", "KEY=1")
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("src/util/mod.rs", "")
+            .create_file("README.md", "readme");
+        let root_path = builder.root_path().to_path_buf();
+
+        let mut registry = create_default_registry(&root_path).unwrap();
+        registry.set_enabled("dotfiles", true).unwrap();
+        let partial = [
+            ScanOptions::builder().max_depth(1).build(),
+            ScanOptions::builder().dirs_only(true).build(),
+            ScanOptions::builder()
+                .rules(Some(Arc::new(registry)))
+                .build(),
+        ];
+        for options in &partial {
+            let mut ctx = GitIgnoreContext::new(&root_path).unwrap();
+            let root = scan(&root_path, &mut ctx, options).unwrap();
+            let snapshot = format_json(&root, None, false);
+            let verification = verify_snapshot(&snapshot, None).unwrap();
+            assert!(verification.is_ok(), "{:?}", verification.divergences);
+        }
+
+        // Complete listings still catch new entries, counted among the checked ones
+        let mut ctx = GitIgnoreContext::new(&root_path).unwrap();
+        let root = scan(&root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        let snapshot = format_json(&root, None, false);
+        builder.create_file("src/lib.rs", "");
+        let verification = verify_snapshot(&snapshot, None).unwrap();
+        assert_eq!(
+            verification.divergences,
+            [Divergence::Unexpected(root_path.join("src/lib.rs"))]
+        );
+        assert_eq!(verification.checked, 7);
+    }
+
+    /// Test that the streaming scanner yields the same entries as the tree scan
+    #[test]
+    fn test_scan_iter_streams_entries() {
+        use crate::{scan, scan_iter, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("src/display/mod.rs", "")
+            .create_file("node_modules/pkg/index.js", "")
+            .create_file("README.md", "readme");
+        let root_path = builder.root_path();
+
+        let ctx = GitIgnoreContext::new(root_path).unwrap();
+        let entries: Vec<_> = scan_iter(root_path, ctx.clone(), &ScanOptions::default()).collect();
+        assert_eq!(entries[0].depth, 0, "Root comes first");
+        let names: Vec<(&str, usize)> = entries[1..]
+            .iter()
+            .map(|e| (e.name.as_str(), e.depth))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("README.md", 1),
+                ("node_modules", 1),
+                ("src", 1),
+                ("display", 2),
+                ("mod.rs", 3),
+                ("main.rs", 2),
+            ],
+            "Depth-first, sorted, without descending into gitignored directories"
+        );
+        assert!(entries[2].is_gitignored);
+
+        // With system directories expanded, both scanners see every entry
+        fn count(entry: &crate::DirectoryEntry) -> usize {
+            1 + entry.children.iter().map(count).sum::<usize>()
+        }
+        let everything = ScanOptions {
+            show_system_dirs: true,
+            ..ScanOptions::default()
+        };
+        let tree = scan(root_path, &mut ctx.clone(), &everything).unwrap();
+        assert_eq!(
+            count(&tree),
+            scan_iter(root_path, ctx.clone(), &everything).count()
+        );
+
+        let shallow = ScanOptions {
+            max_depth: 1,
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan_iter(root_path, ctx, &shallow).count(), 4);
+    }
+
+    /// Test that the streaming scanner applies the given ignore files, rules and kept paths
+    #[test]
+    fn test_scan_iter_applies_rules() {
+        use crate::rules::create_default_registry;
+        use crate::{scan_iter, GitIgnoreContext, IgnoreFile, ScanOptions};
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file(".dockerignore", "secret/\n")
+            .create_file(".gitignore", "*.log\n")
+            .create_file("secret/key.txt", "key")
+            .create_file("app.log", "log")
+            .create_file(".env", "TOKEN=1")
+            .create_file("src/main.rs", "fn main() {}");
+        let root_path = builder.root_path();
+        let mut registry = create_default_registry(root_path).unwrap();
+        registry.set_enabled("dotfiles", true).unwrap();
+        let options = ScanOptions::builder()
+            .rules(Some(Arc::new(registry)))
+            .build();
+        let names = |options: &ScanOptions| {
+            let ctx = GitIgnoreContext::with_ignore_files(root_path, &[IgnoreFile::Docker]);
+            scan_iter(root_path, ctx.unwrap(), options)
+                .skip(1)
+                .map(|entry| (entry.name, entry.is_gitignored))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(&options),
+            [
+                (String::from("app.log"), false),
+                (String::from("secret"), true),
+                (String::from("src"), false),
+                (String::from("main.rs"), false),
+            ],
+            "Dockerignored, not gitignored, and without the omitted dotfiles"
+        );
+
+        let mut keeping = options.clone();
+        keeping.always_show = vec![String::from("secret/key.txt")];
+        keeping.traverse_to_shown = true;
+        assert!(names(&keeping).contains(&(String::from("key.txt"), false)));
+    }
+
+    /// Test that --include/--exclude filter entries and --prune drops emptied directories
+    #[test]
+    fn test_include_exclude_filters() {
+        use crate::{scan, DirectoryEntry, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("src/notes.txt", "notes")
+            .create_file("docs/guide.md", "guide")
+            .create_file("tests/fixtures/data.rs", "data")
+            .create_file("tests/smoke.rs", "smoke");
+        let root_path = builder.root_path();
+
+        let options = ScanOptions {
+            include: vec![String::from("*.rs")],
+            exclude: vec![String::from("tests/fixtures")],
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        let names = |entry: &DirectoryEntry| -> Vec<String> {
+            let mut names: Vec<_> = entry.children.iter().map(|c| c.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(&root), vec!["docs", "src", "tests"]);
+        let src = root.children.iter().find(|c| c.name == "src").unwrap();
+        assert_eq!(names(src), vec!["main.rs"]);
+        assert_eq!(
+            src.metadata.files_count, 1,
+            "Filtered files are not counted"
+        );
+        let tests = root.children.iter().find(|c| c.name == "tests").unwrap();
+        assert_eq!(names(tests), vec!["smoke.rs"], "Excluded paths are dropped");
+
+        let options = ScanOptions {
+            prune: true,
+            ..options
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        assert_eq!(names(&root), vec!["src", "tests"], "docs/ has no .rs files");
+    }
+
+    /// Test that size bounds keep matching files and the directories containing them
+    #[test]
+    fn test_size_filters() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("assets/big.bin", &"x".repeat(5000))
+            .create_file("assets/small.txt", "x")
+            .create_file("docs/readme.md", "tiny")
+            .create_file("huge.log", &"x".repeat(20_000));
+        let root_path = builder.root_path();
+
+        let options = ScanOptions {
+            min_size: Some(1000),
+            max_size: Some(10_000),
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        assert_eq!(root.children.len(), 1, "Only assets/ contains a match");
+        let assets = &root.children[0];
+        assert_eq!(assets.name, "assets");
+        assert_eq!(assets.children.len(), 1);
+        assert_eq!(assets.children[0].name, "big.bin");
+        assert_eq!(root.metadata.files_count, 1);
+    }
+
+    /// Test that modification-time bounds keep recent files and their directories
+    #[test]
+    fn test_time_filters() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+        use std::time::Duration;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("old/archive.txt", "old")
+            .create_file("fresh/today.txt", "new")
+            .create_file("fresh/ancient.txt", "old");
+        let root_path = builder.root_path();
+        let week_ago = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+        for stale in ["old/archive.txt", "fresh/ancient.txt"] {
+            let file = File::options()
+                .write(true)
+                .open(root_path.join(stale))
+                .unwrap();
+            file.set_modified(week_ago).unwrap();
+        }
+
+        let day_ago = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        let options = ScanOptions {
+            newer_than: Some(day_ago),
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        assert_eq!(root.children.len(), 1, "old/ has no recent files");
+        let fresh = &root.children[0];
+        assert_eq!(fresh.children.len(), 1);
+        assert_eq!(fresh.children[0].name, "today.txt");
+
+        let options = ScanOptions {
+            older_than: Some(day_ago),
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        assert_eq!(root.metadata.files_count, 2);
+    }
+
+    /// Test that directories-only mode drops files but keeps their totals
+    #[test]
+    fn test_dirs_only() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("README.md", "readme")
+            .create_file("src/lib.rs", "0123456789")
+            .create_file("src/nested/mod.rs", "0123456789");
+        let root_path = builder.root_path();
+
+        let options = ScanOptions {
+            dirs_only: true,
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        assert_eq!(root.children.len(), 1);
+        let src = &root.children[0];
+        assert_eq!(src.name, "src");
+        assert_eq!(src.children.len(), 1, "Only nested/ is listed");
+        assert_eq!(src.metadata.files_count, 2);
+        assert_eq!(root.metadata.files_count, 3);
+        assert!(root.metadata.size >= 26);
+    }
+
+    /// Test that disk-usage mode counts allocated blocks and hard links once
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_usage_mode() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder.create_file("data/payload.bin", &"x".repeat(10_000));
+        let root_path = builder.root_path();
+        let sparse = File::create(root_path.join("sparse.img")).unwrap();
+        sparse.set_len(50 * 1024 * 1024).unwrap();
+        fs::hard_link(
+            root_path.join("data/payload.bin"),
+            root_path.join("data/link.bin"),
+        )
+        .unwrap();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let apparent = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        assert!(apparent.metadata.size >= 50 * 1024 * 1024 + 20_000);
+
+        let options = ScanOptions {
+            disk_usage: true,
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let usage = scan(root_path, &mut ctx, &options).unwrap();
+        let sparse = usage
+            .children
+            .iter()
+            .find(|c| c.name == "sparse.img")
+            .unwrap();
+        assert!(
+            sparse.metadata.size < 1024 * 1024,
+            "Sparse files take no blocks"
+        );
+
+        let data = usage.children.iter().find(|c| c.name == "data").unwrap();
+        let linked: u64 = data.children.iter().map(|c| c.metadata.size).sum();
+        assert!(linked >= 10_000, "The first link counts its blocks");
+        assert!(
+            linked < 20_000,
+            "The second link to the same inode counts nothing"
+        );
+    }
+
+    /// Test that directories left unlisted at the depth limit note whether they hold anything
+    #[test]
+    fn test_unlisted_directories_at_depth_limit() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("full/file.txt", "data")
+            .create_dir("empty");
+        let root_path = builder.root_path();
+
+        let options = ScanOptions {
+            max_depth: 1,
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        let dir = |name: &str| root.children.iter().find(|c| c.name == name).unwrap();
+        assert!(dir("full").children.is_empty(), "Not listed");
+        assert!(dir("full").has_unlisted);
+        assert!(!dir("empty").has_unlisted);
+    }
+
+    /// Test that scans record permission bits and ownership
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_metadata() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let mut builder = TestFileBuilder::new();
+        builder.create_file("run.sh", "#!/bin/sh\n");
+        let root_path = builder.root_path();
+        let script = root_path.join("run.sh");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o4750)).unwrap();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        let entry = root.children.iter().find(|c| c.name == "run.sh").unwrap();
+        assert_eq!(entry.metadata.mode, Some(0o4750));
+        assert_eq!(
+            entry.metadata.uid,
+            Some(fs::metadata(&script).unwrap().uid())
+        );
+        assert!(root.metadata.gid.is_some());
+    }
+
+    /// Test that folded directories are counted rather than estimated
+    #[test]
+    fn test_folded_directory_totals() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("node_modules/index.js", "module.exports = 1;\n")
+            .create_file("node_modules/pkg/a.js", "a")
+            .create_file("node_modules/pkg/lib/b.js", "bb")
+            .create_file("src/main.js", "");
+        let root_path = builder.root_path();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        let node_modules = root
+            .children
+            .iter()
+            .find(|c| c.name == "node_modules")
+            .unwrap();
+        assert!(node_modules.children.is_empty());
+        assert_eq!(node_modules.metadata.files_count, 3);
+        assert_eq!(node_modules.metadata.size, 23);
+        assert!(!node_modules.metadata.approximate);
+        assert_eq!(root.metadata.files_count, 4);
+    }
+
+    /// Test that scan_async returns the tree scan does
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_scan_async() {
+        use crate::{scan, scan_async, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("README.md", "# demo");
+        let root_path = builder.root_path();
+
+        let options = ScanOptions::builder().max_depth(3).build();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let tree = runtime
+            .block_on(scan_async(root_path, ctx.clone(), options.clone()))
+            .unwrap();
+
+        let expected = scan(root_path, &mut ctx, &options).unwrap();
+        let names = |entry: &crate::DirectoryEntry| {
+            let mut names: Vec<_> = entry.children.iter().map(|c| c.name.clone()).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(tree.metadata.files_count, 2);
+        assert_eq!(tree.metadata.size, expected.metadata.size);
+        assert_eq!(names(&tree), names(&expected));
+    }
+
+    /// Test that a scanned tree survives a round trip through serde
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use crate::{scan, DirectoryEntry, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("README.md", "# readme");
+        let root_path = builder.root_path();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        let json = serde_json::to_string(&root).unwrap();
+        let restored: DirectoryEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.metadata.files_count, 2);
+        assert_eq!(restored.metadata.modified, root.metadata.modified);
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+    }
+
+    /// Test that a directory mostly holding large media is folded by the assets rule
+    #[test]
+    fn test_large_assets_folded_once_listed() {
+        use crate::rules::create_default_registry;
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("renders/README.md", "renders")
+            .create_file("renders/shots/cover.txt", "cover")
+            .create_file("src/main.rs", "fn main() {}");
+        let root_path = builder.root_path();
+        for name in ["a.png", "b.png", "c.mp4"] {
+            let file = File::create(root_path.join("renders").join(name)).unwrap();
+            file.set_len(4 * 1024 * 1024).unwrap();
+        }
+        let registry = Arc::new(create_default_registry(root_path).unwrap());
+        let options = ScanOptions::builder().rules(Some(registry)).build();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        let renders = root.children.iter().find(|c| c.name == "renders").unwrap();
+        assert_eq!(renders.filtered_by.as_deref(), Some("assets"));
+        assert_eq!(
+            renders.filter_annotation.as_deref(),
+            Some("[assets: 12.0MB in 5 files]")
+        );
+        assert!(
+            renders.children.is_empty(),
+            "Folded once its files are known"
+        );
+        assert!(!renders.metadata.approximate);
+        let src = root.children.iter().find(|c| c.name == "src").unwrap();
+        assert_eq!(src.filtered_by, None);
+    }
+
+    /// Test that always-shown entries are neither gitignored nor folded by rules
+    #[test]
+    fn test_always_show() {
+        use crate::rules::create_default_registry;
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file(".gitignore", "dist/\n")
+            .create_file("dist/assets/app.js", "app")
+            .create_file("node_modules/pkg/index.js", "pkg")
+            .create_file("package.json", "{}");
+        let root_path = builder.root_path();
+        let registry = Arc::new(create_default_registry(root_path).unwrap());
+
+        let options = ScanOptions {
+            always_show: vec![String::from("dist"), String::from("node_modules")],
+            rules: Some(registry.clone()),
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        let dist = root.children.iter().find(|c| c.name == "dist").unwrap();
+        assert!(!dist.is_gitignored);
+        let assets = &dist.children[0];
+        assert!(
+            !assets.is_gitignored,
+            "Entries below a shown directory are shown too"
+        );
+        let modules = root
+            .children
+            .iter()
+            .find(|c| c.name == "node_modules")
+            .unwrap();
+        assert_eq!(modules.filtered_by, None);
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(
+            root_path,
+            &mut ctx,
+            &ScanOptions::builder().rules(Some(registry.clone())).build(),
+        )
+        .unwrap();
+        let dist = root.children.iter().find(|c| c.name == "dist").unwrap();
+        assert!(dist.is_gitignored);
+        let modules = root
+            .children
+            .iter()
+            .find(|c| c.name == "node_modules")
+            .unwrap();
+        assert!(modules.filtered_by.is_some());
+    }
+
+    /// Test that kept paths are traversed through otherwise folded directories
+    #[test]
+    fn test_keep_patterns() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("Cargo.toml", "")
+            .create_file("target/doc/index.html", "docs")
+            .create_file("target/debug/app", "bin");
+        let root_path = builder.root_path();
+        let options = ScanOptions {
+            always_show: vec![String::from("target/doc")],
+            traverse_to_shown: true,
+            ..ScanOptions::default()
+        };
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        let target = root.children.iter().find(|c| c.name == "target").unwrap();
+        assert!(!target.is_gitignored);
+        let doc = target.children.iter().find(|c| c.name == "doc").unwrap();
+        assert!(!doc.is_gitignored);
+        assert_eq!(doc.children.len(), 1);
+        let debug = target.children.iter().find(|c| c.name == "debug").unwrap();
+        assert!(debug.is_gitignored, "Only the kept path is unfolded");
+    }
+
+    /// Test that negated patterns resurface entries inside ignored directories
+    #[test]
+    fn test_negation_inside_ignored_directory() {
+        use crate::rules::create_default_registry;
+        use crate::{scan, DirectoryEntry, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file(".gitignore", "logs/\ncache/\n!logs/2024/important.log\n")
+            .create_file("logs/2024/important.log", "keep")
+            .create_file("logs/2024/debug.log", "noise")
+            .create_file("logs/old.log", "noise")
+            .create_file("cache/data.bin", "noise");
+        let root_path = builder.root_path();
+        let registry = Arc::new(create_default_registry(root_path).unwrap());
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(
+            root_path,
+            &mut ctx,
+            &ScanOptions::builder().rules(Some(registry.clone())).build(),
+        )
+        .unwrap();
+        let child = |entry: &DirectoryEntry, name: &str| {
+            entry
+                .children
+                .iter()
+                .find(|c| c.name == name)
+                .cloned()
+                .unwrap()
+        };
+        let hidden = |entry: &DirectoryEntry| {
+            entry.is_gitignored || entry.filtered_by.as_deref() == Some("gitignore")
+        };
+
+        let logs = child(&root, "logs");
+        assert!(!hidden(&logs), "logs holds a re-included file");
+        let year = child(&logs, "2024");
+        assert!(!hidden(&year));
+        assert!(!hidden(&child(&year, "important.log")));
+        assert!(hidden(&child(&year, "debug.log")));
+        assert!(hidden(&child(&logs, "old.log")));
+        assert!(hidden(&child(&root, "cache")), "Nothing re-includes cache");
+    }
+
+    /// Test that disabling a rule shows the directories it would fold
+    #[test]
+    fn test_disable_rule() {
+        use crate::rules::create_default_registry;
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file(".git/HEAD", "ref: refs/heads/main")
+            .create_file("node_modules/pkg/index.js", "pkg")
+            .create_file("package.json", "{}");
+        let root_path = builder.root_path();
+        let child = |root: &crate::DirectoryEntry, name: &str| {
+            root.children
+                .iter()
+                .find(|c| c.name == name)
+                .cloned()
+                .unwrap()
+        };
+
+        let mut registry = Arc::new(create_default_registry(root_path).unwrap());
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(
+            root_path,
+            &mut ctx,
+            &ScanOptions::builder().rules(Some(registry.clone())).build(),
+        )
+        .unwrap();
+        assert!(child(&root, ".git").is_gitignored);
+        let modules = child(&root, "node_modules");
+        assert_eq!(modules.filtered_by.as_deref(), Some("dependencies"));
+        assert_eq!(modules.filter_annotation.as_deref(), Some("[dependencies]"));
+
+        let rules = Arc::get_mut(&mut registry).unwrap();
+        rules.set_enabled("vcs", false).unwrap();
+        rules.set_enabled("dependencies", false).unwrap();
+        assert!(rules.set_enabled("no_such_rule", false).is_err());
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        ctx.unignore_system_dirs(&registry.disabled_system_dirs());
+        let root = scan(
+            root_path,
+            &mut ctx,
+            &ScanOptions::builder().rules(Some(registry.clone())).build(),
+        )
+        .unwrap();
+        let git = child(&root, ".git");
+        assert!(!git.is_gitignored);
+        assert_eq!(git.filtered_by, None);
+        let modules = child(&root, "node_modules");
+        assert!(!modules.is_gitignored);
+        assert_eq!(modules.filtered_by, None);
+    }
+
+    /// Test that `scan` applies the rules carried in its options
+    #[test]
+    fn test_scan_with_built_options() {
+        use crate::rules::create_default_registry;
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("node_modules/pkg/index.js", "pkg")
+            .create_file("src/deep/lib.rs", "")
+            .create_file("package.json", "{}");
+        let root_path = builder.root_path();
+
+        let registry = create_default_registry(root_path).unwrap();
+        let options = ScanOptions::builder()
+            .max_depth(2)
+            .rules(Some(Arc::new(registry)))
+            .build();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        let child = |name: &str| root.children.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(
+            child("node_modules").filtered_by.as_deref(),
+            Some("dependencies")
+        );
+        let deep = &child("src").children[0];
+        assert!(deep.children.is_empty(), "Not below max_depth");
+    }
+
+    /// Test that a progress sink hears about each directory with running totals
+    #[test]
+    fn test_progress_sink() {
+        use crate::{scan, GitIgnoreContext, ProgressSink, ScanOptions, ScanProgress};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(usize, u64, PathBuf)>>);
+        impl ProgressSink for Recorder {
+            fn progress(&self, progress: &ScanProgress) {
+                let update = (
+                    progress.entries,
+                    progress.bytes,
+                    progress.path.to_path_buf(),
+                );
+                self.0.lock().unwrap().push(update);
+            }
+        }
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("a.txt", "12345")
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("node_modules/pkg/index.js", "pkg");
+        let root_path = builder.root_path();
+
+        let recorder = Arc::new(Recorder::default());
+        let options = ScanOptions::builder()
+            .progress(Some(recorder.clone() as Arc<dyn ProgressSink>))
+            .build();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        scan(root_path, &mut ctx, &options).unwrap();
+
+        let updates = recorder.0.lock().unwrap();
+        let paths: Vec<_> = updates.iter().map(|(_, _, path)| path.clone()).collect();
+        assert_eq!(paths[0], root_path);
+        assert!(paths.contains(&root_path.join("src")));
+        // Folded directories are counted too
+        assert!(paths.contains(&root_path.join("node_modules/pkg")));
+        assert!(updates
+            .windows(2)
+            .all(|w| w[0].0 <= w[1].0 && w[0].1 <= w[1].1));
+        let (entries, bytes, _) = updates.last().unwrap();
+        assert!(*entries >= 4 && *bytes >= 5, "{:?}", updates);
+    }
+
+    /// Test that a cancelled scan returns the partial tree, marked as truncated
+    #[test]
+    fn test_cancel_scan() {
+        use crate::{scan, Error, GitIgnoreContext, ProgressSink, ScanOptions, ScanProgress};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        /// Cancels the scan once it reaches the `b` directory
+        struct CancelAt(Arc<AtomicBool>);
+        impl ProgressSink for CancelAt {
+            fn progress(&self, progress: &ScanProgress) {
+                if progress.path.ends_with("b") {
+                    self.0.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("a/one.txt", "1")
+            .create_file("b/two.txt", "2")
+            .create_file("c/three.txt", "3");
+        let root_path = builder.root_path();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let options = ScanOptions::builder()
+            .progress(Some(Arc::new(CancelAt(cancel.clone()))))
+            .cancel(Some(cancel.clone()))
+            .build();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        // Entries are listed in directory order, so only `b` is known to be reached
+        let b = root.children.iter().find(|c| c.name == "b").unwrap();
+        assert!(b.children.is_empty());
+        assert_eq!(
+            b.filter_annotation.as_deref(),
+            Some("[truncated: scan cancelled]")
+        );
+        assert!(b.cancelled && root.cancelled);
+        assert!(root.metadata.approximate);
+        assert_eq!(root.filter_annotation, b.filter_annotation);
+
+        // Cancelled before it starts, there is no tree to return
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let result = scan(root_path, &mut ctx, &options);
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+
+    /// Test that a scan reuses the directories of a previous one that haven't changed
+    #[test]
+    fn test_scan_reuses_unchanged_dirs() {
+        use crate::{scan, DirectoryEntry, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("docs/guide.md", "# Guide");
+        let root_path = builder.root_path();
+        // Start from an old time, so any later change is seen
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        for dir in ["", "src", "docs"] {
+            File::open(root_path.join(dir))
+                .unwrap()
+                .set_modified(past)
+                .unwrap();
+        }
+
+        let scan_once = |previous: Option<DirectoryEntry>| {
+            let options = ScanOptions::builder()
+                .previous(previous.map(Arc::new))
+                .build();
+            let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+            scan(root_path, &mut ctx, &options).unwrap()
+        };
+        let first = scan_once(None);
+        assert_eq!(first.metadata.size, 19);
+
+        // A file written in place leaves its directory's time alone
+        fs::write(root_path.join("src/main.rs"), "fn main() { run() }").unwrap();
+        // Adding a file changes it
+        fs::write(root_path.join("docs/faq.md"), "# FAQ").unwrap();
+
+        let second = scan_once(Some(first));
+        let child = |name: &str| second.children.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(child("src").metadata.size, 12, "Reused from the first scan");
+        assert_eq!(child("docs").children.len(), 2);
+        assert_eq!(second.metadata.files_count, 3);
+        assert_eq!(second.metadata.size, 12 + 7 + 5);
+    }
+
+    /// Test that reused directories see ignore files edited since, and that
+    /// folded directories are counted again
+    #[test]
+    fn test_scan_reuse_after_ignore_changes() {
+        use crate::{scan, DirectoryEntry, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file(".gitignore", "")
+            .create_file("sub/app.log", "log")
+            .create_file("node_modules/pkg/index.js", "pkg");
+        let root_path = builder.root_path();
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        for dir in ["", "sub", "node_modules", "node_modules/pkg"] {
+            File::open(root_path.join(dir))
+                .unwrap()
+                .set_modified(past)
+                .unwrap();
+        }
+
+        let scan_once = |previous: Option<DirectoryEntry>| {
+            let options = ScanOptions::builder()
+                .previous(previous.map(Arc::new))
+                .build();
+            let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+            scan(root_path, &mut ctx, &options).unwrap()
+        };
+        let first = scan_once(None);
+        let child = |root: &DirectoryEntry, name: &str| {
+            root.children
+                .iter()
+                .find(|c| c.name == name)
+                .cloned()
+                .unwrap()
+        };
+        assert!(!child(&child(&first, "sub"), "app.log").is_gitignored);
+        assert_eq!(child(&first, "node_modules").metadata.files_count, 1);
+
+        // Neither change touches the time of the directories reused
+        fs::write(root_path.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root_path.join("node_modules/pkg/extra.js"), "more").unwrap();
+
+        let second = scan_once(Some(first));
+        assert!(child(&child(&second, "sub"), "app.log").is_gitignored);
+        assert_eq!(child(&second, "node_modules").metadata.files_count, 2);
+    }
+
+    /// Test that nested repositories are folded while scanning, keeping their totals
+    #[test]
+    fn test_fold_nested_repos() {
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("vendor/lib/.git/HEAD", "ref: refs/heads/main")
+            .create_file("vendor/lib/src/lib.rs", "pub fn lib() {}")
+            .create_file("src/main.rs", "fn main() {}");
+        let root_path = builder.root_path();
+        let options = ScanOptions::builder().fold_nested_repos(true).build();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+
+        let vendor = root.children.iter().find(|c| c.name == "vendor").unwrap();
+        let lib = &vendor.children[0];
+        assert!(lib.is_git_repo);
+        assert!(lib.children.is_empty(), "Not descended into");
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let expanded = scan(root_path, &mut ctx, &ScanOptions::default()).unwrap();
+        assert_eq!(root.metadata.files_count, expanded.metadata.files_count);
+        assert_eq!(root.metadata.size, expanded.metadata.size);
+
+        // The scan root is expanded even when it is a repository itself
+        let lib_path = root_path.join("vendor/lib");
+        let mut ctx = GitIgnoreContext::new(&lib_path).unwrap();
+        let lib = scan(&lib_path, &mut ctx, &options).unwrap();
+        assert!(!lib.children.is_empty());
+    }
+
+    /// Test that rescanning changed paths gives the tree a full scan would
+    #[test]
+    fn test_rescan_paths() {
+        use crate::{rescan_paths, scan, DirectoryEntry, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/lib.rs", "pub fn lib() {}")
+            .create_file("src/util/mod.rs", "")
+            .create_file("docs/old.md", "# Old")
+            .create_file("README.md", "# demo");
+        let root_path = builder.root_path();
+        let options = ScanOptions::default();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let mut tree = scan(root_path, &mut ctx, &options).unwrap();
+
+        fs::write(root_path.join("src/lib.rs"), "pub fn lib() { todo!() }").unwrap();
+        fs::write(root_path.join("src/util/fmt.rs"), "// fmt").unwrap();
+        fs::remove_dir_all(root_path.join("docs")).unwrap();
+        let changed = ["src/lib.rs", "src/util/fmt.rs", "docs/old.md"].map(|p| root_path.join(p));
+        rescan_paths(&mut tree, &changed, &mut ctx, &options).unwrap();
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let fresh = scan(root_path, &mut ctx, &options).unwrap();
+        fn summary(entry: &DirectoryEntry, out: &mut Vec<String>) {
+            out.push(format!(
+                "{} {} {}",
+                entry.path.display(),
+                entry.metadata.files_count,
+                entry.metadata.size
+            ));
+            let mut children: Vec<_> = entry.children.iter().collect();
+            children.sort_by(|a, b| a.path.cmp(&b.path));
+            for child in children {
+                summary(child, out);
+            }
+        }
+        let (mut rescanned, mut expected) = (Vec::new(), Vec::new());
+        summary(&tree, &mut rescanned);
+        summary(&fresh, &mut expected);
+        assert_eq!(rescanned, expected);
+        assert_eq!(tree.metadata.files_count, 4);
+
+        // A subtree a cancel left unfinished no longer makes the totals
+        // above it approximate once it is rescanned
+        let src = tree.children.iter_mut().find(|c| c.name == "src").unwrap();
+        let util = src.children.iter_mut().find(|c| c.name == "util").unwrap();
+        util.cancelled = true;
+        util.metadata.approximate = true;
+        src.metadata.approximate = true;
+        tree.metadata.approximate = true;
+        let changed = [root_path.join("src/util/fmt.rs")];
+        rescan_paths(&mut tree, &changed, &mut ctx, &options).unwrap();
+        assert!(!tree.metadata.approximate);
+    }
+
+    /// Test that the dotfiles rule, once enabled, leaves dotfiles out entirely
+    #[test]
+    fn test_dotfiles_rule() {
+        use crate::rules::create_default_registry;
+        use crate::{scan, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file(".env", "KEY=1")
+            .create_file(".github/workflows/ci.yml", "on: push")
+            .create_file("src/.keep", "")
+            .create_file("src/main.rs", "fn main() {}");
+        let root_path = builder.root_path();
+        let names = |entry: &crate::DirectoryEntry| -> Vec<String> {
+            let mut names: Vec<_> = entry.children.iter().map(|c| c.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        let registry = create_default_registry(root_path).unwrap();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let options = ScanOptions::builder()
+            .rules(Some(Arc::new(registry)))
+            .build();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        assert_eq!(names(&root), [".env", ".github", "src"], "Off by default");
+
+        let mut registry = create_default_registry(root_path).unwrap();
+        registry.set_enabled("dotfiles", true).unwrap();
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        let options = ScanOptions::builder()
+            .rules(Some(Arc::new(registry)))
+            .build();
+        let root = scan(root_path, &mut ctx, &options).unwrap();
+        assert_eq!(names(&root), ["src"]);
+        assert_eq!(names(&root.children[0]), ["main.rs"]);
+        assert_eq!(root.metadata.files_count, 1, "Left out of the totals");
+    }
+
+    /// Test that rules see the scan root's project types and the real depth
+    #[test]
+    fn test_rules_see_project_and_depth() {
+        use crate::rules::{create_default_registry, FilterContext, FilterRule, ProjectType};
+        use crate::{scan, DirectoryEntry, GitIgnoreContext, ScanOptions};
+        use std::sync::Arc;
+
+        struct DeepRule;
+        impl FilterRule for DeepRule {
+            fn id(&self) -> &str {
+                "deep"
+            }
+            fn priority(&self) -> i32 {
+                0
+            }
+            fn applies_to(&self, context: &FilterContext) -> bool {
+                context.depth >= 3 && context.project_types.contains(&ProjectType::NodeJs)
+            }
+            fn evaluate(&self, _context: &FilterContext) -> f32 {
+                1.0
+            }
+        }
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("package.json", "{}")
+            .create_file("web/node_modules/pkg/index.js", "pkg")
+            .create_file("web/src/deep/app.js", "app");
+        let root_path = builder.root_path();
+        let mut registry = create_default_registry(root_path).unwrap();
+        registry.add_rule(DeepRule);
+        let registry = Arc::new(registry);
+        let child = |entry: &DirectoryEntry, name: &str| {
+            entry
+                .children
+                .iter()
+                .find(|c| c.name == name)
+                .cloned()
+                .unwrap()
+        };
+
+        let mut ctx = GitIgnoreContext::new(root_path).unwrap();
+        ctx.clear_system_dirs();
+        let root = scan(
+            root_path,
+            &mut ctx,
+            &ScanOptions::builder().rules(Some(registry.clone())).build(),
+        )
+        .unwrap();
+        let web = child(&root, "web");
+        assert_eq!(
+            child(&web, "node_modules").filtered_by.as_deref(),
+            Some("dependencies"),
+            "The project's package.json applies below subdirectories too"
+        );
+        let src = child(&web, "src");
+        assert_eq!(src.filtered_by, None);
+        assert_eq!(child(&src, "deep").filtered_by.as_deref(), Some("deep"));
+    }
+
+    /// Test that separately scanned roots merge under one synthetic root
+    #[test]
+    fn test_merge_roots() {
+        use crate::{merge_roots, scan, GitIgnoreContext, ScanOptions};
+
+        let mut builder = TestFileBuilder::new();
+        builder
+            .create_file("src/main.rs", "fn main() {}")
+            .create_file("src/lib.rs", "")
+            .create_file("docs/guide.md", "# Guide")
+            .create_file("README.md", "# Readme");
+        let root_path = builder.root_path();
+
+        let roots: Vec<_> = ["src", "docs", "README.md"]
+            .iter()
+            .map(|name| {
+                let path = root_path.join(name);
+                let mut ctx = GitIgnoreContext::new(&path).unwrap();
+                scan(&path, &mut ctx, &ScanOptions::default()).unwrap()
+            })
+            .collect();
+        let merged = merge_roots(roots);
+
+        assert!(merged.is_dir);
+        assert_eq!(merged.path, std::path::PathBuf::new());
+        let names: Vec<_> = merged.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["src", "docs", "README.md"]);
+        assert_eq!(merged.metadata.files_count, 4);
+    }
 }
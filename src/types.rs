@@ -1,7 +1,9 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DirectoryEntry {
     #[allow(dead_code)]
     pub path: PathBuf,
@@ -10,38 +12,239 @@ pub struct DirectoryEntry {
     pub metadata: EntryMetadata,
     pub children: Vec<DirectoryEntry>,
     pub is_gitignored: bool,
+    pub is_git_repo: bool, // Directory is a nested Git repository or submodule
     pub filtered_by: Option<String>, // Rule ID that filtered this entry
     pub filter_annotation: Option<String>, // Display annotation for filtering
+    pub error: Option<String>, // Why some data for this entry is unavailable
+    pub is_virtual: bool,  // Listed by --fromfile or read from an image, not looked up on disk
+    pub kind: EntryKind,   // Symlinks, sockets, named pipes and devices, told apart when scanning
+    pub has_unlisted: bool, // Directory left unlisted at the depth limit has something in it
+    pub has_omitted: bool, // Entries on disk were left out of the children (filtered, excluded or over a limit)
+    pub cancelled: bool,   // A cancelled scan left the directory's contents unfinished
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EntryMetadata {
     pub size: u64,
     pub created: SystemTime,
     pub modified: SystemTime,
     pub files_count: usize,
+    pub mode: Option<u32>, // Permission bits (approximated from the read-only flag on Windows)
+    pub uid: Option<u32>,  // Owning user id (Unix only)
+    pub gid: Option<u32>,  // Owning group id (Unix only)
+    pub approximate: bool, // Counting stopped early; files_count and size are lower bounds
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct DisplayConfig {
     pub max_lines: usize,
     pub dir_limit: usize,
     pub sort_by: SortBy,
-    pub dirs_first: bool,
+    pub then_by: SortBy,    // Tie-breaker for entries with equal sort keys
+    pub group_by: Grouping, // Whether directories are listed apart from files
     pub use_colors: bool,
     pub color_theme: ColorTheme,
-    pub use_emoji: bool,            // Whether to use emoji icons
-    pub size_colorize: bool,        // Whether to colorize sizes by value
-    pub date_colorize: bool,        // Whether to colorize dates by recency
-    pub detailed_metadata: bool,    // Whether to show detailed metadata
-    pub show_system_dirs: bool,     // Whether to show system directories like .git
-    pub show_filtered: bool,        // Whether to show filtered items
-    pub disable_rules: Vec<String>, // Rules to disable
-    pub enable_rules: Vec<String>,  // Rules to explicitly enable
-    pub rule_debug: bool,           // Show detailed rule evaluation info
+    pub use_emoji: bool,               // Whether to use emoji icons
+    pub size_colorize: bool,           // Whether to colorize sizes by value
+    pub date_colorize: bool,           // Whether to colorize dates by recency
+    pub detailed_metadata: bool,       // Whether to show detailed metadata
+    pub show_system_dirs: bool,        // Whether to show system directories like .git
+    pub show_filtered: bool,           // Whether to show filtered items
+    pub disable_rules: Vec<String>,    // Rules to disable
+    pub enable_rules: Vec<String>,     // Rules to explicitly enable
+    pub rule_debug: bool,              // Show detailed rule evaluation info
+    pub mark_empty: bool,              // Annotate empty files and directories with [empty]
+    pub only_empty: bool,              // Only show empty entries (and their ancestors)
+    pub now: SystemTime,               // Reference time for relative dates (usually render start)
+    pub peek_depth: usize,             // Levels of folded directories to show before folding
+    pub min_depth: usize,              // Hide entries shallower than this (ancestors are kept)
+    pub time_style: TimeStyle,         // How timestamps are rendered
+    pub utc: bool,                     // Render absolute timestamps in UTC instead of local time
+    pub sniff_content: bool,           // Detect types of unknown files from their magic bytes
+    pub fold_nested_repos: bool,       // Don't expand nested Git repositories
+    pub skip_report: bool,             // List folded and truncated entries after the tree
+    pub changes: ChangeSet,            // Entries to highlight as new or modified
+    pub priorities: Vec<Priority>, // Name (or root-relative path, with `/`) patterns to list first or last among their siblings
+    pub format_string: Option<String>, // Template replacing the tree with one line per entry
+    pub max_width: Option<usize>,  // Ellipsize tree lines wider than this many columns
+    pub hyperlinks: bool,          // Make names clickable file:// links (OSC 8)
+    pub ls_colors: Option<LsColors>, // Color names from LS_COLORS instead of the palette
+    pub icon_style: IconStyle,     // Which icon set to use when icons are enabled
+    pub icon_overrides: IconOverrides, // Custom icons by file name or extension
+    pub show_perms: bool,          // Show permission bits (rwxr-xr-x) in the metadata
+    pub show_owner: bool,          // Show owner and group in the metadata
+    pub git_status: GitStatusMap,  // Working-tree status markers from `git status`
+    pub badges: BadgeMap,          // Labels for workspace members, e.g. "crate: foo v0.3.1"
+    pub show_metadata: bool,       // Show sizes and dates after names
+    pub markdown: bool,            // Render the tree as a Markdown bullet list
+    pub max_tokens: Option<usize>, // Fit the tree into this many estimated tokens
+    pub tokenizer: Tokenizer,      // How tokens are estimated for max_tokens
+}
+
+/// The settings the command line starts from: 200 lines, 20 entries per
+/// directory, sorted by name, without colors or icons
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            max_lines: 200,
+            dir_limit: 20,
+            sort_by: SortBy::Name,
+            then_by: SortBy::Name,
+            group_by: Grouping::None,
+            use_colors: false,
+            color_theme: ColorTheme::Auto,
+            use_emoji: false,
+            size_colorize: false,
+            date_colorize: false,
+            detailed_metadata: false,
+            show_system_dirs: false,
+            show_filtered: false,
+            disable_rules: Vec::new(),
+            enable_rules: Vec::new(),
+            rule_debug: false,
+            mark_empty: false,
+            only_empty: false,
+            now: SystemTime::now(),
+            peek_depth: 0,
+            min_depth: 0,
+            time_style: TimeStyle::Relative,
+            utc: false,
+            sniff_content: false,
+            fold_nested_repos: false,
+            skip_report: false,
+            changes: HashMap::new(),
+            priorities: Vec::new(),
+            format_string: None,
+            max_width: None,
+            hyperlinks: false,
+            ls_colors: None,
+            icon_style: IconStyle::Emoji,
+            icon_overrides: HashMap::new(),
+            show_perms: false,
+            show_owner: false,
+            git_status: HashMap::new(),
+            badges: HashMap::new(),
+            show_metadata: true,
+            markdown: false,
+            max_tokens: None,
+            tokenizer: Tokenizer::Chars,
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Start from the defaults and set only the options that differ
+    pub fn builder() -> DisplayConfigBuilder {
+        DisplayConfigBuilder::default()
+    }
+}
+
+/// Builds a `DisplayConfig` one option at a time, so options added later
+/// don't break callers
+#[derive(Debug, Clone, Default)]
+pub struct DisplayConfigBuilder {
+    config: DisplayConfig,
+}
+
+impl DisplayConfigBuilder {
+    setters! { config;
+        /// Most lines the tree may take
+        max_lines: usize;
+        /// Most entries shown per directory
+        dir_limit: usize;
+        /// Key entries are sorted by
+        sort_by: SortBy;
+        /// Tie-breaker for entries with equal sort keys
+        then_by: SortBy;
+        /// Whether directories are listed apart from files
+        group_by: Grouping;
+        /// Whether to color the output
+        use_colors: bool;
+        /// Palette used when coloring
+        color_theme: ColorTheme;
+        /// Whether to use emoji icons
+        use_emoji: bool;
+        /// Whether to colorize sizes by value
+        size_colorize: bool;
+        /// Whether to colorize dates by recency
+        date_colorize: bool;
+        /// Whether to show detailed metadata
+        detailed_metadata: bool;
+        /// Whether to show system directories like .git
+        show_system_dirs: bool;
+        /// Whether to show filtered items
+        show_filtered: bool;
+        /// Rules to disable
+        disable_rules: Vec<String>;
+        /// Rules to explicitly enable
+        enable_rules: Vec<String>;
+        /// Show detailed rule evaluation info
+        rule_debug: bool;
+        /// Annotate empty files and directories with [empty]
+        mark_empty: bool;
+        /// Only show empty entries (and their ancestors)
+        only_empty: bool;
+        /// Reference time for relative dates (usually render start)
+        now: SystemTime;
+        /// Levels of folded directories to show before folding
+        peek_depth: usize;
+        /// Hide entries shallower than this (ancestors are kept)
+        min_depth: usize;
+        /// How timestamps are rendered
+        time_style: TimeStyle;
+        /// Render absolute timestamps in UTC instead of local time
+        utc: bool;
+        /// Detect types of unknown files from their magic bytes
+        sniff_content: bool;
+        /// Don't expand nested Git repositories
+        fold_nested_repos: bool;
+        /// List folded and truncated entries after the tree
+        skip_report: bool;
+        /// Entries to highlight as new or modified
+        changes: ChangeSet;
+        /// Entry name patterns to list first or last among their siblings
+        priorities: Vec<Priority>;
+        /// Template replacing the tree with one line per entry
+        format_string: Option<String>;
+        /// Ellipsize tree lines wider than this many columns
+        max_width: Option<usize>;
+        /// Make names clickable file:// links (OSC 8)
+        hyperlinks: bool;
+        /// Color names from LS_COLORS instead of the palette
+        ls_colors: Option<LsColors>;
+        /// Which icon set to use when icons are enabled
+        icon_style: IconStyle;
+        /// Custom icons by file name or extension
+        icon_overrides: IconOverrides;
+        /// Show permission bits (rwxr-xr-x) in the metadata
+        show_perms: bool;
+        /// Show owner and group in the metadata
+        show_owner: bool;
+        /// Working-tree status markers from `git status`
+        git_status: GitStatusMap;
+        /// Labels for workspace members, e.g. "crate: foo v0.3.1"
+        badges: BadgeMap;
+        /// Show sizes and dates after names
+        show_metadata: bool;
+        /// Render the tree as a Markdown bullet list
+        markdown: bool;
+        /// Fit the tree into this many estimated tokens
+        max_tokens: Option<usize>;
+        /// How tokens are estimated for max_tokens
+        tokenizer: Tokenizer;
+    }
+
+    /// The finished configuration
+    pub fn build(self) -> DisplayConfig {
+        self.config
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ColorTheme {
     Auto,
     Light,
@@ -49,7 +252,132 @@ pub enum ColorTheme {
     None,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    Auto,   // Color when writing to a terminal, unless NO_COLOR is set
+    Always, // Color even when piped, e.g. into `less -R`
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IconStyle {
+    Emoji,
+    Nerd, // Nerd Font glyphs (devicons), one column wide in patched fonts
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Grouping {
+    None,      // Directories and files interleaved in sort order
+    DirsFirst, // Directories before files
+    DirsLast,  // Files before directories
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimeStyle {
+    Relative, // e.g. "5m ago"
+    Iso,      // e.g. "2024-05-01 13:45"
+    Full,     // e.g. "2024-05-01 13:45:12 +02:00"
+}
+
+/// Icons keyed by file name or (lowercase) extension
+pub type IconOverrides = HashMap<String, String>;
+
+/// Entries that changed since a previous scan, keyed by path
+pub type ChangeSet = HashMap<PathBuf, ChangeKind>;
+
+/// A name pattern and where matching entries are placed among their siblings
+pub type Priority = (String, Placement);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Placement {
+    First, // Sorted ahead of its siblings at any depth, whatever the sort key
+    Last,  // Sorted after its siblings, where truncation hides the least useful entries
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChangeKind {
+    New,      // Not present in the previous scan
+    Modified, // A file's size or modification time, or a directory's own modification time, differs
+}
+
+/// Git working-tree state of an entry (or of anything below a directory)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GitStatus {
+    pub staged: bool,     // Changes added to the index
+    pub modified: bool,   // Unstaged changes in the working tree, including deletions
+    pub untracked: bool,  // Not known to git
+    pub conflicted: bool, // Unmerged paths
+}
+
+impl GitStatus {
+    /// Combine with the status of another path, as for a directory's contents
+    pub fn merge(&mut self, other: GitStatus) {
+        self.staged |= other.staged;
+        self.modified |= other.modified;
+        self.untracked |= other.untracked;
+        self.conflicted |= other.conflicted;
+    }
+
+    /// Markers shown after the entry, most urgent last
+    pub fn labels(&self) -> Vec<&'static str> {
+        [
+            (self.staged, "staged"),
+            (self.modified, "modified"),
+            (self.untracked, "untracked"),
+            (self.conflicted, "conflict"),
+        ]
+        .into_iter()
+        .filter_map(|(set, label)| set.then_some(label))
+        .collect()
+    }
+}
+
+/// Git status of changed entries, keyed by path
+pub type GitStatusMap = HashMap<PathBuf, GitStatus>;
+
+/// Parsed LS_COLORS: SGR parameters per file kind and per name suffix
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LsColors {
+    pub(crate) kinds: HashMap<String, String>,
+    pub(crate) suffixes: Vec<(String, String)>,
+}
+
+/// How to estimate the number of tokens in a text
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tokenizer {
+    /// About four ASCII characters per token, one token per other character
+    Chars,
+    /// One token per symbol, and per up to four letters or digits of a word
+    Words,
+    /// A caller-provided estimate (not serializable)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(fn(&str) -> usize),
+}
+
+/// Labels shown after workspace member directories, keyed by path
+pub type BadgeMap = HashMap<PathBuf, String>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Tree,
+    Json,
+    Markdown,
+    Html,
+    TreeJson,
+    Xml,
+    Ndjson,
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SortBy {
     Name,
     Size,
@@ -57,7 +385,27 @@ pub enum SortBy {
     Created,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// What an entry is on disk besides a plain file or directory, recorded when
+/// scanning so that displaying it takes no further lookups
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EntryKind {
+    /// A regular file or a directory
+    #[default]
+    Regular,
+    /// A symlink, or an NTFS junction on Windows, with where it points
+    /// unless the link could not be read, and whether that is missing
+    Symlink {
+        target: Option<String>,
+        broken: bool,
+    },
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FileType {
     Directory,
     Symlink,
@@ -70,4 +418,18 @@ pub enum FileType {
     Document,
     Executable,
     Hidden,
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+}
+
+impl FileType {
+    /// Sockets, named pipes and devices, whose contents are never read
+    pub fn is_special(&self) -> bool {
+        matches!(
+            self,
+            FileType::Socket | FileType::Fifo | FileType::BlockDevice | FileType::CharDevice
+        )
+    }
 }
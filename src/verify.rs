@@ -0,0 +1,208 @@
+//! Verification of a directory against an exported JSON snapshot
+//!
+//! A snapshot is the output of `--format json` (optionally with
+//! `--checksums`). Verifying walks the snapshot and compares every listed
+//! entry with the filesystem, reporting missing, changed and unexpected
+//! entries. Directories whose listing was incomplete when exported (filtered,
+//! folded, over a limit or at the depth limit) aren't checked for unexpected
+//! entries.
+
+use crate::error::{Error, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A difference between a snapshot and the filesystem
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// Listed in the snapshot but gone from disk
+    Missing(PathBuf),
+    /// Present on disk in an expanded directory but not in the snapshot
+    Unexpected(PathBuf),
+    /// A file became a directory or vice versa
+    TypeChanged(PathBuf),
+    /// File size differs: (path, expected, found)
+    SizeMismatch(PathBuf, u64, u64),
+    /// File content differs from the recorded checksum
+    HashMismatch(PathBuf),
+    /// The entry exists but could not be read
+    Unreadable(PathBuf, String),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Missing(path) => write!(f, "missing: {}", path.display()),
+            Divergence::Unexpected(path) => write!(f, "unexpected: {}", path.display()),
+            Divergence::TypeChanged(path) => write!(f, "type changed: {}", path.display()),
+            Divergence::SizeMismatch(path, expected, found) => write!(
+                f,
+                "size mismatch: {} (expected {} bytes, found {})",
+                path.display(),
+                expected,
+                found
+            ),
+            Divergence::HashMismatch(path) => write!(f, "hash mismatch: {}", path.display()),
+            Divergence::Unreadable(path, reason) => {
+                write!(f, "unreadable: {} ({})", path.display(), reason)
+            }
+        }
+    }
+}
+
+/// Outcome of verifying a snapshot
+#[derive(Debug, Default)]
+pub struct Verification {
+    /// Number of entries compared: those in the snapshot, and those only on disk
+    pub checked: usize,
+    /// Every difference found, in snapshot order
+    pub divergences: Vec<Divergence>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Compare a JSON snapshot with the directory `root` (defaults to the
+/// snapshot's own root path)
+pub fn verify_snapshot(snapshot: &str, root: Option<&Path>) -> Result<Verification> {
+    let snapshot: Value = serde_json::from_str(snapshot)
+        .map_err(|e| Error::Format(format!("parsing snapshot: {}", e)))?;
+    let snapshot_root = PathBuf::from(
+        snapshot["path"]
+            .as_str()
+            .ok_or_else(|| Error::Format(String::from("snapshot has no root \"path\"")))?,
+    );
+    let root = root.unwrap_or(&snapshot_root);
+
+    let mut verification = Verification::default();
+    for child in snapshot["children"].as_array().into_iter().flatten() {
+        verify_entry(child, &snapshot_root, root, &mut verification);
+    }
+    verify_listing(&snapshot, root, &mut verification);
+    Ok(verification)
+}
+
+fn verify_entry(entry: &Value, snapshot_root: &Path, root: &Path, out: &mut Verification) {
+    let Some(recorded) = entry["path"].as_str().map(Path::new) else {
+        return;
+    };
+    let path = root.join(recorded.strip_prefix(snapshot_root).unwrap_or(recorded));
+    out.checked += 1;
+
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            out.divergences.push(Divergence::Missing(path));
+            return;
+        }
+        Err(e) => {
+            out.divergences
+                .push(Divergence::Unreadable(path, e.to_string()));
+            return;
+        }
+    };
+
+    let was_dir = entry["type"] == "directory";
+    if was_dir != metadata.is_dir() {
+        out.divergences.push(Divergence::TypeChanged(path));
+        return;
+    }
+
+    if was_dir {
+        for child in entry["children"].as_array().into_iter().flatten() {
+            verify_entry(child, snapshot_root, root, out);
+        }
+        verify_listing(entry, &path, out);
+        return;
+    }
+
+    let expected = entry["size"].as_u64().unwrap_or(0);
+    if expected != metadata.len() {
+        out.divergences
+            .push(Divergence::SizeMismatch(path, expected, metadata.len()));
+        return;
+    }
+    if let Some(expected) = entry["sha256"].as_str() {
+        match file_checksum(&path) {
+            Ok(found) if found == expected => {}
+            Ok(_) => out.divergences.push(Divergence::HashMismatch(path)),
+            Err(e) => out
+                .divergences
+                .push(Divergence::Unreadable(path, e.to_string())),
+        }
+    }
+}
+
+/// Report entries on disk that a completely listed snapshot directory doesn't list
+fn verify_listing(entry: &Value, path: &Path, out: &mut Verification) {
+    let children = entry["children"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    let complete = match entry["complete"].as_bool() {
+        Some(complete) => complete,
+        // Snapshots from before the flag: a folded directory has no children
+        None => !children.is_empty() || entry["files_count"].as_u64().unwrap_or(0) == 0,
+    };
+    if !complete {
+        return;
+    }
+
+    let names: HashSet<&str> = children
+        .iter()
+        .filter_map(|child| child["name"].as_str())
+        .collect();
+    let Ok(listing) = fs::read_dir(path) else {
+        return;
+    };
+    let mut unexpected: Vec<PathBuf> = listing
+        .flatten()
+        .filter(|dir_entry| !names.contains(dir_entry.file_name().to_string_lossy().as_ref()))
+        .map(|dir_entry| dir_entry.path())
+        .collect();
+    unexpected.sort();
+    out.checked += unexpected.len();
+    out.divergences
+        .extend(unexpected.into_iter().map(Divergence::Unexpected));
+}
+
+/// SHA-256 of a file's contents as lowercase hex
+pub fn file_checksum(path: &Path) -> io::Result<String> {
+    // Reading a named pipe or device could block or never end
+    if !path.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a regular file",
+        ));
+    }
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_checksum() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("hello.txt");
+        fs::write(&path, "hello").unwrap();
+        assert_eq!(
+            file_checksum(&path).unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! Gitignore pattern matching, following git's `wildmatch`
+//!
+//! A pattern is compiled once into tokens and matched against paths
+//! relative to the ignore file's directory, with `/` separators:
+//! - `*` and `?` match anything but `/`, `[...]` one character from a class
+//!   (ranges, `!`/`^` negation, POSIX classes like `[:digit:]`)
+//! - `**/` at the start or between slashes matches zero or more
+//!   directories, and a trailing `/**` everything inside; any other `**` is
+//!   a plain `*`
+//! - `\` makes the next character literal
+//!
+//! Anchoring is decided by the caller: an unanchored pattern matches at any
+//! depth, as if it started with `**/`.
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `**/`: zero or more whole directories
+    Dirs,
+    /// Trailing `/**`'s `**`: anything, slashes included
+    Rest,
+    Class(CharClass),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CharClass {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Posix(String),
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match item {
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Posix(name) => posix_class(name, c),
+        });
+        found != self.negated
+    }
+}
+
+fn posix_class(name: &str, c: char) -> bool {
+    match name {
+        "alnum" => c.is_ascii_alphanumeric(),
+        "alpha" => c.is_ascii_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "cntrl" => c.is_ascii_control(),
+        "digit" => c.is_ascii_digit(),
+        "graph" => c.is_ascii_graphic(),
+        "lower" => c.is_ascii_lowercase(),
+        "print" => c.is_ascii_graphic() || c == ' ',
+        "punct" => c.is_ascii_punctuation(),
+        "space" => c.is_ascii_whitespace(),
+        "upper" => c.is_ascii_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// A compiled gitignore pattern
+#[derive(Clone, Debug)]
+pub struct Wildmatch {
+    source: String,
+    tokens: Vec<Token>,
+    anchored: bool,
+}
+
+impl Wildmatch {
+    /// Compile `pattern`, matching at any depth unless `anchored`
+    ///
+    /// The pattern is taken as-is: comments, `!`, trailing spaces and a
+    /// trailing `/` are the caller's to handle.
+    pub fn new(pattern: &str, anchored: bool) -> Self {
+        let mut tokens = Vec::new();
+        if !anchored {
+            tokens.push(Token::Dirs);
+        }
+        tokens.extend(tokenize(pattern));
+        Wildmatch {
+            source: pattern.to_string(),
+            tokens,
+            anchored,
+        }
+    }
+
+    /// Whether the pattern might match something inside the directory `dir`
+    ///
+    /// Errs on the side of yes: only an anchored pattern whose literal start
+    /// leads elsewhere is ruled out.
+    pub fn may_match_below(&self, dir: &str) -> bool {
+        if !self.anchored {
+            return true;
+        }
+        let literal: String = self
+            .tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        let dir = format!("{}/", dir);
+        literal.starts_with(&dir) || dir.starts_with(&literal)
+    }
+
+    /// The pattern as written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the whole of `path` matches
+    pub fn matches(&self, path: &str) -> bool {
+        let text: Vec<char> = path.chars().collect();
+        let tokens = &self.tokens;
+
+        // matched[i][j]: tokens[i..] match text[j..]
+        let mut matched = vec![vec![false; text.len() + 1]; tokens.len() + 1];
+        matched[tokens.len()][text.len()] = true;
+        for i in (0..tokens.len()).rev() {
+            for j in (0..=text.len()).rev() {
+                let c = text.get(j).copied();
+                let one = |ok: bool| ok && matched[i + 1][j + 1];
+                matched[i][j] = match &tokens[i] {
+                    Token::Literal(l) => one(c == Some(*l)),
+                    Token::Any => one(c.is_some_and(|c| c != '/')),
+                    Token::Class(class) => one(c.is_some_and(|c| c != '/' && class.matches(c))),
+                    Token::Star => {
+                        matched[i + 1][j] || (c.is_some_and(|c| c != '/') && matched[i][j + 1])
+                    }
+                    Token::Rest => matched[i + 1][j] || (c.is_some() && matched[i][j + 1]),
+                    // Skip nothing, or up to and including the next slash
+                    Token::Dirs => {
+                        matched[i + 1][j]
+                            || text[j..]
+                                .iter()
+                                .position(|&c| c == '/')
+                                .is_some_and(|slash| matched[i][j + slash + 1])
+                    }
+                };
+            }
+        }
+        matched[0][0]
+    }
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                let start = i;
+                while chars.get(i) == Some(&'*') {
+                    i += 1;
+                }
+                let whole_segment = (start == 0 || chars[start - 1] == '/')
+                    && matches!(chars.get(i), None | Some('/'));
+                match (whole_segment && i - start == 2, chars.get(i)) {
+                    (true, Some('/')) => {
+                        tokens.push(Token::Dirs);
+                        i += 1;
+                    }
+                    (true, None) if start > 0 => tokens.push(Token::Rest),
+                    // A lone `**` matches everything, like `**/*`
+                    (true, None) => tokens.extend([Token::Dirs, Token::Star]),
+                    _ => tokens.push(Token::Star),
+                }
+            }
+            '?' => {
+                tokens.push(Token::Any);
+                i += 1;
+            }
+            '[' => match parse_class(&chars[i..]) {
+                Some((class, len)) => {
+                    tokens.push(Token::Class(class));
+                    i += len;
+                }
+                // An unterminated class is a literal `[`
+                None => {
+                    tokens.push(Token::Literal('['));
+                    i += 1;
+                }
+            },
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Literal(chars[i + 1]));
+                i += 2;
+            }
+            c => {
+                tokens.push(Token::Literal(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Parse a class starting at `chars[0] == '['`, returning it and its length
+fn parse_class(chars: &[char]) -> Option<(CharClass, usize)> {
+    let mut i = 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = Vec::new();
+    let start = i;
+    while i < chars.len() {
+        let mut c = chars[i];
+        // A `]` right after the opening bracket is a literal member
+        if c == ']' && i > start {
+            return Some((CharClass { negated, items }, i + 1));
+        }
+        if c == '[' && chars.get(i + 1) == Some(&':') {
+            let rest: String = chars[i + 2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                items.push(ClassItem::Posix(rest[..end].to_string()));
+                i += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+        if c == '\\' {
+            i += 1;
+            c = *chars.get(i)?;
+        }
+        let is_range =
+            chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&e| e != ']');
+        if is_range {
+            let mut end_at = i + 2;
+            if chars[end_at] == '\\' {
+                end_at += 1;
+            }
+            items.push(ClassItem::Range(c, *chars.get(end_at)?));
+            i = end_at + 1;
+        } else {
+            items.push(ClassItem::Range(c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cases from git's t3070-wildmatch, as (pattern, path, matches)
+    #[test]
+    fn test_wildmatch_spec() {
+        let cases = [
+            ("foo", "foo", true),
+            ("bar", "foo", false),
+            ("", "", true),
+            ("???", "foo", true),
+            ("??", "foo", false),
+            ("*", "foo", true),
+            ("f*", "foo", true),
+            ("*f", "foo", false),
+            ("*foo*", "foo", true),
+            ("*ob*a*r*", "foobar", true),
+            ("*ab", "aaaaaaabababab", true),
+            ("foo\\*", "foo*", true),
+            ("foo\\*bar", "foobar", false),
+            ("f\\\\oo", "f\\oo", true),
+            ("*[al]?", "ball", true),
+            ("[ten]", "ten", false),
+            ("**[!te]", "ten", true),
+            ("**[!ten]", "ten", false),
+            ("t[a-g]n", "ten", true),
+            ("t[!a-g]n", "ten", false),
+            ("t[!a-g]n", "ton", true),
+            ("t[^a-g]n", "ton", true),
+            ("a[]]b", "a]b", true),
+            ("a[]-]b", "a-b", true),
+            ("a[]-]b", "a]b", true),
+            ("a[]-]b", "aab", false),
+            ("a[]a-]b", "aab", true),
+            ("]", "]", true),
+            // Slashes are only matched by `**/` and a trailing `/**`
+            ("foo*bar", "foo/baz/bar", false),
+            ("foo**bar", "foo/baz/bar", false),
+            ("foo**bar", "foobazbar", true),
+            ("foo/**/bar", "foo/baz/bar", true),
+            ("foo/**/**/bar", "foo/baz/bar", true),
+            ("foo/**/bar", "foo/b/a/z/bar", true),
+            ("foo/**/bar", "foo/bar", true),
+            ("foo?bar", "foo/bar", false),
+            ("foo[/]bar", "foo/bar", false),
+            ("foo[^a-z]bar", "foo/bar", false),
+            ("f[^eiu][^eiu][^eiu][^eiu][^eiu]r", "foo/bar", false),
+            ("*/foo", "bar/foo", true),
+            ("**/foo", "foo", true),
+            ("**/foo", "bar/baz/foo", true),
+            ("*/foo", "bar/baz/foo", false),
+            ("**/bar*", "deep/foo/bar/baz", false),
+            ("**/bar/*", "deep/foo/bar/baz", true),
+            ("**/bar/*", "deep/foo/bar/baz/", false),
+            ("**/bar/**", "deep/foo/bar/baz/", true),
+            ("**/bar/*", "deep/foo/bar", false),
+            ("**/bar/**", "deep/foo/bar/", true),
+            ("**", "foo", true),
+            ("**", "foo/bar", true),
+            ("abc/**", "abc/x/y", true),
+            ("abc/**", "abc", false),
+            ("**/.*", "a/.x", true),
+            ("*/*/*", "foo/bba/arr", true),
+            ("*/*/*", "foo/bb/aa/rr", false),
+            ("**/**/**", "foo/bb/aa/rr", true),
+            ("*X*i", "abcXdefXghi", true),
+            ("*/*X*/*/*i", "ab/cXd/efXg/hi", true),
+            ("**/*X*/**/*i", "ab/cXd/efXg/hi", true),
+            // POSIX classes
+            ("[[:alpha:]][[:digit:]][[:upper:]]", "a1B", true),
+            ("[[:digit:][:upper:][:space:]]", "a", false),
+            ("[[:digit:][:upper:][:space:]]", "A", true),
+            ("[[:digit:][:upper:][:space:]]", "1", true),
+            ("[[:xdigit:]]", "f", true),
+            ("[[:punct:]]", ".", true),
+            ("[a-c[:digit:]x-z]", "5", true),
+            ("[a-c[:digit:]x-z]", "q", false),
+            // Escapes inside classes and unterminated classes
+            ("[\\]]", "]", true),
+            ("[\\-_]", "-", true),
+            ("[!\\]]", "a", true),
+            ("[\\1-\\3]", "2", true),
+            ("[", "[", true),
+            ("[!", "[!", true),
+            ("a[", "a[", true),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(
+                Wildmatch::new(pattern, true).matches(path),
+                expected,
+                "{:?} against {:?}",
+                pattern,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_unanchored_patterns() {
+        let log = Wildmatch::new("*.log", false);
+        assert!(log.matches("app.log"));
+        assert!(log.matches("logs/deep/app.log"));
+        assert!(!log.matches("app.log.txt"));
+
+        let cache = Wildmatch::new("cache", false);
+        assert!(cache.matches("cache"));
+        assert!(cache.matches("src/cache"));
+        assert!(!cache.matches("src/cache.rs"));
+        assert_eq!(cache.as_str(), "cache");
+    }
+
+    #[test]
+    fn test_may_match_below() {
+        assert!(Wildmatch::new("important.log", false).may_match_below("logs"));
+        assert!(Wildmatch::new("logs/important.log", true).may_match_below("logs"));
+        assert!(Wildmatch::new("logs/*/keep", true).may_match_below("logs/a"));
+        assert!(Wildmatch::new("*/keep", true).may_match_below("logs"));
+        assert!(!Wildmatch::new("docs/keep", true).may_match_below("logs"));
+        assert!(!Wildmatch::new("logs2/keep", true).may_match_below("logs"));
+    }
+}
@@ -0,0 +1,387 @@
+//! Workspace members, shown as badges like `[crate: foo v0.3.1]`
+//!
+//! A root `Cargo.toml` with a `[workspace]` table lists its member crates as
+//! paths or globs; each member's own manifest gives its name and version,
+//! which may be inherited from `[workspace.package]`.
+//!
+//! JavaScript monorepos list their packages in `pnpm-workspace.yaml`, the
+//! `workspaces` field of `package.json` (npm and yarn) or `lerna.json`; Nx
+//! and Turborepo layouts without such a list use the conventional `apps/`,
+//! `packages/` and `libs/` directories. Each package is badged with the name
+//! and version from its `package.json`.
+
+use crate::error::{Error, Result, WithContext};
+use crate::types::BadgeMap;
+use serde_json::Value;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use toml::Table;
+use tracing::warn;
+
+/// Package directories of Nx and Turborepo layouts that list none
+const CONVENTIONAL_PACKAGES: &[&str] = &["apps/*", "packages/*", "libs/*"];
+
+/// Badges for the workspace members below `root`, keyed like the scanned
+/// entries (`root` joined with the member path)
+///
+/// A root without a workspace manifest has no badges. A manifest that can't
+/// be read is logged and skipped, keeping the other ecosystem's members.
+pub fn workspace_badges(root: &Path) -> BadgeMap {
+    let mut badges = BadgeMap::new();
+    for (ecosystem, found) in [
+        ("Cargo", cargo_badges(root)),
+        ("JavaScript", js_badges(root)),
+    ] {
+        match found {
+            Ok(found) => badges.extend(found),
+            Err(e) => warn!("Failed to read the {} workspace members: {}", ecosystem, e),
+        }
+    }
+    badges
+}
+
+fn cargo_badges(root: &Path) -> Result<BadgeMap> {
+    let mut badges = BadgeMap::new();
+    let manifest = root.join("Cargo.toml");
+    if !manifest.is_file() {
+        return Ok(badges);
+    }
+    let manifest = read_manifest(&manifest)?;
+    let Some(workspace) = manifest.get("workspace").and_then(|w| w.as_table()) else {
+        return Ok(badges);
+    };
+    let inherited = workspace
+        .get("package")
+        .and_then(|package| package.get("version"))
+        .and_then(|version| version.as_str());
+
+    let excluded: Vec<PathBuf> = strings(workspace.get("exclude"))
+        .map(|path| root.join(path))
+        .collect();
+    for dir in member_dirs(root, strings(workspace.get("members"))) {
+        if dir == root || excluded.contains(&dir) {
+            continue;
+        }
+        let Ok(member) = read_manifest(&dir.join("Cargo.toml")) else {
+            continue;
+        };
+        if let Some(badge) = crate_badge(&member, inherited) {
+            badges.insert(dir, badge);
+        }
+    }
+    Ok(badges)
+}
+
+fn js_badges(root: &Path) -> Result<BadgeMap> {
+    let mut badges = BadgeMap::new();
+    let patterns = js_workspace_patterns(root)?;
+    let (excludes, includes): (Vec<&str>, Vec<&str>) = patterns
+        .iter()
+        .map(String::as_str)
+        .partition(|pattern| pattern.starts_with('!'));
+    let excluded = member_dirs(root, excludes.iter().map(|pattern| &pattern[1..]));
+
+    for dir in member_dirs(root, includes.into_iter()) {
+        let installed = dir
+            .components()
+            .any(|c| c == Component::Normal("node_modules".as_ref()));
+        if dir == root || installed || excluded.contains(&dir) {
+            continue;
+        }
+        let Ok(package) = read_package_json(&dir.join("package.json")) else {
+            continue;
+        };
+        if let Some(badge) = package_badge(&package) {
+            badges.insert(dir, badge);
+        }
+    }
+    Ok(badges)
+}
+
+/// Package globs of a JavaScript monorepo, `!`-prefixed ones excluding
+fn js_workspace_patterns(root: &Path) -> Result<Vec<String>> {
+    let pnpm = root.join("pnpm-workspace.yaml");
+    if pnpm.is_file() {
+        let text =
+            fs::read_to_string(&pnpm).with_context(|| format!("reading {}", pnpm.display()))?;
+        return Ok(pnpm_packages(&text));
+    }
+
+    let manifest = root.join("package.json");
+    if manifest.is_file() {
+        let manifest = read_package_json(&manifest)?;
+        // Either a list, or yarn's `{ "packages": [...] }`
+        let workspaces = match manifest.get("workspaces") {
+            Some(Value::Object(workspaces)) => workspaces.get("packages"),
+            workspaces => workspaces,
+        };
+        let patterns = json_strings(workspaces);
+        if !patterns.is_empty() {
+            return Ok(patterns);
+        }
+    }
+
+    let lerna = root.join("lerna.json");
+    if lerna.is_file() {
+        let patterns = json_strings(read_package_json(&lerna)?.get("packages"));
+        if !patterns.is_empty() {
+            return Ok(patterns);
+        }
+    }
+
+    let monorepo = ["nx.json", "turbo.json", "lerna.json"]
+        .iter()
+        .any(|marker| root.join(marker).is_file());
+    Ok(if monorepo {
+        CONVENTIONAL_PACKAGES
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    })
+}
+
+/// The `packages` list of a `pnpm-workspace.yaml`
+///
+/// Only the two shapes pnpm documents are understood: a block list of
+/// `- 'glob'` items, or a flow list `packages: ['a/*', 'b']`.
+fn pnpm_packages(text: &str) -> Vec<String> {
+    let unquote = |item: &str| {
+        item.trim()
+            .trim_matches(|c| c == '\'' || c == '"')
+            .to_string()
+    };
+    let mut packages = Vec::new();
+    let mut in_packages = false;
+    for line in text.lines() {
+        let content = line.split(" #").next().unwrap_or(line).trim_end();
+        if content.trim().is_empty() || content.trim_start().starts_with('#') {
+            continue;
+        }
+        if !line.starts_with([' ', '\t', '-']) {
+            in_packages = false;
+            if let Some(rest) = content.strip_prefix("packages:") {
+                let rest = rest.trim();
+                match rest.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+                    Some(items) => packages.extend(items.split(',').map(unquote)),
+                    None => in_packages = rest.is_empty(),
+                }
+            }
+            continue;
+        }
+        if let (true, Some(item)) = (in_packages, content.trim_start().strip_prefix('-')) {
+            packages.push(unquote(item));
+        }
+    }
+    packages.retain(|package| !package.is_empty());
+    packages
+}
+
+fn read_manifest(path: &Path) -> Result<Table> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    text.parse()
+        .map_err(|e: toml::de::Error| Error::Format(format!("parsing {}: {}", path.display(), e)))
+}
+
+/// The strings of an optional TOML array
+fn strings(value: Option<&toml::Value>) -> impl Iterator<Item = &str> {
+    value
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.as_str())
+}
+
+/// Directories matching the member paths and globs, in listing order
+fn member_dirs<'a>(root: &Path, members: impl Iterator<Item = &'a str>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for member in members {
+        // The root is taken literally, only the member part is a glob
+        let pattern = Path::new(&glob::Pattern::escape(&root.to_string_lossy())).join(member);
+        let Ok(paths) = glob::glob(&pattern.to_string_lossy()) else {
+            continue;
+        };
+        for path in paths.flatten() {
+            // glob drops a leading `./`, which the scanned paths keep
+            let path = root.join(path.strip_prefix(root).unwrap_or(&path));
+            if path.is_dir() && !dirs.contains(&path) {
+                dirs.push(path);
+            }
+        }
+    }
+    dirs
+}
+
+fn read_package_json(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_str(&text)
+        .map_err(|e| Error::Format(format!("parsing {}: {}", path.display(), e)))
+}
+
+/// The strings of an optional JSON array
+fn json_strings(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.as_str().map(String::from))
+        .collect()
+}
+
+/// `package: name vX.Y.Z` from a `package.json`, or just the name when it
+/// has no version
+fn package_badge(package: &Value) -> Option<String> {
+    let name = package.get("name")?.as_str()?;
+    Some(match package.get("version").and_then(Value::as_str) {
+        Some(version) => format!("package: {} v{}", name, version),
+        None => format!("package: {}", name),
+    })
+}
+
+/// `crate: name vX.Y.Z` from a member manifest, or just the name when it
+/// has no version
+fn crate_badge(manifest: &Table, inherited: Option<&str>) -> Option<String> {
+    let package = manifest.get("package")?;
+    let name = package.get("name")?.as_str()?;
+    let version = match package.get("version") {
+        Some(toml::Value::String(version)) => Some(version.as_str()),
+        // `version.workspace = true`
+        Some(toml::Value::Table(_)) => inherited,
+        _ => None,
+    };
+    Some(match version {
+        Some(version) => format!("crate: {} v{}", name, version),
+        None => format!("crate: {}", name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_cargo_workspace_badges() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\", \"cli\"]\nexclude = [\"crates/old\"]\n\n[workspace.package]\nversion = \"1.2.0\"\n",
+        );
+        write(
+            root,
+            "crates/foo/Cargo.toml",
+            "[package]\nname = \"foo\"\nversion = \"0.3.1\"\n",
+        );
+        write(
+            root,
+            "crates/bar/Cargo.toml",
+            "[package]\nname = \"bar\"\nversion.workspace = true\n",
+        );
+        write(
+            root,
+            "crates/old/Cargo.toml",
+            "[package]\nname = \"old\"\nversion = \"0.1.0\"\n",
+        );
+        write(root, "cli/Cargo.toml", "[package]\nname = \"foo-cli\"\n");
+
+        let badges = workspace_badges(root);
+        assert_eq!(badges.len(), 3);
+        assert_eq!(badges[&root.join("crates/foo")], "crate: foo v0.3.1");
+        assert_eq!(badges[&root.join("crates/bar")], "crate: bar v1.2.0");
+        assert_eq!(badges[&root.join("cli")], "crate: foo-cli");
+    }
+
+    #[test]
+    fn test_js_workspace_badges() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        write(
+            root,
+            "package.json",
+            r#"{"private": true, "workspaces": {"packages": ["packages/*", "!packages/scratch"]}}"#,
+        );
+        write(
+            root,
+            "packages/ui/package.json",
+            r#"{"name": "@acme/ui", "version": "2.0.0"}"#,
+        );
+        write(root, "packages/utils/package.json", r#"{"name": "utils"}"#);
+        write(
+            root,
+            "packages/scratch/package.json",
+            r#"{"name": "scratch"}"#,
+        );
+        write(root, "packages/docs/README.md", "no manifest");
+
+        let badges = workspace_badges(root);
+        assert_eq!(badges.len(), 2);
+        assert_eq!(
+            badges[&root.join("packages/ui")],
+            "package: @acme/ui v2.0.0"
+        );
+        assert_eq!(badges[&root.join("packages/utils")], "package: utils");
+    }
+
+    #[test]
+    fn test_pnpm_and_conventional_layouts() {
+        assert_eq!(
+            pnpm_packages(
+                "packages:\n  # apps too\n  - 'apps/*'\n  - \"libs/**\"\ncatalog:\n  - react\n"
+            ),
+            vec!["apps/*", "libs/**"]
+        );
+        assert_eq!(
+            pnpm_packages("packages: ['a/*', \"b\"]\n"),
+            vec!["a/*", "b"]
+        );
+
+        // Turborepo without a workspace list falls back to apps/ and packages/
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        write(root, "turbo.json", "{}");
+        write(
+            root,
+            "apps/web/package.json",
+            r#"{"name": "web", "version": "0.1.0"}"#,
+        );
+        write(root, "tools/package.json", r#"{"name": "tools"}"#);
+        let badges = workspace_badges(root);
+        assert_eq!(badges.len(), 1);
+        assert_eq!(badges[&root.join("apps/web")], "package: web v0.1.0");
+    }
+
+    #[test]
+    fn test_broken_manifest_keeps_other_members() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        write(root, "Cargo.toml", "[workspace\nmembers = ");
+        write(root, "package.json", r#"{"workspaces": ["packages/*"]}"#);
+        write(root, "packages/ui/package.json", r#"{"name": "ui"}"#);
+
+        let badges = workspace_badges(root);
+        assert_eq!(badges.len(), 1);
+        assert_eq!(badges[&root.join("packages/ui")], "package: ui");
+    }
+
+    #[test]
+    fn test_no_workspace() {
+        let temp = TempDir::new().unwrap();
+        assert!(workspace_badges(temp.path()).is_empty());
+
+        write(
+            temp.path(),
+            "Cargo.toml",
+            "[package]\nname = \"single\"\nversion = \"0.1.0\"\n",
+        );
+        assert!(workspace_badges(temp.path()).is_empty());
+    }
+}